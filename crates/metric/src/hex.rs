@@ -0,0 +1,99 @@
+use std::fmt::{self, Display};
+
+/// Formats a byte slice as space separated hex pairs, for use in diagnostics
+pub struct HexDump<'b>(pub &'b [u8]);
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Formats a byte slice in the classic hex editor layout, a line per 16 bytes
+/// with the offset, the bytes in hex and the printable ASCII characters
+///
+/// ```text
+/// 00000000  68 65 6c 6c 6f 00 ff                              |hello..|
+/// ```
+pub struct HexView<'b>(pub &'b [u8]);
+
+impl HexView<'_> {
+    pub const BYTES_PER_LINE: usize = 16;
+}
+
+impl Display for HexView<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (line, chunk) in self.0.chunks(Self::BYTES_PER_LINE).enumerate() {
+            if line != 0 {
+                writeln!(f)?;
+            }
+
+            write!(f, "{:08x} ", line * Self::BYTES_PER_LINE)?;
+
+            for column in 0..Self::BYTES_PER_LINE {
+                // An extra space halfway, like hexdump -C
+                if column == Self::BYTES_PER_LINE / 2 {
+                    write!(f, " ")?;
+                }
+
+                match chunk.get(column) {
+                    Some(byte) => write!(f, " {byte:02x}")?,
+                    None => write!(f, "   ")?,
+                }
+            }
+
+            write!(f, "  |")?;
+            for &byte in chunk {
+                let char = if byte.is_ascii_graphic() || byte == b' ' {
+                    char::from(byte)
+                } else {
+                    '.'
+                };
+
+                write!(f, "{char}")?;
+            }
+            write!(f, "|")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dump_shows_little_endian_bytes() {
+        assert_eq!(HexDump(&0x1234_u16.to_le_bytes()).to_string(), "34 12");
+        assert_eq!(HexDump(&1.0_f32.to_le_bytes()).to_string(), "00 00 80 3f");
+        assert_eq!(HexDump(&(-2_i32).to_le_bytes()).to_string(), "fe ff ff ff");
+        assert_eq!(HexDump(&[]).to_string(), "");
+    }
+
+    #[test]
+    fn view_lines_up_bytes_and_text() {
+        assert_eq!(
+            HexView(b"hello\0\xff").to_string(),
+            "00000000  68 65 6c 6c 6f 00 ff                              |hello..|"
+        );
+
+        let view = HexView(&[b'a'; 17]).to_string();
+        let lines = view.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            "00000000  61 61 61 61 61 61 61 61  61 61 61 61 61 61 61 61  |aaaaaaaaaaaaaaaa|"
+        );
+        assert!(lines[1].starts_with("00000010  61 "));
+        assert!(lines[1].ends_with("|a|"));
+    }
+}
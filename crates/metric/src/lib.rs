@@ -1,12 +1,16 @@
 use std::fmt::Debug;
 
+use time::OffsetDateTime;
+
 use self::{name::MetricName, timestamp::Timestamp, value::MetricValue};
 
+pub mod hex;
 pub mod name;
 pub mod timestamp;
 pub mod value;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 #[non_exhaustive]
 pub enum RobotCommand {
@@ -15,8 +19,18 @@ pub enum RobotCommand {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metric {
     pub timestamp: Timestamp,
+    /// Wall-clock time at which the packet was received
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+    pub arrival: OffsetDateTime,
     pub name: MetricName,
     pub value: MetricValue,
+    /// The raw value bytes the value was decoded from, if retained by the worker
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub raw: Option<Box<[u8]>>,
 }
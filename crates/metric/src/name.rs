@@ -1,31 +1,40 @@
 use std::{
+    collections::HashSet,
     convert::Infallible,
-    fmt::{self, Display},
+    fmt::{self, Display, Write},
     str::FromStr,
+    sync::Arc,
 };
 
 use once_cell::sync::Lazy;
-use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
-use string_interner::{symbol::DefaultSymbol, DefaultStringInterner};
-
-static INTERNER: Lazy<RwLock<DefaultStringInterner>> =
-    Lazy::new(|| RwLock::new(DefaultStringInterner::new()));
-
-fn resolve(symbol: DefaultSymbol) -> MappedRwLockReadGuard<'static, str> {
-    RwLockReadGuard::map(INTERNER.read(), |interner| {
-        interner
-            .resolve(symbol)
-            .expect("string must have been interned")
-    })
+use parking_lot::Mutex;
+
+/// Components of metric names, shared so every distinct component is only stored once
+///
+/// Metric names hold on to their components themselves, so unused components can
+/// be dropped at any time without invalidating a name, see [`MetricName::collect_garbage`].
+static INTERNER: Lazy<Mutex<HashSet<Arc<str>>>> = Lazy::new(Mutex::default);
+
+fn intern(component: &str) -> Arc<str> {
+    let mut interner = INTERNER.lock();
+
+    if let Some(interned) = interner.get(component) {
+        return Arc::clone(interned);
+    }
+
+    let interned = Arc::<str>::from(component);
+    interner.insert(Arc::clone(&interned));
+
+    interned
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum MetricName {
     Namespace {
-        namespace: DefaultSymbol,
+        namespace: Arc<str>,
         name: Box<MetricName>,
     },
-    Name(DefaultSymbol),
+    Name(Arc<str>),
 }
 
 #[macro_export]
@@ -40,30 +49,41 @@ macro_rules! metric_name {
 
 impl MetricName {
     pub fn namespace_static(namespace: &'static str, name: MetricName) -> Self {
-        Self::Namespace {
-            namespace: INTERNER.write().get_or_intern_static(namespace),
-            name: Box::new(name),
-        }
+        Self::namespace(namespace, name)
     }
 
     pub fn namespace(namespace: &str, name: MetricName) -> Self {
         Self::Namespace {
-            namespace: INTERNER.write().get_or_intern(namespace),
+            namespace: intern(namespace),
             name: Box::new(name),
         }
     }
 
     pub fn name_static(name: &'static str) -> Self {
-        Self::Name(INTERNER.write().get_or_intern_static(name))
+        Self::name(name)
     }
 
     pub fn name(name: &str) -> Self {
-        Self::Name(INTERNER.write().get_or_intern(name))
+        Self::Name(intern(name))
     }
 
-    pub fn flatten(&self) -> Flatten {
+    pub fn flatten(&self) -> Flatten<'_> {
         Flatten { name: Some(self) }
     }
+
+    /// Forget the interned components that no metric name uses anymore,
+    /// returning how many were freed
+    ///
+    /// Long sessions with names that keep changing otherwise grow the interner forever.
+    pub fn collect_garbage() -> usize {
+        let mut interner = INTERNER.lock();
+        let interned = interner.len();
+
+        // The interner itself holds one reference to every component
+        interner.retain(|component| Arc::strong_count(component) > 1);
+
+        interned - interner.len()
+    }
 }
 
 pub struct Flatten<'n> {
@@ -71,54 +91,102 @@ pub struct Flatten<'n> {
 }
 
 impl<'n> Iterator for Flatten<'n> {
-    type Item = MappedRwLockReadGuard<'n, str>;
+    type Item = &'n str;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let symbol = match self.name? {
+        match self.name? {
             MetricName::Namespace { namespace, name } => {
                 self.name = Some(name);
 
-                *namespace
+                Some(namespace)
             }
             MetricName::Name(name) => {
                 self.name = None;
 
-                *name
+                Some(name)
             }
-        };
-
-        Some(resolve(symbol))
+        }
     }
 }
 
+/// Separator between the namespaces and the name in the string form of a metric name
+const SEPARATOR: char = ':';
+/// Escapes a literal separator, or itself, inside a single component
+const ESCAPE: char = '\\';
+
 impl Display for MetricName {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut flatten = self.flatten();
-        let first = flatten
-            .next()
-            .expect("metric name must have at least one component")
-            .to_string();
-        let string = flatten.fold(first, |pre, new| pre + ":" + new.as_ref());
+        for (index, component) in self.flatten().enumerate() {
+            if index != 0 {
+                f.write_char(SEPARATOR)?;
+            }
 
-        write!(f, "{string}")
+            for char in component.chars() {
+                if char == SEPARATOR || char == ESCAPE {
+                    f.write_char(ESCAPE)?;
+                }
+
+                f.write_char(char)?;
+            }
+        }
+
+        Ok(())
     }
 }
 
 impl FromStr for MetricName {
     type Err = Infallible;
 
+    /// Parse a metric name, splitting on every separator that is not escaped
+    ///
+    /// A backslash that does not escape a separator or backslash is kept as is,
+    /// so names written before escaping existed parse the same as before.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut split = s.split(':');
+        let mut components = vec![String::new()];
+        let mut chars = s.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            let component = components
+                .last_mut()
+                .expect("there is always at least one component");
+
+            match char {
+                ESCAPE => match chars.next_if(|&next| next == SEPARATOR || next == ESCAPE) {
+                    Some(escaped) => component.push(escaped),
+                    None => component.push(ESCAPE),
+                },
+                SEPARATOR => components.push(String::new()),
+                char => component.push(char),
+            }
+        }
 
         // Get the metric name
-        let name = split
-            .next_back()
-            .expect("split iterator must have at least one element");
+        let name = components
+            .pop()
+            .expect("there is always at least one component");
 
         // Fold the namespaces from the back
-        Ok(split.rfold(MetricName::name(name), |name, namespace| {
-            MetricName::namespace(namespace, name)
-        }))
+        Ok(components
+            .iter()
+            .rfold(MetricName::name(&name), |name, namespace| {
+                MetricName::namespace(namespace, name)
+            }))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for MetricName {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MetricName {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+
+        Ok(string.parse().expect("metric name parsing must never fail"))
     }
 }
 
@@ -137,7 +205,7 @@ impl From<&MetricName> for egui::WidgetText {
             match metric_name {
                 MetricName::Namespace { namespace, name } => {
                     job.append(
-                        resolve(*namespace).as_ref(),
+                        namespace,
                         0.0,
                         TextFormat {
                             color: Color32::KHAKI,
@@ -158,7 +226,7 @@ impl From<&MetricName> for egui::WidgetText {
                 }
                 MetricName::Name(name) => {
                     job.append(
-                        resolve(*name).as_ref(),
+                        name,
                         0.0,
                         TextFormat {
                             color: Color32::GOLD,
@@ -174,3 +242,61 @@ impl From<&MetricName> for egui::WidgetText {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(name: &str) -> MetricName {
+        name.parse().unwrap()
+    }
+
+    fn components(name: &MetricName) -> Vec<String> {
+        name.flatten()
+            .map(|component| component.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn separators_split_namespaces() {
+        let name = parse("motor:left:speed");
+
+        assert_eq!(
+            name,
+            MetricName::namespace(
+                "motor",
+                MetricName::namespace("left", MetricName::name("speed"))
+            )
+        );
+        assert_eq!(name.to_string(), "motor:left:speed");
+    }
+
+    #[test]
+    fn colons_inside_a_component_round_trip() {
+        let name = MetricName::namespace("gps", MetricName::name("time 12:30"));
+
+        assert_eq!(name.to_string(), r"gps:time 12\:30");
+        assert_eq!(parse(&name.to_string()), name);
+    }
+
+    #[test]
+    fn backslashes_inside_a_component_round_trip() {
+        let name = MetricName::namespace(r"C:\", MetricName::name(r"a\:b"));
+
+        assert_eq!(name.to_string(), r"C\:\\:a\\\:b");
+        assert_eq!(parse(&name.to_string()), name);
+        assert_eq!(components(&name), [r"C:\", r"a\:b"]);
+    }
+
+    #[test]
+    fn lone_backslashes_are_kept() {
+        assert_eq!(components(&parse(r"path\to:value")), [r"path\to", "value"]);
+        assert_eq!(components(&parse(r"trailing\")), [r"trailing\"]);
+    }
+
+    #[test]
+    fn empty_components_are_kept() {
+        assert_eq!(components(&parse("a::b")), ["a", "", "b"]);
+        assert_eq!(components(&parse("")), [""]);
+    }
+}
@@ -1,11 +1,63 @@
 use std::{
     fmt::{self, Display},
     ops::Sub,
+    str::FromStr,
+    time::Duration,
 };
 
+use time::OffsetDateTime;
+
+/// The unit of the raw timestamps sent by the firmware
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimestampUnit {
+    #[default]
+    Millis,
+    Micros,
+}
+
+impl TimestampUnit {
+    pub const fn ticks_per_second(self) -> u32 {
+        match self {
+            TimestampUnit::Millis => 1_000,
+            TimestampUnit::Micros => 1_000_000,
+        }
+    }
+
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            TimestampUnit::Millis => "ms",
+            TimestampUnit::Micros => "µs",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseTimestampUnitError;
+
+impl Display for ParseTimestampUnitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of `ms`, `us` or `µs`")
+    }
+}
+
+impl FromStr for TimestampUnit {
+    type Err = ParseTimestampUnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ms" => Ok(TimestampUnit::Millis),
+            "us" | "µs" => Ok(TimestampUnit::Micros),
+            _ => Err(ParseTimestampUnitError),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Timestamp {
     timestamp: u32,
+    unit: TimestampUnit,
 }
 
 impl Timestamp {
@@ -13,44 +65,199 @@ impl Timestamp {
     pub const MIN: Self = Self::from_millis(u32::MIN);
 
     pub const fn from_millis(millis: u32) -> Self {
-        Self { timestamp: millis }
+        Self::from_raw(millis, TimestampUnit::Millis)
+    }
+
+    pub const fn from_micros(micros: u32) -> Self {
+        Self::from_raw(micros, TimestampUnit::Micros)
+    }
+
+    pub const fn from_raw(timestamp: u32, unit: TimestampUnit) -> Self {
+        Self { timestamp, unit }
     }
 }
 
 impl Timestamp {
+    /// The raw timestamp, in ticks of [`Timestamp::unit`]
     pub const fn timestamp(&self) -> u32 {
         self.timestamp
     }
 
+    pub const fn unit(&self) -> TimestampUnit {
+        self.unit
+    }
+
+    /// The sub-second part of the timestamp, in ticks of [`Timestamp::unit`]
+    pub const fn subsecond(&self) -> u32 {
+        self.timestamp % self.unit.ticks_per_second()
+    }
+
     pub const fn millis(&self) -> u32 {
-        self.timestamp % 1_000
+        match self.unit {
+            TimestampUnit::Millis => self.subsecond(),
+            TimestampUnit::Micros => self.subsecond() / 1_000,
+        }
     }
 
     pub const fn seconds(&self) -> u32 {
-        (self.timestamp / 1_000) % 60
+        (self.timestamp / self.unit.ticks_per_second()) % 60
     }
 
     pub const fn minutes(&self) -> u32 {
-        self.timestamp / 60_000
+        self.timestamp / (self.unit.ticks_per_second() * 60)
+    }
+
+    /// Time elapsed since `earlier`, assuming the counter only moves forward and
+    /// may have wrapped around past `u32::MAX` in between
+    ///
+    /// A timestamp that rolled over comes out as a short time after `earlier`,
+    /// while a reboot back to near zero comes out as a very long one.
+    pub fn wrapping_since(&self, earlier: Timestamp) -> Duration {
+        let ticks = self.timestamp.wrapping_sub(earlier.timestamp);
+
+        Duration::from_micros(
+            u64::from(ticks) * 1_000_000 / u64::from(self.unit.ticks_per_second()),
+        )
+    }
+
+    /// Wall-clock time at which this timestamp was taken, given that `anchor` was taken at `anchor_time`
+    ///
+    /// Timestamps before the anchor are placed before `anchor_time`, so this
+    /// only holds while both lie within the same boot of the robot.
+    pub fn to_wall_clock(&self, anchor: Timestamp, anchor_time: OffsetDateTime) -> OffsetDateTime {
+        if *self >= anchor {
+            anchor_time + self.wrapping_since(anchor)
+        } else {
+            anchor_time - anchor.wrapping_since(*self)
+        }
+    }
+
+    /// Move the timestamp forward by a wall-clock duration, saturating at the maximum
+    pub fn saturating_add_duration(self, duration: Duration) -> Self {
+        let ticks = duration.as_micros() * u128::from(self.unit.ticks_per_second()) / 1_000_000;
+
+        Self {
+            timestamp: u32::try_from(ticks)
+                .map_or(u32::MAX, |ticks| self.timestamp.saturating_add(ticks)),
+            unit: self.unit,
+        }
     }
 }
 
 impl Display for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let millis = self.millis();
+        let subsecond = self.subsecond();
         let seconds = self.seconds();
         let minutes = self.minutes();
 
-        write!(f, "{minutes:02}:{seconds:02}.{millis:03}")
+        match self.unit {
+            TimestampUnit::Millis => write!(f, "{minutes:02}:{seconds:02}.{subsecond:03}"),
+            TimestampUnit::Micros => write!(f, "{minutes:02}:{seconds:02}.{subsecond:06}"),
+        }
     }
 }
 
+/// Time elapsed since `rhs`, wrapping around past `u32::MAX` like the robot clock does
+///
+/// Like [`Timestamp::wrapping_since`], a timestamp that rolled over comes out as a
+/// short time after `rhs` instead of zero.
 impl Sub for Timestamp {
     type Output = Timestamp;
 
     fn sub(self, rhs: Self) -> Self::Output {
         Timestamp {
-            timestamp: self.timestamp.saturating_sub(rhs.timestamp),
+            timestamp: self.timestamp.wrapping_sub(rhs.timestamp),
+            unit: self.unit,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sub_within_a_boot() {
+        assert_eq!(
+            Timestamp::from_millis(1_500) - Timestamp::from_millis(500),
+            Timestamp::from_millis(1_000)
+        );
+    }
+
+    #[test]
+    fn sub_across_rollover() {
+        let before = Timestamp::from_millis(u32::MAX - 99);
+        let after = Timestamp::from_millis(150);
+
+        assert_eq!(after - before, Timestamp::from_millis(250));
+        assert_eq!(
+            Timestamp::from_micros(0) - Timestamp::from_micros(u32::MAX),
+            Timestamp::from_micros(1)
+        );
+    }
+
+    #[test]
+    fn sub_at_max_is_zero() {
+        assert_eq!(Timestamp::MAX - Timestamp::MAX, Timestamp::MIN);
+    }
+
+    #[test]
+    fn wrapping_since_across_rollover() {
+        let before = Timestamp::from_micros(u32::MAX - 999);
+        let after = Timestamp::from_micros(1_000);
+
+        assert_eq!(after.wrapping_since(before), Duration::from_micros(2_000));
+        assert_eq!(
+            Timestamp::from_millis(5).wrapping_since(Timestamp::from_millis(u32::MAX)),
+            Duration::from_millis(6)
+        );
+    }
+
+    #[test]
+    fn display_in_milliseconds() {
+        assert_eq!(Timestamp::from_millis(0).to_string(), "00:00.000");
+        assert_eq!(Timestamp::from_millis(61_234).to_string(), "01:01.234");
+        assert_eq!(Timestamp::from_millis(3_600_005).to_string(), "60:00.005");
+    }
+
+    #[test]
+    fn display_in_microseconds() {
+        assert_eq!(Timestamp::from_micros(0).to_string(), "00:00.000000");
+        assert_eq!(
+            Timestamp::from_micros(61_234_567).to_string(),
+            "01:01.234567"
+        );
+        assert_eq!(Timestamp::from_micros(u32::MAX).to_string(), "71:34.967295");
+    }
+
+    #[test]
+    fn parts_follow_the_unit() {
+        let millis = Timestamp::from_millis(61_234);
+        let micros = Timestamp::from_micros(61_234_567);
+
+        for timestamp in [millis, micros] {
+            assert_eq!(timestamp.minutes(), 1);
+            assert_eq!(timestamp.seconds(), 1);
+            assert_eq!(timestamp.millis(), 234);
+        }
+        assert_eq!(millis.subsecond(), 234);
+        assert_eq!(micros.subsecond(), 234_567);
+    }
+
+    #[test]
+    fn unit_parses_from_its_symbol() {
+        assert_eq!(
+            "ms".parse::<TimestampUnit>().ok(),
+            Some(TimestampUnit::Millis)
+        );
+        assert_eq!(
+            "us".parse::<TimestampUnit>().ok(),
+            Some(TimestampUnit::Micros)
+        );
+        assert_eq!(
+            "µs".parse::<TimestampUnit>().ok(),
+            Some(TimestampUnit::Micros)
+        );
+        assert!("s".parse::<TimestampUnit>().is_err());
+    }
+}
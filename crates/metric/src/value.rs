@@ -7,45 +7,68 @@
 
 use std::fmt::Debug;
 
-#[derive(Debug, Clone)]
+use half::f16;
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum MetricValue {
     One(OneValue),
     Many(ManyValues),
     Unknown(String, Box<[u8]>),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum OneValue {
     U8(u8),
     U16(u16),
     U32(u32),
     U64(u64),
+    U128(u128),
     I8(i8),
     I16(i16),
     I32(i32),
     I64(i64),
+    I128(i128),
     Bool(bool),
+    /// Half-precision float, for firmware short on bandwidth
+    F16(f16),
     F32(f32),
     F64(f64),
+    /// Text such as a state name or error message, invalid UTF-8 is replaced
+    Str(Box<str>),
+    /// A pair of coordinates, such as a position
+    Vec2 {
+        x: f32,
+        y: f32,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ManyValues {
     U8(Box<[u8]>),
     U16(Box<[u16]>),
     U32(Box<[u32]>),
     U64(Box<[u64]>),
+    U128(Box<[u128]>),
     I8(Box<[i8]>),
     I16(Box<[i16]>),
     I32(Box<[i32]>),
     I64(Box<[i64]>),
+    I128(Box<[i128]>),
     Bool(Box<[bool]>),
+    F16(Box<[f16]>),
     F32(Box<[f32]>),
     F64(Box<[f64]>),
+    /// Pairs of `(x, y)` coordinates
+    Vec2(Box<[(f32, f32)]>),
 }
 
 #[derive(Debug)]
 pub enum MetricValueError {
+    /// The value bytes do not fit the type, for arrays `expected` is the length
+    /// rounded up to a whole number of elements
     BadLength { expected: usize, got: usize },
 }
 
@@ -61,11 +84,21 @@ impl MetricValue {
                 metric!(@internal window as [$ty])
                     .collect::<Result<Box<[$ty]>, _>>()
             };
-            (@internal $bytes:ident as [$ty:ty]) => {
+            (@internal $bytes:ident as [$ty:ty]) => {{
+                let size = core::mem::size_of::<$ty>();
+
+                // Refuse a trailing partial element instead of silently dropping it
+                if bytes.len() % size != 0 {
+                    return Err(MetricValueError::BadLength {
+                        expected: bytes.len().next_multiple_of(size),
+                        got: bytes.len(),
+                    });
+                }
+
                 bytes
-                    .chunks(core::mem::size_of::<$ty>())
+                    .chunks_exact(size)
                     .map(|window| metric!(window as $ty))
-            };
+            }};
             ($bytes:ident as bool) => {
                 metric!($bytes as u8).map(|byte| byte != 0)
             };
@@ -89,6 +122,8 @@ impl MetricValue {
             "[u32]" => MetricValue::Many(ManyValues::U32(metric!(bytes as [u32])?)),
             "u64" => MetricValue::One(OneValue::U64(metric!(bytes as u64)?)),
             "[u64]" => MetricValue::Many(ManyValues::U64(metric!(bytes as [u64])?)),
+            "u128" => MetricValue::One(OneValue::U128(metric!(bytes as u128)?)),
+            "[u128]" => MetricValue::Many(ManyValues::U128(metric!(bytes as [u128])?)),
 
             "i8" => MetricValue::One(OneValue::I8(metric!(bytes as i8)?)),
             "[i8]" => MetricValue::Many(ManyValues::I8(metric!(bytes as [i8])?)),
@@ -98,15 +133,62 @@ impl MetricValue {
             "[i32]" => MetricValue::Many(ManyValues::I32(metric!(bytes as [i32])?)),
             "i64" => MetricValue::One(OneValue::I64(metric!(bytes as i64)?)),
             "[i64]" => MetricValue::Many(ManyValues::I64(metric!(bytes as [i64])?)),
+            "i128" => MetricValue::One(OneValue::I128(metric!(bytes as i128)?)),
+            "[i128]" => MetricValue::Many(ManyValues::I128(metric!(bytes as [i128])?)),
 
             "bool" => MetricValue::One(OneValue::Bool(metric!(bytes as bool)?)),
             "[bool]" => MetricValue::Many(ManyValues::Bool(metric!(bytes as [bool])?)),
 
+            "f16" => MetricValue::One(OneValue::F16(metric!(bytes as f16)?)),
+            "[f16]" => MetricValue::Many(ManyValues::F16(metric!(bytes as [f16])?)),
             "f32" => MetricValue::One(OneValue::F32(metric!(bytes as f32)?)),
             "[f32]" => MetricValue::Many(ManyValues::F32(metric!(bytes as [f32])?)),
             "f64" => MetricValue::One(OneValue::F64(metric!(bytes as f64)?)),
             "[f64]" => MetricValue::Many(ManyValues::F64(metric!(bytes as [f64])?)),
 
+            "str" => MetricValue::One(OneValue::Str(String::from_utf8_lossy(bytes).into())),
+
+            "vec2" => {
+                // Checked up front, the inner decoder reports lengths that are not a
+                // multiple of an `f32` against the next multiple instead
+                let size = 2 * std::mem::size_of::<f32>();
+                if bytes.len() != size {
+                    return Err(MetricValueError::BadLength {
+                        expected: size,
+                        got: bytes.len(),
+                    });
+                }
+
+                let [x, y] = metric!(bytes as [f32])?[..] else {
+                    return Err(MetricValueError::BadLength {
+                        expected: size,
+                        got: bytes.len(),
+                    });
+                };
+
+                MetricValue::One(OneValue::Vec2 { x, y })
+            }
+            "[vec2]" => {
+                let size = 2 * std::mem::size_of::<f32>();
+
+                // Refuse a trailing partial pair, like a trailing partial element
+                if bytes.len() % size != 0 {
+                    return Err(MetricValueError::BadLength {
+                        expected: bytes.len().next_multiple_of(size),
+                        got: bytes.len(),
+                    });
+                }
+
+                let coordinates = metric!(bytes as [f32])?;
+
+                MetricValue::Many(ManyValues::Vec2(
+                    coordinates
+                        .chunks_exact(2)
+                        .map(|pair| (pair[0], pair[1]))
+                        .collect(),
+                ))
+            }
+
             _ => MetricValue::Unknown(ty, Box::from(bytes)),
         })
     }
@@ -136,31 +218,58 @@ impl MetricValue {
                 OneValue::U16(value) => ("u16", value),
                 OneValue::U32(value) => ("u32", value),
                 OneValue::U64(value) => ("u64", value),
+                OneValue::U128(value) => ("u128", value),
                 OneValue::I8(value) => ("i8", value),
                 OneValue::I16(value) => ("i16", value),
                 OneValue::I32(value) => ("i32", value),
                 OneValue::I64(value) => ("i64", value),
+                OneValue::I128(value) => ("i128", value),
                 OneValue::Bool(value) => ("bool", value),
+                OneValue::F16(value) => ("f16", value),
                 OneValue::F32(value) => ("f32", value),
                 OneValue::F64(value) => ("f64", value),
+                OneValue::Str(value) => ("str", value),
+                vec2 @ OneValue::Vec2 { .. } => ("vec2", vec2),
             },
             MetricValue::Many(value) => match value {
                 ManyValues::U8(value) => ("[u8]", value),
                 ManyValues::U16(value) => ("[u16]", value),
                 ManyValues::U32(value) => ("[u32]", value),
                 ManyValues::U64(value) => ("[u64]", value),
+                ManyValues::U128(value) => ("[u128]", value),
                 ManyValues::I8(value) => ("[i8]", value),
                 ManyValues::I16(value) => ("[i16]", value),
                 ManyValues::I32(value) => ("[i32]", value),
                 ManyValues::I64(value) => ("[i64]", value),
+                ManyValues::I128(value) => ("[i128]", value),
                 ManyValues::Bool(value) => ("[bool]", value),
+                ManyValues::F16(value) => ("[f16]", value),
                 ManyValues::F32(value) => ("[f32]", value),
                 ManyValues::F64(value) => ("[f64]", value),
+                ManyValues::Vec2(value) => ("[vec2]", value),
             },
             MetricValue::Unknown(ty, value) => (ty, value),
         }
     }
 
+    /// The bytes of a value that is stored as bytes, such as the value of an unknown
+    /// type, a `[u8]` array or a string
+    ///
+    /// Other types are decoded when received and do not keep their bytes,
+    /// see the raw bytes of the packet for those.
+    pub fn raw_bytes(&self) -> Option<&[u8]> {
+        match self {
+            MetricValue::Unknown(_, bytes) => Some(bytes),
+            MetricValue::Many(ManyValues::U8(bytes)) => Some(bytes),
+            MetricValue::One(OneValue::Str(value)) => Some(value.as_bytes()),
+            _ => None,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, MetricValue::Unknown(..))
+    }
+
     pub fn is_bool(&self) -> bool {
         self.as_bool().is_some()
     }
@@ -179,6 +288,27 @@ impl MetricValue {
         }
     }
 
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MetricValue::One(OneValue::Str(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec2(&self) -> Option<(f32, f32)> {
+        match self {
+            MetricValue::One(OneValue::Vec2 { x, y }) => Some((*x, *y)),
+            _ => None,
+        }
+    }
+
+    pub fn as_vec2_iter(&self) -> Option<Box<dyn Iterator<Item = (f32, f32)> + '_>> {
+        match self {
+            MetricValue::Many(ManyValues::Vec2(value)) => Some(Box::new(value.iter().copied())),
+            _ => None,
+        }
+    }
+
     pub fn is_unsigned_integer(&self) -> bool {
         self.as_unsigned_integer().is_some()
     }
@@ -190,6 +320,8 @@ impl MetricValue {
                 OneValue::U16(value) => Some(u64::from(*value)),
                 OneValue::U32(value) => Some(u64::from(*value)),
                 OneValue::U64(value) => Some(*value),
+                // Out of range values are not truncated, they are simply not an `u64`
+                OneValue::U128(value) => u64::try_from(*value).ok(),
                 _ => None,
             },
             _ => None,
@@ -202,7 +334,14 @@ impl MetricValue {
                 ManyValues::U8(value) => Some(Box::new(value.iter().copied().map(u64::from))),
                 ManyValues::U16(value) => Some(Box::new(value.iter().copied().map(u64::from))),
                 ManyValues::U32(value) => Some(Box::new(value.iter().copied().map(u64::from))),
-                ManyValues::U64(value) => Some(Box::new(value.iter().copied().map(u64::from))),
+                ManyValues::U64(value) => Some(Box::new(value.iter().copied())),
+                // Only if every element fits, so element indices stay intact
+                ManyValues::U128(value) => value
+                    .iter()
+                    .map(|value| u64::try_from(*value))
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .map(|values| Box::new(values.into_iter()) as Box<dyn Iterator<Item = u64>>),
                 _ => None,
             },
             _ => None,
@@ -220,6 +359,7 @@ impl MetricValue {
                 OneValue::I16(value) => Some(i64::from(*value)),
                 OneValue::I32(value) => Some(i64::from(*value)),
                 OneValue::I64(value) => Some(*value),
+                OneValue::I128(value) => i64::try_from(*value).ok(),
                 _ => None,
             },
             _ => None,
@@ -232,7 +372,13 @@ impl MetricValue {
                 ManyValues::I8(value) => Some(Box::new(value.iter().copied().map(i64::from))),
                 ManyValues::I16(value) => Some(Box::new(value.iter().copied().map(i64::from))),
                 ManyValues::I32(value) => Some(Box::new(value.iter().copied().map(i64::from))),
-                ManyValues::I64(value) => Some(Box::new(value.iter().copied().map(i64::from))),
+                ManyValues::I64(value) => Some(Box::new(value.iter().copied())),
+                ManyValues::I128(value) => value
+                    .iter()
+                    .map(|value| i64::try_from(*value))
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .map(|values| Box::new(values.into_iter()) as Box<dyn Iterator<Item = i64>>),
                 _ => None,
             },
             _ => None,
@@ -246,6 +392,7 @@ impl MetricValue {
     pub fn as_float(&self) -> Option<f64> {
         match self {
             MetricValue::One(value) => match value {
+                OneValue::F16(value) => Some(f64::from(*value)),
                 OneValue::F32(value) => Some(f64::from(*value)),
                 OneValue::F64(value) => Some(*value),
                 _ => None,
@@ -257,6 +404,7 @@ impl MetricValue {
     pub fn as_float_iter(&self) -> Option<Box<dyn Iterator<Item = f64> + '_>> {
         match self {
             MetricValue::Many(value) => match value {
+                ManyValues::F16(value) => Some(Box::new(value.iter().copied().map(f64::from))),
                 ManyValues::F32(value) => Some(Box::new(value.iter().copied().map(f64::from))),
                 ManyValues::F64(value) => Some(Box::new(value.iter().copied())),
                 _ => None,
@@ -265,3 +413,191 @@ impl MetricValue {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Array types with the size of one element in bytes
+    const ARRAYS: [(&str, usize); 15] = [
+        ("[u8]", 1),
+        ("[u16]", 2),
+        ("[u32]", 4),
+        ("[u64]", 8),
+        ("[u128]", 16),
+        ("[i8]", 1),
+        ("[i16]", 2),
+        ("[i32]", 4),
+        ("[i64]", 8),
+        ("[i128]", 16),
+        ("[bool]", 1),
+        ("[f16]", 2),
+        ("[f32]", 4),
+        ("[f64]", 8),
+        ("[vec2]", 8),
+    ];
+
+    #[test]
+    fn whole_elements_are_accepted() {
+        for (ty, size) in ARRAYS {
+            for count in 0..3 {
+                let value = MetricValue::from_bytes(ty.to_owned(), &vec![0; size * count]);
+
+                assert!(
+                    matches!(value, Ok(MetricValue::Many(_))),
+                    "{count} elements of {ty} gave {value:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_partial_element_is_refused() {
+        for (ty, size) in ARRAYS {
+            // Single byte types can not have a partial element
+            for partial in 1..size {
+                let len = size * 2 + partial;
+                let value = MetricValue::from_bytes(ty.to_owned(), &vec![0; len]);
+
+                assert!(
+                    matches!(
+                        value,
+                        Err(MetricValueError::BadLength { expected, got })
+                            if expected == size * 3 && got == len
+                    ),
+                    "{len} bytes of {ty} gave {value:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn array_elements_are_little_endian() {
+        let value = MetricValue::from_bytes("[u16]".to_owned(), &[1, 0, 0, 1]).unwrap();
+        assert_eq!(value, MetricValue::Many(ManyValues::U16([1, 256].into())));
+
+        let value = MetricValue::from_bytes("[bool]".to_owned(), &[0, 2]).unwrap();
+        assert_eq!(
+            value,
+            MetricValue::Many(ManyValues::Bool([false, true].into()))
+        );
+    }
+
+    #[test]
+    fn strings_are_decoded_as_utf8() {
+        let value = MetricValue::from_bytes("str".to_owned(), b"SEARCHING").unwrap();
+
+        assert_eq!(value, MetricValue::One(OneValue::Str("SEARCHING".into())));
+        assert_eq!(value.as_str(), Some("SEARCHING"));
+    }
+
+    #[test]
+    fn invalid_utf8_in_strings_is_replaced() {
+        let value = MetricValue::from_bytes("str".to_owned(), b"ID\xffLE").unwrap();
+
+        assert_eq!(value.as_str(), Some("ID\u{FFFD}LE"));
+    }
+
+    #[test]
+    fn strings_are_not_plottable() {
+        let value = MetricValue::One(OneValue::Str("1.5".into()));
+
+        assert!(!value.is_float());
+        assert!(!value.is_signed_integer());
+        assert!(!value.is_unsigned_integer());
+        assert!(!value.is_bool());
+        assert!(value.as_float_iter().is_none());
+        assert!(value.as_unsigned_integer_iter().is_none());
+        assert!(value.as_signed_integer_iter().is_none());
+        assert!(value.as_bool_iter().is_none());
+    }
+
+    #[test]
+    fn wide_unsigned_integers_are_not_truncated() {
+        let fits = MetricValue::One(OneValue::U128(u128::from(u64::MAX)));
+        assert_eq!(fits.as_unsigned_integer(), Some(u64::MAX));
+
+        let too_big = MetricValue::One(OneValue::U128(u128::from(u64::MAX) + 1));
+        assert_eq!(too_big.as_unsigned_integer(), None);
+        assert!(!too_big.is_unsigned_integer());
+    }
+
+    #[test]
+    fn wide_signed_integers_are_not_truncated() {
+        for (value, expected) in [
+            (i128::from(i64::MAX), Some(i64::MAX)),
+            (i128::from(i64::MAX) + 1, None),
+            (i128::from(i64::MIN), Some(i64::MIN)),
+            (i128::from(i64::MIN) - 1, None),
+        ] {
+            let value = MetricValue::One(OneValue::I128(value));
+            assert_eq!(value.as_signed_integer(), expected, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn wide_arrays_are_refused_whole() {
+        let fits = MetricValue::Many(ManyValues::U128([0, u128::from(u64::MAX)].into()));
+        assert_eq!(
+            fits.as_unsigned_integer_iter()
+                .map(Iterator::collect::<Vec<_>>),
+            Some(vec![0, u64::MAX])
+        );
+
+        let too_big = MetricValue::Many(ManyValues::U128([0, u128::from(u64::MAX) + 1, 2].into()));
+        assert!(too_big.as_unsigned_integer_iter().is_none());
+
+        let too_small = MetricValue::Many(ManyValues::I128([0, i128::from(i64::MIN) - 1].into()));
+        assert!(too_small.as_signed_integer_iter().is_none());
+    }
+
+    #[test]
+    fn vec2_round_trips_through_little_endian_bytes() {
+        let bytes = [1.5_f32.to_le_bytes(), (-2.0_f32).to_le_bytes()].concat();
+        let value = MetricValue::from_bytes("vec2".to_owned(), &bytes).unwrap();
+
+        assert_eq!(value, MetricValue::One(OneValue::Vec2 { x: 1.5, y: -2.0 }));
+        assert_eq!(value.as_vec2(), Some((1.5, -2.0)));
+
+        let bytes = [bytes.clone(), bytes].concat();
+        let value = MetricValue::from_bytes("[vec2]".to_owned(), &bytes).unwrap();
+
+        assert_eq!(
+            value.as_vec2_iter().map(Iterator::collect::<Vec<_>>),
+            Some(vec![(1.5, -2.0), (1.5, -2.0)])
+        );
+    }
+
+    #[test]
+    fn vec2_of_wrong_length_is_refused() {
+        for len in [4, 10, 12] {
+            let value = MetricValue::from_bytes("vec2".to_owned(), &vec![0; len]);
+
+            assert!(
+                matches!(
+                    value,
+                    Err(MetricValueError::BadLength { expected: 8, got }) if got == len
+                ),
+                "{len} bytes gave {value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn f16_widens_to_f64() {
+        let value = MetricValue::from_bytes("f16".to_owned(), &0x3C00_u16.to_le_bytes()).unwrap();
+        assert_eq!(value.as_float(), Some(1.0));
+
+        // 1.0, -2.0 and 0.5 in half precision
+        let bytes = [0x3C00_u16, 0xC000, 0x3800]
+            .iter()
+            .flat_map(|bits| bits.to_le_bytes())
+            .collect::<Vec<_>>();
+        let value = MetricValue::from_bytes("[f16]".to_owned(), &bytes).unwrap();
+
+        assert_eq!(
+            value.as_float_iter().map(Iterator::collect::<Vec<_>>),
+            Some(vec![1.0, -2.0, 0.5])
+        );
+    }
+}
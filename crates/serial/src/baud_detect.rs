@@ -0,0 +1,65 @@
+use std::{
+    io::{self, Read},
+    time::{Duration, Instant},
+};
+
+use tracing::{debug, info, warn};
+
+use super::{framing_detect::count_valid_frames, SerialWorkerConfig};
+
+/// Baud rates commonly used by arduino sketches, most common first
+pub const COMMON_BAUD_RATES: &[u32] = &[
+    115_200, 57_600, 9_600, 38_400, 19_200, 230_400, 250_000, 500_000, 1_000_000,
+];
+
+/// Longest time spent reading at each candidate rate
+const BAUD_SAMPLE_DURATION: Duration = Duration::from_millis(500);
+/// Number of bytes after which a candidate rate is judged early
+const BAUD_SAMPLE_SIZE: usize = 512;
+
+/// Try each candidate rate in order, returning the first at which a COBS frame
+/// with a valid length trailer is read
+///
+/// The port is closed again afterwards, so a worker can open it at the detected rate.
+pub fn autodetect_baud(port: &str, candidates: &[u32], config: &SerialWorkerConfig) -> Option<u32> {
+    for &baud_rate in candidates {
+        let mut serial = match serialport::new(port, baud_rate)
+            .timeout(config.read_timeout)
+            .open()
+        {
+            Ok(serial) => serial,
+            Err(err) => {
+                warn!(%err, baud_rate, "failed to open serial port to detect the baud rate");
+                return None;
+            }
+        };
+
+        let mut sample = Vec::with_capacity(BAUD_SAMPLE_SIZE);
+        let deadline = Instant::now() + BAUD_SAMPLE_DURATION;
+        let mut chunk = [0; 64];
+
+        while sample.len() < BAUD_SAMPLE_SIZE && Instant::now() < deadline {
+            match serial.read(&mut chunk) {
+                Ok(len) => sample.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => {
+                    warn!(%err, baud_rate, "failed to sample serial port to detect the baud rate");
+                    break;
+                }
+            }
+        }
+
+        let (valid, total) =
+            count_valid_frames(&sample, config.framing.frame_delimiter, config.crc16);
+
+        debug!(baud_rate, valid, total, "sampled candidate baud rate");
+
+        if valid > 0 {
+            info!(baud_rate, "detected baud rate");
+
+            return Some(baud_rate);
+        }
+    }
+
+    None
+}
@@ -0,0 +1,57 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+use kestrel_metric::timestamp::TimestampUnit;
+
+/// Settings for a serial worker connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialWorkerConfig {
+    pub baud_rate: u32,
+    pub timestamp_unit: TimestampUnit,
+    pub framing: Framing,
+    /// Lowercase metric names as they are read, so names that only differ in case share a series
+    pub lowercase_names: bool,
+    /// Packets start with a protocol version byte, selecting how the rest is decoded
+    pub versioned_packets: bool,
+    /// How long a read waits for data, which also bounds how quickly commands are handled
+    pub read_timeout: Duration,
+    /// Sample the stream on connect to find the frame delimiter, instead of trusting `framing`
+    pub auto_detect_framing: bool,
+    /// Packets end with a CRC16 (CCITT) over the rest of the decoded frame, which is verified
+    pub crc16: bool,
+    /// Address of the line-based TCP control protocol, for attaching and detaching from scripts
+    pub control_addr: SocketAddr,
+    /// Longest COBS frame read before it is discarded, so a device that never sends
+    /// a delimiter can not grow the read buffer without bound
+    pub max_frame_length: usize,
+}
+
+impl Default for SerialWorkerConfig {
+    fn default() -> Self {
+        Self {
+            baud_rate: 115200,
+            timestamp_unit: TimestampUnit::default(),
+            framing: Framing::default(),
+            lowercase_names: false,
+            versioned_packets: false,
+            read_timeout: Duration::from_millis(100),
+            auto_detect_framing: false,
+            crc16: false,
+            control_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 6969)),
+            max_frame_length: 64 * 1024,
+        }
+    }
+}
+
+/// Bytes used to split the stream into packets and packets into fields
+///
+/// Both default to `0x00`, the standard COBS sentinel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Framing {
+    /// Sentinel ending each COBS frame
+    pub frame_delimiter: u8,
+    /// Separator between the name, type and value sections of a packet
+    pub field_separator: u8,
+}
@@ -1,51 +1,99 @@
 use std::{
+    path::PathBuf,
     sync::{
-        mpsc::{channel, Receiver, Sender},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{channel, Receiver, SendError, Sender},
+        Arc, Mutex, PoisonError, RwLock,
     },
-    thread::{self},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use kestrel_metric::{Metric, RobotCommand};
+use kestrel_metric::RobotCommand;
+use tracing::warn;
 
-use super::{detacher, SerialWorker, SerialWorkerCommand, SerialWorkerState};
+use super::{
+    baud_detect, detacher, DetectedFraming, LengthDeltas, MetricSource, Packet, PacketCounts,
+    SendCommandError, SerialWorker, SerialWorkerCommand, SerialWorkerConfig, SerialWorkerState,
+};
 
 pub struct SerialWorkerController {
     port_name: Arc<str>,
 
     state: Arc<RwLock<SerialWorkerState>>,
+    retain_raw: Arc<AtomicBool>,
+    dropped_frames: Arc<AtomicU64>,
+    length_deltas: Arc<Mutex<LengthDeltas>>,
+    packet_counts: Arc<Mutex<PacketCounts>>,
+    latency: Arc<Mutex<Option<Duration>>>,
+    reconnect_at: Arc<Mutex<Option<Instant>>>,
+    detected_framing: Arc<Mutex<Option<DetectedFraming>>>,
+    recording: Arc<AtomicBool>,
+    detacher_shutdown: Arc<AtomicBool>,
     command_tx: Sender<SerialWorkerCommand>,
-    metric_rx: Receiver<Metric>,
+    packet_rx: Receiver<Packet>,
+    /// Taken once the worker has been joined
+    worker: Option<JoinHandle<()>>,
 }
 
 impl SerialWorkerController {
     pub fn spawn(
         port_name: String,
-        baud_rate: u32,
+        config: SerialWorkerConfig,
         repaint: Box<impl Fn() + Send + 'static>,
     ) -> SerialWorkerController {
-        let (metric_tx, metric_rx) = channel();
+        let (packet_tx, packet_rx) = channel();
         let (command_tx, command_rx) = channel();
 
         let state = Arc::new(RwLock::new(SerialWorkerState::Disconnected));
+        let retain_raw = Arc::new(AtomicBool::new(false));
+        let dropped_frames = Arc::new(AtomicU64::new(0));
+        let length_deltas = Arc::new(Mutex::new(LengthDeltas::default()));
+        let packet_counts = Arc::new(Mutex::new(PacketCounts::default()));
+        let latency = Arc::new(Mutex::new(None));
+        let reconnect_at = Arc::new(Mutex::new(None));
+        let detected_framing = Arc::new(Mutex::new(None));
+        let recording = Arc::new(AtomicBool::new(false));
+        let detacher_shutdown = Arc::new(AtomicBool::new(false));
 
         let port_name = Arc::from(port_name.into_boxed_str());
 
-        thread::Builder::new()
+        let worker = thread::Builder::new()
             .name("serial_worker".into())
             .spawn({
                 let state = Arc::clone(&state);
+                let retain_raw = Arc::clone(&retain_raw);
+                let dropped_frames = Arc::clone(&dropped_frames);
+                let length_deltas = Arc::clone(&length_deltas);
+                let packet_counts = Arc::clone(&packet_counts);
+                let latency = Arc::clone(&latency);
+                let reconnect_at = Arc::clone(&reconnect_at);
+                let detected_framing = Arc::clone(&detected_framing);
+                let recording = Arc::clone(&recording);
                 let port_name = Arc::clone(&port_name);
 
                 move || {
                     SerialWorker {
                         port_name,
-                        baud_rate,
+                        config,
 
-                        metric_tx,
+                        packet_tx,
                         command_rx,
 
                         state,
+                        retain_raw,
+                        dropped_frames,
+                        length_deltas,
+                        packet_counts,
+                        latency,
+                        detected_framing,
+                        recording,
+                        recorder: None,
+                        csv_log: None,
+                        reconnect_at,
+                        deferred_command: None,
+                        consecutive_timeouts: 0,
+                        warned_unknown_version: false,
 
                         repaint,
                     }
@@ -58,47 +106,428 @@ impl SerialWorkerController {
             .name("serial_detacher".into())
             .spawn({
                 let command_tx = command_tx.clone();
+                let state = Arc::clone(&state);
+                let detacher_shutdown = Arc::clone(&detacher_shutdown);
 
-                move || detacher::main(command_tx)
+                move || detacher::main(config.control_addr, command_tx, state, detacher_shutdown)
             })
             .expect("failed to spawn serial detacher thread");
 
         Self {
-            metric_rx,
+            packet_rx,
             command_tx,
 
             port_name,
             state,
+            retain_raw,
+            dropped_frames,
+            length_deltas,
+            packet_counts,
+            latency,
+            reconnect_at,
+            detected_framing,
+            recording,
+            detacher_shutdown,
+            worker: Some(worker),
         }
     }
 
+    /// Shut the worker down and wait for it to release the serial port
+    ///
+    /// Returns the panic payload if the worker thread panicked.
+    pub fn join(mut self) -> thread::Result<()> {
+        self.command(SerialWorkerCommand::Shutdown);
+        self.detacher_shutdown.store(true, Ordering::Relaxed);
+
+        match self.worker.take() {
+            Some(worker) => worker.join(),
+            None => Ok(()),
+        }
+    }
+
+    /// Find the baud rate the robot sends at, by trying each candidate in turn
+    /// until one yields a valid frame
+    ///
+    /// This blocks for up to half a second per candidate, and must be done before
+    /// a worker opens the port.
+    pub fn autodetect_baud(
+        port: &str,
+        candidates: &[u32],
+        config: &SerialWorkerConfig,
+    ) -> Option<u32> {
+        baud_detect::autodetect_baud(port, candidates, config)
+    }
+
     pub fn state(&self) -> SerialWorkerState {
-        *self.state.read().unwrap()
+        *self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Queue a command for the worker
+    ///
+    /// All commands, from both the UI and the detacher, go through the same
+    /// channel so the worker handles them strictly in the order they were sent.
+    fn command(&self, command: SerialWorkerCommand) {
+        if let Err(SendError(command)) = self.command_tx.send(command) {
+            warn!(?command, "serial worker has exited, dropping command");
+        }
+    }
+
+    /// Send a worker command only if the worker is currently connected to the robot
+    fn try_command(&self, command: SerialWorkerCommand) -> Result<(), SendCommandError> {
+        match self.state() {
+            state if state.is_connected() => self
+                .command_tx
+                .send(command)
+                .map_err(|_| SendCommandError::WorkerExited),
+            state => Err(SendCommandError::NotConnected(state)),
+        }
     }
 
     pub fn detach(&self) {
-        self.command_tx.send(SerialWorkerCommand::Detach).unwrap();
+        self.command(SerialWorkerCommand::Detach);
     }
 
     pub fn attach(&self) {
-        self.command_tx.send(SerialWorkerCommand::Attach).unwrap();
+        self.command(SerialWorkerCommand::Attach);
     }
 
     pub fn reset(&self) {
-        self.command_tx.send(SerialWorkerCommand::Reset).unwrap();
+        self.command(SerialWorkerCommand::Reset);
     }
 
+    /// Fire-and-forget a command, the worker drops it if it is not connected
     pub fn send_command(&self, command: RobotCommand) {
-        self.command_tx
-            .send(SerialWorkerCommand::SendCommand(command))
-            .unwrap();
+        self.command(SerialWorkerCommand::SendCommand(command));
+    }
+
+    /// Fire-and-forget a single command byte, for opcodes without a [`RobotCommand`] yet
+    pub fn send_raw_command(&self, command: u8) {
+        self.command(SerialWorkerCommand::SendRawCommand(command));
+    }
+
+    /// Send a command only if the worker is currently connected to the robot
+    ///
+    /// The connection can still drop before the worker handles the command,
+    /// but this catches the common case of sending while detached or waiting.
+    pub fn try_send_command(&self, command: RobotCommand) -> Result<(), SendCommandError> {
+        self.try_command(SerialWorkerCommand::SendCommand(command))
+    }
+
+    /// Send a single command byte, only if the worker is currently connected
+    pub fn try_send_raw_command(&self, command: u8) -> Result<(), SendCommandError> {
+        self.try_command(SerialWorkerCommand::SendRawCommand(command))
+    }
+
+    /// Send raw bytes to the robot, only if the worker is currently connected
+    pub fn try_send_payload(&self, payload: Box<[u8]>) -> Result<(), SendCommandError> {
+        self.try_command(SerialWorkerCommand::SendPayload(payload))
+    }
+
+    /// Number of frames skipped while resynchronizing after malformed COBS data
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Keep the raw value bytes of every metric, for debugging the firmware encoding
+    pub fn set_retain_raw_bytes(&self, retain: bool) {
+        self.retain_raw.store(retain, Ordering::Relaxed);
+    }
+
+    /// Reopen the port with a new read timeout, which also applies to every later connection
+    pub fn set_read_timeout(&self, timeout: Duration) {
+        self.command(SerialWorkerCommand::SetReadTimeout(timeout));
+    }
+
+    /// Start measuring how long commands wait in the queue before the worker handles them
+    ///
+    /// This is not a round trip to the robot, which does not answer the measurement.
+    pub fn measure_queue_latency(&self) {
+        self.command(SerialWorkerCommand::Ping(Instant::now()));
+    }
+
+    /// Time left until the worker tries to open the port again, while it is not available
+    ///
+    /// The wait starts short and doubles after every failed attempt, up to 5 seconds.
+    pub fn reconnect_in(&self) -> Option<Duration> {
+        self.reconnect_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map(|reconnect_at| reconnect_at.saturating_duration_since(Instant::now()))
+    }
+
+    /// The most recently measured time a command waited in the queue of the worker
+    pub fn queue_latency(&self) -> Option<Duration> {
+        *self.latency.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The framing found by auto-detection, if enabled and a port has been connected
+    pub fn detected_framing(&self) -> Option<DetectedFraming> {
+        *self
+            .detected_framing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Write every packet received from now on to `path`, for replaying the session later
+    pub fn start_recording(&self, path: PathBuf) {
+        self.command(SerialWorkerCommand::StartRecording(path));
+    }
+
+    pub fn stop_recording(&self) {
+        self.command(SerialWorkerCommand::StopRecording);
+    }
+
+    /// Append every metric read from now on to a CSV file, see [`MetricSource::start_csv_log`]
+    pub fn start_csv_log(&self, path: PathBuf) {
+        self.command(SerialWorkerCommand::StartCsvLog(path));
+    }
+
+    pub fn stop_csv_log(&self) {
+        self.command(SerialWorkerCommand::StopCsvLog);
+    }
+
+    /// Whether the worker is currently writing packets to a recording
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the length mismatches seen so far
+    pub fn length_deltas(&self) -> LengthDeltas {
+        self.length_deltas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Packets read since the worker started, by outcome
+    pub fn packet_counts(&self) -> PacketCounts {
+        *self
+            .packet_counts
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn clear_length_deltas(&self) {
+        self.length_deltas
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
     }
 
     pub fn port_name(&self) -> &str {
         self.port_name.as_ref()
     }
 
-    pub fn new_metrics(&self) -> impl Iterator<Item = Metric> + '_ {
-        self.metric_rx.try_iter()
+    /// Metrics and system packets received since the last call, in the order they were read
+    pub fn new_packets(&self) -> impl Iterator<Item = Packet> + '_ {
+        self.packet_rx.try_iter()
+    }
+}
+
+impl MetricSource for SerialWorkerController {
+    fn port_name(&self) -> &str {
+        SerialWorkerController::port_name(self)
+    }
+
+    fn state(&self) -> SerialWorkerState {
+        SerialWorkerController::state(self)
+    }
+
+    fn new_packets(&self) -> Box<dyn Iterator<Item = Packet> + '_> {
+        Box::new(SerialWorkerController::new_packets(self))
+    }
+
+    fn detach(&self) {
+        SerialWorkerController::detach(self);
+    }
+
+    fn attach(&self) {
+        SerialWorkerController::attach(self);
+    }
+
+    fn reset(&self) {
+        SerialWorkerController::reset(self);
+    }
+
+    fn send_command(&self, command: RobotCommand) {
+        SerialWorkerController::send_command(self, command);
+    }
+
+    fn send_raw_command(&self, command: u8) {
+        SerialWorkerController::send_raw_command(self, command);
+    }
+
+    fn try_send_command(&self, command: RobotCommand) -> Result<(), SendCommandError> {
+        SerialWorkerController::try_send_command(self, command)
+    }
+
+    fn try_send_raw_command(&self, command: u8) -> Result<(), SendCommandError> {
+        SerialWorkerController::try_send_raw_command(self, command)
+    }
+
+    fn try_send_payload(&self, payload: Box<[u8]>) -> Result<(), SendCommandError> {
+        SerialWorkerController::try_send_payload(self, payload)
+    }
+
+    fn set_retain_raw_bytes(&self, retain: bool) {
+        SerialWorkerController::set_retain_raw_bytes(self, retain);
+    }
+
+    fn set_read_timeout(&self, timeout: Duration) {
+        SerialWorkerController::set_read_timeout(self, timeout);
+    }
+
+    fn measure_queue_latency(&self) {
+        SerialWorkerController::measure_queue_latency(self);
+    }
+
+    fn queue_latency(&self) -> Option<Duration> {
+        SerialWorkerController::queue_latency(self)
+    }
+
+    fn reconnect_in(&self) -> Option<Duration> {
+        SerialWorkerController::reconnect_in(self)
+    }
+
+    fn detected_framing(&self) -> Option<DetectedFraming> {
+        SerialWorkerController::detected_framing(self)
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        SerialWorkerController::dropped_frames(self)
+    }
+
+    fn length_deltas(&self) -> LengthDeltas {
+        SerialWorkerController::length_deltas(self)
+    }
+
+    fn packet_counts(&self) -> PacketCounts {
+        SerialWorkerController::packet_counts(self)
+    }
+
+    fn clear_length_deltas(&self) {
+        SerialWorkerController::clear_length_deltas(self);
+    }
+
+    fn start_recording(&self, path: PathBuf) {
+        SerialWorkerController::start_recording(self, path);
+    }
+
+    fn stop_recording(&self) {
+        SerialWorkerController::stop_recording(self);
+    }
+
+    fn is_recording(&self) -> bool {
+        SerialWorkerController::is_recording(self)
+    }
+
+    fn start_csv_log(&self, path: PathBuf) {
+        SerialWorkerController::start_csv_log(self, path);
+    }
+
+    fn stop_csv_log(&self) {
+        SerialWorkerController::stop_csv_log(self);
+    }
+}
+
+impl Drop for SerialWorkerController {
+    fn drop(&mut self) {
+        // Already shut down by `join`
+        if self.worker.is_none() {
+            return;
+        }
+
+        // Release the serial port, even if the detacher still holds a command sender
+        self.command(SerialWorkerCommand::Shutdown);
+        self.detacher_shutdown.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    /// A worker waiting for a port that never appears, with the detacher on a free address
+    fn without_port() -> SerialWorkerController {
+        SerialWorkerController::spawn(
+            "/dev/kestrel-test-missing-port".to_owned(),
+            SerialWorkerConfig {
+                control_addr: (Ipv4Addr::LOCALHOST, 0).into(),
+                ..SerialWorkerConfig::default()
+            },
+            Box::new(|| {}),
+        )
+    }
+
+    #[test]
+    fn measures_queue_latency_while_waiting_for_the_port() {
+        let controller = without_port();
+        assert_eq!(controller.queue_latency(), None);
+
+        let started = Instant::now();
+        controller.measure_queue_latency();
+
+        // The worker wakes up from waiting to reconnect for the command
+        while controller.queue_latency().is_none() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "latency never measured"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(controller.queue_latency() <= Some(started.elapsed()));
+
+        controller.join().unwrap();
+    }
+
+    /// Wait until the worker handled every command queued so far, which it
+    /// does in order, by queueing a latency measurement behind them
+    fn settle(controller: &SerialWorkerController) {
+        *controller.latency.lock().unwrap() = None;
+        controller.measure_queue_latency();
+
+        let started = Instant::now();
+        while controller.queue_latency().is_none() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "worker stopped handling commands in {:?}",
+                controller.state()
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn rapid_attach_and_detach_end_in_the_last_state() {
+        let controller = without_port();
+
+        // Toggle from several threads at once, like the UI and the detacher can
+        let togglers = (0..4)
+            .map(|_| {
+                let command_tx = controller.command_tx.clone();
+
+                thread::spawn(move || {
+                    for _ in 0..250 {
+                        command_tx.send(SerialWorkerCommand::Detach).unwrap();
+                        command_tx.send(SerialWorkerCommand::Attach).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for toggler in togglers {
+            toggler.join().unwrap();
+        }
+
+        controller.detach();
+        settle(&controller);
+        assert_eq!(controller.state(), SerialWorkerState::Detached);
+
+        controller.attach();
+        settle(&controller);
+        assert_eq!(controller.state(), SerialWorkerState::Disconnected);
+
+        controller.join().unwrap();
     }
 }
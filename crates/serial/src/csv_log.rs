@@ -0,0 +1,75 @@
+use std::{
+    borrow::Cow,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::Metric;
+
+/// How often buffered rows are written out to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends every metric read by the worker to a CSV file of `timestamp,name,type,value` rows
+///
+/// Rows are written from the worker thread, so pausing the UI does not hold them back.
+/// The file is appended to and never rotated or truncated, an existing file keeps its
+/// rows and only a new or empty file gets the header.
+pub struct CsvLog {
+    file: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl CsvLog {
+    pub fn open(path: &Path, now: Instant) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let is_empty = file.metadata()?.len() == 0;
+
+        let mut file = BufWriter::new(file);
+        if is_empty {
+            writeln!(file, "timestamp,name,type,value")?;
+        }
+
+        Ok(Self {
+            file,
+            last_flush: now,
+        })
+    }
+
+    pub fn append(&mut self, metric: &Metric) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{},{},{},{}",
+            metric.timestamp.timestamp(),
+            escape_csv(&metric.name.to_string()),
+            escape_csv(metric.value.ty()),
+            escape_csv(&metric.value.value()),
+        )
+    }
+
+    /// Flush buffered rows to disk, at most once per [`FLUSH_INTERVAL`]
+    pub fn poll_flush(&mut self, now: Instant) -> io::Result<()> {
+        if now.saturating_duration_since(self.last_flush) < FLUSH_INTERVAL {
+            return Ok(());
+        }
+
+        self.last_flush = now;
+
+        self.file.flush()
+    }
+
+    /// Write out everything still buffered, the file is closed once the log is dropped
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Quote a field if it contains a comma, quote or newline
+fn escape_csv(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
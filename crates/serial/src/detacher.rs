@@ -1,32 +1,463 @@
-use std::{io::Read, net::TcpListener, sync::mpsc::Sender};
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    mem,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, PoisonError, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use tracing::{error, warn};
+use tracing::{debug, error, info};
 
-use super::SerialWorkerCommand;
+use super::{parse_payload, SerialWorkerCommand, SerialWorkerState};
 
+/// How often the accept loop checks whether it should shut down
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How many times to retry binding while a previous detacher is still releasing the port
+const BIND_ATTEMPTS: usize = 10;
+/// How long a connected client may stay silent before it is disconnected
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Serve the line-based control protocol on `addr`
+///
+/// Each line is one command: `attach`, `detach`, `reset`, `send <bytes>` with the bytes
+/// written as for [`parse_payload`], or `timeout <milliseconds>`, which reopens the port
+/// with the new read timeout. Every command is answered with `ok <command>` once
+/// it has been queued, or with `error: <reason>` if it was rejected. Every client is served
+/// on its own thread.
 // TODO: move this into the app
-pub(super) fn main(command_tx: Sender<SerialWorkerCommand>) {
-    let listener = TcpListener::bind("127.0.0.1:6969").expect("failed to bind tcp listener");
-
-    let buf = &mut [0u8; 6];
-
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => match stream.read_exact(buf) {
-                Ok(()) => match &buf[..] {
-                    b"attach" => {
-                        command_tx.send(SerialWorkerCommand::Attach).unwrap();
-                    }
-                    b"detach" => {
-                        command_tx.send(SerialWorkerCommand::Detach).unwrap();
-                    }
-                    _ => {
-                        warn!("received non-recognized data over tcp connection");
-                    }
-                },
-                Err(err) => error!(?err, "encountered an error reading from tcp connection"),
-            },
+pub(super) fn main(
+    addr: SocketAddr,
+    command_tx: Sender<SerialWorkerCommand>,
+    state: Arc<RwLock<SerialWorkerState>>,
+    shutdown: Arc<AtomicBool>,
+) {
+    let Some(listener) = bind(addr, &shutdown) else {
+        return;
+    };
+
+    // Accept without blocking so the shutdown flag is noticed promptly
+    if let Err(err) = listener.set_nonblocking(true) {
+        error!(
+            ?err,
+            "failed to make tcp listener non-blocking, detaching over tcp is unavailable"
+        );
+        return;
+    }
+
+    while !shutdown.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, peer)) => {
+                debug!(%peer, "control client connected");
+
+                // Each client gets its own thread, so an idle one can not hold up the others
+                let spawned = thread::Builder::new()
+                    .name("serial_detacher_client".into())
+                    .spawn({
+                        let command_tx = command_tx.clone();
+                        let state = Arc::clone(&state);
+                        let shutdown = Arc::clone(&shutdown);
+
+                        move || {
+                            if let Err(err) = serve_client(stream, &command_tx, &state, &shutdown) {
+                                debug!(?err, %peer, "control client disconnected");
+                            }
+                        }
+                    });
+
+                if let Err(err) = spawned {
+                    error!(?err, %peer, "failed to spawn a thread for the control client");
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
             Err(err) => error!(?err, "failed to accept incoming tcp connection"),
         }
     }
+
+    debug!("serial detacher shut down");
+}
+
+fn bind(addr: SocketAddr, shutdown: &AtomicBool) -> Option<TcpListener> {
+    let mut attempts = 0;
+
+    loop {
+        match TcpListener::bind(addr) {
+            Ok(listener) => return Some(listener),
+            Err(err)
+                if err.kind() == io::ErrorKind::AddrInUse
+                    && attempts < BIND_ATTEMPTS
+                    && !shutdown.load(Ordering::Relaxed) =>
+            {
+                attempts += 1;
+                thread::sleep(POLL_INTERVAL);
+            }
+            Err(err) => {
+                error!(
+                    ?err,
+                    %addr,
+                    "failed to bind tcp listener, detaching over tcp is unavailable"
+                );
+                return None;
+            }
+        }
+    }
+}
+
+/// Answer commands from a client until it disconnects, goes quiet or the detacher shuts down
+fn serve_client(
+    stream: TcpStream,
+    command_tx: &Sender<SerialWorkerCommand>,
+    state: &RwLock<SerialWorkerState>,
+    shutdown: &AtomicBool,
+) -> io::Result<()> {
+    // Accepted streams may inherit non-blocking mode, and a silent client must not stall shutdown
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = Vec::new();
+    let mut last_activity = Instant::now();
+
+    while !shutdown.load(Ordering::Relaxed) {
+        // A timed out read keeps the partial line in `line`, the rest follows on the next read
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) if line.ends_with(b"\n") => {}
+            Ok(_) => continue,
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                if last_activity.elapsed() >= IDLE_TIMEOUT {
+                    debug!("control client went quiet");
+                    return Ok(());
+                }
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+        last_activity = Instant::now();
+
+        let Ok(text) = String::from_utf8(mem::take(&mut line)) else {
+            writeln!(writer, "error: command is not valid UTF-8")?;
+            continue;
+        };
+        let text = text.trim();
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let state = *state.read().unwrap_or_else(PoisonError::into_inner);
+
+        match parse_command(text, state) {
+            Ok(command) => {
+                info!(?command, "received command over tcp");
+
+                if command_tx.send(command).is_err() {
+                    writeln!(writer, "error: serial worker has exited")?;
+                    return Ok(());
+                }
+
+                writeln!(writer, "ok {text}")?;
+            }
+            Err(reason) => writeln!(writer, "error: {reason}")?,
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_command(line: &str, state: SerialWorkerState) -> Result<SerialWorkerCommand, String> {
+    let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match (command, argument) {
+        ("attach", "") => Ok(SerialWorkerCommand::Attach),
+        ("detach", "") => Ok(SerialWorkerCommand::Detach),
+        ("reset", "") => Ok(SerialWorkerCommand::Reset),
+        ("send", "") => Err("send needs hex bytes, such as `send 01 ff`".to_owned()),
+        ("send", bytes) => {
+            let payload = parse_payload(bytes).map_err(|err| format!("invalid bytes: {err}"))?;
+
+            if !state.is_connected() {
+                return Err(format!("serial worker is not connected ({state:?})"));
+            }
+
+            Ok(SerialWorkerCommand::SendPayload(payload.into_boxed_slice()))
+        }
+        ("timeout", "") => Err("timeout needs milliseconds, such as `timeout 100`".to_owned()),
+        ("timeout", millis) => match millis.parse::<u64>() {
+            Ok(0) => Err("timeout must be at least 1 ms".to_owned()),
+            Ok(millis) => Ok(SerialWorkerCommand::SetReadTimeout(Duration::from_millis(
+                millis,
+            ))),
+            Err(_) => Err(format!("invalid milliseconds `{millis}`")),
+        },
+        ("attach" | "detach" | "reset", _) => Err(format!("{command} takes no arguments")),
+        _ => Err(format!("unknown command `{command}`")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, sync::mpsc};
+
+    use super::*;
+
+    /// Connected pair of a client stream and the accepted server stream
+    fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+
+        (client, server)
+    }
+
+    #[test]
+    fn chatty_client_does_not_block_shutdown() {
+        let (mut client, server) = connection();
+        let (command_tx, command_rx) = mpsc::channel();
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let serving = thread::spawn({
+            let shutdown = shutdown.clone();
+            move || {
+                let state = RwLock::new(SerialWorkerState::Connected);
+                serve_client(server, &command_tx, &state, &shutdown)
+            }
+        });
+
+        writeln!(client, "attach").unwrap();
+        assert!(matches!(
+            command_rx.recv_timeout(Duration::from_secs(5)),
+            Ok(SerialWorkerCommand::Attach)
+        ));
+
+        // Keep talking while asking for shutdown, the client must not keep the loop alive
+        let started = Instant::now();
+        shutdown.store(true, Ordering::Relaxed);
+        while !serving.is_finished() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "client kept the detacher busy"
+            );
+            let _ = writeln!(client, "detach");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        serving.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn command_split_across_reads_is_answered() {
+        let (mut client, server) = connection();
+        let (command_tx, command_rx) = mpsc::channel();
+
+        let serving = thread::spawn(move || {
+            let state = RwLock::new(SerialWorkerState::Connected);
+            serve_client(server, &command_tx, &state, &AtomicBool::new(false))
+        });
+
+        // Pause longer than a read timeout halfway through the line
+        client.write_all(b"sen").unwrap();
+        thread::sleep(POLL_INTERVAL * 2);
+        client.write_all(b"d 01 ff\n").unwrap();
+
+        let mut answer = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut answer)
+            .unwrap();
+        assert_eq!(answer, "ok send 01 ff\n");
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(SerialWorkerCommand::SendPayload(payload)) if *payload == [0x01, 0xff]
+        ));
+
+        drop(client);
+        serving.join().unwrap().unwrap();
+    }
+
+    /// Send `line` and read the answer to it
+    fn ask(client: &mut TcpStream, line: &[u8]) -> String {
+        client.write_all(line).unwrap();
+
+        let mut answer = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut answer)
+            .unwrap();
+        answer
+    }
+
+    #[test]
+    fn unknown_command_is_rejected() {
+        let (mut client, server) = connection();
+        let (command_tx, command_rx) = mpsc::channel();
+
+        let serving = thread::spawn(move || {
+            let state = RwLock::new(SerialWorkerState::Connected);
+            serve_client(server, &command_tx, &state, &AtomicBool::new(false))
+        });
+
+        assert_eq!(
+            ask(&mut client, b"launch\n"),
+            "error: unknown command `launch`\n"
+        );
+        assert!(command_rx.try_recv().is_err());
+
+        drop(client);
+        serving.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn invalid_utf8_is_answered_and_keeps_the_connection() {
+        let (mut client, server) = connection();
+        let (command_tx, command_rx) = mpsc::channel();
+
+        let serving = thread::spawn(move || {
+            let state = RwLock::new(SerialWorkerState::Connected);
+            serve_client(server, &command_tx, &state, &AtomicBool::new(false))
+        });
+
+        assert_eq!(
+            ask(&mut client, b"det\xffach\n"),
+            "error: command is not valid UTF-8\n"
+        );
+        assert_eq!(ask(&mut client, b"detach\n"), "ok detach\n");
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(SerialWorkerCommand::Detach)
+        ));
+
+        drop(client);
+        serving.join().unwrap().unwrap();
+    }
+
+    /// Wait up to five seconds for a detacher thread to exit
+    fn assert_exits(detacher: thread::JoinHandle<()>) {
+        let started = Instant::now();
+
+        while !detacher.is_finished() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "detacher kept running after shutdown"
+            );
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        detacher.join().unwrap();
+    }
+
+    fn spawn_main(
+        addr: SocketAddr,
+        shutdown: &Arc<AtomicBool>,
+    ) -> (thread::JoinHandle<()>, mpsc::Receiver<SerialWorkerCommand>) {
+        let (command_tx, command_rx) = mpsc::channel();
+        let state = Arc::new(RwLock::new(SerialWorkerState::Connected));
+        let shutdown = Arc::clone(shutdown);
+
+        (
+            thread::spawn(move || main(addr, command_tx, state, shutdown)),
+            command_rx,
+        )
+    }
+
+    #[test]
+    fn idle_detacher_exits_on_shutdown() {
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (detacher, _command_rx) = spawn_main((Ipv4Addr::LOCALHOST, 0).into(), &shutdown);
+
+        thread::sleep(POLL_INTERVAL * 2);
+        assert!(!detacher.is_finished());
+
+        shutdown.store(true, Ordering::Relaxed);
+        assert_exits(detacher);
+    }
+
+    #[test]
+    fn connected_detacher_exits_on_shutdown() {
+        // Find a free port for the detacher to bind
+        let addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (detacher, command_rx) = spawn_main(addr, &shutdown);
+
+        let started = Instant::now();
+        let mut client = loop {
+            match TcpStream::connect(addr) {
+                Ok(client) => break client,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => panic!("detacher never listened: {err}"),
+            }
+        };
+
+        writeln!(client, "detach").unwrap();
+        let mut answer = String::new();
+        BufReader::new(client.try_clone().unwrap())
+            .read_line(&mut answer)
+            .unwrap();
+        assert_eq!(answer, "ok detach\n");
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(SerialWorkerCommand::Detach)
+        ));
+
+        // The client stays connected, which must not hold up the shutdown
+        shutdown.store(true, Ordering::Relaxed);
+        assert_exits(detacher);
+    }
+
+    #[test]
+    fn idle_client_does_not_block_another() {
+        let addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (detacher, command_rx) = spawn_main(addr, &shutdown);
+
+        let started = Instant::now();
+        let _idle = loop {
+            match TcpStream::connect(addr) {
+                Ok(client) => break client,
+                Err(_) if started.elapsed() < Duration::from_secs(5) => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => panic!("detacher never listened: {err}"),
+            }
+        };
+
+        // Answered long before the idle client would time out
+        let mut client = TcpStream::connect(addr).unwrap();
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(ask(&mut client, b"detach\n"), "ok detach\n");
+        assert!(matches!(
+            command_rx.try_recv(),
+            Ok(SerialWorkerCommand::Detach)
+        ));
+
+        shutdown.store(true, Ordering::Relaxed);
+        assert_exits(detacher);
+    }
+
+    #[test]
+    fn shutdown_before_binding_exits_right_away() {
+        let shutdown = Arc::new(AtomicBool::new(true));
+        let (detacher, _command_rx) = spawn_main((Ipv4Addr::LOCALHOST, 0).into(), &shutdown);
+
+        assert_exits(detacher);
+    }
 }
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+/// Histogram of `expected - got` for packets whose declared length did not match
+///
+/// A firmware bug that is consistently off by N shows up as a single tall bar,
+/// while random corruption spreads out over many deltas.
+#[derive(Debug, Clone, Default)]
+pub struct LengthDeltas {
+    deltas: BTreeMap<i64, u64>,
+}
+
+impl LengthDeltas {
+    pub fn record(&mut self, expected: usize, got: usize) {
+        // Frame lengths are only bounded by the maximum frame length, so clamp instead of wrapping
+        let expected = i64::try_from(expected).unwrap_or(i64::MAX);
+        let got = i64::try_from(got).unwrap_or(i64::MAX);
+        // Both are non-negative, so the difference always fits
+        let delta = expected - got;
+
+        *self.deltas.entry(delta).or_default() += 1;
+    }
+
+    /// Every observed delta with the number of packets it occurred in, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (i64, u64)> + '_ {
+        self.deltas.iter().map(|(&delta, &count)| (delta, count))
+    }
+
+    /// Total number of packets with a mismatched length
+    pub fn total(&self) -> u64 {
+        self.deltas.values().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+    }
+}
+
+/// Running totals of the packets read by the worker, by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketCounts {
+    pub good: u64,
+    pub malformed_cobs: u64,
+    /// Packets whose length did not match the declared length
+    pub bad_length: u64,
+    /// Values whose length did not fit their type
+    pub bad_value_length: u64,
+    pub poor_layout: u64,
+    /// Packets whose CRC16 did not match their contents
+    pub bad_checksum: u64,
+    /// Packets with a non-printable type or an unknown system packet
+    pub other: u64,
+}
+
+impl PacketCounts {
+    pub fn errors(&self) -> u64 {
+        self.malformed_cobs
+            + self.bad_length
+            + self.bad_value_length
+            + self.poor_layout
+            + self.bad_checksum
+            + self.other
+    }
+
+    pub fn total(&self) -> u64 {
+        self.good + self.errors()
+    }
+
+    /// Packets counted since `earlier`, `None` if the counters were reset in between
+    pub fn since(&self, earlier: &Self) -> Option<Self> {
+        Some(Self {
+            good: self.good.checked_sub(earlier.good)?,
+            malformed_cobs: self.malformed_cobs.checked_sub(earlier.malformed_cobs)?,
+            bad_length: self.bad_length.checked_sub(earlier.bad_length)?,
+            bad_value_length: self
+                .bad_value_length
+                .checked_sub(earlier.bad_value_length)?,
+            poor_layout: self.poor_layout.checked_sub(earlier.poor_layout)?,
+            bad_checksum: self.bad_checksum.checked_sub(earlier.bad_checksum)?,
+            other: self.other.checked_sub(earlier.other)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_deltas() {
+        let mut deltas = LengthDeltas::default();
+
+        deltas.record(10, 8);
+        deltas.record(10, 12);
+        deltas.record(20, 18);
+
+        assert_eq!(deltas.iter().collect::<Vec<_>>(), [(-2, 1), (2, 2)]);
+        assert_eq!(deltas.total(), 3);
+
+        deltas.clear();
+        assert!(deltas.is_empty());
+    }
+
+    #[test]
+    fn huge_lengths_do_not_wrap() {
+        let mut deltas = LengthDeltas::default();
+
+        deltas.record(0, usize::MAX);
+        deltas.record(usize::MAX, 0);
+
+        assert_eq!(
+            deltas.iter().collect::<Vec<_>>(),
+            [(-i64::MAX, 1), (i64::MAX, 1)]
+        );
+    }
+}
@@ -1,14 +1,25 @@
-use std::io;
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    io,
+};
 
 use tracing::error;
 
 use kestrel_metric::value::MetricValueError;
 
+use crate::SerialWorkerState;
+
 #[derive(Debug)]
 pub enum TransportError {
     TimedOut,
     SerialPortDisconnected,
     MalformedCOBS(Box<[u8]>),
+    /// A frame ran past the maximum frame length without a delimiter, `len` bytes
+    /// were discarded up to the next delimiter
+    FrameTooLong {
+        len: usize,
+    },
 }
 
 impl From<io::Error> for TransportError {
@@ -26,8 +37,30 @@ impl From<io::Error> for TransportError {
 
 #[derive(Debug)]
 pub enum PacketReadError {
-    PoorLayout { section: usize, packet: Box<[u8]> },
-    BadPacketLength { expected: Option<usize>, got: usize },
+    PoorLayout {
+        section: usize,
+        packet: Box<[u8]>,
+    },
+    InvalidMetricType {
+        ty: Box<[u8]>,
+    },
+    /// A system packet of a kind this version does not know
+    UnknownSystemPacket {
+        kind: String,
+    },
+    /// A firmware log line with a level byte that is not a [`LogLevel`](crate::LogLevel)
+    InvalidLogLevel {
+        level: u8,
+    },
+    BadPacketLength {
+        expected: Option<usize>,
+        got: usize,
+    },
+    /// The CRC16 trailer sent with the packet does not match the one computed over it
+    BadChecksum {
+        expected: u16,
+        got: u16,
+    },
 
     MetricValue(MetricValueError),
     Transport(TransportError),
@@ -50,3 +83,28 @@ impl From<MetricValueError> for PacketReadError {
         Self::MetricValue(error)
     }
 }
+
+/// Why a command could not be handed to a connected robot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendCommandError {
+    /// The worker is not connected, so the command would be dropped
+    NotConnected(SerialWorkerState),
+    /// The worker thread has exited
+    WorkerExited,
+    /// The metrics come from a recording, which can not receive commands
+    ReadOnly,
+}
+
+impl Display for SendCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendCommandError::NotConnected(state) => {
+                write!(f, "serial worker is not connected ({state:?})")
+            }
+            SendCommandError::WorkerExited => write!(f, "serial worker has exited"),
+            SendCommandError::ReadOnly => write!(f, "a recording can not receive commands"),
+        }
+    }
+}
+
+impl Error for SendCommandError {}
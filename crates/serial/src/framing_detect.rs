@@ -0,0 +1,222 @@
+use std::mem::size_of;
+
+/// Framing recognized in a sample of the serial stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFraming {
+    /// COBS frames ending in this delimiter, with a matching length trailer
+    Cobs { frame_delimiter: u8 },
+    /// Newline terminated printable text, such as `Serial.println` debugging
+    AsciiLines,
+    /// Nothing recognizable, such as a wrong baud rate or an idle robot
+    Unknown,
+}
+
+impl DetectedFraming {
+    pub fn describe(self) -> String {
+        match self {
+            DetectedFraming::Cobs { frame_delimiter } => {
+                format!("COBS, delimiter 0x{frame_delimiter:02x}")
+            }
+            DetectedFraming::AsciiLines => "ASCII text lines (not supported)".to_owned(),
+            DetectedFraming::Unknown => "unknown".to_owned(),
+        }
+    }
+}
+
+/// Fewest complete frames that must be seen before trusting a framing
+const MIN_FRAMES: usize = 2;
+
+/// Guess the framing of a sample of the serial stream
+///
+/// Each candidate delimiter is tried in order, and the first one under which
+/// most complete frames decode to a packet with a valid length trailer wins.
+/// With `crc16`, every frame is expected to end in a checksum after the trailer.
+pub fn detect_framing(sample: &[u8], candidates: &[u8], crc16: bool) -> DetectedFraming {
+    for &frame_delimiter in candidates {
+        if cobs_frames_valid(sample, frame_delimiter, crc16) {
+            return DetectedFraming::Cobs { frame_delimiter };
+        }
+    }
+
+    let printable = sample
+        .iter()
+        .filter(|byte| byte.is_ascii_graphic() || byte.is_ascii_whitespace())
+        .count();
+
+    if sample.contains(&b'\n') && printable * 10 >= sample.len() * 9 {
+        return DetectedFraming::AsciiLines;
+    }
+
+    DetectedFraming::Unknown
+}
+
+fn cobs_frames_valid(sample: &[u8], frame_delimiter: u8, crc16: bool) -> bool {
+    let (valid, total) = count_valid_frames(sample, frame_delimiter, crc16);
+
+    total >= MIN_FRAMES && valid * 5 >= total * 4
+}
+
+/// Count the complete frames in a sample that decode to a packet with a valid
+/// length trailer, returning `(valid, total)`
+///
+/// With `crc16`, the checksum following the length trailer is skipped but not verified.
+pub(crate) fn count_valid_frames(
+    sample: &[u8],
+    frame_delimiter: u8,
+    crc16: bool,
+) -> (usize, usize) {
+    let mut frames = sample.split_inclusive(|&byte| byte == frame_delimiter);
+
+    // The sample most likely starts in the middle of a frame
+    frames.next();
+
+    let (mut valid, mut total) = (0, 0);
+
+    for frame in frames.filter(|frame| frame.last() == Some(&frame_delimiter)) {
+        total += 1;
+
+        let mut buffer = frame.to_vec();
+
+        let Ok(len) = postcard_cobs::decode_in_place_with_sentinel(&mut buffer, frame_delimiter)
+        else {
+            continue;
+        };
+
+        let packet = &buffer[..len.saturating_sub(1)];
+        let packet = if crc16 {
+            &packet[..packet.len().saturating_sub(size_of::<u16>())]
+        } else {
+            packet
+        };
+
+        if has_valid_length(packet) {
+            valid += 1;
+        }
+    }
+
+    (valid, total)
+}
+
+/// Whether the trailing length of a decoded packet matches its actual length,
+/// as either the `u16` trailer of v0 packets or the `u32` trailer of v1 packets
+fn has_valid_length(packet: &[u8]) -> bool {
+    [size_of::<u16>(), size_of::<u32>()]
+        .into_iter()
+        .any(|width| trailer_matches(packet, width))
+}
+
+fn trailer_matches(packet: &[u8], width: usize) -> bool {
+    let Some(split) = packet.len().checked_sub(width) else {
+        return false;
+    };
+
+    let (packet, length) = packet.split_at(split);
+    let mut bytes = [0; size_of::<u32>()];
+    bytes[..width].copy_from_slice(length);
+    let length = u32::from_le_bytes(bytes) as usize;
+
+    length.saturating_sub(width) == packet.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: &[u8] = b"\x01\x00\x00\x00speed\x00u8\x00\x07";
+
+    /// A sample starting mid-frame, followed by `frames` whole COBS frames of a valid packet
+    fn cobs_sample(frame_delimiter: u8, frames: usize) -> Vec<u8> {
+        let mut packet = PACKET.to_vec();
+        packet.extend_from_slice(&(packet.len() as u16 + 2).to_le_bytes());
+
+        sample_of(&packet, frame_delimiter, frames)
+    }
+
+    /// A sample starting mid-frame, followed by `frames` whole COBS frames of `packet`
+    fn sample_of(packet: &[u8], frame_delimiter: u8, frames: usize) -> Vec<u8> {
+        let mut sample = vec![0x42, 0x13, frame_delimiter];
+        for _ in 0..frames {
+            sample.extend(postcard_cobs::encode_vec_with_sentinel(
+                packet,
+                frame_delimiter,
+            ));
+            sample.push(frame_delimiter);
+        }
+        sample
+    }
+
+    #[test]
+    fn detects_cobs_with_zero_delimiter() {
+        assert_eq!(
+            detect_framing(&cobs_sample(0x00, 4), &[0x00, b'\n'], false),
+            DetectedFraming::Cobs {
+                frame_delimiter: 0x00
+            }
+        );
+    }
+
+    #[test]
+    fn detects_cobs_with_other_delimiter() {
+        assert_eq!(
+            detect_framing(&cobs_sample(0xaa, 4), &[0x00, 0xaa, b'\n'], false),
+            DetectedFraming::Cobs {
+                frame_delimiter: 0xaa
+            }
+        );
+    }
+
+    #[test]
+    fn needs_more_than_one_frame() {
+        assert_eq!(
+            detect_framing(&cobs_sample(0x00, 1), &[0x00], false),
+            DetectedFraming::Unknown
+        );
+    }
+
+    #[test]
+    fn detects_ascii_lines() {
+        assert_eq!(
+            detect_framing(b"speed=12\nangle=3.5\nhello world\n", &[0x00, b'\n'], false),
+            DetectedFraming::AsciiLines
+        );
+    }
+
+    #[test]
+    fn detects_noise_as_unknown() {
+        let noise = (0..=255).cycle().step_by(7).take(512).collect::<Vec<u8>>();
+
+        assert_eq!(
+            detect_framing(&noise, &[0x00, b'\n'], false),
+            DetectedFraming::Unknown
+        );
+    }
+
+    #[test]
+    fn detects_cobs_with_crc16() {
+        let mut packet = PACKET.to_vec();
+        packet.extend_from_slice(&(packet.len() as u16 + 2).to_le_bytes());
+        packet.extend_from_slice(&crate::crc::crc16_ccitt(&packet).to_le_bytes());
+        let sample = sample_of(&packet, 0x00, 4);
+
+        assert_eq!(
+            detect_framing(&sample, &[0x00, b'\n'], true),
+            DetectedFraming::Cobs {
+                frame_delimiter: 0x00
+            }
+        );
+    }
+
+    #[test]
+    fn detects_cobs_with_u32_length_trailer() {
+        let mut packet = [&[1][..], PACKET].concat();
+        packet.extend_from_slice(&(packet.len() as u32 + 4).to_le_bytes());
+        let sample = sample_of(&packet, 0x00, 4);
+
+        assert_eq!(
+            detect_framing(&sample, &[0x00, b'\n'], false),
+            DetectedFraming::Cobs {
+                frame_delimiter: 0x00
+            }
+        );
+    }
+}
@@ -1,167 +1,392 @@
 use std::{
-    io::{BufRead, BufReader},
+    io::{self, BufRead, BufReader, Read},
     mem::size_of,
+    path::PathBuf,
     sync::{
-        mpsc::{Receiver, Sender},
-        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, PoisonError, RwLock,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use serialport::SerialPort;
+use time::OffsetDateTime;
 use tracing::{debug, error, info, trace, warn};
 
+mod baud_detect;
+mod config;
 mod controller;
+mod crc;
+mod csv_log;
 mod detacher;
+mod diagnostics;
 mod error;
+mod framing_detect;
+mod payload;
+mod recording;
+mod replay;
+mod scripted;
+mod source;
+mod system;
 
+pub use baud_detect::COMMON_BAUD_RATES;
+pub use config::{Framing, SerialWorkerConfig};
 pub use controller::SerialWorkerController;
+pub use diagnostics::{LengthDeltas, PacketCounts};
+pub use error::SendCommandError;
+pub use framing_detect::DetectedFraming;
+pub use payload::{parse_byte, parse_payload, PayloadParseError};
+pub use recording::RecordedPacket;
+pub use replay::ReplayWorkerController;
+pub use scripted::ScriptedSource;
+pub use source::MetricSource;
+pub use system::{LogLevel, SystemMessage, SystemPacket};
 
 use kestrel_metric::{
+    hex::HexDump,
     timestamp::Timestamp,
     value::{MetricValue, MetricValueError},
     Metric, RobotCommand,
 };
 
-use self::error::{PacketReadError, TransportError};
+use self::{
+    crc::crc16_ccitt,
+    csv_log::CsvLog,
+    error::{PacketReadError, TransportError},
+    framing_detect::detect_framing,
+    recording::Recorder,
+    system::SYSTEM_TYPE,
+};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A decoded packet, either a measurement or a message from the firmware about itself
+///
+/// Both are passed to the UI as one stream, so a reboot is seen in order with the
+/// metrics sent before and after it.
+#[derive(Debug)]
+pub enum Packet {
+    Metric(Metric),
+    System(SystemMessage),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum SerialWorkerCommand {
     Detach,
     Attach,
     Reset,
     SendCommand(RobotCommand),
+    /// Write a single command byte without a named [`RobotCommand`], for new firmware opcodes
+    SendRawCommand(u8),
+    /// Write arbitrary bytes to the robot, for ad-hoc protocol testing
+    SendPayload(Box<[u8]>),
+    SetReadTimeout(Duration),
+    /// Write every packet received from now on to a file, replacing any running recording
+    StartRecording(PathBuf),
+    StopRecording,
+    /// Append every metric read from now on to a CSV file, replacing any running log
+    StartCsvLog(PathBuf),
+    StopCsvLog,
+    /// Measure how long a command waits in the queue before the worker handles it
+    Ping(Instant),
+    Shutdown,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SerialWorkerState {
     Resetting,
     Connected,
+    /// Connected, but nothing has been read for a while, as if the firmware froze
+    Stalled,
     Disconnected,
     Detached,
 }
 
+impl SerialWorkerState {
+    /// Whether the port is open, so commands can be sent, even if nothing is being received
+    pub fn is_connected(self) -> bool {
+        matches!(
+            self,
+            SerialWorkerState::Connected | SerialWorkerState::Stalled
+        )
+    }
+}
+
+/// Reader of an open serial port, which first reads back the bytes sampled to detect the framing
+type PortReader = BufReader<io::Chain<io::Cursor<Vec<u8>>, Box<dyn SerialPort>>>;
+
+/// The serial port under a [`PortReader`], for writing and changing its settings
+fn port(reader: &mut PortReader) -> &mut Box<dyn SerialPort> {
+    reader.get_mut().get_mut().1
+}
+
 struct SerialWorker {
     port_name: Arc<str>,
-    baud_rate: u32,
-    metric_tx: Sender<Metric>,
+    config: SerialWorkerConfig,
+    packet_tx: Sender<Packet>,
     command_rx: Receiver<SerialWorkerCommand>,
     state: Arc<RwLock<SerialWorkerState>>,
+    retain_raw: Arc<AtomicBool>,
+    dropped_frames: Arc<AtomicU64>,
+    length_deltas: Arc<Mutex<LengthDeltas>>,
+    packet_counts: Arc<Mutex<PacketCounts>>,
+    latency: Arc<Mutex<Option<Duration>>>,
+    detected_framing: Arc<Mutex<Option<DetectedFraming>>>,
+    recording: Arc<AtomicBool>,
+    recorder: Option<Recorder>,
+    csv_log: Option<CsvLog>,
+    /// When the next attempt to open the port is made, while waiting for it to appear
+    reconnect_at: Arc<Mutex<Option<Instant>>>,
+    /// A command that arrived while waiting to reconnect, handled before any other
+    deferred_command: Option<SerialWorkerCommand>,
+    /// Reads in a row that timed out without any data
+    consecutive_timeouts: u32,
+    /// Whether a packet with an unknown protocol version was already reported on this connection
+    warned_unknown_version: bool,
     repaint: Box<dyn Fn()>,
 }
 
 impl SerialWorker {
-    pub fn spawn(mut self) -> ! {
-        let mut opt_reader: Option<BufReader<Box<dyn SerialPort>>> = None;
+    /// Run the worker until the controller is dropped
+    pub fn spawn(mut self) {
+        let mut opt_reader: Option<PortReader> = None;
         let mut packet_buffer = Vec::new();
+        let mut reconnect_delay = MIN_RECONNECT_DELAY;
 
         loop {
-            for command in self.command_rx.try_iter() {
+            while let Some(command) = self
+                .deferred_command
+                .take()
+                .or_else(|| self.command_rx.try_recv().ok())
+            {
                 match command {
                     SerialWorkerCommand::Detach => {
                         opt_reader.take();
 
                         info!("serial worker detached");
-                        *self.state.write().unwrap() = SerialWorkerState::Detached;
-                        self.repaint();
+                        self.set_state(SerialWorkerState::Detached);
 
                         loop {
-                            let command = self.command_rx.recv().unwrap();
+                            let Ok(command) = self.command_rx.recv() else {
+                                info!("serial worker controller has been dropped, exiting");
+                                return;
+                            };
 
                             // Wait for an attach command
                             match command {
                                 SerialWorkerCommand::Attach => break,
+                                SerialWorkerCommand::Shutdown => {
+                                    info!("serial worker shut down");
+                                    return;
+                                }
+                                SerialWorkerCommand::Detach => {
+                                    debug!("serial worker commanded to detach when already detached");
+                                }
+                                SerialWorkerCommand::SetReadTimeout(timeout) => {
+                                    self.config.read_timeout = timeout;
+                                }
+                                SerialWorkerCommand::Ping(sent) => self.record_latency(sent),
+                                SerialWorkerCommand::StartRecording(path) => {
+                                    self.start_recording(path);
+                                }
+                                SerialWorkerCommand::StopRecording => self.stop_recording(),
+                                SerialWorkerCommand::StartCsvLog(path) => {
+                                    self.start_csv_log(path);
+                                }
+                                SerialWorkerCommand::StopCsvLog => self.stop_csv_log(),
                                 _ => info!(?command, "ignoring command while detached"),
                             }
                         }
 
                         info!("serial worker attached");
-                        *self.state.write().unwrap() = SerialWorkerState::Disconnected;
-                        self.repaint();
+                        self.set_state(SerialWorkerState::Disconnected);
                     }
                     SerialWorkerCommand::Attach => {
                         warn!("serial worker commanded to attach when already attached");
                     }
+                    SerialWorkerCommand::Shutdown => {
+                        info!("serial worker shut down");
+                        return;
+                    }
                     SerialWorkerCommand::Reset => match &mut opt_reader {
                         Some(reader) => {
-                            let serial = reader.get_mut();
-
-                            *self.state.write().unwrap() = SerialWorkerState::Resetting;
+                            self.set_state(SerialWorkerState::Resetting);
 
-                            serial.write_data_terminal_ready(true).unwrap();
-                            thread::sleep(Duration::from_millis(1000));
-                            serial.write_data_terminal_ready(false).unwrap();
+                            if let Err(err) = Self::reset(port(reader)) {
+                                warn!(%err, "failed to reset the arduino, closing the serial port");
 
-                            *self.state.write().unwrap() = SerialWorkerState::Connected;
+                                opt_reader = None;
+                                self.set_state(SerialWorkerState::Disconnected);
+                            } else {
+                                self.set_state(SerialWorkerState::Connected);
+                            }
                         }
                         None => warn!(
                             "serial worker commanded to reset when not connected to an arduino"
                         ),
                     },
                     SerialWorkerCommand::SendCommand(command) => {
-                        match &mut opt_reader {
-                            Some(reader) => {
-                                let serial = reader.get_mut();
-                                serial.write_all(&[command as u8]).unwrap();
-                                serial.flush().unwrap();
+                        Self::write_command(&mut opt_reader, command as u8);
+                    }
+                    SerialWorkerCommand::SendRawCommand(command) => {
+                        Self::write_command(&mut opt_reader, command);
+                    }
+                    SerialWorkerCommand::SendPayload(payload) => match &mut opt_reader {
+                        Some(reader) => {
+                            let serial = port(reader);
+
+                            if let Err(err) =
+                                serial.write_all(&payload).and_then(|()| serial.flush())
+                            {
+                                warn!(%err, payload = %HexDump(&payload), "failed to send payload");
                             }
-                            None => warn!(
-                                "serial worker commanded to send command when not connected to an arduino"
-                            ),
                         }
+                        None => warn!(
+                            "serial worker commanded to send payload when not connected to an arduino"
+                        ),
+                    },
+                    SerialWorkerCommand::SetReadTimeout(timeout) => {
+                        self.set_read_timeout(timeout, &mut opt_reader);
                     }
+                    SerialWorkerCommand::StartRecording(path) => self.start_recording(path),
+                    SerialWorkerCommand::StopRecording => self.stop_recording(),
+                    SerialWorkerCommand::StartCsvLog(path) => self.start_csv_log(path),
+                    SerialWorkerCommand::StopCsvLog => self.stop_csv_log(),
+                    SerialWorkerCommand::Ping(sent) => self.record_latency(sent),
                 }
             }
 
-            match &mut opt_reader {
-                Some(reader) => match self.read_packet(reader, &mut packet_buffer) {
-                    Err(PacketReadError::Transport(TransportError::TimedOut)) => {}
-                    Err(PacketReadError::Transport(TransportError::SerialPortDisconnected)) => {
-                        info!("serial port disconnected");
+            self.flush_recording();
+            self.flush_csv_log();
 
-                        opt_reader = None;
+            match &mut opt_reader {
+                Some(reader) => {
+                    let result = self.read_packet(reader, &mut packet_buffer);
 
-                        *self.state.write().unwrap() = SerialWorkerState::Disconnected;
-                        self.repaint();
-                    }
-                    Err(PacketReadError::Transport(TransportError::MalformedCOBS(data))) => {
-                        warn!(?data, "Received malformed COBS data");
-                    }
-                    Err(PacketReadError::MetricValue(MetricValueError::BadLength {
-                        expected,
-                        got,
-                    })) => {
-                        error!(%expected, %got, "Metric value did not match expected length");
-                    }
-                    Err(PacketReadError::BadPacketLength { expected, got }) => {
-                        debug!(
-                            ?expected,
-                            %got,
-                            "Packet length did not match expected length"
-                        );
+                    // Anything read at all, even garbage, shows the device is still talking
+                    if !matches!(
+                        result,
+                        Err(PacketReadError::Transport(
+                            TransportError::TimedOut | TransportError::SerialPortDisconnected
+                        ))
+                    ) {
+                        self.clear_timeouts();
                     }
-                    Err(PacketReadError::PoorLayout { packet, section }) => {
-                        warn!(?packet, %section, "Received packet with a bad layout");
-                    }
-                    Ok(metric) => {
-                        self.metric_tx.send(metric).expect("ui thread has exited");
-                        self.repaint();
+
+                    match result {
+                        Err(PacketReadError::Transport(TransportError::TimedOut)) => {
+                            self.count_timeout();
+                        }
+                        Err(PacketReadError::Transport(TransportError::SerialPortDisconnected)) => {
+                            info!("serial port disconnected");
+
+                            opt_reader = None;
+
+                            self.set_state(SerialWorkerState::Disconnected);
+                        }
+                        Err(PacketReadError::Transport(TransportError::FrameTooLong { len })) => {
+                            self.count_packet(|counts| &mut counts.bad_length);
+                            warn!(
+                                len,
+                                max = self.config.max_frame_length,
+                                "Discarded a frame longer than the maximum frame length"
+                            );
+                        }
+                        Err(PacketReadError::Transport(TransportError::MalformedCOBS(data))) => {
+                            self.count_packet(|counts| &mut counts.malformed_cobs);
+                            warn!(?data, "Received malformed COBS data");
+                        }
+                        Err(PacketReadError::MetricValue(MetricValueError::BadLength {
+                            expected,
+                            got,
+                        })) => {
+                            self.count_packet(|counts| &mut counts.bad_value_length);
+                            error!(%expected, %got, "Metric value did not match expected length");
+                        }
+                        Err(PacketReadError::BadPacketLength { expected, got }) => {
+                            self.count_packet(|counts| &mut counts.bad_length);
+                            debug!(
+                                ?expected,
+                                %got,
+                                "Packet length did not match expected length"
+                            );
+
+                            if let Some(expected) = expected {
+                                self.length_deltas
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .record(expected, got);
+                            }
+                        }
+                        Err(PacketReadError::BadChecksum { expected, got }) => {
+                            self.count_packet(|counts| &mut counts.bad_checksum);
+                            warn!(
+                                expected = format_args!("{expected:#06x}"),
+                                got = format_args!("{got:#06x}"),
+                                "Packet checksum did not match"
+                            );
+                        }
+                        Err(PacketReadError::PoorLayout { packet, section }) => {
+                            self.count_packet(|counts| &mut counts.poor_layout);
+                            warn!(?packet, %section, "Received packet with a bad layout");
+                        }
+                        Err(PacketReadError::InvalidMetricType { ty }) => {
+                            self.count_packet(|counts| &mut counts.other);
+                            warn!(ty = %HexDump(&ty), "Received metric with a non-printable type");
+                        }
+                        Err(PacketReadError::UnknownSystemPacket { kind }) => {
+                            self.count_packet(|counts| &mut counts.other);
+                            warn!(%kind, "Received an unknown kind of system packet");
+                        }
+                        Err(PacketReadError::InvalidLogLevel { level }) => {
+                            self.count_packet(|counts| &mut counts.other);
+                            warn!(level, "Received a firmware log line with an unknown level");
+                        }
+                        Ok(packet) => {
+                            if !self.forward(packet) {
+                                info!("ui thread has exited, stopping serial worker");
+                                return;
+                            }
+                        }
                     }
-                },
+                }
                 None => match self.connect() {
-                    Some(reader) => {
+                    Some(mut serial) => {
                         info!("serial port connected");
 
-                        opt_reader = Some(reader);
+                        let sample = if self.config.auto_detect_framing {
+                            self.detect_framing(&mut serial)
+                        } else {
+                            Vec::new()
+                        };
+
+                        opt_reader = Some(BufReader::new(io::Cursor::new(sample).chain(serial)));
+                        reconnect_delay = MIN_RECONNECT_DELAY;
+                        self.consecutive_timeouts = 0;
+                        self.warned_unknown_version = false;
+                        self.set_reconnect_at(None);
 
-                        *self.state.write().unwrap() = SerialWorkerState::Connected;
-                        self.repaint();
+                        self.set_state(SerialWorkerState::Connected);
                     }
                     None => {
-                        trace!("serial port not found... sleeping 1 second");
+                        trace!(
+                            ?reconnect_delay,
+                            "serial port not found... waiting to retry"
+                        );
 
-                        thread::sleep(Duration::from_millis(1000));
+                        self.set_reconnect_at(Some(Instant::now() + reconnect_delay));
+
+                        // Wake up early for a command, so detaching is not held up by the wait
+                        match self.command_rx.recv_timeout(reconnect_delay) {
+                            Ok(command) => self.deferred_command = Some(command),
+                            Err(RecvTimeoutError::Timeout) => {
+                                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                            }
+                            Err(RecvTimeoutError::Disconnected) => {
+                                info!("serial worker controller has been dropped, exiting");
+                                return;
+                            }
+                        }
                     }
                 },
             }
@@ -172,108 +397,1234 @@ impl SerialWorker {
         (self.repaint)()
     }
 
-    fn connect(&self) -> Option<BufReader<Box<dyn SerialPort>>> {
-        match serialport::new(self.port_name.as_ref(), self.baud_rate)
-            .timeout(Duration::from_millis(100))
+    /// Pass a decoded packet on to the UI thread
+    ///
+    /// Returns `false` once the UI thread has dropped its receiver, so the worker can stop.
+    fn forward(&mut self, packet: Packet) -> bool {
+        self.count_packet(|counts| &mut counts.good);
+
+        let packet = match packet {
+            Packet::System(message) => {
+                debug!(?message, "received system packet");
+
+                Packet::System(message)
+            }
+            Packet::Metric(mut metric) => {
+                self.record(&metric);
+                self.log_csv(&metric);
+
+                if !self.retain_raw.load(Ordering::Relaxed) {
+                    metric.raw = None;
+                }
+
+                Packet::Metric(metric)
+            }
+        };
+
+        if self.packet_tx.send(packet).is_err() {
+            return false;
+        }
+
+        self.repaint();
+        true
+    }
+
+    /// Change the read timeout, reopening an open port so the new timeout applies to it
+    ///
+    /// Bytes read but not yet decoded are lost, the partial frame they leave
+    /// behind is skipped like any other malformed frame.
+    fn set_read_timeout(&mut self, timeout: Duration, opt_reader: &mut Option<PortReader>) {
+        self.config.read_timeout = timeout;
+
+        // Closed before opening it again, as the port can only be open once
+        if opt_reader.take().is_none() {
+            return;
+        }
+
+        match self.connect() {
+            Some(serial) => {
+                info!(?timeout, "reopened serial port with a new read timeout");
+
+                *opt_reader = Some(BufReader::new(io::Cursor::new(Vec::new()).chain(serial)));
+                self.consecutive_timeouts = 0;
+            }
+            None => {
+                warn!("failed to reopen serial port with a new read timeout");
+
+                self.set_state(SerialWorkerState::Disconnected);
+            }
+        }
+    }
+
+    /// Pulse the data terminal ready line, which resets an arduino
+    fn reset(serial: &mut Box<dyn SerialPort>) -> serialport::Result<()> {
+        serial.write_data_terminal_ready(true)?;
+        thread::sleep(Duration::from_millis(1000));
+        serial.write_data_terminal_ready(false)
+    }
+
+    /// Sample the stream to find its framing, switching to the detected frame delimiter
+    ///
+    /// Once COBS framing has been found it is kept for later reconnects. Returns the
+    /// sampled bytes, which still have to be read as packets.
+    fn detect_framing(&mut self, serial: &mut Box<dyn SerialPort>) -> Vec<u8> {
+        // Not held while sampling, the UI reads the detected framing every frame
+        let detected = *self
+            .detected_framing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        if matches!(detected, Some(DetectedFraming::Cobs { .. })) {
+            return Vec::new();
+        }
+
+        let mut sample = Vec::with_capacity(FRAMING_SAMPLE_SIZE);
+        let deadline = Instant::now() + FRAMING_SAMPLE_DURATION;
+        let mut chunk = [0; 64];
+
+        while sample.len() < FRAMING_SAMPLE_SIZE && Instant::now() < deadline {
+            match serial.read(&mut chunk) {
+                Ok(len) => sample.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::TimedOut => {}
+                Err(err) => {
+                    warn!(%err, "failed to sample serial port for framing detection");
+                    break;
+                }
+            }
+        }
+
+        let framing = detect_framing(
+            &sample,
+            &[self.config.framing.frame_delimiter, 0x00, b'\n'],
+            self.config.crc16,
+        );
+
+        info!(framing = framing.describe(), "detected framing");
+
+        if let DetectedFraming::Cobs { frame_delimiter } = framing {
+            self.config.framing.frame_delimiter = frame_delimiter;
+        }
+
+        *self
+            .detected_framing
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(framing);
+
+        self.repaint();
+
+        sample
+    }
+
+    fn write_command(opt_reader: &mut Option<PortReader>, command: u8) {
+        match opt_reader {
+            Some(reader) => {
+                let serial = port(reader);
+
+                if let Err(err) = serial.write_all(&[command]).and_then(|()| serial.flush()) {
+                    warn!(%err, command, "failed to send command");
+                }
+            }
+            None => {
+                warn!("serial worker commanded to send command when not connected to an arduino")
+            }
+        }
+    }
+
+    fn start_recording(&mut self, path: PathBuf) {
+        self.stop_recording();
+
+        match Recorder::create(&path, Instant::now()) {
+            Ok(recorder) => {
+                info!(path = %path.display(), "started recording packets");
+
+                self.recorder = Some(recorder);
+                self.recording.store(true, Ordering::Relaxed);
+            }
+            Err(err) => warn!(%err, path = %path.display(), "failed to create recording"),
+        }
+
+        self.repaint();
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            if let Err(err) = recorder.finish() {
+                warn!(%err, "failed to finish recording");
+            }
+
+            info!("stopped recording packets");
+        }
+
+        self.recording.store(false, Ordering::Relaxed);
+        self.repaint();
+    }
+
+    fn start_csv_log(&mut self, path: PathBuf) {
+        self.stop_csv_log();
+
+        match CsvLog::open(&path, Instant::now()) {
+            Ok(csv_log) => {
+                info!(path = %path.display(), "started logging metrics to csv");
+
+                self.csv_log = Some(csv_log);
+            }
+            Err(err) => warn!(%err, path = %path.display(), "failed to open csv log"),
+        }
+    }
+
+    fn stop_csv_log(&mut self) {
+        if let Some(csv_log) = self.csv_log.take() {
+            if let Err(err) = csv_log.finish() {
+                warn!(%err, "failed to finish csv log");
+            }
+
+            info!("stopped logging metrics to csv");
+        }
+    }
+
+    /// Append a metric to the running csv log, stopping the log if the file can not be written
+    fn log_csv(&mut self, metric: &Metric) {
+        let Some(csv_log) = &mut self.csv_log else {
+            return;
+        };
+
+        if let Err(err) = csv_log.append(metric) {
+            warn!(%err, "failed to write csv log, stopping it");
+
+            self.stop_csv_log();
+        }
+    }
+
+    fn flush_csv_log(&mut self) {
+        let Some(csv_log) = &mut self.csv_log else {
+            return;
+        };
+
+        if let Err(err) = csv_log.poll_flush(Instant::now()) {
+            warn!(%err, "failed to flush csv log, stopping it");
+
+            self.stop_csv_log();
+        }
+    }
+
+    fn flush_recording(&mut self) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+
+        if let Err(err) = recorder.poll_flush(Instant::now()) {
+            warn!(%err, "failed to flush recording, stopping it");
+
+            self.stop_recording();
+        }
+    }
+
+    /// Append a packet to the running recording, stopping the recording if the file can not be written
+    fn record(&mut self, metric: &Metric) {
+        let Some(recorder) = &mut self.recorder else {
+            return;
+        };
+        let Some(packet) = RecordedPacket::from_metric(metric) else {
+            return;
+        };
+
+        if let Err(err) = recorder.write(&packet) {
+            warn!(%err, "failed to write recording, stopping it");
+
+            self.stop_recording();
+        }
+    }
+
+    /// Count a read that timed out, marking the connection stalled after [`STALL_TIMEOUTS`] in a row
+    fn count_timeout(&mut self) {
+        self.consecutive_timeouts = self.consecutive_timeouts.saturating_add(1);
+
+        if self.consecutive_timeouts == STALL_TIMEOUTS
+            && self.state() == SerialWorkerState::Connected
+        {
+            warn!(
+                timeouts = self.consecutive_timeouts,
+                "nothing received for a while, serial connection stalled"
+            );
+
+            self.set_state(SerialWorkerState::Stalled);
+        }
+    }
+
+    fn clear_timeouts(&mut self) {
+        self.consecutive_timeouts = 0;
+
+        if self.state() == SerialWorkerState::Stalled {
+            info!("serial connection receiving again");
+
+            self.set_state(SerialWorkerState::Connected);
+        }
+    }
+
+    fn set_reconnect_at(&self, reconnect_at: Option<Instant>) {
+        *self
+            .reconnect_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = reconnect_at;
+    }
+
+    /// Count a packet read with the outcome selected by `counter`
+    fn count_packet(&self, counter: impl FnOnce(&mut PacketCounts) -> &mut u64) {
+        *counter(
+            &mut self
+                .packet_counts
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        ) += 1;
+    }
+
+    fn record_latency(&self, sent: Instant) {
+        *self.latency.lock().unwrap_or_else(PoisonError::into_inner) = Some(sent.elapsed());
+
+        self.repaint();
+    }
+
+    fn state(&self) -> SerialWorkerState {
+        *self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Publish a new state to the controller
+    ///
+    /// The state is only ever written by the worker, in the order the commands
+    /// were received, so the last command sent always determines the final state.
+    fn set_state(&self, state: SerialWorkerState) {
+        *self.state.write().unwrap_or_else(PoisonError::into_inner) = state;
+
+        self.repaint();
+    }
+
+    fn connect(&self) -> Option<Box<dyn SerialPort>> {
+        match serialport::new(self.port_name.as_ref(), self.config.baud_rate)
+            .timeout(self.config.read_timeout)
             .open()
         {
-            Ok(stream) => Some(BufReader::new(stream)),
+            Ok(stream) => Some(stream),
             Err(e) if e.kind() == serialport::ErrorKind::NoDevice => None,
-            Err(e) => panic!("{e}"),
+            Err(e) => {
+                // Another worker may still be releasing the port, so try again later
+                warn!(%e, "failed to open serial port");
+                None
+            }
         }
     }
 
+    /// Read and decode the next packet
+    ///
+    /// Reads from any [`BufRead`] rather than only the serial port, so the
+    /// framing and layout logic does not depend on a real port.
     fn read_packet(
         &mut self,
-        reader: &mut BufReader<Box<dyn SerialPort>>,
+        reader: &mut dyn BufRead,
         buffer: &mut Vec<u8>,
-    ) -> Result<Metric, PacketReadError> {
+    ) -> Result<Packet, PacketReadError> {
         let buffer = self.read_cobs(reader, buffer)?;
 
-        let packet = {
-            let (packet, packet_length) = buffer.split_at(buffer.len().saturating_sub(2));
-
-            let packet_length =
-                packet_length
-                    .try_into()
-                    .map_err(|_| PacketReadError::BadPacketLength {
-                        expected: None,
-                        got: packet.len(),
-                    })?;
-            let packet_length =
-                (u16::from_le_bytes(packet_length) as usize).saturating_sub(size_of::<u16>());
-
-            if packet_length != packet.len() {
-                dbg!(buffer);
-
-                return dbg!(Err(PacketReadError::BadPacketLength {
-                    expected: Some(packet_length),
-                    got: packet.len(),
-                }));
+        let buffer = if self.config.crc16 {
+            let (buffer, checksum) = buffer.split_at(buffer.len().saturating_sub(2));
+
+            let expected = checksum.try_into().map(u16::from_le_bytes).map_err(|_| {
+                PacketReadError::BadPacketLength {
+                    expected: None,
+                    got: buffer.len(),
+                }
+            })?;
+            let got = crc16_ccitt(buffer);
+
+            if expected != got {
+                return Err(PacketReadError::BadChecksum { expected, got });
             }
 
-            packet
+            buffer
+        } else {
+            buffer
         };
 
-        let (packet, timestamp) = {
-            // Should never panic since packet length has been verified
-            let (timestamp, packet) = packet.split_at(size_of::<u32>());
+        // The version byte comes first, as it selects how wide the length trailer is
+        let version = match (self.config.versioned_packets, buffer.first()) {
+            (true, Some(&version)) => Some(version),
+            _ => None,
+        };
 
-            let timestamp = u32::from_le_bytes(
-                timestamp
-                    .try_into()
-                    .expect("timestamp should always be one u32 wide"),
-            );
+        let packet = match version {
+            Some(PROTOCOL_V1) => split_length_trailer(buffer, size_of::<u32>())?,
+            _ => split_length_trailer(buffer, size_of::<u16>())?,
+        };
 
-            (packet, timestamp)
+        let Some(version) = version else {
+            return self.decode_v0(packet);
         };
 
-        let mut split = packet.splitn(3, |&b| b == 0x00);
+        let Some((_, packet)) = packet.split_first() else {
+            return Err(PacketReadError::PoorLayout {
+                section: 0,
+                packet: Box::from(packet),
+            });
+        };
+
+        match version {
+            // Only the length trailer differs, the packet itself has the same layout
+            PROTOCOL_V0 | PROTOCOL_V1 => self.decode_v0(packet),
+            version => {
+                // Every packet of the firmware would repeat it, so only warn once per connection
+                if !self.warned_unknown_version {
+                    self.warned_unknown_version = true;
+                    warn!(version, "unknown protocol version, decoding as v0");
+                }
+
+                self.decode_v0(packet)
+            }
+        }
+    }
+
+    /// Decode the original packet layout: timestamp, name, type and value
+    ///
+    /// Packets of the [`SYSTEM_TYPE`] type are decoded as a system packet instead of a metric.
+    fn decode_v0(&self, packet: &[u8]) -> Result<Packet, PacketReadError> {
+        // The length trailer only says the frame is whole, not that it holds a timestamp
+        let Some((&timestamp, packet)) = packet.split_first_chunk::<{ size_of::<u32>() }>() else {
+            return Err(PacketReadError::PoorLayout {
+                section: 0,
+                packet: Box::from(packet),
+            });
+        };
+        let timestamp = u32::from_le_bytes(timestamp);
+
+        let separator = self.config.framing.field_separator;
+        let mut split = packet.splitn(3, |&b| b == separator);
 
         let metric_name = split.next().ok_or_else(|| PacketReadError::PoorLayout {
             section: 0,
             packet: Box::from(packet),
         })?;
-        let metric_name = String::from_utf8_lossy(metric_name).into_owned();
+        let metric_name = if self.config.lowercase_names {
+            String::from_utf8_lossy(metric_name).to_lowercase()
+        } else {
+            String::from_utf8_lossy(metric_name).into_owned()
+        };
 
         let metric_type = split.next().ok_or_else(|| PacketReadError::PoorLayout {
             section: 1,
             packet: Box::from(packet),
         })?;
-        let metric_type = String::from_utf8_lossy(metric_type).into_owned();
+        let metric_type = parse_metric_type(metric_type)?;
 
         let metric = split.next().ok_or_else(|| PacketReadError::PoorLayout {
             section: 2,
             packet: Box::from(packet),
         })?;
 
+        let timestamp = Timestamp::from_raw(timestamp, self.config.timestamp_unit);
+
+        if metric_type == SYSTEM_TYPE {
+            return Ok(Packet::System(SystemMessage {
+                timestamp,
+                packet: SystemPacket::decode(&metric_name, metric)?,
+            }));
+        }
+
         let metric_value = MetricValue::from_bytes(metric_type, metric)?;
 
-        Ok(Metric {
-            timestamp: Timestamp::from_millis(timestamp),
+        Ok(Packet::Metric(Metric {
+            timestamp,
+            arrival: OffsetDateTime::now_utc(),
             name: metric_name
                 .parse()
                 .expect("metric name parsing must never fail"),
             value: metric_value,
-        })
+            // Kept while recording as well, the worker strips them again if they are not wanted
+            raw: (self.retain_raw.load(Ordering::Relaxed) || self.recorder.is_some())
+                .then(|| Box::from(metric)),
+        }))
     }
 
     fn read_cobs<'buffer>(
         &mut self,
-        reader: &mut BufReader<Box<dyn SerialPort>>,
+        reader: &mut dyn BufRead,
         buffer: &'buffer mut Vec<u8>,
     ) -> Result<&'buffer [u8], TransportError> {
         buffer.clear();
 
-        let buffer = {
-            let len = reader.read_until(0, buffer)?;
+        let framing = self.config.framing;
+        let max_len = self.config.max_frame_length;
 
-            &mut buffer[..len]
+        let mut frame_end = read_frame(reader, framing.frame_delimiter, buffer, max_len)?;
+        let mut frame_start = 0;
+        let mut skipped = 0;
+
+        // A malformed frame usually means the read started mid-frame, such as after the
+        // arduino reset while sending, so skip frames until one decodes cleanly again
+        let decoded = loop {
+            match postcard_cobs::decode_in_place_with_sentinel(
+                &mut buffer[frame_start..frame_end],
+                framing.frame_delimiter,
+            ) {
+                Ok(len) => break Ok(len),
+                Err(()) if skipped < MAX_RESYNC_FRAMES => {
+                    skipped += 1;
+                    self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+
+                    frame_start = frame_end;
+                    frame_end += read_frame(reader, framing.frame_delimiter, buffer, max_len)?;
+                }
+                Err(()) => break Err(()),
+            }
         };
 
-        match postcard_cobs::decode_in_place(buffer) {
+        if skipped > 0 {
+            debug!(skipped, "resynchronized after malformed COBS data");
+        }
+
+        let buffer = &buffer[frame_start..frame_end];
+
+        match decoded {
             Ok(len) => Ok(&buffer[..len.saturating_sub(1)]),
-            Err(()) => Err(TransportError::MalformedCOBS(Box::from(&*buffer))),
+            Err(()) => Err(TransportError::MalformedCOBS(Box::from(buffer))),
+        }
+    }
+}
+
+/// Append a frame up to and including `delimiter` to `buffer` like [`BufRead::read_until`],
+/// returning the number of bytes appended
+///
+/// A frame longer than `max_len` is not buffered, the rest of it is discarded up to
+/// the next delimiter and [`TransportError::FrameTooLong`] is returned instead.
+fn read_frame(
+    reader: &mut (impl BufRead + ?Sized),
+    delimiter: u8,
+    buffer: &mut Vec<u8>,
+    max_len: usize,
+) -> Result<usize, TransportError> {
+    let start = buffer.len();
+    let mut too_long = None;
+
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        // End of the stream
+        if available.is_empty() {
+            break;
+        }
+
+        let (used, done) = match available.iter().position(|&byte| byte == delimiter) {
+            Some(index) => (index + 1, true),
+            None => (available.len(), false),
+        };
+
+        match &mut too_long {
+            Some(len) => *len += used,
+            None if buffer.len() - start + used > max_len => {
+                too_long = Some(buffer.len() - start + used);
+                buffer.truncate(start);
+            }
+            None => buffer.extend_from_slice(&available[..used]),
+        }
+
+        reader.consume(used);
+
+        if done {
+            break;
+        }
+    }
+
+    match too_long {
+        Some(len) => Err(TransportError::FrameTooLong { len }),
+        None => Ok(buffer.len() - start),
+    }
+}
+
+/// Reads in a row that time out before a connection counts as stalled,
+/// about two seconds at the default read timeout
+///
+/// Counted in reads rather than time, so a longer read timeout also takes
+/// proportionally longer to notice a stall, and a shorter one sooner.
+const STALL_TIMEOUTS: u32 = 20;
+
+/// Wait before the first retry to open a port that is not available
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(250);
+/// Longest wait between retries, the wait doubles after every failed attempt up to this
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Most frames skipped in a row while resynchronizing, before giving up on a read
+const MAX_RESYNC_FRAMES: u64 = 32;
+
+/// Number of bytes sampled to detect the framing
+const FRAMING_SAMPLE_SIZE: usize = 512;
+/// Longest time spent sampling to detect the framing
+const FRAMING_SAMPLE_DURATION: Duration = Duration::from_secs(1);
+
+/// Protocol version of the original packet layout
+const PROTOCOL_V0: u8 = 0;
+/// Protocol version with a `u32` length trailer, for packets longer than a `u16` can count
+const PROTOCOL_V1: u8 = 1;
+
+/// Split the little endian length trailer of `width` bytes off the end of a frame
+///
+/// The trailer counts the whole frame including itself, so a mismatch means
+/// bytes were lost or the frame was cut short.
+fn split_length_trailer(buffer: &[u8], width: usize) -> Result<&[u8], PacketReadError> {
+    let (packet, trailer) = buffer.split_at(buffer.len().saturating_sub(width));
+
+    if trailer.len() != width {
+        return Err(PacketReadError::BadPacketLength {
+            expected: None,
+            got: packet.len(),
+        });
+    }
+
+    let mut length = [0; size_of::<u64>()];
+    length[..width].copy_from_slice(trailer);
+    let length = usize::try_from(u64::from_le_bytes(length))
+        .unwrap_or(usize::MAX)
+        .saturating_sub(width);
+
+    if length != packet.len() {
+        debug!(frame = %HexDump(buffer), "length trailer does not match the packet");
+
+        return Err(PacketReadError::BadPacketLength {
+            expected: Some(length),
+            got: packet.len(),
+        });
+    }
+
+    Ok(packet)
+}
+
+/// Decode the type section of a packet, rejecting anything that is not a
+/// printable UTF-8 string instead of letting it become a junk `Unknown` type
+fn parse_metric_type(ty: &[u8]) -> Result<String, PacketReadError> {
+    match std::str::from_utf8(ty) {
+        Ok(string) if !string.chars().any(char::is_control) => Ok(string.to_owned()),
+        _ => Err(PacketReadError::InvalidMetricType { ty: Box::from(ty) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::mpsc::channel};
+
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    /// A worker that is never spawned, to decode packets with
+    fn worker(config: SerialWorkerConfig) -> SerialWorker {
+        let (packet_tx, _) = channel();
+        let (_, command_rx) = channel();
+
+        SerialWorker {
+            port_name: Arc::from("test"),
+            config,
+            packet_tx,
+            command_rx,
+            state: Arc::new(RwLock::new(SerialWorkerState::Connected)),
+            retain_raw: Arc::default(),
+            dropped_frames: Arc::default(),
+            length_deltas: Arc::default(),
+            packet_counts: Arc::default(),
+            latency: Arc::default(),
+            detected_framing: Arc::default(),
+            recording: Arc::default(),
+            recorder: None,
+            csv_log: None,
+            reconnect_at: Arc::default(),
+            deferred_command: None,
+            consecutive_timeouts: 0,
+            warned_unknown_version: false,
+            repaint: Box::new(|| {}),
+        }
+    }
+
+    /// Frame a packet body like the firmware does: a length trailer counting
+    /// itself, then COBS encoded and ended by the delimiter
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut decoded = body.to_vec();
+        decoded.extend_from_slice(&(body.len() as u16 + 2).to_le_bytes());
+
+        let mut frame = postcard_cobs::encode_vec(&decoded);
+        frame.push(0);
+        frame
+    }
+
+    /// The body of a v0 packet with the default field separator
+    fn body(timestamp: u32, name: &str, ty: &str, value: &[u8]) -> Vec<u8> {
+        let mut body = timestamp.to_le_bytes().to_vec();
+        body.extend_from_slice(name.as_bytes());
+        body.push(0);
+        body.extend_from_slice(ty.as_bytes());
+        body.push(0);
+        body.extend_from_slice(value);
+        body
+    }
+
+    fn read(config: SerialWorkerConfig, stream: &[u8]) -> Result<Packet, PacketReadError> {
+        worker(config).read_packet(&mut Cursor::new(stream), &mut Vec::new())
+    }
+
+    #[test]
+    fn reads_u32_metric() {
+        let stream = frame(&body(1234, "motor:speed", "u32", &42u32.to_le_bytes()));
+
+        let Ok(Packet::Metric(metric)) = read(SerialWorkerConfig::default(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.timestamp.timestamp(), 1234);
+        assert_eq!(metric.name.to_string(), "motor:speed");
+        assert_eq!(metric.value, MetricValue::One(OneValue::U32(42)));
+    }
+
+    #[test]
+    fn retains_raw_value_bytes_when_asked() {
+        let mut worker = worker(SerialWorkerConfig::default());
+        worker.retain_raw.store(true, Ordering::Relaxed);
+
+        for (ty, value) in [
+            ("u16", 0x1234_u16.to_le_bytes().to_vec()),
+            ("f32", 1.0_f32.to_le_bytes().to_vec()),
+            ("[i8]", vec![0xff, 0x01, 0x80]),
+            ("str", b"idle".to_vec()),
+        ] {
+            let stream = frame(&body(0, "a", ty, &value));
+
+            let Ok(Packet::Metric(metric)) =
+                worker.read_packet(&mut Cursor::new(stream), &mut Vec::new())
+            else {
+                panic!("expected a {ty} metric");
+            };
+
+            assert_eq!(metric.raw.as_deref(), Some(&value[..]), "raw bytes of {ty}");
+        }
+    }
+
+    fn names_read(config: SerialWorkerConfig, names: &[&str]) -> Vec<String> {
+        let mut worker = worker(config);
+
+        names
+            .iter()
+            .map(|name| {
+                let stream = frame(&body(0, name, "u8", &[1]));
+
+                let Ok(Packet::Metric(metric)) =
+                    worker.read_packet(&mut Cursor::new(stream), &mut Vec::new())
+                else {
+                    panic!("expected a metric named {name}");
+                };
+
+                metric.name.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lowercasing_merges_names_that_differ_in_case() {
+        let config = SerialWorkerConfig {
+            lowercase_names: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            names_read(config, &["Motor:Speed", "motor:SPEED", "motor:speed"]),
+            ["motor:speed", "motor:speed", "motor:speed"]
+        );
+    }
+
+    #[test]
+    fn names_keep_their_case_by_default() {
+        assert_eq!(
+            names_read(
+                SerialWorkerConfig::default(),
+                &["Motor:Speed", "motor:speed"]
+            ),
+            ["Motor:Speed", "motor:speed"]
+        );
+    }
+
+    #[test]
+    fn drops_raw_value_bytes_by_default() {
+        let stream = frame(&body(0, "a", "u16", &[0x34, 0x12]));
+
+        let Ok(Packet::Metric(metric)) = read(SerialWorkerConfig::default(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.raw, None);
+    }
+
+    #[test]
+    fn rejects_wrong_length_trailer() {
+        let mut decoded = body(0, "a", "u8", &[1]);
+        decoded.extend_from_slice(&100u16.to_le_bytes());
+        let mut stream = postcard_cobs::encode_vec(&decoded);
+        stream.push(0);
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &stream),
+            Err(PacketReadError::BadPacketLength {
+                expected: Some(98),
+                got: 10,
+            })
+        ));
+    }
+
+    /// Frame a packet body like [`frame`], with a CRC16 over the packet and its
+    /// length trailer appended after the trailer
+    fn frame_crc16(body: &[u8]) -> Vec<u8> {
+        let mut decoded = body.to_vec();
+        decoded.extend_from_slice(&(body.len() as u16 + 2).to_le_bytes());
+        decoded.extend_from_slice(&crc16_ccitt(&decoded).to_le_bytes());
+
+        let mut frame = postcard_cobs::encode_vec(&decoded);
+        frame.push(0);
+        frame
+    }
+
+    fn crc16() -> SerialWorkerConfig {
+        SerialWorkerConfig {
+            crc16: true,
+            ..SerialWorkerConfig::default()
         }
     }
+
+    #[test]
+    fn reads_packet_with_valid_checksum() {
+        let stream = frame_crc16(&body(5, "a", "u16", &0x1234_u16.to_le_bytes()));
+
+        let Ok(Packet::Metric(metric)) = read(crc16(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.timestamp.timestamp(), 5);
+        assert_eq!(metric.value, MetricValue::One(OneValue::U16(0x1234)));
+    }
+
+    #[test]
+    fn rejects_packet_with_flipped_bit() {
+        let mut decoded = body(5, "a", "u16", &0x1234_u16.to_le_bytes());
+        decoded.extend_from_slice(&(decoded.len() as u16 + 2).to_le_bytes());
+        let expected = crc16_ccitt(&decoded);
+        decoded.extend_from_slice(&expected.to_le_bytes());
+
+        // The low bit of the value, after the timestamp, name and type sections
+        decoded[10] ^= 0x01;
+        let got = crc16_ccitt(&decoded[..decoded.len() - 2]);
+
+        let mut stream = postcard_cobs::encode_vec(&decoded);
+        stream.push(0);
+
+        assert_ne!(expected, got);
+        assert!(matches!(
+            read(crc16(), &stream),
+            Err(PacketReadError::BadChecksum { expected: e, got: g }) if e == expected && g == got
+        ));
+    }
+
+    #[test]
+    fn rejects_packet_too_short_for_a_checksum() {
+        let mut stream = postcard_cobs::encode_vec(&[0x42]);
+        stream.push(0);
+
+        assert!(matches!(
+            read(crc16(), &stream),
+            Err(PacketReadError::BadPacketLength {
+                expected: None,
+                got: 0,
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_missing_sections() {
+        let mut body = 0u32.to_le_bytes().to_vec();
+        body.extend_from_slice(b"only_a_name");
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &frame(&body)),
+            Err(PacketReadError::PoorLayout { section: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_cobs() {
+        // A code byte pointing past the end of the frame, in every frame until giving up
+        let stream = [0x05, 0x01, 0x00].repeat(MAX_RESYNC_FRAMES as usize + 1);
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &stream),
+            Err(PacketReadError::Transport(TransportError::MalformedCOBS(_)))
+        ));
+    }
+
+    #[test]
+    fn resyncs_after_malformed_cobs() {
+        let mut stream = [0x05, 0x01, 0x00].repeat(2);
+        stream.extend(frame(&body(3, "a", "u8", &[7])));
+
+        let mut worker = worker(SerialWorkerConfig::default());
+
+        let Ok(Packet::Metric(metric)) =
+            worker.read_packet(&mut Cursor::new(stream), &mut Vec::new())
+        else {
+            panic!("expected the metric after the malformed frames");
+        };
+
+        assert_eq!(metric.name.to_string(), "a");
+        assert_eq!(metric.value, MetricValue::One(OneValue::U8(7)));
+        assert_eq!(worker.dropped_frames.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn rejects_packet_shorter_than_timestamp() {
+        let versioned = SerialWorkerConfig {
+            versioned_packets: true,
+            ..SerialWorkerConfig::default()
+        };
+
+        for len in 0..4 {
+            let packet = &[1; 4][..len];
+
+            assert!(matches!(
+                read(SerialWorkerConfig::default(), &frame(packet)),
+                Err(PacketReadError::PoorLayout { section: 0, .. })
+            ));
+            assert!(matches!(
+                read(versioned, &frame(&[&[PROTOCOL_V0][..], packet].concat())),
+                Err(PacketReadError::PoorLayout { section: 0, .. })
+            ));
+        }
+    }
+
+    /// Frame a packet body with the `u32` length trailer of [`PROTOCOL_V1`]
+    fn frame_v1(body: &[u8]) -> Vec<u8> {
+        let mut decoded = vec![PROTOCOL_V1];
+        decoded.extend_from_slice(body);
+        decoded.extend_from_slice(&(decoded.len() as u32 + 4).to_le_bytes());
+
+        let mut frame = postcard_cobs::encode_vec(&decoded);
+        frame.push(0);
+        frame
+    }
+
+    fn versioned() -> SerialWorkerConfig {
+        SerialWorkerConfig {
+            versioned_packets: true,
+            ..SerialWorkerConfig::default()
+        }
+    }
+
+    #[test]
+    fn decodes_v0_through_version_switch() {
+        let mut versioned_body = vec![PROTOCOL_V0];
+        versioned_body.extend(body(7, "a", "u8", &[3]));
+
+        let Ok(Packet::Metric(metric)) = read(versioned(), &frame(&versioned_body)) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.timestamp.timestamp(), 7);
+        assert_eq!(metric.value, MetricValue::One(OneValue::U8(3)));
+    }
+
+    #[test]
+    fn decodes_v1_through_version_switch() {
+        let values = (0..40_000_u32).map(|value| value as u8).collect::<Vec<_>>();
+        let stream = frame_v1(&body(9, "big", "[u8]", &values));
+
+        let Ok(Packet::Metric(metric)) = read(versioned(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.timestamp.timestamp(), 9);
+        assert_eq!(metric.name.to_string(), "big");
+        assert_eq!(
+            metric.value,
+            MetricValue::Many(ManyValues::U8(values.into()))
+        );
+    }
+
+    #[test]
+    fn v1_requires_a_u32_length_trailer() {
+        let mut versioned_body = vec![PROTOCOL_V1];
+        versioned_body.extend(body(9, "a", "u8", &[3]));
+
+        assert!(matches!(
+            read(versioned(), &frame(&versioned_body)),
+            Err(PacketReadError::BadPacketLength { .. })
+        ));
+    }
+
+    #[test]
+    fn decodes_unknown_version_as_v0_and_warns_once() {
+        let mut worker = worker(versioned());
+        let mut versioned_body = vec![7];
+        versioned_body.extend(body(7, "a", "u8", &[3]));
+
+        for _ in 0..3 {
+            let Ok(Packet::Metric(metric)) =
+                worker.read_packet(&mut Cursor::new(frame(&versioned_body)), &mut Vec::new())
+            else {
+                panic!("expected a metric");
+            };
+
+            assert_eq!(metric.name.to_string(), "a");
+            assert_eq!(metric.value, MetricValue::One(OneValue::U8(3)));
+            assert!(worker.warned_unknown_version);
+        }
+    }
+
+    #[test]
+    fn rejects_value_of_wrong_length() {
+        let stream = frame(&body(0, "a", "u32", &[1, 2, 3]));
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &stream),
+            Err(PacketReadError::MetricValue(MetricValueError::BadLength {
+                expected: 4,
+                got: 3,
+            }))
+        ));
+    }
+
+    #[test]
+    fn forward_stops_once_the_ui_dropped_its_receiver() {
+        let stream = frame(&body(0, "a", "u8", &[1]));
+        let packet = read(SerialWorkerConfig::default(), &stream).unwrap();
+
+        // The receivers of a test worker are dropped right away
+        assert!(!worker(SerialWorkerConfig::default()).forward(packet));
+    }
+
+    /// Spawn a worker reading from a pseudo terminal, whose receivers are
+    /// already dropped, and feed it a metric
+    #[cfg(unix)]
+    #[test]
+    fn worker_stops_once_the_ui_dropped_its_receiver() {
+        use std::{
+            io::Write,
+            thread,
+            time::{Duration, Instant},
+        };
+
+        use serialport::TTYPort;
+
+        let (mut robot, port) = TTYPort::pair().unwrap();
+        let port_name = Arc::<str>::from(port.name().unwrap());
+        let packet_counts = Arc::<Mutex<PacketCounts>>::default();
+
+        let handle = thread::spawn({
+            let packet_counts = Arc::clone(&packet_counts);
+
+            move || {
+                SerialWorker {
+                    port_name,
+                    packet_counts,
+                    ..worker(SerialWorkerConfig::default())
+                }
+                .spawn();
+            }
+        });
+        robot.write_all(&frame(&body(0, "a", "u8", &[1]))).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !handle.is_finished() {
+            assert!(Instant::now() < deadline, "worker kept running");
+            thread::sleep(Duration::from_millis(10));
+        }
+        handle.join().expect("worker panicked");
+
+        // The worker stopped on the forwarded metric, not for failing to open the port
+        assert_eq!(packet_counts.lock().unwrap().good, 1);
+    }
+
+    #[test]
+    fn forward_passes_metrics_to_the_ui() {
+        let stream = frame(&body(0, "a", "u8", &[1]));
+        let packet = read(SerialWorkerConfig::default(), &stream).unwrap();
+
+        let (packet_tx, packet_rx) = channel();
+        let mut worker = SerialWorker {
+            packet_tx,
+            ..worker(SerialWorkerConfig::default())
+        };
+
+        assert!(worker.forward(packet));
+        let Ok(Packet::Metric(metric)) = packet_rx.try_recv() else {
+            panic!("expected a metric");
+        };
+        assert_eq!(metric.name.to_string(), "a");
+    }
+
+    #[test]
+    fn read_timeout_without_a_port_applies_to_the_next_connection() {
+        let mut worker = worker(SerialWorkerConfig::default());
+        let mut reader = None;
+
+        worker.set_read_timeout(Duration::from_millis(7), &mut reader);
+
+        assert!(reader.is_none());
+        assert_eq!(worker.config.read_timeout, Duration::from_millis(7));
+        assert_eq!(worker.state(), SerialWorkerState::Connected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_timeout_reopens_the_open_port() {
+        let (_controller, device) =
+            serialport::TTYPort::pair().expect("failed to open a pseudo terminal");
+        let name = device.name().expect("pseudo terminal without a name");
+        // The worker opens the port exclusively, so no other handle may hold it
+        drop(device);
+
+        let mut worker = SerialWorker {
+            port_name: Arc::from(name),
+            ..worker(SerialWorkerConfig::default())
+        };
+        let mut reader = worker
+            .connect()
+            .map(|serial| BufReader::new(io::Cursor::new(Vec::new()).chain(serial)));
+        assert!(reader.is_some(), "failed to open the pseudo terminal");
+
+        worker.set_read_timeout(Duration::from_millis(7), &mut reader);
+
+        let reader = reader.as_mut().expect("port was not reopened");
+        assert_eq!(port(reader).timeout(), Duration::from_millis(7));
+        assert_eq!(worker.config.read_timeout, Duration::from_millis(7));
+        assert_eq!(worker.state(), SerialWorkerState::Connected);
+    }
+
+    #[test]
+    fn reads_with_non_default_framing() {
+        let framing = Framing {
+            frame_delimiter: 0xaa,
+            field_separator: b';',
+        };
+        let config = SerialWorkerConfig {
+            framing,
+            ..SerialWorkerConfig::default()
+        };
+
+        // The value holds both the delimiter and a zero, which must come through unchanged
+        let mut body = 9_u32.to_le_bytes().to_vec();
+        body.extend_from_slice(b"a;u32;");
+        body.extend_from_slice(&0x00aa_0000_u32.to_le_bytes());
+        body.extend_from_slice(&(body.len() as u16 + 2).to_le_bytes());
+
+        let mut stream = postcard_cobs::encode_vec_with_sentinel(&body, framing.frame_delimiter);
+        stream.push(framing.frame_delimiter);
+        assert!(!stream[..stream.len() - 1].contains(&framing.frame_delimiter));
+
+        let Ok(Packet::Metric(metric)) = read(config, &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.name.to_string(), "a");
+        assert_eq!(metric.timestamp, Timestamp::from_millis(9));
+        assert_eq!(metric.value, MetricValue::One(OneValue::U32(0x00aa_0000)));
+    }
+
+    /// The body of a v0 packet with raw bytes for the name and type
+    fn raw_body(name: &[u8], ty: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut body = 0_u32.to_le_bytes().to_vec();
+        for section in [name, ty] {
+            body.extend_from_slice(section);
+            body.push(0);
+        }
+        body.extend_from_slice(value);
+        body
+    }
+
+    #[test]
+    fn rejects_type_that_is_not_utf8() {
+        let stream = frame(&raw_body(b"a", &[0xff, 0xfe], &[1]));
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &stream),
+            Err(PacketReadError::InvalidMetricType { ty }) if *ty == [0xff, 0xfe]
+        ));
+    }
+
+    #[test]
+    fn rejects_type_with_control_characters() {
+        let stream = frame(&raw_body(b"a", b"u8\x07", &[1]));
+
+        assert!(matches!(
+            read(SerialWorkerConfig::default(), &stream),
+            Err(PacketReadError::InvalidMetricType { ty }) if *ty == *b"u8\x07"
+        ));
+    }
+
+    #[test]
+    fn keeps_printable_unknown_types() {
+        let stream = frame(&raw_body(b"a", "grüße".as_bytes(), &[1, 2]));
+
+        let Ok(Packet::Metric(metric)) = read(SerialWorkerConfig::default(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(
+            metric.value,
+            MetricValue::Unknown("grüße".to_owned(), Box::new([1, 2]))
+        );
+    }
+
+    #[test]
+    fn replaces_invalid_utf8_in_names() {
+        let stream = frame(&raw_body(&[b'a', 0xff], b"u8", &[1]));
+
+        let Ok(Packet::Metric(metric)) = read(SerialWorkerConfig::default(), &stream) else {
+            panic!("expected a metric");
+        };
+
+        assert_eq!(metric.name.to_string(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn unterminated_stream_is_too_long() {
+        let mut reader = Cursor::new(vec![0x01; 100]);
+        let mut buffer = Vec::new();
+
+        assert!(matches!(
+            read_frame(&mut reader, 0x00, &mut buffer, 64),
+            Err(TransportError::FrameTooLong { len: 100 })
+        ));
+        assert!(buffer.is_empty());
+        assert!(matches!(
+            read_frame(&mut reader, 0x00, &mut buffer, 64),
+            Ok(0)
+        ));
+    }
+
+    #[test]
+    fn resyncs_after_a_frame_that_is_too_long() {
+        let config = SerialWorkerConfig {
+            max_frame_length: 64,
+            ..SerialWorkerConfig::default()
+        };
+        let mut worker = worker(config);
+
+        let mut stream = vec![0x01; 100];
+        stream.extend(frame(&body(1, "lost", "u8", &[1])));
+        stream.extend(frame(&body(2, "kept", "u8", &[2])));
+        let mut reader = Cursor::new(stream);
+
+        assert!(matches!(
+            worker.read_packet(&mut reader, &mut Vec::new()),
+            Err(PacketReadError::Transport(
+                TransportError::FrameTooLong { .. }
+            ))
+        ));
+
+        let Ok(Packet::Metric(metric)) = worker.read_packet(&mut reader, &mut Vec::new()) else {
+            panic!("expected a metric after the frame that was too long");
+        };
+        assert_eq!(metric.name.to_string(), "kept");
+    }
 }
@@ -0,0 +1,213 @@
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PayloadParseError {
+    Empty,
+    /// A `0x` or `0b` prefix without any digits after it
+    MissingDigits {
+        token: String,
+    },
+    InvalidDigit {
+        token: String,
+        digit: char,
+    },
+    /// Hex bytes need two digits each, unless the token is a single digit
+    OddHexDigits {
+        token: String,
+    },
+    /// Binary tokens are a single byte of at most 8 digits
+    BinaryTooLong {
+        token: String,
+    },
+    /// A single byte was expected, such as for a command opcode
+    NotSingleByte {
+        count: usize,
+    },
+}
+
+impl Display for PayloadParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadParseError::Empty => write!(f, "no bytes given"),
+            PayloadParseError::MissingDigits { token } => write!(f, "{token} has no digits"),
+            PayloadParseError::InvalidDigit { token, digit } => {
+                write!(f, "'{digit}' is not a valid digit in {token}")
+            }
+            PayloadParseError::OddHexDigits { token } => {
+                write!(f, "{token} has an odd number of hex digits")
+            }
+            PayloadParseError::BinaryTooLong { token } => {
+                write!(f, "{token} does not fit in a byte")
+            }
+            PayloadParseError::NotSingleByte { count } => {
+                write!(f, "expected a single byte, got {count}")
+            }
+        }
+    }
+}
+
+/// Parse a payload written as hex or binary bytes, such as `0x01 0xFF 0x2A`,
+/// `01ff2a` or `0b1010, 0x10`
+///
+/// Tokens are separated by whitespace or commas. Hex tokens with or without
+/// `0x` are one byte for a single digit, or a byte per pair of digits.
+/// Binary tokens need the `0b` prefix and are always a single byte.
+pub fn parse_payload(input: &str) -> Result<Vec<u8>, PayloadParseError> {
+    let mut payload = Vec::new();
+
+    for token in input
+        .split(|char: char| char.is_whitespace() || char == ',')
+        .filter(|token| !token.is_empty())
+    {
+        let error_token = || token.to_owned();
+        let lowercase = token.to_ascii_lowercase();
+
+        if let Some(digits) = lowercase.strip_prefix("0b") {
+            if digits.is_empty() {
+                return Err(PayloadParseError::MissingDigits {
+                    token: error_token(),
+                });
+            }
+            if let Some(digit) = digits.chars().find(|digit| !matches!(digit, '0' | '1')) {
+                return Err(PayloadParseError::InvalidDigit {
+                    token: error_token(),
+                    digit,
+                });
+            }
+            if digits.len() > 8 {
+                return Err(PayloadParseError::BinaryTooLong {
+                    token: error_token(),
+                });
+            }
+
+            payload.push(u8::from_str_radix(digits, 2).expect("digits were validated"));
+            continue;
+        }
+
+        let digits = lowercase.strip_prefix("0x").unwrap_or(&lowercase);
+
+        if digits.is_empty() {
+            return Err(PayloadParseError::MissingDigits {
+                token: error_token(),
+            });
+        }
+        if let Some(digit) = digits.chars().find(|digit| !digit.is_ascii_hexdigit()) {
+            return Err(PayloadParseError::InvalidDigit {
+                token: error_token(),
+                digit,
+            });
+        }
+
+        match digits.len() {
+            1 => payload.push(u8::from_str_radix(digits, 16).expect("digits were validated")),
+            len if len % 2 == 0 => payload.extend((0..len).step_by(2).map(|i| {
+                u8::from_str_radix(&digits[i..i + 2], 16).expect("digits were validated")
+            })),
+            _ => {
+                return Err(PayloadParseError::OddHexDigits {
+                    token: error_token(),
+                })
+            }
+        }
+    }
+
+    if payload.is_empty() {
+        return Err(PayloadParseError::Empty);
+    }
+
+    Ok(payload)
+}
+
+/// Parse a single byte written the same way as a payload, such as `0x2A` or `0b101010`
+pub fn parse_byte(input: &str) -> Result<u8, PayloadParseError> {
+    match parse_payload(input)?.as_slice() {
+        &[byte] => Ok(byte),
+        bytes => Err(PayloadParseError::NotSingleByte { count: bytes.len() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_split_on_whitespace_and_commas() {
+        assert_eq!(parse_payload("0x01 0xFF 0x2A"), Ok(vec![0x01, 0xFF, 0x2A]));
+        assert_eq!(parse_payload("  01,ff ,\t2a\n"), Ok(vec![0x01, 0xFF, 0x2A]));
+        assert_eq!(parse_payload("0b1010, 0x10"), Ok(vec![0b1010, 0x10]));
+    }
+
+    #[test]
+    fn hex_with_and_without_prefix() {
+        assert_eq!(parse_payload("01ff2a"), Ok(vec![0x01, 0xFF, 0x2A]));
+        assert_eq!(parse_payload("0x01FF2A"), Ok(vec![0x01, 0xFF, 0x2A]));
+        assert_eq!(parse_payload("0XaB"), Ok(vec![0xAB]));
+        assert_eq!(parse_payload("f"), Ok(vec![0x0F]));
+        assert_eq!(parse_payload("0xf"), Ok(vec![0x0F]));
+    }
+
+    #[test]
+    fn binary_is_a_single_byte() {
+        assert_eq!(parse_payload("0b0"), Ok(vec![0]));
+        assert_eq!(parse_payload("0B11111111"), Ok(vec![0xFF]));
+        assert_eq!(
+            parse_payload("0b111111111"),
+            Err(PayloadParseError::BinaryTooLong {
+                token: "0b111111111".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn invalid_input_is_refused() {
+        assert_eq!(parse_payload(""), Err(PayloadParseError::Empty));
+        assert_eq!(parse_payload(" , "), Err(PayloadParseError::Empty));
+        assert_eq!(
+            parse_payload("0x"),
+            Err(PayloadParseError::MissingDigits {
+                token: "0x".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_payload("0b"),
+            Err(PayloadParseError::MissingDigits {
+                token: "0b".to_owned()
+            })
+        );
+        assert_eq!(
+            parse_payload("01 0xZZ"),
+            Err(PayloadParseError::InvalidDigit {
+                token: "0xZZ".to_owned(),
+                digit: 'z'
+            })
+        );
+        assert_eq!(
+            parse_payload("0b102"),
+            Err(PayloadParseError::InvalidDigit {
+                token: "0b102".to_owned(),
+                digit: '2'
+            })
+        );
+        assert_eq!(
+            parse_payload("0x123"),
+            Err(PayloadParseError::OddHexDigits {
+                token: "0x123".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn byte_needs_exactly_one() {
+        assert_eq!(parse_byte("0x2A"), Ok(0x2A));
+        assert_eq!(parse_byte(" 0b101010 "), Ok(0x2A));
+        assert_eq!(
+            parse_byte("0x2A2B"),
+            Err(PayloadParseError::NotSingleByte { count: 2 })
+        );
+        assert_eq!(
+            parse_byte("1 2 3"),
+            Err(PayloadParseError::NotSingleByte { count: 3 })
+        );
+        assert_eq!(parse_byte(""), Err(PayloadParseError::Empty));
+    }
+}
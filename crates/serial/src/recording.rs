@@ -0,0 +1,216 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::{
+    timestamp::Timestamp,
+    value::{MetricValue, MetricValueError},
+    Metric,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// How often buffered packets are written out to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest frame written to or read from a recording, well above any serial frame
+///
+/// Guards against allocating up to 4 GiB for the length of a corrupt or truncated file.
+const MAX_RECORDED_FRAME_LENGTH: usize = 16 * 1024 * 1024;
+
+/// A single packet as written to a recording, with everything needed to decode it again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedPacket {
+    /// Wall-clock time at which the packet was received, in nanoseconds since the unix epoch
+    pub arrival: i128,
+    pub timestamp: Timestamp,
+    pub name: String,
+    pub ty: String,
+    /// The value bytes exactly as they were received
+    pub value: Box<[u8]>,
+}
+
+impl RecordedPacket {
+    /// Record a decoded metric, `None` if its raw value bytes were not retained
+    pub fn from_metric(metric: &Metric) -> Option<Self> {
+        Some(Self {
+            arrival: metric.arrival.unix_timestamp_nanos(),
+            timestamp: metric.timestamp,
+            name: metric.name.to_string(),
+            ty: metric.value.ty().to_owned(),
+            value: metric.raw.clone()?,
+        })
+    }
+
+    /// Decode the recorded value again, as if the packet arrived at `arrival`
+    pub fn into_metric(self, arrival: OffsetDateTime) -> Result<Metric, MetricValueError> {
+        Ok(Metric {
+            timestamp: self.timestamp,
+            arrival,
+            name: self
+                .name
+                .parse()
+                .expect("metric name parsing must never fail"),
+            value: MetricValue::from_bytes(self.ty, &self.value)?,
+            raw: None,
+        })
+    }
+}
+
+/// Writes packets to a file as postcard frames, each prefixed with its length as a little endian `u32`
+///
+/// Packets are flushed to disk every [`FLUSH_INTERVAL`], so a crash only loses the last moments.
+pub struct Recorder {
+    file: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, now: Instant) -> io::Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            last_flush: now,
+        })
+    }
+
+    pub fn write(&mut self, packet: &RecordedPacket) -> io::Result<()> {
+        let frame = postcard::to_stdvec(packet)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if frame.len() > MAX_RECORDED_FRAME_LENGTH {
+            return Err(frame_too_long(frame.len()));
+        }
+        let length = u32::try_from(frame.len())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+        self.file.write_all(&length.to_le_bytes())?;
+        self.file.write_all(&frame)
+    }
+
+    /// Flush buffered packets to disk, at most once per [`FLUSH_INTERVAL`]
+    pub fn poll_flush(&mut self, now: Instant) -> io::Result<()> {
+        if now.saturating_duration_since(self.last_flush) < FLUSH_INTERVAL {
+            return Ok(());
+        }
+
+        self.last_flush = now;
+
+        self.file.flush()
+    }
+
+    /// Write out everything still buffered, the file is closed once the recorder is dropped
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Reads back the packets written by a [`Recorder`]
+pub struct Playback {
+    file: BufReader<File>,
+    frame: Vec<u8>,
+}
+
+impl Playback {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: BufReader::new(File::open(path)?),
+            frame: Vec::new(),
+        })
+    }
+
+    /// The next packet in the recording, `None` once the end of the file is reached
+    pub fn read(&mut self) -> io::Result<Option<RecordedPacket>> {
+        let mut length = [0; 4];
+
+        match self.file.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+
+        let length = u32::from_le_bytes(length) as usize;
+        if length > MAX_RECORDED_FRAME_LENGTH {
+            return Err(frame_too_long(length));
+        }
+
+        self.frame.resize(length, 0);
+        self.file.read_exact(&mut self.frame)?;
+
+        postcard::from_bytes(&self.frame)
+            .map(Some)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+fn frame_too_long(length: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "recorded frame of {length} bytes is longer than {MAX_RECORDED_FRAME_LENGTH} bytes"
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use kestrel_metric::value::OneValue;
+
+    use super::*;
+
+    fn packet(timestamp: u32) -> RecordedPacket {
+        RecordedPacket {
+            arrival: 0,
+            timestamp: Timestamp::from_millis(timestamp),
+            name: "a".to_owned(),
+            ty: "u8".to_owned(),
+            value: Box::new([7]),
+        }
+    }
+
+    #[test]
+    fn flushed_packets_survive_without_finishing() {
+        let path = env::temp_dir().join(format!("kestrel-recording-{}.bin", process::id()));
+        let start = Instant::now();
+
+        let mut recorder = Recorder::create(&path, start).unwrap();
+        recorder.write(&packet(1)).unwrap();
+        recorder.write(&packet(2)).unwrap();
+
+        // Too soon to flush, nothing reached the file yet
+        recorder.poll_flush(start).unwrap();
+        assert_eq!(Playback::open(&path).unwrap().read().unwrap(), None);
+
+        recorder.poll_flush(start + FLUSH_INTERVAL).unwrap();
+
+        // Read back while the recorder is still open, as after a crash
+        let mut playback = Playback::open(&path).unwrap();
+        assert_eq!(playback.read().unwrap(), Some(packet(1)));
+        assert_eq!(playback.read().unwrap(), Some(packet(2)));
+        assert_eq!(playback.read().unwrap(), None);
+
+        drop(recorder);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn recorded_packet_decodes_again() {
+        let metric = packet(5).into_metric(OffsetDateTime::UNIX_EPOCH).unwrap();
+
+        assert_eq!(metric.value, MetricValue::One(OneValue::U8(7)));
+        assert_eq!(metric.timestamp, Timestamp::from_millis(5));
+    }
+
+    #[test]
+    fn corrupt_frame_length_is_refused() {
+        let path = env::temp_dir().join(format!("kestrel-corrupt-{}.bin", process::id()));
+        std::fs::write(&path, [0xff, 0xff, 0xff, 0xff, 0x01]).unwrap();
+
+        let err = Playback::open(&path).unwrap().read().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError, SendError, Sender},
+        Arc, PoisonError, RwLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use super::{recording::Playback, MetricSource, Packet, SerialWorkerState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReplayCommand {
+    Pause,
+    Resume,
+    /// Play the recording again from the start
+    Restart,
+    Shutdown,
+}
+
+/// How playback continues after waiting for the next packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    Restart,
+    Exit,
+}
+
+struct ReplayWorker {
+    path: PathBuf,
+    speed: f32,
+    packet_tx: Sender<Packet>,
+    command_rx: Receiver<ReplayCommand>,
+    state: Arc<RwLock<SerialWorkerState>>,
+    repaint: Box<dyn Fn()>,
+}
+
+impl ReplayWorker {
+    /// Run the worker until the controller is dropped
+    fn spawn(self) {
+        loop {
+            match self.play() {
+                Flow::Continue => {}
+                Flow::Restart => continue,
+                Flow::Exit => return,
+            }
+
+            info!("replay finished");
+            self.set_state(SerialWorkerState::Disconnected);
+
+            // Wait for a restart once the recording has finished
+            loop {
+                match self.command_rx.recv() {
+                    Ok(ReplayCommand::Restart) => break,
+                    Ok(ReplayCommand::Shutdown) | Err(_) => return,
+                    Ok(ReplayCommand::Pause | ReplayCommand::Resume) => {}
+                }
+            }
+        }
+    }
+
+    /// Play the recording from the start, until it ends or a command interrupts it
+    fn play(&self) -> Flow {
+        let mut playback = match Playback::open(&self.path) {
+            Ok(playback) => playback,
+            Err(err) => {
+                warn!(%err, path = %self.path.display(), "failed to open recording");
+                return Flow::Continue;
+            }
+        };
+
+        self.set_state(SerialWorkerState::Connected);
+
+        let mut previous_arrival = None;
+
+        loop {
+            let packet = match playback.read() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return Flow::Continue,
+                Err(err) => {
+                    warn!(%err, "failed to read recording, stopping replay");
+                    return Flow::Continue;
+                }
+            };
+
+            let delay = previous_arrival.map_or(Duration::ZERO, |previous| {
+                self.delay(packet.arrival - previous)
+            });
+            previous_arrival = Some(packet.arrival);
+
+            match self.wait(delay) {
+                Flow::Continue => {}
+                flow => return flow,
+            }
+
+            match packet.into_metric(OffsetDateTime::now_utc()) {
+                Ok(metric) => {
+                    if self.packet_tx.send(Packet::Metric(metric)).is_err() {
+                        info!("ui thread has exited, stopping replay worker");
+                        return Flow::Exit;
+                    }
+
+                    self.repaint();
+                }
+                Err(err) => warn!(?err, "failed to decode recorded metric"),
+            }
+        }
+    }
+
+    /// Time to wait between two packets that arrived `nanos` apart, scaled by the speed
+    fn delay(&self, nanos: i128) -> Duration {
+        let nanos = u64::try_from(nanos).unwrap_or(if nanos < 0 { 0 } else { u64::MAX });
+
+        Duration::try_from_secs_f64(
+            Duration::from_nanos(nanos).as_secs_f64() / f64::from(self.speed),
+        )
+        .unwrap_or(Duration::MAX)
+    }
+
+    /// Sleep for `delay` while handling commands, the clock stops while paused
+    fn wait(&self, delay: Duration) -> Flow {
+        let mut remaining = delay;
+
+        loop {
+            let start = Instant::now();
+
+            match self.command_rx.recv_timeout(remaining) {
+                Err(RecvTimeoutError::Timeout) => return Flow::Continue,
+                Err(RecvTimeoutError::Disconnected) | Ok(ReplayCommand::Shutdown) => {
+                    return Flow::Exit
+                }
+                Ok(ReplayCommand::Restart) => return Flow::Restart,
+                Ok(ReplayCommand::Resume) => {}
+                Ok(ReplayCommand::Pause) => {
+                    self.set_state(SerialWorkerState::Detached);
+
+                    loop {
+                        match self.command_rx.recv() {
+                            Ok(ReplayCommand::Resume) => break,
+                            Ok(ReplayCommand::Restart) => return Flow::Restart,
+                            Ok(ReplayCommand::Shutdown) | Err(_) => return Flow::Exit,
+                            Ok(ReplayCommand::Pause) => {}
+                        }
+                    }
+
+                    self.set_state(SerialWorkerState::Connected);
+                }
+            }
+
+            remaining = remaining.saturating_sub(start.elapsed());
+        }
+    }
+
+    fn repaint(&self) {
+        (self.repaint)()
+    }
+
+    fn set_state(&self, state: SerialWorkerState) {
+        *self.state.write().unwrap_or_else(PoisonError::into_inner) = state;
+
+        self.repaint();
+    }
+}
+
+/// Plays back a recorded session with its original timing, as a stand-in for a live robot
+///
+/// Detaching pauses the replay and resetting starts it over from the beginning.
+pub struct ReplayWorkerController {
+    port_name: Arc<str>,
+
+    state: Arc<RwLock<SerialWorkerState>>,
+    command_tx: Sender<ReplayCommand>,
+    packet_rx: Receiver<Packet>,
+}
+
+impl ReplayWorkerController {
+    /// Replay the recording at `path`, `speed` times faster than it was recorded
+    pub fn spawn(
+        path: PathBuf,
+        speed: f32,
+        repaint: Box<impl Fn() + Send + 'static>,
+    ) -> io::Result<ReplayWorkerController> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "replay speed must be a positive number",
+            ));
+        }
+
+        // Fail early on a recording that can not be opened, instead of only in the worker
+        Playback::open(&path)?;
+
+        let (packet_tx, packet_rx) = channel();
+        let (command_tx, command_rx) = channel();
+
+        let state = Arc::new(RwLock::new(SerialWorkerState::Disconnected));
+        let port_name = Arc::from(format!("replay of {}", path.display()).into_boxed_str());
+
+        thread::Builder::new().name("replay_worker".into()).spawn({
+            let state = Arc::clone(&state);
+
+            move || {
+                ReplayWorker {
+                    path,
+                    speed,
+
+                    packet_tx,
+                    command_rx,
+
+                    state,
+
+                    repaint,
+                }
+                .spawn()
+            }
+        })?;
+
+        Ok(Self {
+            packet_rx,
+            command_tx,
+
+            port_name,
+            state,
+        })
+    }
+
+    fn command(&self, command: ReplayCommand) {
+        if let Err(SendError(command)) = self.command_tx.send(command) {
+            warn!(?command, "replay worker has exited, dropping command");
+        }
+    }
+}
+
+impl MetricSource for ReplayWorkerController {
+    fn port_name(&self) -> &str {
+        self.port_name.as_ref()
+    }
+
+    fn state(&self) -> SerialWorkerState {
+        *self.state.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn new_packets(&self) -> Box<dyn Iterator<Item = Packet> + '_> {
+        Box::new(self.packet_rx.try_iter())
+    }
+
+    fn detach(&self) {
+        self.command(ReplayCommand::Pause);
+    }
+
+    fn attach(&self) {
+        self.command(ReplayCommand::Resume);
+    }
+
+    fn reset(&self) {
+        self.command(ReplayCommand::Restart);
+    }
+}
+
+impl Drop for ReplayWorkerController {
+    fn drop(&mut self) {
+        self.command(ReplayCommand::Shutdown);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use kestrel_metric::timestamp::Timestamp;
+
+    use super::*;
+    use crate::recording::{RecordedPacket, Recorder};
+
+    /// Generous bound on how long the worker may take to get to something it should do right away
+    const PATIENCE: Duration = Duration::from_secs(2);
+
+    /// Write a recording with a packet per arrival, in nanoseconds, timestamped with its index
+    fn record(test: &str, arrivals: &[i128]) -> PathBuf {
+        let path = env::temp_dir().join(format!("kestrel-replay-{}-{test}.bin", process::id()));
+
+        let mut recorder = Recorder::create(&path, Instant::now()).unwrap();
+        for (index, &arrival) in arrivals.iter().enumerate() {
+            recorder
+                .write(&RecordedPacket {
+                    arrival,
+                    timestamp: Timestamp::from_millis(index as u32),
+                    name: "a".to_owned(),
+                    ty: "u8".to_owned(),
+                    value: Box::new([7]),
+                })
+                .unwrap();
+        }
+        recorder.finish().unwrap();
+
+        path
+    }
+
+    /// Timestamps of the next `count` metrics, cut short after [`PATIENCE`]
+    fn receive(source: &dyn MetricSource, count: usize) -> Vec<Timestamp> {
+        let deadline = Instant::now() + PATIENCE;
+        let mut timestamps = Vec::new();
+
+        while timestamps.len() < count && Instant::now() < deadline {
+            timestamps.extend(source.new_metrics().map(|metric| metric.timestamp));
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        timestamps
+    }
+
+    fn wait_for_state(source: &dyn MetricSource, state: SerialWorkerState) {
+        let deadline = Instant::now() + PATIENCE;
+
+        while source.state() != state {
+            assert!(Instant::now() < deadline, "still {:?}", source.state());
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn pause_holds_back_packets_until_resumed() {
+        let path = record("pause", &[0, 300_000_000]);
+        let replay = ReplayWorkerController::spawn(path.clone(), 1.0, Box::new(|| {})).unwrap();
+
+        assert_eq!(receive(&replay, 1), [Timestamp::from_millis(0)]);
+
+        replay.detach();
+        wait_for_state(&replay, SerialWorkerState::Detached);
+
+        // Well past when the second packet was due
+        thread::sleep(Duration::from_millis(500));
+        assert_eq!(replay.new_metrics().count(), 0);
+
+        replay.attach();
+        assert_eq!(receive(&replay, 1), [Timestamp::from_millis(1)]);
+
+        drop(replay);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn restart_plays_the_recording_again() {
+        let path = record("restart", &[0, 0]);
+        let replay = ReplayWorkerController::spawn(path.clone(), 1.0, Box::new(|| {})).unwrap();
+
+        let all = [Timestamp::from_millis(0), Timestamp::from_millis(1)];
+        assert_eq!(receive(&replay, 2), all);
+        wait_for_state(&replay, SerialWorkerState::Disconnected);
+
+        replay.reset();
+        assert_eq!(receive(&replay, 2), all);
+
+        drop(replay);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn speed_scales_the_time_between_packets() {
+        let path = record("speed", &[0, 1_000_000_000]);
+        let replay = ReplayWorkerController::spawn(path.clone(), 10.0, Box::new(|| {})).unwrap();
+
+        assert_eq!(receive(&replay, 1), [Timestamp::from_millis(0)]);
+        let first = Instant::now();
+
+        assert_eq!(receive(&replay, 1), [Timestamp::from_millis(1)]);
+        let elapsed = first.elapsed();
+
+        // A second apart as recorded, a tenth of that at ten times the speed
+        assert!(
+            (Duration::from_millis(50)..Duration::from_millis(500)).contains(&elapsed),
+            "second packet after {elapsed:?}"
+        );
+
+        drop(replay);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn speed_must_be_positive() {
+        for speed in [0.0, -1.0, f32::NAN, f32::INFINITY] {
+            let result = ReplayWorkerController::spawn(PathBuf::new(), speed, Box::new(|| {}));
+
+            assert!(
+                matches!(&result, Err(err) if err.kind() == io::ErrorKind::InvalidInput),
+                "speed {speed} was accepted"
+            );
+        }
+    }
+}
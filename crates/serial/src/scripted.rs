@@ -0,0 +1,123 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use kestrel_metric::{Metric, RobotCommand};
+
+use super::{MetricSource, Packet, SendCommandError, SerialWorkerState, SystemMessage};
+
+/// A [`MetricSource`] without hardware, which hands out the packets queued with
+/// [`ScriptedSource::push_metric`] and [`ScriptedSource::push_system_message`]
+/// on the next call that drains them
+///
+/// Commands are recorded instead of sent, so the UI logic can be driven and checked
+/// without a robot. Clones share their queues, so a clone kept aside can script the
+/// source handed to the UI.
+#[derive(Debug, Clone)]
+pub struct ScriptedSource {
+    port_name: Arc<str>,
+    state: Arc<Mutex<SerialWorkerState>>,
+    packets: Arc<Mutex<VecDeque<Packet>>>,
+    sent_commands: Arc<Mutex<Vec<u8>>>,
+    resets: Arc<Mutex<usize>>,
+}
+
+impl ScriptedSource {
+    /// A connected source without any queued packets
+    pub fn new(port_name: impl Into<Arc<str>>) -> Self {
+        Self {
+            port_name: port_name.into(),
+            state: Arc::new(Mutex::new(SerialWorkerState::Connected)),
+            packets: Arc::default(),
+            sent_commands: Arc::default(),
+            resets: Arc::default(),
+        }
+    }
+
+    pub fn push_metric(&self, metric: Metric) {
+        self.push_packet(Packet::Metric(metric));
+    }
+
+    pub fn push_system_message(&self, message: SystemMessage) {
+        self.push_packet(Packet::System(message));
+    }
+
+    fn push_packet(&self, packet: Packet) {
+        self.packets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push_back(packet);
+    }
+
+    pub fn set_state(&self, state: SerialWorkerState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+
+    /// Opcodes of every command sent so far, oldest first
+    pub fn sent_commands(&self) -> Vec<u8> {
+        self.sent_commands
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// How often the robot was asked to reset
+    pub fn resets(&self) -> usize {
+        *self.resets.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl MetricSource for ScriptedSource {
+    fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    fn state(&self) -> SerialWorkerState {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn new_packets(&self) -> Box<dyn Iterator<Item = Packet> + '_> {
+        let packets =
+            std::mem::take(&mut *self.packets.lock().unwrap_or_else(PoisonError::into_inner));
+
+        Box::new(packets.into_iter())
+    }
+
+    fn detach(&self) {
+        self.set_state(SerialWorkerState::Detached);
+    }
+
+    fn attach(&self) {
+        self.set_state(SerialWorkerState::Connected);
+    }
+
+    fn reset(&self) {
+        *self.resets.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+    }
+
+    fn send_command(&self, command: RobotCommand) {
+        self.send_raw_command(command as u8);
+    }
+
+    fn send_raw_command(&self, command: u8) {
+        self.sent_commands
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(command);
+    }
+
+    fn try_send_command(&self, command: RobotCommand) -> Result<(), SendCommandError> {
+        self.try_send_raw_command(command as u8)
+    }
+
+    fn try_send_raw_command(&self, command: u8) -> Result<(), SendCommandError> {
+        let state = self.state();
+        if !state.is_connected() {
+            return Err(SendCommandError::NotConnected(state));
+        }
+
+        self.send_raw_command(command);
+        Ok(())
+    }
+}
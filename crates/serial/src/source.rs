@@ -0,0 +1,104 @@
+use std::{path::PathBuf, time::Duration};
+
+use kestrel_metric::{Metric, RobotCommand};
+
+use super::{
+    DetectedFraming, LengthDeltas, Packet, PacketCounts, SendCommandError, SerialWorkerState,
+};
+
+/// Where the metrics shown in the UI come from, either a live serial port or a recording
+///
+/// Only receiving metrics and controlling the connection are required, everything
+/// that only makes sense for a live robot does nothing by default.
+pub trait MetricSource {
+    /// The port or file the metrics are read from
+    fn port_name(&self) -> &str;
+
+    fn state(&self) -> SerialWorkerState;
+
+    /// Metrics and system packets, such as command acknowledgements, received since
+    /// the last call, in the order they arrived
+    fn new_packets(&self) -> Box<dyn Iterator<Item = Packet> + '_>;
+
+    /// Metrics received since the last call, dropping any system packets in between
+    fn new_metrics(&self) -> Box<dyn Iterator<Item = Metric> + '_> {
+        Box::new(self.new_packets().filter_map(|packet| match packet {
+            Packet::Metric(metric) => Some(metric),
+            Packet::System(_) => None,
+        }))
+    }
+
+    fn detach(&self);
+
+    fn attach(&self);
+
+    fn reset(&self);
+
+    /// Fire-and-forget a command, dropped if the source can not receive commands
+    fn send_command(&self, _command: RobotCommand) {}
+
+    fn send_raw_command(&self, _command: u8) {}
+
+    fn try_send_command(&self, _command: RobotCommand) -> Result<(), SendCommandError> {
+        Err(SendCommandError::ReadOnly)
+    }
+
+    fn try_send_raw_command(&self, _command: u8) -> Result<(), SendCommandError> {
+        Err(SendCommandError::ReadOnly)
+    }
+
+    // The payload is boxed to hand it to a worker thread, which implementors may not need
+    #[allow(clippy::boxed_local)]
+    fn try_send_payload(&self, _payload: Box<[u8]>) -> Result<(), SendCommandError> {
+        Err(SendCommandError::ReadOnly)
+    }
+
+    fn set_retain_raw_bytes(&self, _retain: bool) {}
+
+    fn set_read_timeout(&self, _timeout: Duration) {}
+
+    fn measure_queue_latency(&self) {}
+
+    fn queue_latency(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Time left until the next attempt to open a port that is not available
+    fn reconnect_in(&self) -> Option<Duration> {
+        None
+    }
+
+    fn detected_framing(&self) -> Option<DetectedFraming> {
+        None
+    }
+
+    fn dropped_frames(&self) -> u64 {
+        0
+    }
+
+    fn length_deltas(&self) -> LengthDeltas {
+        LengthDeltas::default()
+    }
+
+    fn packet_counts(&self) -> PacketCounts {
+        PacketCounts::default()
+    }
+
+    fn clear_length_deltas(&self) {}
+
+    fn start_recording(&self, _path: PathBuf) {}
+
+    fn stop_recording(&self) {}
+
+    fn is_recording(&self) -> bool {
+        false
+    }
+
+    /// Append every metric received from now on to a CSV file of `timestamp,name,type,value`
+    /// rows, flushed every second
+    ///
+    /// The file is appended to and never rotated or truncated.
+    fn start_csv_log(&self, _path: PathBuf) {}
+
+    fn stop_csv_log(&self) {}
+}
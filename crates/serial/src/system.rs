@@ -0,0 +1,91 @@
+use kestrel_metric::{timestamp::Timestamp, value::MetricValueError};
+
+use super::error::PacketReadError;
+
+/// Metric type of the packets that carry a [`SystemPacket`] instead of a measurement
+///
+/// The metric name of such a packet selects the kind of system packet,
+/// and its value holds the contents.
+pub(crate) const SYSTEM_TYPE: &str = "sys";
+
+/// A message from the firmware about itself, rather than a measurement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SystemPacket {
+    /// The firmware received a command, sent as `ack` with the command byte as its value
+    CommandAck { command: u8 },
+    /// A log line, sent as `log` with the level byte followed by the UTF-8 message as its value
+    Log { level: LogLevel, message: Box<str> },
+    /// The firmware just started, sent as `reboot` without a value
+    Reboot,
+}
+
+/// Severity of a firmware log line, sent as a single byte
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    Error = 0,
+    Warn = 1,
+    Info = 2,
+    Debug = 3,
+    Trace = 4,
+}
+
+impl LogLevel {
+    pub const ALL: [Self; 5] = [
+        Self::Error,
+        Self::Warn,
+        Self::Info,
+        Self::Debug,
+        Self::Trace,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        Self::ALL.into_iter().find(|level| *level as u8 == byte)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+}
+
+impl SystemPacket {
+    /// Decode the value of a system packet of the given kind
+    pub(crate) fn decode(kind: &str, value: &[u8]) -> Result<Self, PacketReadError> {
+        match kind {
+            "ack" => match value {
+                &[command] => Ok(SystemPacket::CommandAck { command }),
+                _ => Err(PacketReadError::MetricValue(MetricValueError::BadLength {
+                    expected: 1,
+                    got: value.len(),
+                })),
+            },
+            "log" => match value {
+                &[level, ref message @ ..] => Ok(SystemPacket::Log {
+                    level: LogLevel::from_byte(level)
+                        .ok_or(PacketReadError::InvalidLogLevel { level })?,
+                    message: String::from_utf8_lossy(message).into(),
+                }),
+                [] => Err(PacketReadError::MetricValue(MetricValueError::BadLength {
+                    expected: 1,
+                    got: 0,
+                })),
+            },
+            "reboot" => Ok(SystemPacket::Reboot),
+            kind => Err(PacketReadError::UnknownSystemPacket {
+                kind: kind.to_owned(),
+            }),
+        }
+    }
+}
+
+/// A system packet together with the robot time it was sent at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemMessage {
+    pub timestamp: Timestamp,
+    pub packet: SystemPacket,
+}
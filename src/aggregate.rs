@@ -0,0 +1,134 @@
+use kestrel_metric::{name::MetricName, value::MetricValue};
+
+use crate::visualization::focused_metrics::as_plot_values;
+
+/// Reduces every sample of an array metric to a single number, so it can be plotted over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Sum,
+    Mean,
+    Min,
+    Max,
+    /// A single element of the array, missing for samples that are too short
+    Element(usize),
+}
+
+impl Aggregation {
+    pub const KINDS: [Aggregation; 5] = [
+        Aggregation::Sum,
+        Aggregation::Mean,
+        Aggregation::Min,
+        Aggregation::Max,
+        Aggregation::Element(0),
+    ];
+
+    pub fn label(self) -> String {
+        match self {
+            Aggregation::Sum => "sum".to_owned(),
+            Aggregation::Mean => "mean".to_owned(),
+            Aggregation::Min => "min".to_owned(),
+            Aggregation::Max => "max".to_owned(),
+            Aggregation::Element(index) => format!("[{index}]"),
+        }
+    }
+
+    /// Whether both are the same kind of aggregation, ignoring the element index
+    pub fn same_kind(self, other: Self) -> bool {
+        std::mem::discriminant(&self) == std::mem::discriminant(&other)
+    }
+
+    /// Name of the series holding the aggregate of `name`
+    pub fn derived_name(self, name: &MetricName) -> MetricName {
+        format!("{name}#{}", self.label())
+            .parse()
+            .expect("metric name parsing must never fail")
+    }
+
+    /// Reduce an array sample, `None` for values that are not numeric arrays,
+    /// or that have too few elements for anything but the sum
+    pub fn apply(self, value: &MetricValue) -> Option<f64> {
+        let mut values = as_plot_values(value)?;
+
+        match self {
+            Aggregation::Sum => Some(values.sum()),
+            Aggregation::Mean => {
+                let (sum, count) = values.fold((0.0, 0_usize), |(sum, count), value| {
+                    (sum + value, count + 1)
+                });
+
+                (count > 0).then(|| sum / count as f64)
+            }
+            Aggregation::Min => values.reduce(f64::min),
+            Aggregation::Max => values.reduce(f64::max),
+            Aggregation::Element(index) => values.nth(index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    fn array(values: &[i16]) -> MetricValue {
+        MetricValue::Many(ManyValues::I16(values.into()))
+    }
+
+    #[test]
+    fn reduces_an_array() {
+        let value = array(&[4, -2, 7, 3]);
+
+        assert_eq!(Aggregation::Sum.apply(&value), Some(12.0));
+        assert_eq!(Aggregation::Mean.apply(&value), Some(3.0));
+        assert_eq!(Aggregation::Min.apply(&value), Some(-2.0));
+        assert_eq!(Aggregation::Max.apply(&value), Some(7.0));
+        assert_eq!(Aggregation::Element(2).apply(&value), Some(7.0));
+    }
+
+    #[test]
+    fn element_index_bounds() {
+        let value = array(&[1, 2, 3]);
+
+        assert_eq!(Aggregation::Element(0).apply(&value), Some(1.0));
+        assert_eq!(Aggregation::Element(2).apply(&value), Some(3.0));
+        assert_eq!(Aggregation::Element(3).apply(&value), None);
+        assert_eq!(Aggregation::Element(usize::MAX).apply(&value), None);
+    }
+
+    #[test]
+    fn empty_array_only_has_a_sum() {
+        let value = array(&[]);
+
+        assert_eq!(Aggregation::Sum.apply(&value), Some(0.0));
+        assert_eq!(Aggregation::Mean.apply(&value), None);
+        assert_eq!(Aggregation::Min.apply(&value), None);
+        assert_eq!(Aggregation::Max.apply(&value), None);
+        assert_eq!(Aggregation::Element(0).apply(&value), None);
+    }
+
+    #[test]
+    fn non_numeric_values_are_not_reduced() {
+        let value = MetricValue::One(OneValue::Str("text".into()));
+
+        for aggregation in Aggregation::KINDS {
+            assert_eq!(aggregation.apply(&value), None);
+        }
+    }
+
+    #[test]
+    fn derived_names() {
+        let name = "motor.currents".parse().unwrap();
+
+        assert_eq!(
+            Aggregation::Mean.derived_name(&name).to_string(),
+            "motor.currents#mean"
+        );
+        assert_eq!(
+            Aggregation::Element(3).derived_name(&name).to_string(),
+            "motor.currents#[3]"
+        );
+        assert!(Aggregation::Element(1).same_kind(Aggregation::Element(4)));
+        assert!(!Aggregation::Sum.same_kind(Aggregation::Mean));
+    }
+}
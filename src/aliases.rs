@@ -0,0 +1,52 @@
+use std::collections::BTreeMap;
+
+use eframe::{
+    egui::{RichText, WidgetText},
+    epaint::Color32,
+};
+use kestrel_metric::name::MetricName;
+use serde::{Deserialize, Serialize};
+
+/// User chosen display names of metrics, such as `distance` for `us:d`
+///
+/// Only changes how a metric is shown, the metric name itself stays the key
+/// for storage, focus and everything else.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Aliases {
+    aliases: BTreeMap<MetricName, String>,
+}
+
+impl Aliases {
+    /// The alias of a metric, `None` if it has none or it was cleared
+    pub fn get(&self, metric_name: &MetricName) -> Option<&str> {
+        self.aliases
+            .get(metric_name)
+            .map(|alias| alias.trim())
+            .filter(|alias| !alias.is_empty())
+    }
+
+    /// The alias of a metric for editing in place, empty if it has none
+    pub fn edit(&mut self, metric_name: &MetricName) -> &mut String {
+        self.aliases.entry(metric_name.clone()).or_default()
+    }
+
+    /// Forget the metrics whose alias was cleared
+    pub fn remove_empty(&mut self) {
+        self.aliases.retain(|_, alias| !alias.trim().is_empty());
+    }
+
+    /// The alias of a metric, or its name if it has none
+    pub fn display(&self, metric_name: &MetricName) -> String {
+        self.get(metric_name)
+            .map_or_else(|| metric_name.to_string(), str::to_owned)
+    }
+
+    /// The alias of a metric in the color of metric names, or its highlighted name if it has none
+    pub fn label(&self, metric_name: &MetricName) -> WidgetText {
+        match self.get(metric_name) {
+            Some(alias) => RichText::new(alias).color(Color32::GOLD).into(),
+            None => metric_name.into(),
+        }
+    }
+}
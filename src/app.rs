@@ -1,280 +1,2433 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    net::SocketAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 use eframe::{
-    egui::{self, Button, CentralPanel, Context, Grid, RichText, TopBottomPanel, Window},
+    egui::{
+        self, Align2, Button, CentralPanel, Checkbox, CollapsingHeader, ComboBox, Context,
+        DragValue, Grid, Key, KeyboardShortcut, Modifiers, RichText, SelectableLabel, Slider,
+        TextEdit, TopBottomPanel, Ui, Vec2, Window,
+    },
     epaint::Color32,
     App,
 };
 use kestrel_metric::{
-    name::MetricName, timestamp::Timestamp, value::MetricValue, Metric, RobotCommand,
+    hex::HexDump,
+    metric_name,
+    name::MetricName,
+    timestamp::{Timestamp, TimestampUnit},
+    value::{MetricValue, OneValue},
+    Metric, RobotCommand,
+};
+use kestrel_serial::{
+    parse_byte, parse_payload, DetectedFraming, MetricSource, Packet, SerialWorkerConfig,
+    SerialWorkerController, SerialWorkerState, SystemMessage, SystemPacket,
 };
-use kestrel_serial::{SerialWorkerController, SerialWorkerState};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
 
 use crate::{
+    aggregate::Aggregation,
+    aliases::Aliases,
+    auto_clear::AutoClear,
+    auto_hide::StaleAutoHide,
+    autosave::Autosave,
+    command_ack::CommandAcks,
+    command_list::NamedCommand,
+    command_macro::{CommandMacro, MacroPlayback, MacroRecorder},
+    confirmation::{Confirmations, GuardedAction},
+    diagnostics::{save_bundle_dialog, DiagnosticsBundle},
+    export::{
+        save_csv_dialog, save_recording_dialog, write_aligned_csv, write_metric_samples_csv,
+        write_metrics_csv, Interpolation,
+    },
+    favorites::{toggle_focus, Favorites},
+    firmware_log::{log_metric, FirmwareLog},
+    formula::Formula,
+    influx::InfluxSink,
+    link_quality::LinkQuality,
+    mqtt::MqttPublisher,
     new_metric_ring_buffer,
+    pause_clock::PauseClock,
+    ports::PortSession,
+    profile::{ConnectionProfile, PortMatcher},
+    prometheus::PrometheusExporter,
+    rate_limit::{RateDecision, RateLimiter},
+    recent_changes::RecentChanges,
+    runs::Runs,
+    session_summary::{ErrorCounts, SessionSummary, SessionTracker},
+    theme::ThemePreference,
+    type_change::{TypeChangePolicy, TypeChanges},
+    units::Units,
     version::GIT_VERSION,
     visualization::{
-        focused_metrics::focused_metrics_plot, latest_metrics::latest_metrics,
-        metrics_history::metrics_history, robot::robot,
+        array_profile::array_profile,
+        bool_waveforms::bool_waveforms,
+        category_lanes::category_lanes,
+        focused_metrics::{
+            as_plot_value, as_plot_values, focused_metrics_plot, ArrayPlotMode, PlotStyle,
+        },
+        format_cache::FormatCache,
+        histogram::HistogramView,
+        latest_metrics::{
+            group_label, latest_metrics, latest_metrics_header, latest_metrics_scroll_area,
+            matches_filter, namespace_group,
+        },
+        metric_explorer::{metric_explorer, MetricTree},
+        metric_stats::StatsCache,
+        metrics_history::metrics_history,
+        packet_inspector::PacketInspection,
+        plot_view::PlotView,
+        robot::{robot, SweepConfig},
+        spectrum::SpectrumView,
+        time_cursor::TimeCursor,
     },
+    websocket::MetricBroadcaster,
 };
 
 pub struct Application {
     pub pause_metrics: bool,
     pub show_visualization: bool,
     pub show_info: bool,
-    pub connect_the_dots: bool,
+    pub show_raw_bytes: bool,
+    pub show_profiles: bool,
+    pub show_explorer: bool,
+    pub histogram: HistogramView,
+    pub spectrum: SpectrumView,
+    pub firmware_log: FirmwareLog,
+    pub plot_style: PlotStyle,
+    pub plot_view: PlotView,
 
-    pub serial: SerialWorkerController,
+    pub serial: Box<dyn MetricSource>,
+    /// Metrics and reboots received while paused, kept in order until ingest resumes
+    pub paused_packets: VecDeque<Packet>,
+    /// Ports open next to `serial`, swapped in when selected
+    pub background_ports: Vec<PortSession>,
+    pub autosave: Option<Autosave>,
+    pub broadcaster: Option<MetricBroadcaster>,
+    pub influx: Option<InfluxSink>,
+    pub mqtt: Option<MqttPublisher>,
+    pub prometheus: Option<PrometheusExporter>,
+    /// Baud rate found by `--autobaud` on startup
+    pub detected_baud: Option<u32>,
+    /// CSV file every metric is logged to with `--csv`, kept when switching profiles
+    pub csv_log: Option<PathBuf>,
+    pub link_quality: LinkQuality,
+    /// Read timeout of the serial worker, adjustable while connected
+    pub read_timeout: Duration,
+    /// Address of the TCP control protocol, kept when switching profiles
+    pub control_addr: SocketAddr,
+    /// Offset of the control address of the shown port from `control_addr`,
+    /// as every open port has its own
+    pub control_offset: u16,
+    /// Longest COBS frame read, kept when switching profiles
+    pub max_frame_length: usize,
+    pub profiles: Vec<ConnectionProfile>,
+    pub active_profile: String,
+
+    pub macros: Vec<CommandMacro>,
+    pub macro_recorder: Option<MacroRecorder>,
+    pub macro_playback: Option<MacroPlayback>,
+    /// Hex or binary bytes typed in to send to the robot as-is
+    pub payload_input: String,
+    /// Opcode typed into the robot commands panel, for commands without a button
+    pub raw_command_input: String,
+    /// Commands loaded with `--commands`, sent by their raw opcode
+    pub named_commands: Vec<NamedCommand>,
+    pub command_acks: CommandAcks,
+    pub confirmations: Confirmations,
 
     pub current_time: Timestamp,
+    /// Robot timestamp of the first metric since connecting or rebooting, with its arrival time,
+    /// to convert the other timestamps into wall-clock time
+    pub wall_clock_anchor: Option<(Timestamp, OffsetDateTime)>,
+    /// A timestamp going backwards is a clock rollover instead of a reboot if,
+    /// counting through the wraparound, at most this much time has passed
+    pub rollover_window: Duration,
+    pub timestamp_unit: TimestampUnit,
+    pub time_cursor: TimeCursor,
+    pub pause_clock: PauseClock,
+    pub auto_clear: AutoClear,
+    pub sweep: SweepConfig,
+    pub runs: Runs,
 
     pub raw_metrics: AllocRingBuffer<Metric>,
-    pub sorted_metrics: BTreeMap<MetricName, AllocRingBuffer<(Timestamp, MetricValue)>>,
+    /// Capacity of `raw_metrics` and of every history in `sorted_metrics`
+    pub history_length: usize,
+    /// Total number of metrics pushed into `raw_metrics`, the sequence number
+    /// of the history table rows
+    ///
+    /// Samples the rate limiter folded into the newest one are not pushed, so
+    /// they are not counted here but in `RateLimiter::total_dropped`.
+    pub metrics_received: u64,
+    pub history_format_cache: FormatCache,
+    /// Packet opened from the history table in the inspector window
+    pub inspected_packet: Option<PacketInspection>,
+    pub sorted_metrics:
+        BTreeMap<MetricName, AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>>,
+    /// Metrics whose history filled up, so every new sample drops their oldest one
+    pub truncated_metrics: BTreeSet<MetricName>,
+    /// Timestamp of the newest sample of each metric whose value differs from the one before
+    pub value_changes: BTreeMap<MetricName, Timestamp>,
+    pub recent_changes: RecentChanges,
+    /// Statistics over each history in `sorted_metrics`, shown in the latest metrics table
+    pub stats_cache: StatsCache,
 
     pub hidden_metrics: BTreeSet<MetricName>,
+    /// Filter on the names in the latest metrics table, see [`matches_filter`]
+    pub metric_filter: String,
+    /// Namespace groups of the latest metrics table the user collapsed, `None` is the global group
+    pub collapsed_groups: BTreeSet<Option<String>>,
+    pub stale_auto_hide: StaleAutoHide,
     pub focused_metrics: BTreeSet<MetricName>,
+    pub favorites: Favorites,
+    /// Units shown next to the values of metrics, set in the metric explorer
+    pub units: Units,
+    /// Display names of metrics, set in the metric explorer
+    pub aliases: Aliases,
+    pub theme: ThemePreference,
+    /// Calculator expressions over the latest metric values
+    pub formulas: Vec<Formula>,
+    /// How the aligned export of the focused metrics fills in missing samples
+    pub export_interpolation: Interpolation,
+    /// Array metrics that also get a derived series of one number per sample
+    pub aggregations: BTreeMap<MetricName, Aggregation>,
+    /// Array metrics whose newest sample is plotted against the element index
+    pub array_profiles: BTreeSet<MetricName>,
+    /// Most elements drawn in an array profile, larger arrays are decimated
+    pub max_array_elements: usize,
+
+    /// Raw value bytes of the newest sample of each metric, while `show_raw_bytes` is enabled
+    pub latest_raw: BTreeMap<MetricName, Box<[u8]>>,
+
+    pub type_changes: TypeChanges,
+    pub type_change_policy: TypeChangePolicy,
+    pub rate_limiter: RateLimiter,
+
+    pub session: SessionTracker,
+    /// Summary of the last session, shown until dismissed
+    pub session_summary: Option<SessionSummary>,
 }
 
-impl App for Application {
-    fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
-        if !self.pause_metrics {
-            for metric in self.serial.new_metrics() {
-                // Clear data if the arduino has rebooted
-                if metric.timestamp < self.current_time {
-                    self.raw_metrics.clear();
-                    self.sorted_metrics.clear();
-                }
+/// Newest sample of every metric, borrowed from their histories
+///
+/// Shared by the UI and the integrations that only publish the current values,
+/// taking the histories rather than the application so it can be called while
+/// an integration is borrowed mutably.
+pub fn latest_values(
+    sorted_metrics: &BTreeMap<
+        MetricName,
+        AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>,
+    >,
+) -> impl Iterator<Item = (&MetricName, &(Timestamp, OffsetDateTime, MetricValue))> {
+    sorted_metrics
+        .iter()
+        .filter_map(|(name, history)| history.back().map(|newest| (name, newest)))
+}
 
-                // FIXME: TODO: tick clock when receiving no metrics
-                self.current_time = metric.timestamp;
+impl Application {
+    /// Newest sample of every metric, borrowed from their histories
+    pub fn latest_values(
+        &self,
+    ) -> impl Iterator<Item = (&MetricName, &(Timestamp, OffsetDateTime, MetricValue))> {
+        latest_values(&self.sorted_metrics)
+    }
 
-                self.sorted_metrics
-                    .entry(metric.name.clone())
-                    .or_insert_with(new_metric_ring_buffer)
-                    .push((metric.timestamp, metric.value.clone()));
+    /// Newest sample of a single metric
+    pub fn latest_value(
+        &self,
+        name: &MetricName,
+    ) -> Option<&(Timestamp, OffsetDateTime, MetricValue)> {
+        self.sorted_metrics.get(name)?.back()
+    }
 
-                self.raw_metrics.push(metric);
-            }
+    fn favorites_ui(&mut self, ui: &mut Ui) {
+        if self.favorites.is_empty() {
+            return;
         }
 
-        TopBottomPanel::top("serial_info").show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                ui.toggle_value(&mut self.show_info, "ℹ");
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Favorites:");
 
-                ui.separator();
+            for favorite in self.favorites.iter() {
+                let is_present = self.sorted_metrics.contains_key(favorite);
 
-                ui.label(format!("Serial port {}", self.serial.port_name()));
+                let response = ui
+                    .add_enabled(
+                        is_present,
+                        SelectableLabel::new(
+                            self.focused_metrics.contains(favorite),
+                            self.aliases.label(favorite),
+                        ),
+                    )
+                    .on_hover_text_at_pointer("Click to toggle focus")
+                    .on_disabled_hover_text("This metric has not been received yet");
 
-                ui.separator();
+                if response.clicked() {
+                    toggle_focus(&mut self.focused_metrics, favorite);
+                }
+            }
+        });
+    }
 
-                match self.serial.state() {
-                    SerialWorkerState::Detached => {
-                        if ui.button("Watch Serial").clicked() {
-                            self.serial.attach();
+    fn formulas_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Formulas:");
+
+            if ui
+                .button("Add Formula")
+                .on_hover_text_at_pointer(
+                    "Calculate a value from the latest metrics, using + - * / abs min and max",
+                )
+                .clicked()
+            {
+                self.formulas.push(Formula::default());
+            }
+        });
+
+        if self.formulas.is_empty() {
+            return;
+        }
+
+        let lookup = |metric_name: &MetricName| {
+            self.sorted_metrics
+                .get(metric_name)
+                .and_then(|history| history.back())
+                .map(|(_timestamp, _arrival, value)| value)
+        };
+        let mut to_remove = None;
+
+        Grid::new("formulas")
+            .striped(true)
+            .num_columns(3)
+            .show(ui, |ui| {
+                for (index, formula) in self.formulas.iter_mut().enumerate() {
+                    ui.add(
+                        TextEdit::singleline(&mut formula.source)
+                            .hint_text("abs(motor:left - motor:right)")
+                            .code_editor()
+                            .desired_width(240.0),
+                    );
+
+                    match formula.evaluate(&lookup) {
+                        Ok(value) => ui.monospace(value.to_string()),
+                        Err(err) => {
+                            ui.label(RichText::new(err.to_string()).color(Color32::LIGHT_RED))
                         }
+                    };
 
-                        ui.label(RichText::new("Ignoring Serial").color(Color32::RED));
+                    if ui
+                        .button("🗙")
+                        .on_hover_text_at_pointer("Remove this formula")
+                        .clicked()
+                    {
+                        to_remove = Some(index);
                     }
-                    SerialWorkerState::Connected => {
-                        if ui.button("Disconnect").clicked() {
-                            self.serial.detach();
+
+                    ui.end_row();
+                }
+            });
+
+        if let Some(index) = to_remove {
+            self.formulas.remove(index);
+        }
+    }
+
+    fn aggregations_ui(&mut self, ui: &mut Ui) {
+        let arrays = self
+            .sorted_metrics
+            .iter()
+            .filter(|(_, history)| {
+                history
+                    .back()
+                    .is_some_and(|(_, _, value)| as_plot_values(value).is_some())
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+
+        if arrays.is_empty() {
+            return;
+        }
+
+        ui.collapsing("Arrays", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Draw at most");
+                ui.add(
+                    DragValue::new(&mut self.max_array_elements)
+                        .clamp_range(2..=100_000)
+                        .suffix(" elements"),
+                )
+                .on_hover_text_at_pointer(
+                    "Larger arrays are decimated in the profile, exports keep every element",
+                );
+            });
+
+            Grid::new("aggregations")
+                .striped(true)
+                .num_columns(4)
+                .show(ui, |ui| {
+                    for name in &arrays {
+                        let mut profiled = self.array_profiles.contains(name);
+                        if ui
+                            .toggle_value(&mut profiled, "Profile")
+                            .on_hover_text_at_pointer(
+                                "Plot the newest sample against the element index",
+                            )
+                            .changed()
+                        {
+                            if profiled {
+                                self.array_profiles.insert(name.clone());
+                            } else {
+                                self.array_profiles.remove(name);
+                            }
                         }
 
-                        ui.label(RichText::new("Connected").color(Color32::GREEN));
+                        ui.label(name);
 
-                        ui.separator();
+                        let mut aggregation = self.aggregations.get(name).copied();
+
+                        ComboBox::from_id_source(("aggregation", name))
+                            .selected_text(
+                                aggregation
+                                    .map_or("none".to_owned(), |aggregation| aggregation.label()),
+                            )
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut aggregation, None, "none");
 
-                        ui.add_enabled_ui(
-                            self.serial.state() == SerialWorkerState::Connected,
-                            |ui| {
-                                if ui.button("Reset Arduino").clicked() {
-                                    self.serial.reset();
+                                for kind in Aggregation::KINDS {
+                                    let selected =
+                                        aggregation.is_some_and(|current| current.same_kind(kind));
+
+                                    if ui.selectable_label(selected, kind.label()).clicked()
+                                        && !selected
+                                    {
+                                        aggregation = Some(kind);
+                                    }
                                 }
-                            },
-                        );
-                    }
-                    SerialWorkerState::Disconnected => {
-                        if ui.button("Stop Waiting").clicked() {
-                            self.serial.detach();
+                            });
+
+                        if let Some(Aggregation::Element(index)) = &mut aggregation {
+                            ui.add(DragValue::new(index).prefix("index "));
+                        } else {
+                            ui.label("");
                         }
 
-                        ui.label(
-                            RichText::new("Waiting for serial port to become available")
-                                .color(Color32::YELLOW),
-                        );
+                        match aggregation {
+                            Some(aggregation) => {
+                                self.aggregations.insert(name.clone(), aggregation)
+                            }
+                            None => self.aggregations.remove(name),
+                        };
 
-                        ui.spinner();
+                        ui.end_row();
                     }
-                    SerialWorkerState::Resetting => {
-                        ui.label(RichText::new("Resetting").color(Color32::LIGHT_BLUE));
+                });
 
-                        ui.spinner();
-                    }
+            for name in arrays
+                .iter()
+                .filter(|name| self.array_profiles.contains(*name))
+            {
+                if let Some((_, _, value)) = self
+                    .sorted_metrics
+                    .get(name)
+                    .and_then(|history| history.back())
+                {
+                    array_profile(ui, name, value, self.max_array_elements, &self.aliases);
                 }
-            });
+            }
         });
+    }
 
-        TopBottomPanel::top("commands").show(ctx, |ui| {
-            ui.heading("Robot Commands");
-            ui.horizontal_wrapped(|ui| {
-                ui.label("Infrared");
-                if ui.button("Calibrate Ambient Measurements").clicked() {
-                    self.serial
-                        .send_command(RobotCommand::CalibrateAmbientInfrared);
-                }
-                if ui.button("Calibrate Reference Measurements").clicked() {
-                    self.serial
-                        .send_command(RobotCommand::CalibrateReferenceInfrared);
+    fn runs_ui(&mut self, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            let open_run = self.runs.open_run().map(|run| run.name.clone());
+
+            if ui.button("Start Run").clicked() {
+                self.runs.start(self.current_time);
+            }
+
+            if let Some(open_run) = open_run {
+                if ui.button("Stop Run").clicked() {
+                    self.runs.stop(self.current_time);
                 }
-            });
+
+                ui.label(RichText::new(format!("Recording {open_run}")).color(Color32::LIGHT_BLUE));
+            }
         });
 
-        CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal_wrapped(|ui| {
-                ui.heading(format!("Current time: {}", self.current_time));
+        if self.runs.is_empty() {
+            return;
+        }
 
-                if ui.button("Reset Metrics").clicked() {
-                    self.current_time = Timestamp::default();
-                    self.sorted_metrics.clear();
-                    self.raw_metrics.clear();
-                }
+        ui.collapsing("Runs", |ui| {
+            let mut to_remove = None;
 
-                ui.toggle_value(&mut self.show_visualization, "Show Visualization");
-                ui.toggle_value(&mut self.pause_metrics, "Pause metric ingest");
-            });
+            Grid::new("runs")
+                .striped(true)
+                .num_columns(4)
+                .show(ui, |ui| {
+                    for (index, run) in self.runs.iter_mut().enumerate() {
+                        ui.text_edit_singleline(&mut run.name);
+                        ui.monospace(match run.end {
+                            Some(end) => format!("{} - {end}", run.start),
+                            None => format!("{} - ...", run.start),
+                        });
 
-            ui.separator();
+                        if ui.button("Export").clicked() {
+                            if let Some(path) = save_csv_dialog(&format!("{}.csv", run.name)) {
+                                let metrics = run.metrics(self.raw_metrics.iter());
 
-            ui.heading(format!("{} Latest Metrics", self.sorted_metrics.len()));
-            ui.horizontal_wrapped(|ui| {
-                if ui.button("Reset Hidden").clicked() {
-                    self.hidden_metrics.clear();
-                }
-                ui.label("Hidden:");
+                                if let Err(err) = write_metrics_csv(&path, metrics) {
+                                    error!(?err, ?path, "failed to export run");
+                                }
+                            }
+                        }
 
-                let mut to_remove = Vec::new();
-                for hidden in &self.hidden_metrics {
-                    if ui
-                        .add(Button::new(hidden).small())
-                        .on_hover_text_at_pointer("Click to to unhide")
-                        .clicked()
-                    {
-                        to_remove.push(hidden.clone());
-                    };
-                }
+                        if ui
+                            .button("🗙")
+                            .on_hover_text_at_pointer("Remove this run")
+                            .clicked()
+                        {
+                            to_remove = Some(index);
+                        }
 
-                // Remove clicked items
-                for to_remove in to_remove {
-                    self.hidden_metrics.remove(&to_remove);
+                        ui.end_row();
+                    }
+                });
+
+            if let Some(index) = to_remove {
+                self.runs.remove(index);
+            }
+        });
+    }
+
+    /// Send a command to the robot, recording it if a macro is being recorded
+    fn send_command(&mut self, command: RobotCommand) {
+        match self.serial.try_send_command(command) {
+            Ok(()) => {
+                // Commands that were not sent are left out, so playback does not send them either
+                if let Some(recorder) = &mut self.macro_recorder {
+                    recorder.record(command, Instant::now());
                 }
+
+                self.command_acks
+                    .sent(command as u8, format!("{command:?}"), Instant::now());
+            }
+            Err(err) => {
+                warn!(?command, %err, "failed to send command");
+                self.command_acks
+                    .not_sent(format!("{command:?}"), err, Instant::now());
+            }
+        }
+    }
+
+    /// Send a single command byte to the robot, waiting for the firmware to acknowledge it
+    fn send_raw_command(&mut self, opcode: u8, label: String) {
+        match self.serial.try_send_raw_command(opcode) {
+            Ok(()) => self.command_acks.sent(opcode, label, Instant::now()),
+            Err(err) => {
+                warn!(opcode, %err, "failed to send command");
+                self.command_acks.not_sent(label, err, Instant::now());
+            }
+        }
+    }
+
+    /// Carry out an action, or hold it until confirmed if it requires confirmation
+    fn request_action(&mut self, action: GuardedAction) {
+        if let Some(action) = self.confirmations.request(action) {
+            self.dispatch_action(action);
+        }
+    }
+
+    fn dispatch_action(&mut self, action: GuardedAction) {
+        match action {
+            GuardedAction::Reset => self.serial.reset(),
+            GuardedAction::Command(command) => self.send_command(command),
+        }
+    }
+
+    fn confirmation_ui(&mut self, ctx: &Context) {
+        let Some(action) = self.confirmations.pending() else {
+            return;
+        };
+
+        let mut confirmed = false;
+        let mut cancelled = false;
+
+        Window::new("Are you sure?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                ui.label(format!("Really carry out {}?", action.label()));
+
+                ui.horizontal(|ui| {
+                    confirmed = ui.button("Confirm").clicked();
+                    cancelled = ui.button("Cancel").clicked();
+                });
             });
-            ui.horizontal_wrapped(|ui| {
-                if ui.button("Reset Focused").clicked() {
-                    self.focused_metrics.clear();
-                }
-                ui.label("Focused:");
 
-                let mut to_remove = Vec::new();
-                for focused in &self.focused_metrics {
+        if confirmed || cancelled {
+            self.answer_confirmation(confirmed);
+        }
+    }
+
+    /// Dispatch the action waiting for confirmation, or drop it if the user cancelled
+    fn answer_confirmation(&mut self, confirmed: bool) {
+        if !confirmed {
+            self.confirmations.cancel();
+        } else if let Some(action) = self.confirmations.confirm() {
+            self.dispatch_action(action);
+        }
+    }
+
+    /// Issue the commands of the playing macro that are due, stopping if the connection is lost
+    fn poll_macro_playback(&mut self, ctx: &Context) {
+        let Some(playback) = &mut self.macro_playback else {
+            return;
+        };
+
+        if !self.serial.state().is_connected() {
+            warn!(
+                command_macro = playback.name(),
+                "stopping macro playback, serial port is not connected"
+            );
+            self.macro_playback = None;
+            return;
+        }
+
+        let now = Instant::now();
+        while let Some(command) = playback.poll(now) {
+            self.serial.send_command(command);
+        }
+
+        match playback.time_until_next(now) {
+            Some(delay) => ctx.request_repaint_after(delay),
+            None => self.macro_playback = None,
+        }
+    }
+
+    fn macros_ui(&mut self, ui: &mut Ui) {
+        let is_connected = self.serial.state().is_connected();
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Macros");
+
+            match self.macro_recorder.take() {
+                Some(recorder) => {
+                    if ui.button("Stop Recording").clicked() {
+                        let name = format!("Macro {}", self.macros.len() + 1);
+                        self.macros.push(recorder.finish(name));
+                    } else {
+                        ui.label(
+                            RichText::new(format!("Recording ({} commands)", recorder.len()))
+                                .color(Color32::LIGHT_BLUE),
+                        );
+                        self.macro_recorder = Some(recorder);
+                    }
+                }
+                None => {
                     if ui
-                        .add(Button::new(focused).small())
-                        .on_hover_text_at_pointer("Click to to unfocus")
+                        .button("Record Macro")
+                        .on_hover_text_at_pointer("Record the commands sent from now on")
                         .clicked()
                     {
-                        to_remove.push(focused.clone());
+                        self.macro_recorder = Some(MacroRecorder::new(Instant::now()));
                     }
                 }
+            }
 
-                // Remove clicked items
-                for to_remove in to_remove {
-                    self.focused_metrics.remove(&to_remove);
+            if let Some(playback) = &self.macro_playback {
+                let (done, total) = playback.progress();
+
+                ui.label(
+                    RichText::new(format!("Playing {} ({done}/{total})", playback.name()))
+                        .color(Color32::LIGHT_BLUE),
+                );
+
+                if ui.button("Stop").clicked() {
+                    self.macro_playback = None;
                 }
-            });
-            let to_clear = latest_metrics(
-                ui,
-                self.current_time,
-                &mut self.focused_metrics,
-                &mut self.hidden_metrics,
-                self.sorted_metrics.iter().filter_map(|(name, history)| {
-                    history.back().map(|newest| (name, newest, history.len()))
-                }),
-            );
-            for to_clear in to_clear {
-                self.sorted_metrics.remove(&to_clear);
             }
+        });
 
-            ui.separator();
-            if self
-                .focused_metrics
-                .iter()
-                .all(|metric_name| !self.sorted_metrics.contains_key(metric_name))
-            {
-                ui.heading(format!("{} Historical Metrics", self.raw_metrics.len()));
+        if self.macros.is_empty() {
+            return;
+        }
 
-                metrics_history(ui, &self.raw_metrics)
-            } else {
-                ui.horizontal_wrapped(|ui| {
-                    ui.checkbox(&mut self.connect_the_dots, "Connect The Dots?")
-                        .on_hover_text_at_pointer(
-                            "Should lines be drawn between points on the plot",
-                        );
-                });
-                ui.collapsing("Plot Instructions", |ui| {
-                    ui.label("Pan by dragging, or scroll (+ shift = horizontal).");
-                    ui.label("Box zooming: Right click to zoom in and zoom out using a selection.");
-                    if cfg!(target_arch = "wasm32") {
-                        ui.label("Zoom with ctrl / ⌘ + pointer wheel, or with pinch gesture.");
-                    } else if cfg!(target_os = "macos") {
-                        ui.label("Zoom with ctrl / ⌘ + scroll.");
-                    } else {
-                        ui.label("Zoom with ctrl + scroll.");
+        ui.collapsing("Saved Macros", |ui| {
+            let mut to_remove = None;
+
+            Grid::new("macros")
+                .striped(true)
+                .num_columns(4)
+                .show(ui, |ui| {
+                    for (index, command_macro) in self.macros.iter_mut().enumerate() {
+                        ui.text_edit_singleline(&mut command_macro.name);
+                        ui.label(format!("{} commands", command_macro.steps.len()));
+
+                        if ui
+                            .add_enabled(
+                                is_connected && self.macro_playback.is_none(),
+                                Button::new("▶"),
+                            )
+                            .on_hover_text_at_pointer("Replay this macro")
+                            .on_disabled_hover_text(
+                                "Requires a connected robot and no macro playing",
+                            )
+                            .clicked()
+                        {
+                            self.macro_playback =
+                                Some(MacroPlayback::new(command_macro, Instant::now()));
+                        }
+
+                        if ui
+                            .button("🗙")
+                            .on_hover_text_at_pointer("Remove this macro")
+                            .clicked()
+                        {
+                            to_remove = Some(index);
+                        }
+
+                        ui.end_row();
                     }
-                    ui.label("Reset view with double-click.");
                 });
 
-                focused_metrics_plot(
-                    ui,
-                    self.focused_metrics.iter().filter_map(|metric_name| {
-                        self.sorted_metrics
-                            .get(metric_name)
-                            .map(|metric_values| (metric_name, metric_values.iter()))
-                    }),
-                    self.connect_the_dots,
-                );
+            if let Some(index) = to_remove {
+                self.macros.remove(index);
             }
         });
+    }
 
-        Window::new("Visualization")
-            .open(&mut self.show_visualization)
-            .frame(egui::Frame::dark_canvas(&ctx.style()))
-            .show(ctx, |ui| {
-                robot(ui, |metric_name| {
-                    self.sorted_metrics
-                        .get(&metric_name)
-                        .and_then(|metrics| metrics.back())
-                        .map(|(_timestamp, value)| value)
-                });
-            });
+    fn payload_ui(&mut self, ui: &mut Ui) {
+        let is_connected = self.serial.state().is_connected();
 
-        Window::new("Information")
-            .open(&mut self.show_info)
-            .resizable(false)
-            .default_width(280.0)
-            .show(ctx, |ui| {
-                ui.heading(env!("CARGO_PKG_NAME"));
-                ui.label(env!("CARGO_PKG_DESCRIPTION"));
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Raw bytes");
+            ui.add(
+                TextEdit::singleline(&mut self.payload_input)
+                    .hint_text("0x01 0xFF 0x2A")
+                    .desired_width(200.0),
+            );
 
-                ui.separator();
+            let payload = parse_payload(&self.payload_input);
 
-                Grid::new("version_information")
-                    .striped(true)
-                    .num_columns(2)
-                    .show(ui, |ui| {
-                        ui.label("cargo-version:");
-                        ui.label(env!("CARGO_PKG_VERSION"));
-                        ui.end_row();
+            match &payload {
+                Ok(payload) => {
+                    ui.monospace(format!("{} bytes: {}", payload.len(), HexDump(payload)));
+                }
+                Err(_) if self.payload_input.trim().is_empty() => {}
+                Err(err) => {
+                    ui.label(RichText::new(err.to_string()).color(Color32::RED));
+                }
+            }
+
+            let send = ui
+                .add_enabled(is_connected && payload.is_ok(), Button::new("Send"))
+                .on_hover_text_at_pointer("Send exactly these bytes to the robot")
+                .on_disabled_hover_text("Requires a connected robot and valid bytes")
+                .clicked();
+
+            if let (true, Ok(payload)) = (send, payload) {
+                if let Err(err) = self.serial.try_send_payload(payload.into_boxed_slice()) {
+                    warn!(%err, "failed to send payload");
+                }
+            }
+        });
+    }
+
+    fn connect_profile(&mut self, ctx: &Context, profile: ConnectionProfile) {
+        let Some(port) = profile.resolve_port() else {
+            warn!(
+                profile = profile.name,
+                "no serial port matches the connection profile"
+            );
+            return;
+        };
+
+        info!(profile = profile.name, port, "switching connection profile");
+
+        self.serial = Box::new(SerialWorkerController::spawn(
+            port,
+            SerialWorkerConfig {
+                control_addr: self.control_addr_at(self.control_offset),
+                max_frame_length: self.max_frame_length,
+                ..profile.config()
+            },
+            Box::new({
+                let ctx = ctx.clone();
+
+                move || ctx.request_repaint()
+            }),
+        ));
+        self.serial.set_retain_raw_bytes(self.show_raw_bytes);
+        self.serial.set_read_timeout(self.read_timeout);
+        if let Some(path) = &self.csv_log {
+            self.serial.start_csv_log(path.clone());
+        }
+
+        self.timestamp_unit = profile.timestamp_unit;
+        self.active_profile = profile.name;
+        self.detected_baud = None;
+
+        self.clear_metrics();
+    }
+
+    fn profiles_ui(&mut self, ui: &mut Ui) -> Option<ConnectionProfile> {
+        let mut to_connect = None;
+        let mut to_remove = None;
+        let names = self
+            .profiles
+            .iter()
+            .map(|profile| profile.name.clone())
+            .collect::<Vec<_>>();
+
+        for (index, profile) in self.profiles.iter_mut().enumerate() {
+            let is_active = profile.name == self.active_profile;
+
+            ui.horizontal(|ui| {
+                if ui.add_enabled(!is_active, Button::new("Connect")).clicked() {
+                    to_connect = Some(profile.clone());
+                }
+                if ui
+                    .add_enabled(!profile.is_default(), Button::new("🗙"))
+                    .on_hover_text_at_pointer("Delete this profile")
+                    .clicked()
+                {
+                    to_remove = Some(index);
+                }
+
+                // Edit a copy, so the name is only taken over once no other profile has it
+                let id = ui.id().with(("profile_name", index));
+                let mut name = ui
+                    .data_mut(|data| data.get_temp::<String>(id))
+                    .unwrap_or_else(|| profile.name.clone());
+
+                ui.add_enabled(
+                    !profile.is_default(),
+                    TextEdit::singleline(&mut name).desired_width(120.0),
+                );
+
+                let others = names
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(_, name)| name.as_str());
+                match ConnectionProfile::validate_name(&name, others) {
+                    _ if name == profile.name => ui.data_mut(|data| data.remove::<String>(id)),
+                    Ok(()) => {
+                        if is_active {
+                            self.active_profile.clone_from(&name);
+                        }
+                        profile.name = name;
+                        ui.data_mut(|data| data.remove::<String>(id));
+                    }
+                    Err(reason) => {
+                        ui.label(RichText::new(reason).color(Color32::RED));
+                        ui.data_mut(|data| data.insert_temp(id, name));
+                    }
+                }
+            });
+
+            ui.push_id(index, |ui| {
+                Grid::new("profile").num_columns(2).show(ui, |ui| {
+                    ui.label("Port");
+                    ui.horizontal(|ui| {
+                        let (is_name, value) = match &mut profile.port {
+                            PortMatcher::Name(name) => (true, name),
+                            PortMatcher::SerialNumber(serial_number) => (false, serial_number),
+                        };
+
+                        ui.text_edit_singleline(value);
+
+                        if ui.selectable_label(!is_name, "by serial number").clicked() {
+                            let value = std::mem::take(value);
+
+                            profile.port = if is_name {
+                                PortMatcher::SerialNumber(value)
+                            } else {
+                                PortMatcher::Name(value)
+                            };
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Baud rate");
+                    ui.add(DragValue::new(&mut profile.baud_rate));
+                    ui.end_row();
+
+                    ui.label("Timestamp unit");
+                    ui.horizontal(|ui| {
+                        for unit in [TimestampUnit::Millis, TimestampUnit::Micros] {
+                            ui.selectable_value(&mut profile.timestamp_unit, unit, unit.symbol());
+                        }
+                    });
+                    ui.end_row();
+
+                    ui.label("Frame delimiter");
+                    ui.add(
+                        DragValue::new(&mut profile.frame_delimiter).hexadecimal(2, false, true),
+                    );
+                    ui.end_row();
+
+                    ui.label("Field separator");
+                    ui.add(
+                        DragValue::new(&mut profile.field_separator).hexadecimal(2, false, true),
+                    );
+                    ui.end_row();
+
+                    ui.label("Lowercase names");
+                    ui.checkbox(&mut profile.lowercase_names, "")
+                        .on_hover_text_at_pointer("Merge metric names that only differ in case");
+                    ui.end_row();
+
+                    ui.label("Version byte");
+                    ui.checkbox(&mut profile.versioned_packets, "")
+                        .on_hover_text_at_pointer("Packets start with a protocol version byte");
+                    ui.end_row();
+
+                    ui.label("CRC16");
+                    ui.checkbox(&mut profile.crc16, "")
+                        .on_hover_text_at_pointer("Packets end with a CRC16 (CCITT) checksum");
+                    ui.end_row();
+
+                    ui.label("Detect framing");
+                    ui.checkbox(&mut profile.auto_detect_framing, "")
+                        .on_hover_text_at_pointer(
+                            "Find the frame delimiter from the first data received",
+                        );
+                    ui.end_row();
+                });
+            });
+
+            ui.separator();
+        }
+
+        if let Some(index) = to_remove {
+            self.profiles.remove(index);
+        }
+
+        if ui.button("New Profile").clicked() {
+            let mut profile = self
+                .profiles
+                .iter()
+                .find(|profile| profile.name == self.active_profile)
+                .or(self.profiles.first())
+                .cloned()
+                // Replaying without a port has no default profile to start from
+                .unwrap_or_else(|| {
+                    ConnectionProfile::default_profile(String::new(), SerialWorkerConfig::default())
+                });
+
+            profile.name = ConnectionProfile::unused_name(&self.profiles);
+            self.profiles.push(profile);
+        }
+
+        to_connect
+    }
+
+    /// Add a sample to its series, returning whether it replaced the newest sample instead
+    fn store_sample(
+        &mut self,
+        name: MetricName,
+        sample: (Timestamp, OffsetDateTime, MetricValue),
+        decision: RateDecision,
+    ) -> bool {
+        let series = self
+            .sorted_metrics
+            .entry(name.clone())
+            .or_insert_with(|| new_metric_ring_buffer(self.history_length));
+
+        if series
+            .back()
+            .map_or(true, |(_, _, latest)| *latest != sample.2)
+        {
+            self.value_changes.insert(name.clone(), sample.0);
+        }
+
+        // Over the rate, only keep the newest value visible without growing the history
+        if let (RateDecision::ReplaceLatest, Some(latest)) = (decision, series.back_mut()) {
+            *latest = sample;
+            return true;
+        }
+
+        if series.is_full() {
+            self.truncated_metrics.insert(name);
+        }
+        series.push(sample);
+
+        false
+    }
+
+    /// Add a metric to the collected metrics
+    pub fn ingest(&mut self, metric: Metric) {
+        // Clear data if the arduino has rebooted, but not when its clock merely rolled over
+        if metric.timestamp < self.current_time
+            && metric.timestamp.wrapping_since(self.current_time) > self.rollover_window
+        {
+            self.forget_robot_session();
+        }
+        self.wall_clock_anchor
+            .get_or_insert((metric.timestamp, metric.arrival));
+
+        self.session.record(&metric.name);
+
+        // FIXME: TODO: tick clock when receiving no metrics
+        self.current_time = metric.timestamp;
+
+        let (name, value) = self.type_changes.apply(
+            self.type_change_policy,
+            &metric.name,
+            &metric.value,
+            self.sorted_metrics.get_mut(&metric.name),
+        );
+
+        if let Some(raw) = &metric.raw {
+            self.latest_raw.insert(name.clone(), raw.clone());
+        }
+
+        let decision = self.rate_limiter.admit(&name, metric.arrival);
+        let derived = self.aggregations.get(&name).and_then(|aggregation| {
+            aggregation
+                .apply(&value)
+                .map(|derived| (aggregation.derived_name(&name), derived))
+        });
+
+        let replaced = self.store_sample(name, (metric.timestamp, metric.arrival, value), decision);
+        if let Some((derived_name, derived)) = derived {
+            self.store_sample(
+                derived_name,
+                (
+                    metric.timestamp,
+                    metric.arrival,
+                    MetricValue::One(OneValue::F64(derived)),
+                ),
+                decision,
+            );
+        }
+
+        // Not counted in `metrics_received`, which has to stay in step with `raw_metrics`
+        if replaced {
+            return;
+        }
+
+        self.raw_metrics.push(metric);
+        self.metrics_received += 1;
+    }
+
+    /// Ingest the metrics and system messages received since the last frame, from every open port
+    pub fn poll_source(&mut self) {
+        if !self.serial.state().is_connected() {
+            self.wall_clock_anchor = None;
+        }
+        let mut packets = std::mem::take(&mut self.paused_packets);
+        packets.extend(self.serial.new_packets());
+
+        for packet in packets {
+            match packet {
+                // Acknowledgements and log lines can not wait, but a reboot would clear
+                // the paused view, so it waits in order with the metrics around it
+                Packet::System(message)
+                    if self.pause_metrics && message.packet != SystemPacket::Reboot =>
+                {
+                    self.handle_system_message(message);
+                }
+                packet if self.pause_metrics => self.paused_packets.push_back(packet),
+                Packet::Metric(metric) => {
+                    if let Some(autosave) = &mut self.autosave {
+                        autosave.append(&metric);
+                    }
+                    if let Some(broadcaster) = &self.broadcaster {
+                        broadcaster.send(&metric);
+                    }
+                    if let Some(influx) = &self.influx {
+                        influx.send(&metric);
+                    }
+                    if let Some(mqtt) = &self.mqtt {
+                        mqtt.send(&metric);
+                    }
+
+                    self.ingest(metric);
+                }
+                Packet::System(message) => self.handle_system_message(message),
+            }
+        }
+
+        if !self.pause_metrics {
+            self.ingest_background_ports();
+        }
+    }
+
+    /// Ingest packets into the metrics shown, in the order they arrived, without the sinks
+    pub fn ingest_packets(&mut self, packets: impl IntoIterator<Item = Packet>) {
+        for packet in packets {
+            match packet {
+                Packet::Metric(metric) => self.ingest(metric),
+                Packet::System(message) => self.handle_system_message(message),
+            }
+        }
+    }
+
+    /// Follow the connection of the port shown, keeping the summary of a session that ended
+    pub fn poll_session_summary(&mut self) {
+        let summary = self
+            .session
+            .poll(self.serial.state(), OffsetDateTime::now_utc(), || {
+                ErrorCounts {
+                    length_mismatches: self.serial.length_deltas().total(),
+                    rate_limited: self.rate_limiter.total_dropped(),
+                }
+            });
+        if summary.is_some() {
+            self.session_summary = summary;
+        }
+    }
+
+    /// Drop the data of a robot session that ended, because the robot rebooted
+    fn forget_robot_session(&mut self) {
+        self.raw_metrics.clear();
+        self.sorted_metrics.clear();
+        self.truncated_metrics.clear();
+        self.value_changes.clear();
+        self.type_changes.clear();
+        self.runs.clear();
+        self.latest_raw.clear();
+        self.stats_cache.clear();
+        self.rate_limiter.clear();
+        self.wall_clock_anchor = None;
+
+        let freed = MetricName::collect_garbage();
+        if freed > 0 {
+            info!(freed, "freed the names of cleared metrics");
+        }
+    }
+
+    /// Handle a message the firmware sent about itself
+    pub fn handle_system_message(&mut self, message: SystemMessage) {
+        match message.packet {
+            SystemPacket::CommandAck { command } => {
+                self.command_acks.acknowledged(command, Instant::now());
+            }
+            SystemPacket::Log {
+                level,
+                message: text,
+            } => {
+                // Like metrics, log lines do not change the history while it is paused
+                if !self.pause_metrics {
+                    self.raw_metrics.push(log_metric(
+                        message.timestamp,
+                        OffsetDateTime::now_utc(),
+                        level,
+                        text.clone(),
+                    ));
+                    self.metrics_received += 1;
+                }
+
+                self.firmware_log.push(message.timestamp, level, text);
+            }
+            SystemPacket::Reboot => {
+                info!("robot reported a reboot");
+                self.forget_robot_session();
+                self.current_time = Timestamp::from_raw(0, self.timestamp_unit);
+            }
+        }
+    }
+
+    /// Port the shown metrics came from, only while several ports are open
+    fn shown_port(&self) -> Option<String> {
+        (!self.background_ports.is_empty()).then(|| self.serial.port_name().to_owned())
+    }
+
+    /// Port the shown metrics came from, for the table headings while several ports are open
+    fn port_suffix(&self) -> String {
+        self.shown_port()
+            .map(|port| format!(" from {port}"))
+            .unwrap_or_default()
+    }
+
+    /// Collect the recent packets, error counters, metric catalog and settings for a bug report
+    fn diagnostics_bundle(&self) -> serde_json::Result<DiagnosticsBundle> {
+        let mut bundle = DiagnosticsBundle::default();
+
+        bundle.add_text(
+            "version.txt",
+            format!(
+                "{} {}\ngit-version: {GIT_VERSION}\n",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ),
+        );
+
+        bundle.add_metrics("raw_frames.jsonl", self.raw_metrics.iter())?;
+
+        bundle.add_json(
+            "errors.json",
+            &serde_json::json!({
+                "length_mismatches": self
+                    .serial
+                    .length_deltas()
+                    .iter()
+                    .map(|(delta, count)| (delta.to_string(), count))
+                    .collect::<BTreeMap<_, _>>(),
+                "rate_limited": self.rate_limiter.total_dropped(),
+                "dropped_frames": self.serial.dropped_frames(),
+                "command_round_trip_us": self
+                    .command_acks
+                    .round_trip()
+                    .map(|round_trip| round_trip.as_micros()),
+                "worker_queue_latency_us": self
+                    .serial
+                    .queue_latency()
+                    .map(|latency| latency.as_micros()),
+            }),
+        )?;
+
+        bundle.add_json(
+            "catalog.json",
+            &self
+                .sorted_metrics
+                .iter()
+                .map(|(name, history)| {
+                    (
+                        name.to_string(),
+                        serde_json::json!({
+                            "type": history.back().map(|(_, _, value)| value.ty()),
+                            "samples": history.len(),
+                            "rate_limited": self.rate_limiter.dropped(name),
+                            "changed_types": self.type_changes.changed_types(name),
+                        }),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>(),
+        )?;
+
+        bundle.add_json(
+            "settings.json",
+            &serde_json::json!({
+                "profile": self
+                    .profiles
+                    .iter()
+                    .find(|profile| profile.name == self.active_profile),
+                "port": self.serial.port_name(),
+                "state": format!("{:?}", self.serial.state()),
+                "detected_framing": self
+                    .serial
+                    .detected_framing()
+                    .map(|framing| framing.describe()),
+                "read_timeout_ms": self.read_timeout.as_millis(),
+                "type_change_policy": self.type_change_policy.label(),
+                "rate_limit": self.rate_limiter.global_limit,
+                "rate_limit_overrides": self
+                    .rate_limiter
+                    .overrides
+                    .iter()
+                    .map(|(name, limit)| (name.to_string(), limit))
+                    .collect::<BTreeMap<_, _>>(),
+                "retain_raw_bytes": self.show_raw_bytes,
+                "paused": self.pause_metrics,
+            }),
+        )?;
+
+        Ok(bundle)
+    }
+
+    fn export_diagnostics(&self) {
+        let bundle = match self.diagnostics_bundle() {
+            Ok(bundle) => bundle,
+            Err(err) => {
+                error!(?err, "failed to collect diagnostics");
+                return;
+            }
+        };
+
+        if let Some(path) = save_bundle_dialog("kestrel-diagnostics.zip") {
+            if let Err(err) = bundle.save(&path) {
+                error!(?err, ?path, "failed to export diagnostics bundle");
+            }
+        }
+    }
+
+    /// Export the focused metrics as columns on a shared timestamp axis
+    fn export_focused_aligned(&self, interpolation: Interpolation) {
+        let Some(path) = save_csv_dialog("focused.csv") else {
+            return;
+        };
+
+        let (names, series): (Vec<_>, Vec<_>) = self
+            .focused_metrics
+            .iter()
+            .filter_map(|name| {
+                let samples = self
+                    .sorted_metrics
+                    .get(name)?
+                    .iter()
+                    .filter_map(|(timestamp, _, value)| {
+                        as_plot_value(value).map(|value| (timestamp.timestamp(), value))
+                    })
+                    .collect::<Vec<_>>();
+
+                Some((name.to_string(), samples))
+            })
+            .unzip();
+
+        if let Err(err) = write_aligned_csv(&path, &names, &series, interpolation) {
+            error!(?err, ?path, "failed to export focused metrics");
+        }
+    }
+
+    /// Handle the shortcuts for pausing, clearing the metrics and resetting the robot,
+    /// unless they are being typed into a text field
+    fn keyboard_shortcuts(&mut self, ctx: &Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+
+        if ctx.input_mut(|input| input.consume_shortcut(&PAUSE_SHORTCUT)) {
+            self.pause_metrics = !self.pause_metrics;
+        }
+        if ctx.input_mut(|input| input.consume_shortcut(&CLEAR_SHORTCUT)) {
+            self.clear_metrics();
+        }
+        if ctx.input_mut(|input| input.consume_shortcut(&RESET_SHORTCUT))
+            && self.serial.state().is_connected()
+        {
+            self.request_action(GuardedAction::Reset);
+        }
+    }
+
+    fn clear_metrics(&mut self) {
+        self.forget_robot_session();
+        self.current_time = Timestamp::from_raw(0, self.timestamp_unit);
+        self.auto_clear.reset(Instant::now());
+    }
+}
+
+/// Samples per second allowed when enabling the rate limit from the UI
+pub const DEFAULT_RATE_LIMIT: u32 = 100;
+
+const PAUSE_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::Space);
+const CLEAR_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::C);
+const RESET_SHORTCUT: KeyboardShortcut = KeyboardShortcut::new(Modifiers::NONE, Key::R);
+
+const FAVORITES_KEY: &str = "favorites";
+const PROFILES_KEY: &str = "connection_profiles";
+const MACROS_KEY: &str = "command_macros";
+const FORMULAS_KEY: &str = "formulas";
+const CONFIRMATIONS_KEY: &str = "confirmations";
+const HIDDEN_METRICS_KEY: &str = "hidden_metrics";
+const FOCUSED_METRICS_KEY: &str = "focused_metrics";
+const UNITS_KEY: &str = "metric_units";
+const ALIASES_KEY: &str = "metric_aliases";
+const THEME_KEY: &str = "theme";
+const LOG_SCALE_KEY: &str = "plot_log_scale";
+
+impl Application {
+    /// Restore the state persisted by [`App::save`]
+    pub fn restore(&mut self, storage: &dyn eframe::Storage) {
+        if let Some(favorites) = eframe::get_value(storage, FAVORITES_KEY) {
+            self.favorites = favorites;
+        }
+        if let Some(profiles) = eframe::get_value::<Vec<ConnectionProfile>>(storage, PROFILES_KEY) {
+            self.profiles
+                .extend(profiles.into_iter().filter(|profile| !profile.is_default()));
+        }
+        if let Some(macros) = eframe::get_value(storage, MACROS_KEY) {
+            self.macros = macros;
+        }
+        if let Some(formulas) = eframe::get_value::<Vec<String>>(storage, FORMULAS_KEY) {
+            self.formulas = formulas.into_iter().map(Formula::new).collect();
+        }
+        if let Some(required) = eframe::get_value(storage, CONFIRMATIONS_KEY) {
+            self.confirmations.required = required;
+        }
+        // Metrics that never show up again are simply never drawn or hidden
+        if let Some(hidden_metrics) = eframe::get_value(storage, HIDDEN_METRICS_KEY) {
+            self.hidden_metrics = hidden_metrics;
+        }
+        if let Some(units) = eframe::get_value(storage, UNITS_KEY) {
+            self.units = units;
+        }
+        if let Some(aliases) = eframe::get_value(storage, ALIASES_KEY) {
+            self.aliases = aliases;
+        }
+        if let Some(theme) = eframe::get_value(storage, THEME_KEY) {
+            self.theme = theme;
+        }
+        if let Some(focused_metrics) = eframe::get_value(storage, FOCUSED_METRICS_KEY) {
+            self.focused_metrics = focused_metrics;
+        }
+        if let Some(log_scale) = eframe::get_value(storage, LOG_SCALE_KEY) {
+            self.plot_style.log_scale = log_scale;
+        }
+    }
+}
+
+impl App for Application {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, FAVORITES_KEY, &self.favorites);
+        eframe::set_value(
+            storage,
+            PROFILES_KEY,
+            &self
+                .profiles
+                .iter()
+                .filter(|profile| !profile.is_default())
+                .collect::<Vec<_>>(),
+        );
+        eframe::set_value(storage, MACROS_KEY, &self.macros);
+        let formulas = self
+            .formulas
+            .iter()
+            .map(|formula| &formula.source)
+            .collect::<Vec<_>>();
+        eframe::set_value(storage, FORMULAS_KEY, &formulas);
+        eframe::set_value(storage, CONFIRMATIONS_KEY, &self.confirmations.required);
+        eframe::set_value(storage, HIDDEN_METRICS_KEY, &self.hidden_metrics);
+        eframe::set_value(storage, FOCUSED_METRICS_KEY, &self.focused_metrics);
+        self.units.remove_empty();
+        eframe::set_value(storage, UNITS_KEY, &self.units);
+        self.aliases.remove_empty();
+        eframe::set_value(storage, ALIASES_KEY, &self.aliases);
+        eframe::set_value(storage, THEME_KEY, &self.theme);
+        eframe::set_value(storage, LOG_SCALE_KEY, &self.plot_style.log_scale);
+    }
+
+    fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
+        self.time_cursor.begin_frame();
+        self.theme.apply(ctx, frame.info().system_theme);
+
+        if self.auto_clear.poll(Instant::now(), self.pause_metrics) {
+            self.clear_metrics();
+        }
+        if self.auto_clear.enabled {
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+
+        self.keyboard_shortcuts(ctx);
+        self.poll_macro_playback(ctx);
+        self.confirmation_ui(ctx);
+
+        self.poll_source();
+        if let Some(delay) = self.command_acks.poll(Instant::now()) {
+            ctx.request_repaint_after(delay);
+        }
+        self.command_acks.ui(ctx);
+        if let Some(autosave) = &mut self.autosave {
+            autosave.poll_flush(Instant::now());
+        }
+        if let Some(prometheus) = &mut self.prometheus {
+            prometheus.poll_render(Instant::now(), latest_values(&self.sorted_metrics));
+        }
+
+        self.poll_session_summary();
+
+        self.plot_view.observe_state(self.serial.state());
+        self.link_quality
+            .poll(Instant::now(), self.serial.packet_counts());
+
+        let display_time =
+            self.pause_clock
+                .now(self.current_time, self.pause_metrics, Instant::now());
+        if self.pause_metrics && self.pause_clock.advance_while_paused {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        TopBottomPanel::top("ports").show(ctx, |ui| {
+            self.ports_ui(ctx, ui);
+        });
+
+        TopBottomPanel::top("serial_info").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.toggle_value(&mut self.show_info, "ℹ");
+
+                ui.separator();
+
+                ui.toggle_value(&mut self.show_profiles, "Profiles")
+                    .on_hover_text_at_pointer("Switch between connection profiles");
+
+                ui.label(format!(
+                    "Serial port {} ({})",
+                    self.serial.port_name(),
+                    self.active_profile
+                ));
+
+                if let Some(baud) = self.detected_baud {
+                    ui.separator();
+                    ui.label(
+                        RichText::new(format!("Baud: {baud} (detected)")).color(Color32::GREEN),
+                    );
+                }
+
+                if let Some(framing) = self.serial.detected_framing() {
+                    ui.separator();
+
+                    let color = match framing {
+                        DetectedFraming::Cobs { .. } => Color32::GREEN,
+                        DetectedFraming::AsciiLines | DetectedFraming::Unknown => Color32::YELLOW,
+                    };
+
+                    ui.label(
+                        RichText::new(format!("Framing: {}", framing.describe())).color(color),
+                    );
+                }
+
+                if let Some(broadcaster) = &self.broadcaster {
+                    ui.separator();
+                    ui.label(format!(
+                        "WebSocket {} ({} clients)",
+                        broadcaster.addr(),
+                        broadcaster.client_count()
+                    ));
+                }
+
+                if let Some(influx) = &self.influx {
+                    ui.separator();
+
+                    let failed = influx.failed_batches();
+                    let dropped = influx.dropped_points();
+                    ui.label(RichText::new("InfluxDB").color(if failed == 0 && dropped == 0 {
+                        Color32::GREEN
+                    } else {
+                        Color32::YELLOW
+                    }))
+                    .on_hover_text_at_pointer(format!(
+                        "{}\n{failed} batches failed to send\n{dropped} points dropped while the queue was full",
+                        influx.url()
+                    ));
+                }
+
+                if let Some(mqtt) = &self.mqtt {
+                    ui.separator();
+
+                    ui.label(RichText::new("MQTT").color(if mqtt.is_connected() {
+                        Color32::GREEN
+                    } else {
+                        Color32::YELLOW
+                    }))
+                    .on_hover_text_at_pointer(format!(
+                        "{}\n{}",
+                        mqtt.url(),
+                        if mqtt.is_connected() {
+                            "connected"
+                        } else {
+                            "connecting"
+                        }
+                    ));
+                }
+
+                if let Some(prometheus) = &self.prometheus {
+                    ui.separator();
+                    ui.label(format!("Prometheus {}", prometheus.addr()));
+                }
+
+                if let Some(autosave) = &self.autosave {
+                    ui.separator();
+
+                    if autosave.is_active() {
+                        ui.label(RichText::new("Autosaving").color(Color32::GREEN))
+                            .on_hover_text_at_pointer(autosave.path().display().to_string());
+                    } else {
+                        ui.label(RichText::new("Autosave stopped").color(Color32::RED))
+                            .on_hover_text_at_pointer(
+                                "Writing to the autosave file failed, see the log",
+                            );
+                    }
+                }
+
+                ui.separator();
+
+                match self.serial.state() {
+                    SerialWorkerState::Detached => {
+                        if ui.button("Watch Serial").clicked() {
+                            self.serial.attach();
+                        }
+
+                        ui.label(RichText::new("Ignoring Serial").color(Color32::RED));
+                    }
+                    state @ (SerialWorkerState::Connected | SerialWorkerState::Stalled) => {
+                        if ui.button("Disconnect").clicked() {
+                            self.serial.detach();
+                        }
+
+                        if state == SerialWorkerState::Stalled {
+                            ui.label(RichText::new("Stalled").color(Color32::from_rgb(255, 165, 0)))
+                                .on_hover_text_at_pointer(
+                                    "The port is still open but nothing has been received \
+                                     for a while, the firmware may be frozen",
+                                );
+                        } else {
+                            ui.label(RichText::new("Connected").color(Color32::GREEN));
+                        }
+                        self.link_quality.ui(ui);
+
+                        ui.separator();
+
+                        ui.add_enabled_ui(state.is_connected(), |ui| {
+                            if ui
+                                .button("Reset Arduino")
+                                .on_hover_text_at_pointer(format!(
+                                    "Shortcut: {}",
+                                    ctx.format_shortcut(&RESET_SHORTCUT)
+                                ))
+                                .clicked()
+                            {
+                                self.request_action(GuardedAction::Reset);
+                            }
+                        });
+                    }
+                    SerialWorkerState::Disconnected => {
+                        if ui.button("Stop Waiting").clicked() {
+                            self.serial.detach();
+                        }
+
+                        ui.label(
+                            RichText::new("Waiting for serial port to become available")
+                                .color(Color32::YELLOW),
+                        );
+
+                        let spinner = ui.spinner();
+                        if let Some(reconnect_in) = self.serial.reconnect_in() {
+                            spinner.on_hover_text_at_pointer(format!(
+                                "retrying in {:.1}s",
+                                reconnect_in.as_secs_f32()
+                            ));
+                        }
+                    }
+                    SerialWorkerState::Resetting => {
+                        ui.label(RichText::new("Resetting").color(Color32::LIGHT_BLUE));
+
+                        ui.spinner();
+                    }
+                }
+            });
+        });
+
+        TopBottomPanel::top("commands").show(ctx, |ui| {
+            ui.heading("Robot Commands");
+            ui.horizontal_wrapped(|ui| {
+                ui.set_enabled(self.serial.state().is_connected());
+
+                ui.label("Infrared");
+                if ui.button("Calibrate Ambient Measurements").clicked() {
+                    self.request_action(GuardedAction::Command(
+                        RobotCommand::CalibrateAmbientInfrared,
+                    ));
+                }
+                if ui.button("Calibrate Reference Measurements").clicked() {
+                    self.request_action(GuardedAction::Command(
+                        RobotCommand::CalibrateReferenceInfrared,
+                    ));
+                }
+
+                if !self.named_commands.is_empty() {
+                    ui.label("Firmware");
+                    let mut to_send = None;
+                    for command in &self.named_commands {
+                        let button = ui.button(&command.name);
+                        let button = match &command.description {
+                            Some(description) => button.on_hover_text_at_pointer(format!(
+                                "{description}\nOpcode {:#04x}",
+                                command.opcode
+                            )),
+                            None => button.on_hover_text_at_pointer(format!(
+                                "Opcode {:#04x}",
+                                command.opcode
+                            )),
+                        };
+
+                        if button.clicked() {
+                            to_send = Some((command.opcode, command.name.clone()));
+                        }
+                    }
+                    if let Some((opcode, name)) = to_send {
+                        self.send_raw_command(opcode, name);
+                    }
+                }
+
+                ui.label("Opcode");
+                ui.add(
+                    TextEdit::singleline(&mut self.raw_command_input)
+                        .hint_text("0x02")
+                        .desired_width(50.0),
+                );
+
+                let opcode = parse_byte(&self.raw_command_input);
+                if let (Err(err), false) = (&opcode, self.raw_command_input.trim().is_empty()) {
+                    ui.label(RichText::new(err.to_string()).color(Color32::RED));
+                }
+
+                if ui
+                    .add_enabled(opcode.is_ok(), Button::new("Send Opcode"))
+                    .on_hover_text_at_pointer(
+                        "Send a single command byte, for opcodes that have no button yet",
+                    )
+                    .clicked()
+                {
+                    if let Ok(opcode) = opcode {
+                        self.send_raw_command(opcode, format!("opcode {opcode:#04x}"));
+                    }
+                }
+
+                ui.menu_button("Confirm…", |ui| {
+                    ui.label("Ask before");
+
+                    for action in GuardedAction::ALL {
+                        let mut required = self.confirmations.required.contains(&action);
+
+                        if ui.checkbox(&mut required, action.label()).changed() {
+                            self.confirmations.set_required(action, required);
+                        }
+                    }
+                })
+                .response
+                .on_hover_text_at_pointer("Choose which actions need to be confirmed");
+            });
+            self.macros_ui(ui);
+            self.payload_ui(ui);
+        });
+
+        CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.heading(format!("Current time: {display_time}"));
+
+                if ui
+                    .button("Reset Metrics")
+                    .on_hover_text_at_pointer(format!(
+                        "Shortcut: {}",
+                        ctx.format_shortcut(&CLEAR_SHORTCUT)
+                    ))
+                    .clicked()
+                {
+                    self.clear_metrics();
+                }
+
+                ui.toggle_value(&mut self.show_visualization, "Show Visualization");
+                ui.toggle_value(&mut self.show_explorer, "Metric Explorer")
+                    .on_hover_text_at_pointer("Browse the metrics grouped by namespace");
+                ui.toggle_value(&mut self.histogram.open, "Histogram")
+                    .on_hover_text_at_pointer("Show the distribution of a focused metric");
+                ui.toggle_value(&mut self.spectrum.open, "Spectrum")
+                    .on_hover_text_at_pointer("Show the frequency spectrum of a focused metric");
+                ui.toggle_value(&mut self.firmware_log.open, "Firmware Log")
+                    .on_hover_text_at_pointer("Show the log lines sent by the robot");
+                ui.toggle_value(&mut self.pause_metrics, "Pause metric ingest")
+                    .on_hover_text_at_pointer(format!(
+                        "Shortcut: {}",
+                        ctx.format_shortcut(&PAUSE_SHORTCUT)
+                    ));
+                ui.checkbox(
+                    &mut self.pause_clock.advance_while_paused,
+                    "Keep clock running while paused",
+                )
+                .on_hover_text_at_pointer(
+                    "Advance the current time with the wall clock while paused, so the age of the frozen metrics keeps growing",
+                );
+
+                ComboBox::from_label("On type change")
+                    .selected_text(self.type_change_policy.label())
+                    .show_ui(ui, |ui| {
+                        for policy in [TypeChangePolicy::Coerce, TypeChangePolicy::Split] {
+                            ui.selectable_value(
+                                &mut self.type_change_policy,
+                                policy,
+                                policy.label(),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text_at_pointer(
+                        "How to store samples of a metric that changes type mid-session",
+                    );
+
+                let mut rate_limited = self.rate_limiter.global_limit.is_some();
+                if ui
+                    .checkbox(&mut rate_limited, "Limit each metric to")
+                    .on_hover_text_at_pointer(
+                        "Drop samples of metrics arriving faster than this, keeping only the newest value",
+                    )
+                    .changed()
+                {
+                    self.rate_limiter.global_limit = rate_limited.then_some(DEFAULT_RATE_LIMIT);
+                }
+                if let Some(limit) = &mut self.rate_limiter.global_limit {
+                    ui.add(DragValue::new(limit).clamp_range(1..=10_000).suffix(" Hz"));
+                }
+
+                ui.checkbox(&mut self.session.enabled, "Summary on disconnect")
+                    .on_hover_text_at_pointer(
+                        "Show a wrap-up of the session when the robot disconnects",
+                    );
+
+                ui.checkbox(&mut self.auto_clear.enabled, "Auto-clear every");
+                ui.add(
+                    DragValue::new(&mut self.auto_clear.interval_minutes)
+                        .clamp_range(1..=24 * 60)
+                        .suffix(" min"),
+                );
+                if let Some(remaining) = self.auto_clear.remaining(Instant::now()) {
+                    let remaining = remaining.as_secs();
+
+                    ui.monospace(format!(
+                        "clearing in {:02}:{:02}",
+                        remaining / 60,
+                        remaining % 60
+                    ));
+                }
+            });
+
+            self.runs_ui(ui);
+
+            ui.separator();
+
+            self.favorites_ui(ui);
+            self.formulas_ui(ui);
+            self.aggregations_ui(ui);
+
+            ui.heading(format!(
+                "{} Latest Metrics{}",
+                self.sorted_metrics.len(),
+                self.port_suffix()
+            ));
+            ui.horizontal_wrapped(|ui| {
+                if ui.button("Reset Hidden").clicked() {
+                    self.hidden_metrics.clear();
+                }
+                ui.label("Hidden:");
+
+                let mut to_remove = Vec::new();
+                for hidden in &self.hidden_metrics {
+                    if ui
+                        .add(Button::new(hidden).small())
+                        .on_hover_text_at_pointer("Click to to unhide")
+                        .clicked()
+                    {
+                        to_remove.push(hidden.clone());
+                    };
+                }
+
+                // Remove clicked items
+                for to_remove in to_remove {
+                    self.hidden_metrics.remove(&to_remove);
+                }
+            });
+            ui.horizontal_wrapped(|ui| {
+                if ui.button("Reset Focused").clicked() {
+                    self.focused_metrics.clear();
+                }
+                ui.label("Focused:");
+
+                let mut to_remove = Vec::new();
+                let mut to_export = None;
+                for focused in &self.focused_metrics {
+                    let response = ui
+                        .add(Button::new(focused).small())
+                        .on_hover_text_at_pointer("Click to to unfocus, right click for more");
+
+                    if response.clicked() {
+                        to_remove.push(focused.clone());
+                    }
+
+                    response.context_menu(|ui| {
+                        if ui.button("Export samples as CSV").clicked() {
+                            to_export = Some(focused.clone());
+                            ui.close_menu();
+                        }
+                    });
+                }
+
+                // Remove clicked items
+                for to_remove in to_remove {
+                    self.focused_metrics.remove(&to_remove);
+                }
+
+                if let Some(name) = to_export {
+                    if let Some(path) = save_csv_dialog(&format!("{name}.csv")) {
+                        let samples = self.sorted_metrics.get(&name).into_iter().flatten();
+
+                        if let Err(err) = write_metric_samples_csv(&path, samples) {
+                            error!(?err, ?path, "failed to export metric samples");
+                        }
+                    }
+                }
+
+                if !self.focused_metrics.is_empty() {
+                    ui.separator();
+
+                    ComboBox::from_id_source("export_interpolation")
+                        .selected_text(self.export_interpolation.label())
+                        .show_ui(ui, |ui| {
+                            for interpolation in Interpolation::ALL {
+                                ui.selectable_value(
+                                    &mut self.export_interpolation,
+                                    interpolation,
+                                    interpolation.label(),
+                                );
+                            }
+                        });
+
+                    if ui
+                        .button("Export aligned CSV")
+                        .on_hover_text_at_pointer(
+                            "One row per timestamp with a column per focused metric",
+                        )
+                        .clicked()
+                    {
+                        self.export_focused_aligned(self.export_interpolation);
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.recent_changes.enabled, "Highlight changed within")
+                    .on_hover_text_at_pointer(
+                        "Highlight metrics whose value changed recently, instead of only being sent again",
+                    );
+                ui.add(
+                    DragValue::new(&mut self.recent_changes.window_secs)
+                        .clamp_range(0.1..=3600.0)
+                        .speed(0.1)
+                        .suffix(" s"),
+                );
+                ui.add_enabled(
+                    self.recent_changes.enabled,
+                    Checkbox::new(&mut self.recent_changes.only_changed, "Only changed"),
+                )
+                .on_hover_text_at_pointer("Hide the metrics that did not change recently");
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.stale_auto_hide.enabled, "Auto-hide stale after")
+                    .on_hover_text_at_pointer(
+                        "Move metrics that stopped updating to a separate section",
+                    );
+                ui.add(
+                    DragValue::new(&mut self.stale_auto_hide.timeout_secs)
+                        .clamp_range(0.1..=3600.0)
+                        .speed(0.1)
+                        .suffix(" s"),
+                );
+            });
+
+            let stale = self
+                .latest_values()
+                .filter(|(name, (timestamp, ..))| {
+                    !self.hidden_metrics.contains(*name)
+                        && self.stale_auto_hide.is_stale(display_time, *timestamp)
+                })
+                .collect::<Vec<_>>();
+
+            if !stale.is_empty() {
+                ui.collapsing(format!("{} Stale Metrics", stale.len()), |ui| {
+                    Grid::new("stale_metrics")
+                        .striped(true)
+                        .num_columns(3)
+                        .show(ui, |ui| {
+                            for (name, (timestamp, _arrival, value)) in &stale {
+                                ui.monospace((display_time - *timestamp).to_string());
+                                ui.label(*name);
+                                ui.monospace(value.value());
+                                ui.end_row();
+                            }
+                        });
+                });
+            }
+
+            let stale = stale
+                .into_iter()
+                .map(|(name, _)| name.clone())
+                .collect::<BTreeSet<_>>();
+
+            ui.horizontal(|ui| {
+                ui.label("Filter");
+                ui.add(
+                    TextEdit::singleline(&mut self.metric_filter)
+                        .hint_text("name, or namespace:name")
+                        .desired_width(200.0),
+                )
+                .on_hover_text_at_pointer(
+                    "Only show metrics whose name contains this, ignoring case. Everything before the last : must match the namespaces exactly",
+                );
+                if !self.metric_filter.is_empty() && ui.small_button("🗙").clicked() {
+                    self.metric_filter.clear();
+                }
+            });
+
+            let mut groups = BTreeMap::<_, Vec<_>>::new();
+            for metric in self
+                .sorted_metrics
+                .iter()
+                .filter(|(name, _)| {
+                    !stale.contains(*name)
+                        && !self.hidden_metrics.contains(*name)
+                        && self
+                            .recent_changes
+                            .is_shown(display_time, self.value_changes.get(*name).copied())
+                        && matches_filter(name, &self.metric_filter)
+                })
+                .filter_map(|(name, history)| {
+                    history.back().map(|newest| {
+                        (
+                            name,
+                            self.aliases.label(name),
+                            newest,
+                            history.len(),
+                            self.truncated_metrics.contains(name),
+                            self.recent_changes
+                                .is_recent(display_time, self.value_changes.get(name).copied()),
+                            self.latest_raw.get(name).map(AsRef::as_ref),
+                            self.stats_cache.get_or_compute(name, history.len(), newest.1, || {
+                                history.iter().filter_map(|(_, _, value)| as_plot_value(value))
+                            }),
+                            self.units.get(name),
+                        )
+                    })
+                })
+            {
+                groups
+                    .entry(namespace_group(metric.0))
+                    .or_default()
+                    .push(metric);
+            }
+
+            let shown_port = self.shown_port();
+            let mut to_clear = Vec::new();
+            latest_metrics_scroll_area().show(ui, |ui| {
+                latest_metrics_header(ui, shown_port.as_deref());
+
+                for (group, metrics) in groups {
+                    let collapsed = self.collapsed_groups.contains(&group);
+
+                    let response = CollapsingHeader::new(
+                        RichText::new(format!(
+                            "{} ({})",
+                            group_label(group.as_deref()),
+                            metrics.len()
+                        ))
+                        .color(Color32::KHAKI),
+                    )
+                    .id_source(("latest_metrics_group", &group))
+                    .open(Some(!collapsed))
+                    .show_unindented(ui, |ui| {
+                        // The body of every group is a scope with the same id otherwise
+                        ui.push_id(&group, |ui| {
+                            latest_metrics(
+                                ui,
+                                display_time,
+                                &mut self.focused_metrics,
+                                &mut self.hidden_metrics,
+                                &self.type_changes,
+                                &mut self.favorites,
+                                metrics.into_iter(),
+                            )
+                        })
+                        .inner
+                    });
+
+                    if response.header_response.clicked() {
+                        if collapsed {
+                            self.collapsed_groups.remove(&group);
+                        } else {
+                            self.collapsed_groups.insert(group);
+                        }
+                    }
+
+                    to_clear.extend(response.body_returned.into_iter().flatten());
+                }
+            });
+            for to_clear in to_clear {
+                self.sorted_metrics.remove(&to_clear);
+                self.truncated_metrics.remove(&to_clear);
+                self.value_changes.remove(&to_clear);
+                self.latest_raw.remove(&to_clear);
+            }
+
+            ui.separator();
+            if self
+                .focused_metrics
+                .iter()
+                .all(|metric_name| !self.sorted_metrics.contains_key(metric_name))
+            {
+                ui.heading(format!(
+                    "{} Historical Metrics{}",
+                    self.raw_metrics.len(),
+                    self.port_suffix()
+                ));
+
+                if let Some(clicked) = metrics_history(
+                    ui,
+                    &self.raw_metrics,
+                    self.metrics_received,
+                    &mut self.history_format_cache,
+                    self.inspected_packet.as_ref().map(|packet| packet.sequence),
+                    self.wall_clock_anchor,
+                    &self.aliases,
+                    shown_port.as_deref(),
+                ) {
+                    self.inspected_packet = Some(clicked);
+                }
+            } else {
+                ui.horizontal_wrapped(|ui| {
+                    ui.checkbox(&mut self.plot_style.connect_the_dots, "Connect The Dots?")
+                        .on_hover_text_at_pointer(
+                            "Should lines be drawn between points on the plot",
+                        );
+                    ui.checkbox(&mut self.plot_style.bool_waveforms, "Bool Waveforms?")
+                        .on_hover_text_at_pointer(
+                            "Draw bool metrics as square waves in their own tracks, like a logic analyzer",
+                        );
+
+                    ui.checkbox(&mut self.plot_style.log_scale, "Log Scale?")
+                        .on_hover_text_at_pointer(
+                            "Plot the values on a log10 axis, values that are not positive are skipped",
+                        );
+
+                    ui.label("Y margin");
+                    ui.add(
+                        DragValue::new(&mut self.plot_style.y_margin_percent)
+                            .clamp_range(0.0..=50.0)
+                            .suffix("%"),
+                    )
+                    .on_hover_text_at_pointer("Space kept above and below the plotted data");
+
+                    ui.checkbox(&mut self.plot_style.moving_average, "SMA")
+                        .on_hover_text_at_pointer("Overlay a simple moving average");
+                    ui.checkbox(&mut self.plot_style.exponential_average, "EMA")
+                        .on_hover_text_at_pointer("Overlay an exponential moving average");
+                    if self.plot_style.moving_average || self.plot_style.exponential_average {
+                        ui.add(
+                            Slider::new(&mut self.plot_style.smoothing_window, 2..=200)
+                                .logarithmic(true)
+                                .text("samples"),
+                        )
+                        .on_hover_text_at_pointer("Window of the moving averages");
+                    }
+
+                    ComboBox::from_id_source("array_mode")
+                        .selected_text(self.plot_style.array_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in ArrayPlotMode::ALL {
+                                ui.selectable_value(
+                                    &mut self.plot_style.array_mode,
+                                    mode,
+                                    mode.label(),
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text_at_pointer("How focused array metrics are plotted");
+
+                    ui.label("Downsample above");
+                    ui.add(
+                        DragValue::new(&mut self.plot_style.downsample_threshold)
+                            .speed(16.0)
+                            .suffix(" points"),
+                    )
+                    .on_hover_text_at_pointer(
+                        "Draw only the lowest and highest point per pixel column of metrics with more points than this, 0 to always draw every point",
+                    );
+
+                    if ui
+                        .checkbox(&mut self.plot_view.follow, "Follow after reconnect")
+                        .on_hover_text_at_pointer(
+                            "Fit the plot to the new data after a reconnect, instead of keeping a panned or zoomed view",
+                        )
+                        .changed()
+                        && self.plot_view.follow
+                    {
+                        self.plot_view.fit_to_data();
+                    }
+
+                    if ui
+                        .button("Export CSV")
+                        .on_hover_text_at_pointer(
+                            "Save the plotted data with a row per timestamp, leaving blanks where a metric has no sample",
+                        )
+                        .clicked()
+                    {
+                        self.export_focused_aligned(Interpolation::None);
+                    }
+                });
+                ui.collapsing("Plot Instructions", |ui| {
+                    ui.label("Pan by dragging, or scroll (+ shift = horizontal).");
+                    ui.label("Box zooming: Right click to zoom in and zoom out using a selection.");
+                    if cfg!(target_arch = "wasm32") {
+                        ui.label("Zoom with ctrl / ⌘ + pointer wheel, or with pinch gesture.");
+                    } else if cfg!(target_os = "macos") {
+                        ui.label("Zoom with ctrl / ⌘ + scroll.");
+                    } else {
+                        ui.label("Zoom with ctrl + scroll.");
+                    }
+                    ui.label("Reset view with double-click.");
+                });
+
+                // Metrics without a numeric value are drawn as category lanes instead
+                let (plotted, lanes): (Vec<_>, Vec<_>) = self
+                    .focused_metrics
+                    .iter()
+                    .filter_map(|metric_name| {
+                        self.sorted_metrics
+                            .get(metric_name)
+                            .map(|metric_values| (metric_name, metric_values))
+                    })
+                    .partition(|(_, metric_values)| {
+                        metric_values
+                            .back()
+                            .is_some_and(|(_, _, value)| {
+                                as_plot_value(value).is_some() || as_plot_values(value).is_some()
+                            })
+                    });
+
+                // Arrays are drawn against their index below the plot if chosen
+                let (profiles, plotted): (Vec<_>, Vec<_>) =
+                    plotted.into_iter().partition(|(_, metric_values)| {
+                        self.plot_style.array_mode == ArrayPlotMode::LatestProfile
+                            && metric_values
+                                .back()
+                                .is_some_and(|(_, _, value)| as_plot_values(value).is_some())
+                    });
+
+                // Bool metrics get their own waveform tracks if enabled
+                let (waveforms, plotted): (Vec<_>, Vec<_>) =
+                    plotted.into_iter().partition(|(_, metric_values)| {
+                        self.plot_style.bool_waveforms
+                            && metric_values
+                                .back()
+                                .is_some_and(|(_, _, value)| value.as_bool().is_some())
+                    });
+
+                if !waveforms.is_empty() {
+                    TopBottomPanel::bottom("bool_waveforms").show_inside(ui, |ui| {
+                        bool_waveforms(
+                            ui,
+                            waveforms
+                                .into_iter()
+                                .map(|(metric_name, metric_values)| {
+                                    (metric_name, metric_values.iter())
+                                })
+                                .collect(),
+                            display_time,
+                            self.timestamp_unit,
+                            &self.aliases,
+                        );
+                    });
+                }
+
+                if !profiles.is_empty() {
+                    TopBottomPanel::bottom("focused_array_profiles").show_inside(ui, |ui| {
+                        for (metric_name, metric_values) in profiles {
+                            if let Some((_, _, value)) = metric_values.back() {
+                                array_profile(
+                                    ui,
+                                    metric_name,
+                                    value,
+                                    self.max_array_elements,
+                                    &self.aliases,
+                                );
+                            }
+                        }
+                    });
+                }
+
+                if !lanes.is_empty() {
+                    TopBottomPanel::bottom("category_lanes").show_inside(ui, |ui| {
+                        category_lanes(
+                            ui,
+                            lanes
+                                .into_iter()
+                                .map(|(metric_name, metric_values)| {
+                                    (metric_name, metric_values.iter())
+                                })
+                                .collect(),
+                            display_time,
+                            self.timestamp_unit,
+                            &self.aliases,
+                        );
+                    });
+                }
+
+                focused_metrics_plot(
+                    ui,
+                    plotted
+                        .into_iter()
+                        .map(|(metric_name, metric_values)| {
+                            (
+                                metric_name,
+                                self.aliases.display(metric_name),
+                                self.units.get(metric_name),
+                                metric_values.iter(),
+                            )
+                        }),
+                    self.plot_style,
+                    &mut self.plot_view,
+                    &mut self.time_cursor,
+                    &self.runs,
+                    self.current_time,
+                );
+            }
+        });
+
+        if self.show_visualization {
+            // Copied out so the window can borrow the rest of the application
+            let mut open = self.show_visualization;
+            let mut sweep = self.sweep;
+
+            Window::new("Visualization")
+                .open(&mut open)
+                .frame(egui::Frame::dark_canvas(&ctx.style()))
+                .show(ctx, |ui| {
+                    sweep.ui(ui);
+
+                    robot(
+                        ui,
+                        &sweep,
+                        |metric_name| {
+                            self.latest_value(&metric_name)
+                                .map(|(_timestamp, _arrival, value)| value)
+                        },
+                        self.sorted_metrics
+                            .get(&metric_name!("navigation", "position"))
+                            .into_iter()
+                            .flatten()
+                            .map(|(_timestamp, _arrival, value)| value),
+                    );
+                });
+
+            self.show_visualization = open;
+            self.sweep = sweep;
+        }
+
+        if let Some(packet) = &self.inspected_packet {
+            let mut open = true;
+
+            Window::new("Packet Inspector")
+                .open(&mut open)
+                .show(ctx, |ui| packet.ui(ui));
+
+            if !open {
+                self.inspected_packet = None;
+            }
+        }
+
+        if let Some(summary) = &self.session_summary {
+            let mut open = true;
+            let mut export = false;
+            let mut dismiss = false;
+
+            Window::new("Session Summary")
+                .open(&mut open)
+                .show(ctx, |ui| {
+                    summary.ui(ui);
+
+                    ui.separator();
+
+                    ui.horizontal(|ui| {
+                        export = ui
+                            .button("Export metrics")
+                            .on_hover_text_at_pointer("Save all metrics still in memory as CSV")
+                            .clicked();
+
+                        dismiss = ui.button("Dismiss").clicked();
+                    });
+
+                    ui.checkbox(&mut self.session.enabled, "Show after every disconnect");
+                });
+
+            if export {
+                if let Some(path) = save_csv_dialog("session.csv") {
+                    if let Err(err) = write_metrics_csv(&path, self.raw_metrics.iter()) {
+                        error!(?err, ?path, "failed to export session");
+                    }
+                }
+            }
+
+            if !open || dismiss {
+                self.session_summary = None;
+            }
+        }
+
+        if self.show_explorer {
+            let tree = MetricTree::build(self.sorted_metrics.keys());
+
+            Window::new("Metric Explorer")
+                .open(&mut self.show_explorer)
+                .vscroll(true)
+                .show(ctx, |ui| {
+                    metric_explorer(
+                        ui,
+                        &tree,
+                        &self.sorted_metrics,
+                        &mut self.focused_metrics,
+                        &mut self.hidden_metrics,
+                        &mut self.units,
+                        &mut self.aliases,
+                    );
+                });
+        }
+
+        let mut histogram_open = self.histogram.open;
+        Window::new("Histogram")
+            .open(&mut histogram_open)
+            .show(ctx, |ui| {
+                self.histogram.ui(
+                    ui,
+                    &self.focused_metrics,
+                    &self.sorted_metrics,
+                    &self.aliases,
+                );
+            });
+        self.histogram.open = histogram_open;
+
+        let mut spectrum_open = self.spectrum.open;
+        Window::new("Spectrum")
+            .open(&mut spectrum_open)
+            .show(ctx, |ui| {
+                self.spectrum.ui(
+                    ui,
+                    &self.focused_metrics,
+                    &self.sorted_metrics,
+                    &self.aliases,
+                );
+            });
+        self.spectrum.open = spectrum_open;
+
+        let mut firmware_log_open = self.firmware_log.open;
+        Window::new("Firmware Log")
+            .open(&mut firmware_log_open)
+            .show(ctx, |ui| self.firmware_log.ui(ui));
+        self.firmware_log.open = firmware_log_open;
+
+        let mut show_profiles = self.show_profiles;
+        let to_connect = Window::new("Connection Profiles")
+            .open(&mut show_profiles)
+            .show(ctx, |ui| self.profiles_ui(ui))
+            .and_then(|response| response.inner.flatten());
+        self.show_profiles = show_profiles;
+
+        if let Some(profile) = to_connect {
+            self.connect_profile(ctx, profile);
+        }
+
+        let mut export_diagnostics = false;
+
+        Window::new("Information")
+            .open(&mut self.show_info)
+            .resizable(false)
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                ui.heading(env!("CARGO_PKG_NAME"));
+                ui.label(env!("CARGO_PKG_DESCRIPTION"));
+
+                ui.separator();
+
+                Grid::new("version_information")
+                    .striped(true)
+                    .num_columns(2)
+                    .show(ui, |ui| {
+                        ui.label("cargo-version:");
+                        ui.label(env!("CARGO_PKG_VERSION"));
+                        ui.end_row();
 
                         ui.label("git-version:");
                         if GIT_VERSION.ends_with("-modified") {
@@ -291,6 +2444,797 @@ impl App for Application {
                         ui.hyperlink(env!("CARGO_PKG_REPOSITORY")).clicked();
                         ui.end_row();
                     });
+
+                ui.separator();
+
+                ui.horizontal(|ui| {
+                    ui.label("Theme:");
+                    ComboBox::from_id_source("theme")
+                        .selected_text(self.theme.label())
+                        .show_ui(ui, |ui| {
+                            for theme in ThemePreference::ALL {
+                                ui.selectable_value(&mut self.theme, theme, theme.label());
+                            }
+                        });
+                });
+
+                ui.separator();
+
+                ui.heading("Debug");
+                if ui
+                    .button("Export diagnostics bundle")
+                    .on_hover_text_at_pointer(
+                        "Save the recent packets, error counters, metric list and settings as a zip to attach to a bug report",
+                    )
+                    .clicked()
+                {
+                    export_diagnostics = true;
+                }
+                if self.serial.is_recording() {
+                    if ui
+                        .button("Stop recording")
+                        .on_hover_text_at_pointer("Close the packet recording")
+                        .clicked()
+                    {
+                        self.serial.stop_recording();
+                    }
+                } else if ui
+                    .button("Record packets")
+                    .on_hover_text_at_pointer(
+                        "Write every received packet to a file, to replay the session later",
+                    )
+                    .clicked()
+                {
+                    if let Some(path) = save_recording_dialog("recording.kestrel") {
+                        self.serial.start_recording(path);
+                    }
+                }
+                if ui
+                    .checkbox(&mut self.show_raw_bytes, "Show raw value bytes")
+                    .on_hover_text_at_pointer(
+                        "Keep the bytes each value was decoded from, at the cost of extra memory",
+                    )
+                    .changed()
+                {
+                    self.serial.set_retain_raw_bytes(self.show_raw_bytes);
+
+                    if !self.show_raw_bytes {
+                        self.latest_raw.clear();
+                    }
+                }
+
+                ui.horizontal(|ui| {
+                    let mut timeout_ms =
+                        self.read_timeout.as_millis().try_into().unwrap_or(u64::MAX);
+
+                    ui.label("Read timeout");
+                    if ui
+                        .add(
+                            Slider::new(&mut timeout_ms, 1..=1000)
+                                .logarithmic(true)
+                                .suffix(" ms"),
+                        )
+                        .on_hover_text_at_pointer(
+                            "Shorter timeouts handle commands sooner, at the cost of more CPU time. 20 timeouts in a row mark the connection stalled",
+                        )
+                        .changed()
+                    {
+                        self.read_timeout = Duration::from_millis(timeout_ms);
+                        self.serial.set_read_timeout(self.read_timeout);
+                        self.serial.measure_queue_latency();
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Command round trip");
+                    match self.command_acks.round_trip() {
+                        Some(round_trip) => {
+                            ui.monospace(format!("{:.1} ms", round_trip.as_secs_f64() * 1000.0))
+                        }
+                        None => ui.monospace("no acknowledged command yet"),
+                    }
+                    .on_hover_text_at_pointer(
+                        "Time from sending the last acknowledged command until the robot acknowledged it",
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Worker queue latency");
+                    match self.serial.queue_latency() {
+                        Some(latency) => {
+                            ui.monospace(format!("{:.1} ms", latency.as_secs_f64() * 1000.0))
+                        }
+                        None => ui.monospace("unmeasured"),
+                    }
+                    .on_hover_text_at_pointer(
+                        "How long a command waited in the queue of the serial worker, not a round trip to the robot",
+                    );
+
+                    if ui.button("Measure").clicked() {
+                        self.serial.measure_queue_latency();
+                    }
+                });
+
+                self.rate_limiter
+                    .ui(ui, self.sorted_metrics.keys(), DEFAULT_RATE_LIMIT);
+
+                ui.horizontal(|ui| {
+                    ui.label("Rollover window");
+
+                    let mut seconds = self.rollover_window.as_secs_f64();
+                    if ui
+                        .add(
+                            DragValue::new(&mut seconds)
+                                .clamp_range(0.0..=3600.0)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text_at_pointer(
+                            "A timestamp jumping back is treated as the robot clock wrapping around, instead of a reboot that clears the metrics, if at most this much time passed across the wraparound",
+                        )
+                        .changed()
+                    {
+                        self.rollover_window = Duration::from_secs_f64(seconds);
+                    }
+                });
+
+                ui.label(format!("Dropped frames: {}", self.serial.dropped_frames()))
+                    .on_hover_text_at_pointer(
+                        "Frames skipped to resynchronize after receiving malformed COBS data",
+                    );
+
+                let length_deltas = self.serial.length_deltas();
+
+                ui.collapsing(
+                    format!("Packet length mismatches ({})", length_deltas.total()),
+                    |ui| {
+                        if length_deltas.is_empty() {
+                            ui.label("None so far");
+                            return;
+                        }
+
+                        Grid::new("length_deltas")
+                            .striped(true)
+                            .num_columns(2)
+                            .show(ui, |ui| {
+                                ui.label("expected - got").on_hover_text_at_pointer(
+                                    "A single dominant delta points at a consistent firmware bug",
+                                );
+                                ui.label("packets");
+                                ui.end_row();
+
+                                for (delta, count) in length_deltas.iter() {
+                                    ui.monospace(format!("{delta:+}"));
+                                    ui.monospace(count.to_string());
+                                    ui.end_row();
+                                }
+                            });
+
+                        if ui.button("Reset").clicked() {
+                            self.serial.clear_length_deltas();
+                        }
+                    },
+                );
+            });
+
+        if export_diagnostics {
+            self.export_diagnostics();
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::{self, Read};
+
+    use kestrel_metric::value::ManyValues;
+    use kestrel_serial::ScriptedSource;
+
+    use crate::{
+        new_metric_ring_buffer,
+        visualization::{
+            array_profile::DEFAULT_MAX_ARRAY_ELEMENTS, histogram::HistogramView,
+            metric_stats::StatsCache, robot::SweepConfig, spectrum::SpectrumView,
+        },
+    };
+
+    use super::*;
+
+    /// An application fed by `source`, with every setting at its default as if started without arguments
+    pub(crate) fn application(source: ScriptedSource) -> Application {
+        let history_length = 16;
+        let config = SerialWorkerConfig::default();
+
+        Application {
+            pause_metrics: false,
+            show_visualization: false,
+            show_info: false,
+            show_raw_bytes: false,
+            show_profiles: false,
+            show_explorer: false,
+            histogram: HistogramView::default(),
+            spectrum: SpectrumView::default(),
+            firmware_log: FirmwareLog::default(),
+            plot_style: PlotStyle::default(),
+            plot_view: PlotView::default(),
+
+            raw_metrics: new_metric_ring_buffer(history_length),
+            history_length,
+            metrics_received: 0,
+            history_format_cache: FormatCache::default(),
+            inspected_packet: None,
+            sorted_metrics: BTreeMap::new(),
+            truncated_metrics: BTreeSet::new(),
+            value_changes: BTreeMap::new(),
+            recent_changes: RecentChanges::default(),
+            stats_cache: StatsCache::default(),
+
+            current_time: Timestamp::from_millis(0),
+            wall_clock_anchor: None,
+            rollover_window: Duration::from_secs(60),
+            timestamp_unit: TimestampUnit::Millis,
+            time_cursor: TimeCursor::default(),
+            pause_clock: PauseClock::default(),
+            auto_clear: AutoClear::new(Instant::now()),
+            sweep: SweepConfig::default(),
+            runs: Runs::default(),
+
+            focused_metrics: BTreeSet::new(),
+            hidden_metrics: BTreeSet::new(),
+            metric_filter: String::new(),
+            collapsed_groups: BTreeSet::new(),
+            stale_auto_hide: StaleAutoHide::default(),
+            favorites: Favorites::default(),
+            units: Units::default(),
+            aliases: Aliases::default(),
+            theme: ThemePreference::default(),
+            formulas: Vec::new(),
+            export_interpolation: Interpolation::default(),
+            aggregations: BTreeMap::new(),
+            array_profiles: BTreeSet::new(),
+            max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+            latest_raw: BTreeMap::new(),
+
+            type_changes: TypeChanges::default(),
+            type_change_policy: TypeChangePolicy::default(),
+            rate_limiter: RateLimiter::new(None),
+            session: SessionTracker::default(),
+            session_summary: None,
+
+            profiles: vec![ConnectionProfile::default_profile(
+                source.port_name().to_owned(),
+                config,
+            )],
+            serial: Box::new(source),
+            paused_packets: VecDeque::new(),
+            background_ports: Vec::new(),
+            csv_log: None,
+            link_quality: LinkQuality::default(),
+            read_timeout: config.read_timeout,
+            control_addr: config.control_addr,
+            control_offset: 0,
+            max_frame_length: config.max_frame_length,
+            detected_baud: None,
+            autosave: None,
+            broadcaster: None,
+            influx: None,
+            mqtt: None,
+            prometheus: None,
+            active_profile: ConnectionProfile::DEFAULT_NAME.to_owned(),
+
+            macros: Vec::new(),
+            macro_recorder: None,
+            macro_playback: None,
+            payload_input: String::new(),
+            raw_command_input: String::new(),
+            named_commands: Vec::new(),
+            command_acks: CommandAcks::default(),
+            confirmations: Confirmations::default(),
+        }
+    }
+
+    pub(crate) fn metric(millis: u32, name: &str, value: MetricValue) -> Metric {
+        Metric {
+            timestamp: Timestamp::from_millis(millis),
+            arrival: OffsetDateTime::UNIX_EPOCH,
+            name: name.parse().unwrap(),
+            value,
+            raw: None,
+        }
+    }
+
+    fn names(app: &Application) -> Vec<String> {
+        app.sorted_metrics.keys().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn ingests_metrics_from_the_source() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(10, "a", MetricValue::One(OneValue::U8(1))));
+        source.push_metric(metric(20, "b", MetricValue::One(OneValue::U8(2))));
+        app.poll_source();
+
+        assert_eq!(names(&app), ["a", "b"]);
+        assert_eq!(app.metrics_received, 2);
+        assert_eq!(app.current_time, Timestamp::from_millis(20));
+    }
+
+    #[test]
+    fn paused_ingest_leaves_metrics_queued() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        app.pause_metrics = true;
+        source.push_metric(metric(10, "a", MetricValue::One(OneValue::U8(1))));
+        app.poll_source();
+        assert!(app.sorted_metrics.is_empty());
+
+        app.pause_metrics = false;
+        app.poll_source();
+        assert_eq!(names(&app), ["a"]);
+    }
+
+    #[test]
+    fn timestamp_going_back_is_a_reboot() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(100_000, "a", MetricValue::One(OneValue::U8(1))));
+        app.poll_source();
+        source.push_metric(metric(10, "b", MetricValue::One(OneValue::U8(2))));
+        app.poll_source();
+
+        assert_eq!(names(&app), ["b"]);
+        assert_eq!(app.raw_metrics.len(), 1);
+    }
+
+    #[test]
+    fn clock_rollover_is_not_a_reboot() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(
+            u32::MAX - 10,
+            "a",
+            MetricValue::One(OneValue::U8(1)),
+        ));
+        source.push_metric(metric(5, "b", MetricValue::One(OneValue::U8(2))));
+        app.poll_source();
+
+        assert_eq!(names(&app), ["a", "b"]);
+    }
+
+    #[test]
+    fn reboot_packet_forgets_the_session() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(500, "a", MetricValue::One(OneValue::U8(1))));
+        app.poll_source();
+        source.push_system_message(SystemMessage {
+            timestamp: Timestamp::from_millis(0),
+            packet: SystemPacket::Reboot,
+        });
+        app.poll_source();
+
+        assert!(app.sorted_metrics.is_empty());
+        assert_eq!(app.current_time, Timestamp::from_millis(0));
+    }
+
+    #[test]
+    fn reboot_only_forgets_the_metrics_before_it() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(500, "old", MetricValue::One(OneValue::U8(1))));
+        source.push_system_message(SystemMessage {
+            timestamp: Timestamp::from_millis(0),
+            packet: SystemPacket::Reboot,
+        });
+        source.push_metric(metric(10, "new", MetricValue::One(OneValue::U8(2))));
+        app.poll_source();
+
+        assert_eq!(names(&app), ["new"]);
+        assert_eq!(app.current_time, Timestamp::from_millis(10));
+    }
+
+    #[test]
+    fn reboot_while_paused_waits_for_resume() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(500, "old", MetricValue::One(OneValue::U8(1))));
+        app.poll_source();
+
+        app.pause_metrics = true;
+        source.push_metric(metric(600, "queued", MetricValue::One(OneValue::U8(2))));
+        source.push_system_message(SystemMessage {
+            timestamp: Timestamp::from_millis(0),
+            packet: SystemPacket::Reboot,
+        });
+        source.push_metric(metric(10, "new", MetricValue::One(OneValue::U8(3))));
+        app.poll_source();
+        assert_eq!(names(&app), ["old"]);
+
+        app.pause_metrics = false;
+        app.poll_source();
+        assert_eq!(names(&app), ["new"]);
+    }
+
+    #[test]
+    fn disconnect_drops_the_wall_clock_anchor() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.push_metric(metric(500, "a", MetricValue::One(OneValue::U8(1))));
+        app.poll_source();
+        assert!(app.wall_clock_anchor.is_some());
+
+        source.set_state(SerialWorkerState::Disconnected);
+        app.poll_source();
+        assert!(app.wall_clock_anchor.is_none());
+    }
+
+    #[test]
+    fn ingest_stores_the_aggregate_next_to_the_array() {
+        let mut app = application(ScriptedSource::new("scripted"));
+        let name = "currents".parse::<MetricName>().unwrap();
+        app.aggregations.insert(name.clone(), Aggregation::Max);
+
+        let array = MetricValue::Many(ManyValues::U8([1, 9, 4].into()));
+        app.ingest(metric(10, "currents", array));
+
+        assert_eq!(names(&app), ["currents", "currents#max"]);
+        assert_eq!(
+            app.sorted_metrics[&Aggregation::Max.derived_name(&name)]
+                .back()
+                .map(|(_, _, value)| value),
+            Some(&MetricValue::One(OneValue::F64(9.0)))
+        );
+    }
+
+    #[test]
+    fn rate_limited_samples_replace_the_newest_without_counting() {
+        let mut app = application(ScriptedSource::new("scripted"));
+        app.rate_limiter.global_limit = Some(10);
+
+        // Every sample arrives at the same instant, so all but the first are over the rate
+        for millis in 0..3 {
+            app.ingest(metric(
+                millis,
+                "speed",
+                MetricValue::One(OneValue::U32(millis)),
+            ));
+        }
+
+        let series = &app.sorted_metrics[&"speed".parse::<MetricName>().unwrap()];
+        assert_eq!(series.len(), 1);
+        assert_eq!(
+            series.back().map(|(_, _, value)| value),
+            Some(&MetricValue::One(OneValue::U32(2)))
+        );
+        assert_eq!(app.raw_metrics.len(), 1);
+        assert_eq!(app.metrics_received, 1);
+        assert_eq!(app.rate_limiter.total_dropped(), 2);
+    }
+
+    /// An application showing `shown` with `background` open next to it
+    fn with_background_port(shown: ScriptedSource, background: ScriptedSource) -> Application {
+        let mut app = application(shown);
+        app.background_ports.push(PortSession::new(
+            Box::new(background),
+            Timestamp::from_millis(0),
+            app.history_length,
+            1,
+        ));
+        app
+    }
+
+    #[test]
+    fn switching_ports_drops_the_pending_confirmation_and_macro() {
+        let shown = ScriptedSource::new("shown");
+        let background = ScriptedSource::new("background");
+        let mut app = with_background_port(shown.clone(), background.clone());
+        app.confirmations.set_required(GuardedAction::Reset, true);
+
+        app.request_action(GuardedAction::Reset);
+        app.macro_playback = Some(MacroPlayback::new(
+            &CommandMacro {
+                name: "macro".to_owned(),
+                steps: Vec::new(),
+            },
+            Instant::now(),
+        ));
+        app.show_port(0);
+
+        assert_eq!(app.serial.port_name(), "background");
+        assert_eq!(app.confirmations.pending(), None);
+        assert!(app.macro_playback.is_none());
+
+        app.answer_confirmation(true);
+        assert_eq!(shown.resets() + background.resets(), 0);
+    }
+
+    #[test]
+    fn background_disconnect_keeps_a_summary_for_its_port() {
+        let background = ScriptedSource::new("background");
+        let mut app = with_background_port(ScriptedSource::new("shown"), background.clone());
+
+        app.poll_source();
+        background.set_state(SerialWorkerState::Disconnected);
+        app.poll_source();
+
+        assert!(app.session_summary.is_none());
+        assert!(app.background_ports[0].session_summary.is_some());
+
+        app.show_port(0);
+        assert!(app.session_summary.is_some());
+    }
+
+    #[test]
+    fn confirmed_action_is_dispatched() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+        app.confirmations.set_required(GuardedAction::Reset, true);
+
+        app.request_action(GuardedAction::Reset);
+        assert_eq!(source.resets(), 0);
+        assert_eq!(app.confirmations.pending(), Some(GuardedAction::Reset));
+
+        app.answer_confirmation(true);
+        assert_eq!(source.resets(), 1);
+        assert_eq!(app.confirmations.pending(), None);
+    }
+
+    #[test]
+    fn cancelled_action_is_not_dispatched() {
+        let source = ScriptedSource::new("scripted");
+        source.set_state(SerialWorkerState::Connected);
+        let mut app = application(source.clone());
+        let calibrate = GuardedAction::Command(RobotCommand::CalibrateAmbientInfrared);
+        app.confirmations.set_required(calibrate, true);
+
+        app.request_action(calibrate);
+        app.answer_confirmation(false);
+
+        assert_eq!(app.confirmations.pending(), None);
+        assert!(source.sent_commands().is_empty());
+
+        // Without confirmation required the command goes out right away
+        app.confirmations.set_required(calibrate, false);
+        app.request_action(calibrate);
+        assert_eq!(
+            source.sent_commands(),
+            [RobotCommand::CalibrateAmbientInfrared as u8]
+        );
+    }
+
+    #[test]
+    fn commands_are_only_sent_while_connected() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        for state in [
+            SerialWorkerState::Resetting,
+            SerialWorkerState::Disconnected,
+            SerialWorkerState::Detached,
+        ] {
+            source.set_state(state);
+            app.send_command(RobotCommand::CalibrateAmbientInfrared);
+
+            assert!(source.sent_commands().is_empty(), "sent while {state:?}");
+        }
+        // Only the notices that they were not sent are shown, nothing waits for an
+        // acknowledgement that will never come and so nothing times out once they are gone
+        assert!(app.command_acks.poll(Instant::now()).is_some());
+        assert_eq!(
+            app.command_acks
+                .poll(Instant::now() + Duration::from_secs(60)),
+            None
+        );
+
+        source.set_state(SerialWorkerState::Connected);
+        app.send_command(RobotCommand::CalibrateAmbientInfrared);
+        source.set_state(SerialWorkerState::Stalled);
+        app.send_command(RobotCommand::CalibrateReferenceInfrared);
+
+        assert_eq!(
+            source.sent_commands(),
+            [
+                RobotCommand::CalibrateAmbientInfrared as u8,
+                RobotCommand::CalibrateReferenceInfrared as u8
+            ]
+        );
+        assert!(app.command_acks.poll(Instant::now()).is_some());
+    }
+
+    #[test]
+    fn macro_records_only_commands_that_were_sent() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+        app.macro_recorder = Some(MacroRecorder::new(Instant::now()));
+
+        source.set_state(SerialWorkerState::Detached);
+        app.send_command(RobotCommand::CalibrateAmbientInfrared);
+        source.set_state(SerialWorkerState::Connected);
+        app.send_command(RobotCommand::CalibrateReferenceInfrared);
+
+        let recorded = app
+            .macro_recorder
+            .take()
+            .unwrap()
+            .finish("macro".to_owned());
+        assert_eq!(
+            recorded
+                .steps
+                .iter()
+                .map(|step| step.command as u8)
+                .collect::<Vec<_>>(),
+            [RobotCommand::CalibrateReferenceInfrared as u8]
+        );
+    }
+
+    #[test]
+    fn raw_commands_are_only_sent_while_connected() {
+        let source = ScriptedSource::new("scripted");
+        let mut app = application(source.clone());
+
+        source.set_state(SerialWorkerState::Detached);
+        app.send_raw_command(0x05, "Beep".to_owned());
+        assert!(source.sent_commands().is_empty());
+        assert_eq!(
+            app.command_acks
+                .poll(Instant::now() + Duration::from_secs(60)),
+            None
+        );
+
+        source.set_state(SerialWorkerState::Connected);
+        app.send_raw_command(0x05, "Beep".to_owned());
+        assert_eq!(source.sent_commands(), [0x05]);
+
+        // Left unacknowledged, the command times out and stays shown as such
+        let timed_out = Instant::now() + Duration::from_secs(60);
+        assert!(app.command_acks.poll(timed_out).is_some());
+    }
+
+    #[test]
+    fn diagnostics_bundle_describes_the_session() {
+        let source = ScriptedSource::new("scripted");
+        source.set_state(SerialWorkerState::Connected);
+        let mut app = application(source);
+        app.rate_limiter.global_limit = Some(10);
+
+        for millis in 0..2 {
+            app.ingest(metric(
+                millis,
+                "speed",
+                MetricValue::One(OneValue::U32(millis)),
+            ));
+        }
+        app.ingest(metric(2, "motor:left", MetricValue::One(OneValue::I16(-4))));
+
+        let mut zip = io::Cursor::new(Vec::new());
+        app.diagnostics_bundle()
+            .unwrap()
+            .write_zip(&mut zip)
+            .unwrap();
+        let mut archive = zip::ZipArchive::new(zip).unwrap();
+
+        let mut entries = archive.file_names().collect::<Vec<_>>();
+        entries.sort_unstable();
+        assert_eq!(
+            entries,
+            [
+                "catalog.json",
+                "errors.json",
+                "raw_frames.jsonl",
+                "settings.json",
+                "version.txt"
+            ]
+        );
+
+        let mut read = |name: &str| {
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+        let json = |contents: String| serde_json::from_str::<serde_json::Value>(&contents).unwrap();
+
+        // The second speed sample was rate limited into the first
+        assert_eq!(read("raw_frames.jsonl").lines().count(), 2);
+
+        let errors = json(read("errors.json"));
+        assert_eq!(errors["rate_limited"], 1);
+
+        let catalog = json(read("catalog.json"));
+        assert_eq!(catalog["speed"]["type"], "u32");
+        assert_eq!(catalog["speed"]["samples"], 1);
+        assert_eq!(catalog["speed"]["rate_limited"], 1);
+        assert_eq!(catalog["motor:left"]["type"], "i16");
+
+        let settings = json(read("settings.json"));
+        assert_eq!(settings["port"], "scripted");
+        assert_eq!(settings["state"], "Connected");
+        assert_eq!(settings["rate_limit"], 10);
+
+        assert!(read("version.txt").starts_with(env!("CARGO_PKG_NAME")));
+    }
+
+    #[test]
+    fn latest_values_reflect_the_most_recent_pushes() {
+        let mut app = application(ScriptedSource::new("scripted"));
+        let speed = "speed".parse::<MetricName>().unwrap();
+
+        assert_eq!(app.latest_values().count(), 0);
+        assert_eq!(app.latest_value(&speed), None);
+
+        app.ingest(metric(1, "speed", MetricValue::One(OneValue::U32(1))));
+        app.ingest(metric(2, "heading", MetricValue::One(OneValue::I16(-90))));
+        app.ingest(metric(3, "speed", MetricValue::One(OneValue::U32(3))));
+
+        let latest = app
+            .latest_values()
+            .map(|(name, (timestamp, _, value))| (name.to_string(), *timestamp, value.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            latest,
+            [
+                (
+                    "heading".to_owned(),
+                    Timestamp::from_millis(2),
+                    MetricValue::One(OneValue::I16(-90))
+                ),
+                (
+                    "speed".to_owned(),
+                    Timestamp::from_millis(3),
+                    MetricValue::One(OneValue::U32(3))
+                ),
+            ]
+        );
+        assert_eq!(
+            app.latest_value(&speed).map(|(_, _, value)| value),
+            Some(&MetricValue::One(OneValue::U32(3)))
+        );
+    }
+
+    #[test]
+    fn latest_values_include_rate_limited_samples() {
+        let mut app = application(ScriptedSource::new("scripted"));
+        app.rate_limiter.global_limit = Some(1);
+
+        app.ingest(metric(1, "speed", MetricValue::One(OneValue::U32(1))));
+        app.ingest(metric(2, "speed", MetricValue::One(OneValue::U32(2))));
+
+        assert_eq!(
+            app.latest_value(&"speed".parse().unwrap())
+                .map(|(_, _, value)| value),
+            Some(&MetricValue::One(OneValue::U32(2)))
+        );
+    }
+
+    #[test]
+    fn ingest_keeps_the_raw_bytes_of_the_newest_sample() {
+        let mut app = application(ScriptedSource::new("scripted"));
+        let speed = "speed".parse::<MetricName>().unwrap();
+
+        for (millis, value) in [(1, 0x0102_u16), (2, 0x0304)] {
+            app.ingest(Metric {
+                raw: Some(value.to_le_bytes().into()),
+                ..metric(millis, "speed", MetricValue::One(OneValue::U16(value)))
             });
+        }
+        app.ingest(metric(3, "heading", MetricValue::One(OneValue::I16(1))));
+
+        assert_eq!(
+            app.latest_raw.get(&speed).map(AsRef::as_ref),
+            Some(&[4, 3][..])
+        );
+        assert_eq!(HexDump(&app.latest_raw[&speed]).to_string(), "04 03");
+        assert!(!app
+            .latest_raw
+            .contains_key(&"heading".parse::<MetricName>().unwrap()));
     }
 }
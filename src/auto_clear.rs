@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+/// Periodically clears all collected metrics, for unattended displays
+#[derive(Debug)]
+pub struct AutoClear {
+    pub enabled: bool,
+    pub interval_minutes: u32,
+
+    last_clear: Instant,
+}
+
+impl AutoClear {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            enabled: false,
+            interval_minutes: 10,
+
+            last_clear: now,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(u64::from(self.interval_minutes.max(1)) * 60)
+    }
+
+    /// Time left until the next clear, if enabled
+    pub fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.enabled.then(|| {
+            self.interval()
+                .saturating_sub(now.saturating_duration_since(self.last_clear))
+        })
+    }
+
+    /// Restart the countdown, such as after a manual clear
+    pub fn reset(&mut self, now: Instant) {
+        self.last_clear = now;
+    }
+
+    /// Returns true if the metrics should be cleared now
+    ///
+    /// The timer will not fire while paused, instead firing once ingest resumes
+    pub fn poll(&mut self, now: Instant, paused: bool) -> bool {
+        if paused || self.remaining(now) != Some(Duration::ZERO) {
+            return false;
+        }
+
+        self.last_clear = now;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    fn enabled(start: Instant, interval_minutes: u32) -> AutoClear {
+        AutoClear {
+            enabled: true,
+            interval_minutes,
+            ..AutoClear::new(start)
+        }
+    }
+
+    #[test]
+    fn fires_once_the_interval_elapsed() {
+        let start = Instant::now();
+        let mut auto_clear = enabled(start, 2);
+
+        assert!(!auto_clear.poll(start, false));
+        assert!(!auto_clear.poll(start + MINUTE, false));
+        assert_eq!(auto_clear.remaining(start + MINUTE), Some(MINUTE));
+        assert!(auto_clear.poll(start + 2 * MINUTE, false));
+
+        // The countdown starts again from the clear
+        assert!(!auto_clear.poll(start + 3 * MINUTE, false));
+        assert!(auto_clear.poll(start + 4 * MINUTE, false));
+    }
+
+    #[test]
+    fn late_poll_fires_once() {
+        let start = Instant::now();
+        let mut auto_clear = enabled(start, 1);
+
+        assert!(auto_clear.poll(start + 10 * MINUTE, false));
+        assert!(!auto_clear.poll(start + 10 * MINUTE, false));
+    }
+
+    #[test]
+    fn disabled_never_fires() {
+        let start = Instant::now();
+        let mut auto_clear = AutoClear::new(start);
+
+        assert_eq!(auto_clear.remaining(start + MINUTE), None);
+        assert!(!auto_clear.poll(start + 60 * MINUTE, false));
+    }
+
+    #[test]
+    fn pause_holds_the_clear_until_ingest_resumes() {
+        let start = Instant::now();
+        let mut auto_clear = enabled(start, 1);
+
+        assert!(!auto_clear.poll(start + 2 * MINUTE, true));
+        assert!(auto_clear.poll(start + 3 * MINUTE, false));
+    }
+
+    #[test]
+    fn reset_restarts_the_countdown() {
+        let start = Instant::now();
+        let mut auto_clear = enabled(start, 1);
+
+        auto_clear.reset(start + Duration::from_secs(30));
+
+        assert!(!auto_clear.poll(start + MINUTE, false));
+        assert!(auto_clear.poll(start + MINUTE + Duration::from_secs(30), false));
+    }
+
+    #[test]
+    fn zero_interval_is_one_minute() {
+        let start = Instant::now();
+        let mut auto_clear = enabled(start, 0);
+
+        assert_eq!(auto_clear.interval(), MINUTE);
+        assert!(!auto_clear.poll(start, false));
+        assert!(auto_clear.poll(start + MINUTE, false));
+    }
+}
@@ -0,0 +1,84 @@
+use kestrel_metric::timestamp::Timestamp;
+
+/// Moves metrics that stopped updating out of the latest metrics table,
+/// and back in as soon as they update again
+#[derive(Debug)]
+pub struct StaleAutoHide {
+    pub enabled: bool,
+    /// Time since the latest sample after which a metric counts as stale
+    pub timeout_secs: f64,
+}
+
+impl Default for StaleAutoHide {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: 5.0,
+        }
+    }
+}
+
+impl StaleAutoHide {
+    /// Whether a metric last updated at `latest` should be moved to the stale section at `now`
+    pub fn is_stale(&self, now: Timestamp, latest: Timestamp) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let ticks_per_second = f64::from(now.unit().ticks_per_second());
+        let tslm = f64::from((now - latest).timestamp()) / ticks_per_second;
+
+        tslm > self.timeout_secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled() -> StaleAutoHide {
+        StaleAutoHide {
+            enabled: true,
+            timeout_secs: 5.0,
+        }
+    }
+
+    #[test]
+    fn hides_once_stale_and_restores_on_update() {
+        let auto_hide = enabled();
+        let mut latest = Timestamp::from_millis(1_000);
+
+        assert!(!auto_hide.is_stale(Timestamp::from_millis(6_000), latest));
+        assert!(auto_hide.is_stale(Timestamp::from_millis(6_001), latest));
+
+        // A new sample brings the metric back right away
+        latest = Timestamp::from_millis(6_001);
+        assert!(!auto_hide.is_stale(Timestamp::from_millis(6_001), latest));
+    }
+
+    #[test]
+    fn disabled_never_hides() {
+        let auto_hide = StaleAutoHide::default();
+
+        assert!(!auto_hide.is_stale(Timestamp::from_millis(u32::MAX / 2), Timestamp::MIN));
+    }
+
+    #[test]
+    fn timeout_in_microseconds() {
+        let auto_hide = enabled();
+        let latest = Timestamp::from_micros(0);
+
+        assert!(!auto_hide.is_stale(Timestamp::from_micros(5_000_000), latest));
+        assert!(auto_hide.is_stale(Timestamp::from_micros(5_000_001), latest));
+    }
+
+    #[test]
+    fn stays_shown_across_clock_rollover() {
+        let auto_hide = enabled();
+
+        assert!(!auto_hide.is_stale(
+            Timestamp::from_millis(1_000),
+            Timestamp::from_millis(u32::MAX - 1_000)
+        ));
+    }
+}
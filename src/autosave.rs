@@ -0,0 +1,213 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::{name::MetricName, Metric};
+use tracing::{error, info, warn};
+
+/// How often buffered metrics are written out to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Continuously appends every received metric to a JSON lines file, so a crash
+/// does not lose the session
+#[derive(Debug)]
+pub struct Autosave {
+    path: PathBuf,
+    /// `None` once writing has failed, after which autosaving stops
+    writer: Option<BufWriter<File>>,
+    last_flush: Instant,
+}
+
+impl Autosave {
+    pub fn open(path: PathBuf, now: Instant) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+        info!(?path, "autosaving metrics");
+
+        Ok(Self {
+            path,
+            writer: Some(BufWriter::new(file)),
+            last_flush: now,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether metrics are still being written, as opposed to stopped by a disk error
+    pub fn is_active(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn append(&mut self, metric: &Metric) {
+        let Some(writer) = &mut self.writer else {
+            return;
+        };
+
+        let result = serde_json::to_writer(&mut *writer, metric)
+            .map_err(io::Error::from)
+            .and_then(|()| writer.write_all(b"\n"));
+
+        self.stop_on_error(result);
+    }
+
+    /// Flush buffered metrics to disk, at most once per [`FLUSH_INTERVAL`]
+    pub fn poll_flush(&mut self, now: Instant) {
+        if now.saturating_duration_since(self.last_flush) < FLUSH_INTERVAL {
+            return;
+        }
+
+        self.last_flush = now;
+
+        if let Some(writer) = &mut self.writer {
+            let result = writer.flush();
+
+            self.stop_on_error(result);
+        }
+    }
+
+    /// Disk errors stop the autosave instead of interrupting ingest
+    fn stop_on_error(&mut self, result: io::Result<()>) {
+        if let Err(err) = result {
+            error!(?err, path = ?self.path, "failed to autosave metrics, autosave stopped");
+
+            self.writer = None;
+        }
+    }
+}
+
+/// Read the last `count` samples of every metric in an autosave file, in the order
+/// they were saved, skipping lines that can not be parsed, such as one cut off by a crash
+///
+/// Each metric keeps its own tail, so a chatty metric does not crowd out the others.
+pub fn load_tail(path: &Path, count: usize) -> io::Result<Vec<Metric>> {
+    let mut tails = BTreeMap::<MetricName, VecDeque<(usize, Metric)>>::new();
+
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        match serde_json::from_str::<Metric>(&line?) {
+            Ok(metric) => {
+                let tail = tails.entry(metric.name.clone()).or_default();
+                if tail.len() == count {
+                    tail.pop_front();
+                }
+
+                if count > 0 {
+                    tail.push_back((index, metric));
+                }
+            }
+            Err(err) => warn!(%err, line = index + 1, "skipping unreadable autosaved metric"),
+        }
+    }
+
+    let mut metrics = tails.into_values().flatten().collect::<Vec<_>>();
+    metrics.sort_unstable_by_key(|(index, _)| *index);
+
+    Ok(metrics.into_iter().map(|(_, metric)| metric).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use kestrel_metric::{
+        timestamp::Timestamp,
+        value::{MetricValue, OneValue},
+    };
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn metric(millis: u32, name: &str) -> Metric {
+        Metric {
+            timestamp: Timestamp::from_millis(millis),
+            arrival: OffsetDateTime::UNIX_EPOCH,
+            name: name.parse().unwrap(),
+            value: MetricValue::One(OneValue::U32(millis)),
+            raw: None,
+        }
+    }
+
+    fn loaded(path: &Path, count: usize) -> Vec<(u32, String)> {
+        load_tail(path, count)
+            .unwrap()
+            .into_iter()
+            .map(|metric| (metric.timestamp.timestamp(), metric.name.to_string()))
+            .collect()
+    }
+
+    /// A path in the temporary directory unique to this test
+    fn temp_path(test: &str) -> PathBuf {
+        env::temp_dir().join(format!("kestrel-autosave-{test}-{}.jsonl", process::id()))
+    }
+
+    #[test]
+    fn appended_metrics_are_on_disk_after_a_flush() {
+        let path = temp_path("flush");
+        let start = Instant::now();
+        let mut autosave = Autosave::open(path.clone(), start).unwrap();
+
+        autosave.append(&metric(1, "a"));
+        autosave.poll_flush(start);
+        assert_eq!(loaded(&path, 8), []);
+
+        autosave.poll_flush(start + FLUSH_INTERVAL);
+        assert_eq!(loaded(&path, 8), [(1, "a".to_owned())]);
+        assert!(autosave.is_active());
+
+        drop(autosave);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn resumes_after_a_crash_mid_line() {
+        let path = temp_path("crash");
+        let start = Instant::now();
+        let mut autosave = Autosave::open(path.clone(), start).unwrap();
+
+        autosave.append(&metric(1, "a"));
+        autosave.append(&metric(2, "b"));
+        autosave.poll_flush(start + FLUSH_INTERVAL);
+        drop(autosave);
+
+        // The process died while writing the next line
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"timestamp":{"timest"#).unwrap();
+        drop(file);
+
+        assert_eq!(loaded(&path, 8), [(1, "a".to_owned()), (2, "b".to_owned())]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_a_tail_per_metric() {
+        let path = temp_path("tail");
+        let start = Instant::now();
+        let mut autosave = Autosave::open(path.clone(), start).unwrap();
+
+        autosave.append(&metric(1, "rare"));
+        for millis in 2..10 {
+            autosave.append(&metric(millis, "chatty"));
+        }
+        autosave.append(&metric(10, "rare"));
+        autosave.poll_flush(start + FLUSH_INTERVAL);
+
+        assert_eq!(
+            loaded(&path, 2),
+            [
+                (1, "rare".to_owned()),
+                (8, "chatty".to_owned()),
+                (9, "chatty".to_owned()),
+                (10, "rare".to_owned()),
+            ]
+        );
+        assert_eq!(loaded(&path, 0), []);
+
+        drop(autosave);
+        fs::remove_file(&path).unwrap();
+    }
+}
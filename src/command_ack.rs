@@ -0,0 +1,181 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use eframe::{
+    egui::{Align2, Area, Context, Frame, Id, RichText, Vec2},
+    epaint::Color32,
+};
+use kestrel_serial::SendCommandError;
+
+/// Longest the firmware may take to acknowledge a command before a warning is shown
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the outcome of a command stays on screen
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AckOutcome {
+    Acknowledged,
+    TimedOut,
+    /// The command never left the UI, so no acknowledgement is awaited
+    NotSent(SendCommandError),
+}
+
+#[derive(Debug)]
+struct Toast {
+    label: String,
+    outcome: AckOutcome,
+    shown_at: Instant,
+}
+
+/// Commands sent from the UI that wait for the firmware to acknowledge them,
+/// with the recent outcomes shown as notifications in the corner of the window
+#[derive(Debug, Default)]
+pub struct CommandAcks {
+    /// Opcode and label of each command waiting for an acknowledgement, with when it was sent
+    pending: Vec<(u8, String, Instant)>,
+    toasts: VecDeque<Toast>,
+    /// Time from sending the most recently acknowledged command to its acknowledgement
+    round_trip: Option<Duration>,
+}
+
+impl CommandAcks {
+    /// Start waiting for the acknowledgement of a command that was just sent
+    pub fn sent(&mut self, command: u8, label: String, now: Instant) {
+        self.pending.push((command, label, now));
+    }
+
+    /// Show that a command could not be sent, instead of waiting for an acknowledgement
+    pub fn not_sent(&mut self, label: String, err: SendCommandError, now: Instant) {
+        self.toasts.push_back(Toast {
+            label,
+            outcome: AckOutcome::NotSent(err),
+            shown_at: now,
+        });
+    }
+
+    /// Handle an acknowledgement from the firmware, matched to the oldest command with its opcode
+    ///
+    /// Acknowledgements of commands that were not sent from the UI, or already
+    /// timed out, are ignored.
+    pub fn acknowledged(&mut self, command: u8, now: Instant) {
+        let Some(index) = self
+            .pending
+            .iter()
+            .position(|(pending, _, _)| *pending == command)
+        else {
+            return;
+        };
+
+        let (_, label, sent) = self.pending.remove(index);
+        self.round_trip = Some(now.saturating_duration_since(sent));
+        self.toasts.push_back(Toast {
+            label,
+            outcome: AckOutcome::Acknowledged,
+            shown_at: now,
+        });
+    }
+
+    /// Round trip of the most recently acknowledged command, from sending it to the
+    /// firmware echoing it back
+    pub fn round_trip(&self) -> Option<Duration> {
+        self.round_trip
+    }
+
+    /// Give up on the commands that waited too long, and hide the outcomes that were shown long enough
+    ///
+    /// Returns when this should be polled again, if anything is pending or shown.
+    pub fn poll(&mut self, now: Instant) -> Option<Duration> {
+        let (timed_out, pending) = self
+            .pending
+            .drain(..)
+            .partition::<Vec<_>, _>(|(_, _, sent)| now.duration_since(*sent) >= ACK_TIMEOUT);
+        self.pending = pending;
+
+        self.toasts
+            .extend(timed_out.into_iter().map(|(_, label, _)| Toast {
+                label,
+                outcome: AckOutcome::TimedOut,
+                shown_at: now,
+            }));
+        self.toasts
+            .retain(|toast| now.duration_since(toast.shown_at) < TOAST_DURATION);
+
+        let next_timeout = self
+            .pending
+            .iter()
+            .map(|(_, _, sent)| (*sent + ACK_TIMEOUT).saturating_duration_since(now));
+        let next_expiry = self
+            .toasts
+            .iter()
+            .map(|toast| (toast.shown_at + TOAST_DURATION).saturating_duration_since(now));
+
+        next_timeout.chain(next_expiry).min()
+    }
+
+    /// Draw the shown outcomes in the bottom right corner, newest at the bottom
+    pub fn ui(&self, ctx: &Context) {
+        if self.toasts.is_empty() {
+            return;
+        }
+
+        Area::new(Id::new("command_acks"))
+            .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-10.0, -10.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                for toast in &self.toasts {
+                    Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.label(match toast.outcome {
+                            AckOutcome::Acknowledged => {
+                                RichText::new(format!("✔ Robot acknowledged {}", toast.label))
+                                    .color(Color32::GREEN)
+                            }
+                            AckOutcome::TimedOut => RichText::new(format!(
+                                "⚠ No acknowledgement for {} within {} s",
+                                toast.label,
+                                ACK_TIMEOUT.as_secs()
+                            ))
+                            .color(Color32::YELLOW),
+                            AckOutcome::NotSent(err) => {
+                                RichText::new(format!("✖ {} was not sent: {err}", toast.label))
+                                    .color(Color32::RED)
+                            }
+                        });
+                    });
+                }
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip_is_measured_from_sending_to_acknowledgement() {
+        let mut acks = CommandAcks::default();
+        let sent = Instant::now();
+        assert_eq!(acks.round_trip(), None);
+
+        acks.sent(0x01, "first".to_owned(), sent);
+        acks.sent(0x02, "second".to_owned(), sent + Duration::from_millis(10));
+        acks.acknowledged(0x02, sent + Duration::from_millis(35));
+        assert_eq!(acks.round_trip(), Some(Duration::from_millis(25)));
+
+        acks.acknowledged(0x01, sent + Duration::from_millis(50));
+        assert_eq!(acks.round_trip(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn unmatched_acknowledgement_keeps_the_last_round_trip() {
+        let mut acks = CommandAcks::default();
+        let sent = Instant::now();
+
+        acks.sent(0x01, "command".to_owned(), sent);
+        acks.acknowledged(0x01, sent + Duration::from_millis(20));
+        acks.acknowledged(0x01, sent + Duration::from_millis(90));
+
+        assert_eq!(acks.round_trip(), Some(Duration::from_millis(20)));
+    }
+}
@@ -0,0 +1,26 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+};
+
+use serde::Deserialize;
+
+/// A command of the firmware sent as its raw opcode, defined in a config file
+/// so new commands do not need a rebuild
+#[derive(Debug, Clone, Deserialize)]
+pub struct NamedCommand {
+    pub name: String,
+    pub opcode: u8,
+    /// Shown when hovering the button of the command
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Read a JSON array of commands, such as
+/// `[{ "name": "Stop", "opcode": 5, "description": "Halt the motors" }]`
+pub fn load(path: &Path) -> io::Result<Vec<NamedCommand>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    Ok(serde_json::from_reader(reader)?)
+}
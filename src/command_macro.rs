@@ -0,0 +1,196 @@
+use std::time::{Duration, Instant};
+
+use kestrel_metric::RobotCommand;
+use serde::{Deserialize, Serialize};
+
+/// A recorded sequence of robot commands, replayable with the original timing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandMacro {
+    pub name: String,
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MacroStep {
+    /// Time since the previous step, or since recording started for the first step
+    pub delay: Duration,
+    pub command: RobotCommand,
+}
+
+/// Records commands as they are sent by the user
+#[derive(Debug)]
+pub struct MacroRecorder {
+    last: Instant,
+    steps: Vec<MacroStep>,
+}
+
+impl MacroRecorder {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            last: now,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, command: RobotCommand, now: Instant) {
+        self.steps.push(MacroStep {
+            delay: now.saturating_duration_since(self.last),
+            command,
+        });
+        self.last = now;
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn finish(self, name: String) -> CommandMacro {
+        CommandMacro {
+            name,
+            steps: self.steps,
+        }
+    }
+}
+
+/// Replays a macro, handing out each command once its delay has elapsed
+///
+/// Steps are scheduled from when playback started, so a late frame does not push
+/// back every step after it.
+#[derive(Debug)]
+pub struct MacroPlayback {
+    name: String,
+    steps: Vec<MacroStep>,
+    next: usize,
+    started: Instant,
+    /// Time from the start of playback to the step before `next`
+    elapsed: Duration,
+}
+
+impl MacroPlayback {
+    pub fn new(command_macro: &CommandMacro, now: Instant) -> Self {
+        Self {
+            name: command_macro.name.clone(),
+            steps: command_macro.steps.clone(),
+            next: 0,
+            started: now,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Number of steps issued so far, and the total number of steps
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.steps.len())
+    }
+
+    /// Time left until the next command is due
+    pub fn time_until_next(&self, now: Instant) -> Option<Duration> {
+        self.steps.get(self.next).map(|step| {
+            (self.elapsed + step.delay).saturating_sub(now.saturating_duration_since(self.started))
+        })
+    }
+
+    /// The next command, if it is due
+    pub fn poll(&mut self, now: Instant) -> Option<RobotCommand> {
+        if self.time_until_next(now)? != Duration::ZERO {
+            return None;
+        }
+
+        let step = self.steps[self.next];
+
+        self.next += 1;
+        self.elapsed += step.delay;
+
+        Some(step.command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMANDS: [RobotCommand; 3] = [
+        RobotCommand::CalibrateAmbientInfrared,
+        RobotCommand::CalibrateReferenceInfrared,
+        RobotCommand::CalibrateAmbientInfrared,
+    ];
+
+    fn recorded(start: Instant) -> CommandMacro {
+        let mut recorder = MacroRecorder::new(start);
+
+        recorder.record(COMMANDS[0], start + Duration::from_millis(100));
+        recorder.record(COMMANDS[1], start + Duration::from_millis(300));
+        recorder.record(COMMANDS[2], start + Duration::from_millis(300));
+        assert_eq!(recorder.len(), 3);
+
+        recorder.finish("test".to_owned())
+    }
+
+    #[test]
+    fn records_the_time_between_commands() {
+        let command_macro = recorded(Instant::now());
+
+        assert_eq!(
+            command_macro
+                .steps
+                .iter()
+                .map(|step| step.delay)
+                .collect::<Vec<_>>(),
+            [
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::ZERO
+            ]
+        );
+    }
+
+    #[test]
+    fn plays_back_in_order() {
+        let start = Instant::now();
+        let mut playback = MacroPlayback::new(&recorded(start), start);
+        let mut sent = Vec::new();
+
+        for millis in (0..=400).step_by(50) {
+            while let Some(command) = playback.poll(start + Duration::from_millis(millis)) {
+                sent.push(command);
+            }
+        }
+
+        assert_eq!(sent, COMMANDS);
+        assert_eq!(playback.progress(), (3, 3));
+        assert_eq!(playback.time_until_next(start), None);
+    }
+
+    #[test]
+    fn late_polls_do_not_delay_later_steps() {
+        let start = Instant::now();
+        let mut playback = MacroPlayback::new(&recorded(start), start);
+
+        assert_eq!(
+            playback.time_until_next(start),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(playback.poll(start + Duration::from_millis(50)), None);
+
+        // The first step is handled 80 ms late, the second is still due 300 ms after the start
+        assert_eq!(
+            playback.poll(start + Duration::from_millis(180)),
+            Some(COMMANDS[0])
+        );
+        assert_eq!(
+            playback.time_until_next(start + Duration::from_millis(180)),
+            Some(Duration::from_millis(120))
+        );
+        assert_eq!(
+            playback.poll(start + Duration::from_millis(300)),
+            Some(COMMANDS[1])
+        );
+        assert_eq!(
+            playback.poll(start + Duration::from_millis(300)),
+            Some(COMMANDS[2])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::BTreeSet;
+
+use kestrel_metric::RobotCommand;
+use serde::{Deserialize, Serialize};
+
+/// An action that can be set to require confirmation before it is carried out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GuardedAction {
+    Reset,
+    Command(RobotCommand),
+}
+
+impl GuardedAction {
+    pub const ALL: [GuardedAction; 3] = [
+        GuardedAction::Reset,
+        GuardedAction::Command(RobotCommand::CalibrateAmbientInfrared),
+        GuardedAction::Command(RobotCommand::CalibrateReferenceInfrared),
+    ];
+
+    pub fn label(self) -> String {
+        match self {
+            GuardedAction::Reset => "Reset Arduino".to_owned(),
+            GuardedAction::Command(command) => format!("{command:?}"),
+        }
+    }
+}
+
+/// Holds back actions that need an "Are you sure?" until the user confirms or cancels them
+#[derive(Debug, Default)]
+pub struct Confirmations {
+    /// Actions that need to be confirmed before they are carried out
+    pub required: BTreeSet<GuardedAction>,
+
+    pending: Option<GuardedAction>,
+}
+
+impl Confirmations {
+    /// Ask to carry out an action, returning it if it can be dispatched right away
+    ///
+    /// Actions that need confirmation are held until [`Confirmations::confirm`]
+    /// returns them, replacing any action that was already waiting.
+    pub fn request(&mut self, action: GuardedAction) -> Option<GuardedAction> {
+        if self.required.contains(&action) {
+            self.pending = Some(action);
+
+            None
+        } else {
+            Some(action)
+        }
+    }
+
+    /// The action waiting for confirmation
+    pub fn pending(&self) -> Option<GuardedAction> {
+        self.pending
+    }
+
+    /// Release the waiting action to be dispatched
+    pub fn confirm(&mut self) -> Option<GuardedAction> {
+        self.pending.take()
+    }
+
+    /// Drop the waiting action without dispatching it
+    pub fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    pub fn set_required(&mut self, action: GuardedAction, required: bool) {
+        if required {
+            self.required.insert(action);
+        } else {
+            self.required.remove(&action);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALIBRATE: GuardedAction = GuardedAction::Command(RobotCommand::CalibrateAmbientInfrared);
+
+    #[test]
+    fn unguarded_actions_pass_right_away() {
+        let mut confirmations = Confirmations::default();
+
+        assert_eq!(
+            confirmations.request(GuardedAction::Reset),
+            Some(GuardedAction::Reset)
+        );
+        assert_eq!(confirmations.pending(), None);
+    }
+
+    #[test]
+    fn confirm_releases_the_held_action() {
+        let mut confirmations = Confirmations::default();
+        confirmations.set_required(GuardedAction::Reset, true);
+
+        assert_eq!(confirmations.request(GuardedAction::Reset), None);
+        assert_eq!(confirmations.pending(), Some(GuardedAction::Reset));
+
+        assert_eq!(confirmations.confirm(), Some(GuardedAction::Reset));
+        assert_eq!(confirmations.confirm(), None);
+    }
+
+    #[test]
+    fn cancel_drops_the_held_action() {
+        let mut confirmations = Confirmations::default();
+        confirmations.set_required(CALIBRATE, true);
+
+        assert_eq!(confirmations.request(CALIBRATE), None);
+        confirmations.cancel();
+
+        assert_eq!(confirmations.pending(), None);
+        assert_eq!(confirmations.confirm(), None);
+    }
+
+    #[test]
+    fn newer_request_replaces_the_held_action() {
+        let mut confirmations = Confirmations::default();
+        confirmations.set_required(GuardedAction::Reset, true);
+        confirmations.set_required(CALIBRATE, true);
+
+        confirmations.request(GuardedAction::Reset);
+        confirmations.request(CALIBRATE);
+
+        assert_eq!(confirmations.confirm(), Some(CALIBRATE));
+    }
+
+    #[test]
+    fn requirement_can_be_lifted() {
+        let mut confirmations = Confirmations::default();
+        confirmations.set_required(CALIBRATE, true);
+        confirmations.set_required(CALIBRATE, false);
+
+        assert_eq!(confirmations.request(CALIBRATE), Some(CALIBRATE));
+    }
+}
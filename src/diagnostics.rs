@@ -0,0 +1,78 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use kestrel_metric::Metric;
+use serde::Serialize;
+use zip::{write::FileOptions, ZipWriter};
+
+/// Ask the user where to save a diagnostics bundle
+pub fn save_bundle_dialog(default_name: &str) -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .set_file_name(default_name)
+        .add_filter("Zip archive", &["zip"])
+        .save_file()
+}
+
+/// Files describing the state of a session, bundled into a zip archive to attach to bug reports
+///
+/// Only telemetry and settings are meant to go in here, never paths or other user input.
+#[derive(Debug, Default)]
+pub struct DiagnosticsBundle {
+    entries: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl DiagnosticsBundle {
+    pub fn add_text(&mut self, name: &'static str, text: String) {
+        self.entries.push((name, text.into_bytes()));
+    }
+
+    pub fn add_json(
+        &mut self,
+        name: &'static str,
+        value: &impl Serialize,
+    ) -> serde_json::Result<()> {
+        self.entries.push((name, serde_json::to_vec_pretty(value)?));
+
+        Ok(())
+    }
+
+    /// Add metrics as JSON lines, one metric per line
+    pub fn add_metrics<'m>(
+        &mut self,
+        name: &'static str,
+        metrics: impl Iterator<Item = &'m Metric>,
+    ) -> serde_json::Result<()> {
+        let mut lines = Vec::new();
+
+        for metric in metrics {
+            serde_json::to_writer(&mut lines, metric)?;
+            lines.push(b'\n');
+        }
+
+        self.entries.push((name, lines));
+
+        Ok(())
+    }
+
+    pub fn write_zip(&self, writer: impl Write + Seek) -> zip::result::ZipResult<()> {
+        let mut zip = ZipWriter::new(writer);
+
+        for (name, contents) in &self.entries {
+            zip.start_file(*name, FileOptions::default())?;
+            zip.write_all(contents)?;
+        }
+
+        zip.finish()?;
+
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> zip::result::ZipResult<()> {
+        let file = BufWriter::new(File::create(path)?);
+
+        self.write_zip(file)
+    }
+}
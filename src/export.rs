@@ -0,0 +1,331 @@
+use std::{
+    borrow::Cow,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use kestrel_metric::{timestamp::Timestamp, value::MetricValue, Metric};
+use time::{format_description::well_known::Iso8601, OffsetDateTime};
+
+/// Ask the user where to save a csv file
+pub fn save_csv_dialog(default_name: &str) -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .set_file_name(default_name)
+        .add_filter("CSV", &["csv"])
+        .save_file()
+}
+
+/// Ask the user where to save a packet recording
+pub fn save_recording_dialog(default_name: &str) -> Option<PathBuf> {
+    rfd::FileDialog::new()
+        .set_file_name(default_name)
+        .add_filter("Packet recording", &["kestrel"])
+        .save_file()
+}
+
+/// Quote a csv field if it contains a separator, quote or newline
+pub fn escape_csv(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Write metrics as rows of `timestamp,name,type,value`
+pub fn write_metrics_csv<'m>(
+    path: &Path,
+    metrics: impl Iterator<Item = &'m Metric>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "timestamp,name,type,value")?;
+
+    for metric in metrics {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            metric.timestamp.timestamp(),
+            escape_csv(&metric.name.to_string()),
+            escape_csv(metric.value.ty()),
+            escape_csv(&metric.value.value()),
+        )?;
+    }
+
+    writer.flush()
+}
+
+/// Format a wall-clock arrival time as ISO-8601
+pub fn format_arrival(arrival: OffsetDateTime) -> String {
+    arrival
+        .format(&Iso8601::DEFAULT)
+        .expect("every OffsetDateTime can be formatted as ISO-8601")
+}
+
+/// Write the samples of a single metric as rows of `timestamp,arrival,type,value`,
+/// with both the robot clock and the wall-clock arrival time
+pub fn write_metric_samples_csv<'m>(
+    path: &Path,
+    samples: impl Iterator<Item = &'m (Timestamp, OffsetDateTime, MetricValue)>,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writeln!(writer, "timestamp,arrival,type,value")?;
+
+    for (timestamp, arrival, value) in samples {
+        writeln!(
+            writer,
+            "{},{},{},{}",
+            timestamp.timestamp(),
+            format_arrival(*arrival),
+            escape_csv(value.ty()),
+            escape_csv(&value.value()),
+        )?;
+    }
+
+    writer.flush()
+}
+
+/// How to fill in a column at timestamps where its metric has no sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Interpolation {
+    /// Leave the cell empty
+    #[default]
+    None,
+    /// Repeat the previous sample, the right choice for step and bool signals
+    /// which hold their value until the next sample
+    ZeroOrderHold,
+    /// Draw a straight line between the surrounding samples, for continuous
+    /// signals, leaving cells after the last sample empty
+    Linear,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 3] = [
+        Interpolation::None,
+        Interpolation::ZeroOrderHold,
+        Interpolation::Linear,
+    ];
+
+    pub const fn label(self) -> &'static str {
+        match self {
+            Interpolation::None => "No interpolation",
+            Interpolation::ZeroOrderHold => "Zero-order hold",
+            Interpolation::Linear => "Linear",
+        }
+    }
+}
+
+/// Align numeric series on the union of their timestamps, returning the
+/// timestamps and a row of one cell per series for each of them
+///
+/// Every series must be sorted by timestamp. Cells before the first sample of
+/// a series are always empty.
+pub fn align_series(
+    series: &[Vec<(u32, f64)>],
+    interpolation: Interpolation,
+) -> Vec<(u32, Vec<Option<f64>>)> {
+    let mut timestamps = series
+        .iter()
+        .flat_map(|samples| samples.iter().map(|&(timestamp, _)| timestamp))
+        .collect::<Vec<_>>();
+    timestamps.sort_unstable();
+    timestamps.dedup();
+
+    // Number of samples of each series at or before the current timestamp
+    let mut seen = vec![0; series.len()];
+
+    timestamps
+        .into_iter()
+        .map(|timestamp| {
+            let row = series
+                .iter()
+                .zip(&mut seen)
+                .map(|(samples, seen)| {
+                    while samples
+                        .get(*seen)
+                        .is_some_and(|&(sample_time, _)| sample_time <= timestamp)
+                    {
+                        *seen += 1;
+                    }
+
+                    let previous = seen.checked_sub(1).map(|index| samples[index])?;
+                    if previous.0 == timestamp {
+                        return Some(previous.1);
+                    }
+
+                    match interpolation {
+                        Interpolation::None => None,
+                        Interpolation::ZeroOrderHold => Some(previous.1),
+                        Interpolation::Linear => {
+                            let (next_time, next) = *samples.get(*seen)?;
+                            let fraction = f64::from(timestamp - previous.0)
+                                / f64::from(next_time - previous.0);
+
+                            Some(previous.1 + (next - previous.1) * fraction)
+                        }
+                    }
+                })
+                .collect();
+
+            (timestamp, row)
+        })
+        .collect()
+}
+
+/// Write numeric series as one row per timestamp with a column per series
+pub fn write_aligned_csv(
+    path: &Path,
+    names: &[String],
+    series: &[Vec<(u32, f64)>],
+    interpolation: Interpolation,
+) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    write!(writer, "timestamp")?;
+    for name in names {
+        write!(writer, ",{}", escape_csv(name))?;
+    }
+    writeln!(writer)?;
+
+    for (timestamp, row) in align_series(series, interpolation) {
+        write!(writer, "{timestamp}")?;
+        for cell in row {
+            match cell {
+                Some(value) => write!(writer, ",{value}")?,
+                None => write!(writer, ",")?,
+            }
+        }
+        writeln!(writer)?;
+    }
+
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    /// Two series that never share a timestamp, the second starting late
+    fn sparse() -> Vec<Vec<(u32, f64)>> {
+        vec![vec![(0, 0.0), (40, 4.0)], vec![(20, 10.0), (30, 20.0)]]
+    }
+
+    #[test]
+    fn no_interpolation_leaves_gaps() {
+        assert_eq!(
+            align_series(&sparse(), Interpolation::None),
+            [
+                (0, vec![Some(0.0), None]),
+                (20, vec![None, Some(10.0)]),
+                (30, vec![None, Some(20.0)]),
+                (40, vec![Some(4.0), None]),
+            ]
+        );
+    }
+
+    #[test]
+    fn zero_order_hold_repeats_the_previous_sample() {
+        assert_eq!(
+            align_series(&sparse(), Interpolation::ZeroOrderHold),
+            [
+                (0, vec![Some(0.0), None]),
+                (20, vec![Some(0.0), Some(10.0)]),
+                (30, vec![Some(0.0), Some(20.0)]),
+                (40, vec![Some(4.0), Some(20.0)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn linear_interpolates_between_samples() {
+        assert_eq!(
+            align_series(&sparse(), Interpolation::Linear),
+            [
+                (0, vec![Some(0.0), None]),
+                (20, vec![Some(2.0), Some(10.0)]),
+                (30, vec![Some(3.0), Some(20.0)]),
+                (40, vec![Some(4.0), None]),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_series_have_no_rows() {
+        assert!(align_series(&[], Interpolation::Linear).is_empty());
+        assert_eq!(
+            align_series(&[vec![], vec![(5, 1.0)]], Interpolation::ZeroOrderHold),
+            [(5, vec![None, Some(1.0)])]
+        );
+    }
+
+    /// Write a csv with `write` to a temporary file and read it back
+    fn written(name: &str, write: impl FnOnce(&Path) -> io::Result<()>) -> String {
+        let path =
+            std::env::temp_dir().join(format!("kestrel-{name}-export-{}.csv", std::process::id()));
+        write(&path).unwrap();
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        csv
+    }
+
+    #[test]
+    fn aligned_csv_writes_a_column_per_series() {
+        let csv = written("aligned", |path| {
+            write_aligned_csv(
+                path,
+                &["a".to_owned(), "b,c".to_owned()],
+                &sparse(),
+                Interpolation::ZeroOrderHold,
+            )
+        });
+
+        assert_eq!(
+            csv,
+            "timestamp,a,\"b,c\"\n0,0,\n20,0,10\n30,0,20\n40,4,20\n"
+        );
+    }
+
+    #[test]
+    fn arrival_is_iso_8601() {
+        let arrival = OffsetDateTime::UNIX_EPOCH + time::Duration::milliseconds(1_709_642_096_789);
+
+        assert_eq!(format_arrival(arrival), "2024-03-05T12:34:56.789000000Z");
+        assert_eq!(
+            format_arrival(OffsetDateTime::UNIX_EPOCH),
+            "1970-01-01T00:00:00.000000000Z"
+        );
+    }
+
+    #[test]
+    fn samples_csv_has_both_clocks() {
+        let arrival = OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(90);
+        let samples = [
+            (
+                Timestamp::from_millis(1_500),
+                arrival,
+                MetricValue::One(OneValue::U16(7)),
+            ),
+            (
+                Timestamp::from_micros(2_000_000),
+                arrival + time::Duration::milliseconds(500),
+                MetricValue::Many(ManyValues::U8([1, 2].into())),
+            ),
+        ];
+
+        let csv = written("samples", |path| {
+            write_metric_samples_csv(path, samples.iter())
+        });
+
+        assert_eq!(
+            csv,
+            "timestamp,arrival,type,value\n\
+             1500,1970-01-01T00:01:30.000000000Z,u16,7\n\
+             2000000,1970-01-01T00:01:30.500000000Z,[u8],\"[1, 2]\"\n"
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::BTreeSet;
+
+use kestrel_metric::name::MetricName;
+use serde::{Deserialize, Serialize};
+
+/// User chosen metrics, shown in an always visible bar for quick focusing
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Favorites {
+    metrics: BTreeSet<MetricName>,
+}
+
+impl Favorites {
+    pub fn contains(&self, metric_name: &MetricName) -> bool {
+        self.metrics.contains(metric_name)
+    }
+
+    pub fn toggle(&mut self, metric_name: &MetricName) {
+        if !self.metrics.remove(metric_name) {
+            self.metrics.insert(metric_name.clone());
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MetricName> {
+        self.metrics.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+}
+
+/// Toggle a metric in or out of the focused set
+pub fn toggle_focus(focused_metrics: &mut BTreeSet<MetricName>, metric_name: &MetricName) {
+    if !focused_metrics.remove(metric_name) {
+        focused_metrics.insert(metric_name.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> MetricName {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn toggle_adds_and_removes_a_favorite() {
+        let mut favorites = Favorites::default();
+
+        favorites.toggle(&name("motor:left"));
+        favorites.toggle(&name("speed"));
+        assert!(favorites.contains(&name("motor:left")));
+        assert_eq!(
+            favorites.iter().cloned().collect::<Vec<_>>(),
+            [name("motor:left"), name("speed")]
+        );
+
+        favorites.toggle(&name("motor:left"));
+        favorites.toggle(&name("speed"));
+        assert!(!favorites.contains(&name("motor:left")));
+        assert!(favorites.is_empty());
+    }
+
+    #[test]
+    fn toggle_focus_leaves_other_metrics_focused() {
+        let mut focused = BTreeSet::from([name("speed")]);
+
+        toggle_focus(&mut focused, &name("heading"));
+        assert_eq!(focused, BTreeSet::from([name("heading"), name("speed")]));
+
+        toggle_focus(&mut focused, &name("heading"));
+        assert_eq!(focused, BTreeSet::from([name("speed")]));
+    }
+
+    #[test]
+    fn favorites_persist_as_a_list_of_names() {
+        let mut favorites = Favorites::default();
+        favorites.toggle(&name("speed"));
+        favorites.toggle(&name("motor:left"));
+
+        let json = serde_json::to_string(&favorites).unwrap();
+        assert_eq!(json, r#"["motor:left","speed"]"#);
+
+        let restored = serde_json::from_str::<Favorites>(&json).unwrap();
+        assert!(restored.contains(&name("motor:left")) && restored.contains(&name("speed")));
+    }
+}
@@ -0,0 +1,176 @@
+use std::fmt::Write as _;
+
+use eframe::{
+    egui::{ComboBox, RichText, ScrollArea, Ui},
+    epaint::Color32,
+};
+use kestrel_metric::{
+    name::MetricName,
+    timestamp::Timestamp,
+    value::{MetricValue, OneValue},
+    Metric,
+};
+use kestrel_serial::LogLevel;
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
+
+/// Most log lines kept in the pane, older lines are dropped first
+const LOG_CAPACITY: usize = 2048;
+
+#[derive(Debug)]
+struct LogLine {
+    timestamp: Timestamp,
+    level: LogLevel,
+    message: Box<str>,
+}
+
+/// Pane with the log lines sent by the firmware, apart from the metrics
+#[derive(Debug)]
+pub struct FirmwareLog {
+    pub open: bool,
+    lines: AllocRingBuffer<LogLine>,
+    /// Least severe level that is shown, the more severe levels are shown too
+    max_level: LogLevel,
+}
+
+impl Default for FirmwareLog {
+    fn default() -> Self {
+        Self {
+            open: false,
+            lines: AllocRingBuffer::new(LOG_CAPACITY),
+            max_level: LogLevel::Trace,
+        }
+    }
+}
+
+impl FirmwareLog {
+    /// Exchange the kept lines with those of another robot, keeping the pane settings
+    pub fn swap_lines(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.lines, &mut other.lines);
+    }
+
+    pub fn push(&mut self, timestamp: Timestamp, level: LogLevel, message: Box<str>) {
+        self.lines.push(LogLine {
+            timestamp,
+            level,
+            message,
+        });
+    }
+
+    /// All kept lines, including the filtered ones, one per line
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+
+        for line in self.lines.iter() {
+            // Formatting into a string can not fail
+            let _ = writeln!(
+                text,
+                "{} [{}] {}",
+                line.timestamp,
+                line.level.label(),
+                line.message
+            );
+        }
+
+        text
+    }
+
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ComboBox::from_label("Levels up to")
+                .selected_text(self.max_level.label())
+                .show_ui(ui, |ui| {
+                    for level in LogLevel::ALL {
+                        ui.selectable_value(&mut self.max_level, level, level.label());
+                    }
+                });
+
+            if ui
+                .button("Copy all")
+                .on_hover_text_at_pointer("Copy every kept line, including the filtered ones")
+                .clicked()
+            {
+                ui.ctx().copy_text(self.to_text());
+            }
+            if ui.button("Clear").clicked() {
+                self.lines.clear();
+            }
+
+            ui.label(format!("{} / {LOG_CAPACITY} lines", self.lines.len()));
+        });
+
+        ui.separator();
+
+        let shown = self
+            .lines
+            .iter()
+            .filter(|line| line.level <= self.max_level)
+            .collect::<Vec<_>>();
+
+        ScrollArea::vertical()
+            .auto_shrink([false, false])
+            .stick_to_bottom(true)
+            .show_rows(ui, 15.0, shown.len(), |ui, rows| {
+                for line in &shown[rows] {
+                    let color = level_color(line.level);
+
+                    ui.horizontal(|ui| {
+                        ui.monospace(line.timestamp.to_string());
+                        ui.label(
+                            RichText::new(format!("{:5}", line.level.label()))
+                                .monospace()
+                                .color(color),
+                        );
+                        ui.label(RichText::new(&*line.message).monospace().color(color));
+                    });
+                }
+            });
+    }
+}
+
+/// A firmware log line as a row of the metrics history, named `sys:log:<level>`
+pub fn log_metric(
+    timestamp: Timestamp,
+    arrival: OffsetDateTime,
+    level: LogLevel,
+    message: Box<str>,
+) -> Metric {
+    Metric {
+        timestamp,
+        arrival,
+        name: MetricName::namespace_static(
+            "sys",
+            MetricName::namespace_static("log", MetricName::name_static(level.label())),
+        ),
+        value: MetricValue::One(OneValue::Str(message)),
+        raw: None,
+    }
+}
+
+/// The level of a row made by [`log_metric`], or [`None`] for any other metric
+pub fn log_level(name: &MetricName) -> Option<LogLevel> {
+    let mut components = name.flatten();
+
+    match (
+        components.next(),
+        components.next(),
+        components.next(),
+        components.next(),
+    ) {
+        (Some("sys"), Some("log"), Some(level), None) => LogLevel::ALL
+            .into_iter()
+            .find(|candidate| candidate.label() == level),
+        _ => None,
+    }
+}
+
+/// Color of the log lines of a level, readable on both dark and light backgrounds
+pub fn level_color(level: LogLevel) -> Color32 {
+    match level {
+        LogLevel::Error => Color32::from_rgb(230, 60, 60),
+        LogLevel::Warn => Color32::from_rgb(220, 160, 0),
+        LogLevel::Info => Color32::from_rgb(70, 140, 230),
+        LogLevel::Debug => Color32::GRAY,
+        LogLevel::Trace => Color32::DARK_GRAY,
+    }
+}
@@ -0,0 +1,532 @@
+use std::fmt::{self, Display};
+
+use kestrel_metric::{name::MetricName, value::MetricValue};
+
+use crate::visualization::focused_metrics::as_plot_value;
+
+/// A calculator expression over the latest metric values, such as
+/// `abs(motor:left - motor:right) / 2`
+///
+/// Metric names containing characters other than letters, digits, `_`, `.` and
+/// `:` can be written between backticks.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Metric(MetricName),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormulaError {
+    Empty,
+    UnexpectedChar { char: char, position: usize },
+    UnexpectedEnd,
+    UnexpectedToken { token: String },
+    UnknownFunction { name: String },
+    WrongArgumentCount { function: Function, got: usize },
+    UnknownMetric { name: String },
+    NotNumeric { name: String },
+}
+
+impl Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormulaError::Empty => write!(f, "empty formula"),
+            FormulaError::UnexpectedChar { char, position } => {
+                write!(f, "unexpected '{char}' at {position}")
+            }
+            FormulaError::UnexpectedEnd => write!(f, "unexpected end of formula"),
+            FormulaError::UnexpectedToken { token } => write!(f, "unexpected {token}"),
+            FormulaError::UnknownFunction { name } => write!(f, "unknown function {name}"),
+            FormulaError::WrongArgumentCount { function, got } => {
+                write!(f, "{function:?} does not take {got} arguments")
+            }
+            FormulaError::UnknownMetric { name } => write!(f, "no metric named {name}"),
+            FormulaError::NotNumeric { name } => write!(f, "{name} is not numeric"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    Open,
+    Close,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Ident(ident) => write!(f, "{ident}"),
+            Token::Op(op) => write!(f, "'{op}'"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+fn is_ident_char(char: char) -> bool {
+    char.is_alphanumeric() || matches!(char, '_' | '.' | ':')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FormulaError> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+
+    while let Some((position, char)) = chars.next() {
+        let token = match char {
+            char if char.is_whitespace() => continue,
+            '+' | '-' | '*' | '/' => Token::Op(char),
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '`' => {
+                let mut name = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, '`')) => break,
+                        Some((_, char)) => name.push(char),
+                        None => return Err(FormulaError::UnexpectedEnd),
+                    }
+                }
+
+                Token::Ident(name)
+            }
+            char if char.is_ascii_digit() => {
+                let mut end = position + char.len_utf8();
+
+                while let Some(&(next, char)) = chars.peek() {
+                    if !(char.is_ascii_digit() || char == '.') {
+                        break;
+                    }
+
+                    end = next + char.len_utf8();
+                    chars.next();
+                }
+
+                Token::Number(
+                    source[position..end]
+                        .parse()
+                        .map_err(|_| FormulaError::UnexpectedChar { char, position })?,
+                )
+            }
+            char if is_ident_char(char) => {
+                let mut ident = String::from(char);
+
+                while let Some(&(_, char)) = chars.peek() {
+                    if !is_ident_char(char) {
+                        break;
+                    }
+
+                    ident.push(char);
+                    chars.next();
+                }
+
+                Token::Ident(ident)
+            }
+            char => return Err(FormulaError::UnexpectedChar { char, position }),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<Token>,
+    peeked: Option<Token>,
+}
+
+impl Parser {
+    fn peek(&mut self) -> Option<&Token> {
+        if self.peeked.is_none() {
+            self.peeked = self.tokens.next();
+        }
+
+        self.peeked.as_ref()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.peeked.take().or_else(|| self.tokens.next())
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), FormulaError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            Some(token) => Err(FormulaError::UnexpectedToken {
+                token: token.to_string(),
+            }),
+            None => Err(FormulaError::UnexpectedEnd),
+        }
+    }
+
+    /// `sum := product (('+' | '-') product)*`
+    fn sum(&mut self) -> Result<Expr, FormulaError> {
+        let mut lhs = self.product()?;
+
+        while let Some(Token::Op(op @ ('+' | '-'))) = self.peek() {
+            let op = if *op == '+' {
+                BinaryOp::Add
+            } else {
+                BinaryOp::Sub
+            };
+            self.next();
+
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.product()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// `product := unary (('*' | '/') unary)*`
+    fn product(&mut self) -> Result<Expr, FormulaError> {
+        let mut lhs = self.unary()?;
+
+        while let Some(Token::Op(op @ ('*' | '/'))) = self.peek() {
+            let op = if *op == '*' {
+                BinaryOp::Mul
+            } else {
+                BinaryOp::Div
+            };
+            self.next();
+
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+
+        Ok(lhs)
+    }
+
+    /// `unary := '-' unary | atom`
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if let Some(Token::Op('-')) = self.peek() {
+            self.next();
+
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+
+        self.atom()
+    }
+
+    /// `atom := number | metric | function '(' sum (',' sum)* ')' | '(' sum ')'`
+    fn atom(&mut self) -> Result<Expr, FormulaError> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Expr::Number(number)),
+            Some(Token::Ident(ident)) => {
+                if self.peek() != Some(&Token::Open) {
+                    return Ok(Expr::Metric(
+                        ident.parse().expect("metric name parsing must never fail"),
+                    ));
+                }
+
+                let function = Function::from_name(&ident)
+                    .ok_or(FormulaError::UnknownFunction { name: ident })?;
+                self.next();
+
+                let mut arguments = vec![self.sum()?];
+                while self.peek() == Some(&Token::Comma) {
+                    self.next();
+                    arguments.push(self.sum()?);
+                }
+                self.expect(Token::Close)?;
+
+                let valid = match function {
+                    Function::Abs => arguments.len() == 1,
+                    Function::Min | Function::Max => !arguments.is_empty(),
+                };
+                if !valid {
+                    return Err(FormulaError::WrongArgumentCount {
+                        function,
+                        got: arguments.len(),
+                    });
+                }
+
+                Ok(Expr::Call(function, arguments))
+            }
+            Some(Token::Open) => {
+                let expr = self.sum()?;
+                self.expect(Token::Close)?;
+
+                Ok(expr)
+            }
+            Some(token) => Err(FormulaError::UnexpectedToken {
+                token: token.to_string(),
+            }),
+            None => Err(FormulaError::UnexpectedEnd),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, FormulaError> {
+        let tokens = tokenize(source)?;
+
+        if tokens.is_empty() {
+            return Err(FormulaError::Empty);
+        }
+
+        let mut parser = Parser {
+            tokens: tokens.into_iter(),
+            peeked: None,
+        };
+
+        let expr = parser.sum()?;
+
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(FormulaError::UnexpectedToken {
+                token: token.to_string(),
+            }),
+        }
+    }
+
+    /// Evaluate the expression, looking up the current value of every metric it references
+    pub fn eval<'v>(
+        &self,
+        lookup: &impl Fn(&MetricName) -> Option<&'v MetricValue>,
+    ) -> Result<f64, FormulaError> {
+        Ok(match self {
+            Expr::Number(number) => *number,
+            Expr::Metric(name) => {
+                let value = lookup(name).ok_or_else(|| FormulaError::UnknownMetric {
+                    name: name.to_string(),
+                })?;
+
+                as_plot_value(value).ok_or_else(|| FormulaError::NotNumeric {
+                    name: name.to_string(),
+                })?
+            }
+            Expr::Neg(expr) => -expr.eval(lookup)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(lookup)?, rhs.eval(lookup)?);
+
+                match op {
+                    BinaryOp::Add => lhs + rhs,
+                    BinaryOp::Sub => lhs - rhs,
+                    BinaryOp::Mul => lhs * rhs,
+                    BinaryOp::Div => lhs / rhs,
+                }
+            }
+            Expr::Call(function, arguments) => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.eval(lookup))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match function {
+                    Function::Abs => arguments[0].abs(),
+                    Function::Min => arguments.into_iter().fold(f64::INFINITY, f64::min),
+                    Function::Max => arguments.into_iter().fold(f64::NEG_INFINITY, f64::max),
+                }
+            }
+        })
+    }
+}
+
+/// A formula as typed by the user, only parsed again once its text changes
+#[derive(Debug, Clone, Default)]
+pub struct Formula {
+    pub source: String,
+    /// The source the expression was parsed from, with the result
+    parsed: Option<(String, Result<Expr, FormulaError>)>,
+}
+
+impl Formula {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            parsed: None,
+        }
+    }
+
+    /// The parsed expression, parsing the source if it changed since the last call
+    pub fn expr(&mut self) -> &Result<Expr, FormulaError> {
+        if self
+            .parsed
+            .as_ref()
+            .map_or(true, |(parsed, _)| *parsed != self.source)
+        {
+            self.parsed = Some((self.source.clone(), Expr::parse(&self.source)));
+        }
+
+        &self.parsed.as_ref().expect("parsed right above").1
+    }
+
+    /// Evaluate the cached expression against the current metric values
+    pub fn evaluate<'v>(
+        &mut self,
+        lookup: &impl Fn(&MetricName) -> Option<&'v MetricValue>,
+    ) -> Result<f64, FormulaError> {
+        self.expr().as_ref().map_err(Clone::clone)?.eval(lookup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use kestrel_metric::value::OneValue;
+
+    use super::*;
+
+    fn values() -> BTreeMap<MetricName, MetricValue> {
+        [
+            ("motor:left", MetricValue::One(OneValue::I16(-40))),
+            ("motor:right", MetricValue::One(OneValue::F32(10.0))),
+            ("odd name!", MetricValue::One(OneValue::U8(3))),
+            ("state", MetricValue::One(OneValue::Str("driving".into()))),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.parse().unwrap(), value))
+        .collect()
+    }
+
+    fn eval(source: &str) -> Result<f64, FormulaError> {
+        let values = values();
+
+        Expr::parse(source)?.eval(&|name| values.get(name))
+    }
+
+    #[test]
+    fn arithmetic_follows_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("8 / 4 / 2"), Ok(1.0));
+        assert_eq!(eval("10 - 4 - 3"), Ok(3.0));
+        assert_eq!(eval("--2.5"), Ok(2.5));
+        assert_eq!(eval("-2 * -3"), Ok(6.0));
+    }
+
+    #[test]
+    fn functions_and_metrics() {
+        assert_eq!(eval("abs(motor:left - motor:right) / 2"), Ok(25.0));
+        assert_eq!(eval("min(motor:left, motor:right, 0)"), Ok(-40.0));
+        assert_eq!(eval("max(motor:left, motor:right)"), Ok(10.0));
+        assert_eq!(eval("`odd name!` * 2"), Ok(6.0));
+    }
+
+    #[test]
+    fn division_by_zero_is_not_an_error() {
+        assert_eq!(eval("1 / 0"), Ok(f64::INFINITY));
+        assert!(eval("0 / 0").unwrap().is_nan());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(eval(""), Err(FormulaError::Empty));
+        assert_eq!(eval("   "), Err(FormulaError::Empty));
+        assert_eq!(eval("1 +"), Err(FormulaError::UnexpectedEnd));
+        assert_eq!(eval("(1"), Err(FormulaError::UnexpectedEnd));
+        assert_eq!(eval("`unterminated"), Err(FormulaError::UnexpectedEnd));
+        assert_eq!(
+            eval("1 2"),
+            Err(FormulaError::UnexpectedToken {
+                token: "2".to_owned()
+            })
+        );
+        assert_eq!(
+            eval("1 ) "),
+            Err(FormulaError::UnexpectedToken {
+                token: "')'".to_owned()
+            })
+        );
+        assert_eq!(
+            eval("1 % 2"),
+            Err(FormulaError::UnexpectedChar {
+                char: '%',
+                position: 2
+            })
+        );
+        assert_eq!(
+            eval("1.2.3"),
+            Err(FormulaError::UnexpectedChar {
+                char: '1',
+                position: 0
+            })
+        );
+    }
+
+    #[test]
+    fn function_errors() {
+        assert_eq!(
+            eval("sqrt(4)"),
+            Err(FormulaError::UnknownFunction {
+                name: "sqrt".to_owned()
+            })
+        );
+        assert_eq!(
+            eval("abs(1, 2)"),
+            Err(FormulaError::WrongArgumentCount {
+                function: Function::Abs,
+                got: 2
+            })
+        );
+    }
+
+    #[test]
+    fn metric_errors() {
+        assert_eq!(
+            eval("missing + 1"),
+            Err(FormulaError::UnknownMetric {
+                name: "missing".to_owned()
+            })
+        );
+        assert_eq!(
+            eval("state * 2"),
+            Err(FormulaError::NotNumeric {
+                name: "state".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn formula_is_parsed_again_once_edited() {
+        let values = values();
+        let lookup = |name: &MetricName| values.get(name);
+        let mut formula = Formula::new("motor:right * 2");
+
+        assert_eq!(formula.evaluate(&lookup), Ok(20.0));
+        assert_eq!(formula.evaluate(&lookup), Ok(20.0));
+
+        formula.source.push_str(" +");
+        assert_eq!(formula.evaluate(&lookup), Err(FormulaError::UnexpectedEnd));
+
+        formula.source.push_str(" 1");
+        assert_eq!(formula.evaluate(&lookup), Ok(21.0));
+        assert!(formula.expr().is_ok());
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    io::{self, BufWriter, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use kestrel_serial::MetricSource;
+use tracing::info;
+
+/// How often new metrics are collected from the worker
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Print every metric received from `source` to stdout as a JSON object per line,
+/// in the same format as the autosave file, until interrupted with Ctrl-C
+///
+/// Stdout being closed, such as by piping into `head`, also stops cleanly.
+pub fn run(source: Box<dyn MetricSource>) -> color_eyre::Result<()> {
+    let running = Arc::new(AtomicBool::new(true));
+
+    ctrlc::set_handler({
+        let running = Arc::clone(&running);
+
+        move || running.store(false, Ordering::Relaxed)
+    })?;
+
+    info!(port = source.port_name(), "printing metrics to stdout");
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+
+    while running.load(Ordering::Relaxed) {
+        let result = source.new_metrics().try_for_each(|metric| {
+            serde_json::to_writer(&mut stdout, &metric)?;
+            stdout.write_all(b"\n")
+        });
+
+        match result.and_then(|()| stdout.flush()) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+            Err(err) => return Err(err.into()),
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    info!("stopping headless mode");
+
+    // Dropping the source shuts its worker down
+    drop(source);
+
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use std::{
+    fmt::Write,
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::Metric;
+use tracing::{debug, info, warn};
+
+use crate::visualization::focused_metrics::as_plot_value;
+
+/// Longest a point waits before its batch is sent, even if the batch is not full
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest a single request to the endpoint may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// Points queued while a batch is being sent, newer ones are dropped
+const QUEUE_CAPACITY: usize = 16 * 1024;
+/// Measurement every point is written to, with the metric name as a tag
+const MEASUREMENT: &str = "kestrel";
+
+/// Writes every numeric metric as an InfluxDB line protocol point, POSTed in batches
+/// to an HTTP endpoint such as `http://localhost:8086/api/v2/write?bucket=robot`
+///
+/// Points are sent from their own thread so a slow or unreachable database never
+/// stalls the UI. A batch that fails to send is logged and dropped, as are points
+/// received while the queue is full.
+pub struct InfluxSink {
+    url: Arc<str>,
+    line_tx: SyncSender<String>,
+    failed_batches: Arc<AtomicU64>,
+    dropped_points: AtomicU64,
+}
+
+impl InfluxSink {
+    pub fn spawn(url: String, batch_size: usize) -> io::Result<Self> {
+        let url = Arc::<str>::from(url);
+        let failed_batches = Arc::new(AtomicU64::new(0));
+        let (line_tx, line_rx) = sync_channel(QUEUE_CAPACITY);
+
+        thread::Builder::new().name("influx_sink".into()).spawn({
+            let url = Arc::clone(&url);
+            let failed_batches = Arc::clone(&failed_batches);
+
+            move || send_batches(&url, batch_size.max(1), &line_rx, &failed_batches)
+        })?;
+
+        info!(%url, batch_size, "writing metrics to influxdb");
+
+        Ok(Self {
+            url,
+            line_tx,
+            failed_batches,
+            dropped_points: AtomicU64::new(0),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Batches that could not be written since startup
+    pub fn failed_batches(&self) -> u64 {
+        self.failed_batches.load(Ordering::Relaxed)
+    }
+
+    /// Points dropped since startup because the queue was full
+    pub fn dropped_points(&self) -> u64 {
+        self.dropped_points.load(Ordering::Relaxed)
+    }
+
+    /// Queue a metric for the next batch, metrics without a numeric value are skipped
+    pub fn send(&self, metric: &Metric) {
+        let Some(line) = line_protocol(metric) else {
+            return;
+        };
+
+        match self.line_tx.try_send(line) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                self.dropped_points.fetch_add(1, Ordering::Relaxed);
+                debug!("influxdb queue is full, dropped point");
+            }
+            Err(TrySendError::Disconnected(_)) => warn!("influxdb sink thread has exited"),
+        }
+    }
+}
+
+/// Format a metric as a line protocol point, `None` if its value is not a number or bool
+///
+/// Every value is written as a float, since InfluxDB refuses a field that changes type.
+fn line_protocol(metric: &Metric) -> Option<String> {
+    let value = as_plot_value(&metric.value).filter(|value| value.is_finite())?;
+
+    let mut line = String::from(MEASUREMENT);
+    line.push_str(",name=");
+    escape_tag(&mut line, &metric.name.to_string());
+    line.push_str(",type=");
+    escape_tag(&mut line, metric.value.ty());
+
+    // Formatting into a string can not fail
+    let _ = write!(
+        line,
+        " value={value:?} {}",
+        metric.arrival.unix_timestamp_nanos()
+    );
+
+    Some(line)
+}
+
+/// Escape the characters with a meaning in the tags of the line protocol
+fn escape_tag(line: &mut String, tag: &str) {
+    for char in tag.chars() {
+        if matches!(char, ',' | '=' | ' ' | '\\') {
+            line.push('\\');
+        }
+
+        line.push(char);
+    }
+}
+
+fn send_batches(url: &str, batch_size: usize, line_rx: &Receiver<String>, failed: &AtomicU64) {
+    let agent = ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build();
+
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut deadline = Instant::now() + FLUSH_INTERVAL;
+
+    loop {
+        let disconnected =
+            match line_rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => {
+                    batch.push(line);
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+        let due = batch.len() >= batch_size || Instant::now() >= deadline || disconnected;
+
+        if due && !batch.is_empty() {
+            match agent.post(url).send_string(&batch.join("\n")) {
+                Ok(_) => debug!(points = batch.len(), "wrote batch to influxdb"),
+                Err(err) => {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    warn!(
+                        %err,
+                        points = batch.len(),
+                        "failed to write batch to influxdb, dropping it"
+                    );
+                }
+            }
+
+            batch.clear();
+        }
+        if due {
+            deadline = Instant::now() + FLUSH_INTERVAL;
+        }
+
+        if disconnected {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+
+    use kestrel_metric::{
+        timestamp::Timestamp,
+        value::{MetricValue, OneValue},
+    };
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn metric() -> Metric {
+        Metric {
+            timestamp: Timestamp::from_millis(0),
+            arrival: OffsetDateTime::UNIX_EPOCH,
+            name: "motor.speed".parse().unwrap(),
+            value: MetricValue::One(OneValue::U8(3)),
+            raw: None,
+        }
+    }
+
+    #[test]
+    fn formats_line_protocol() {
+        assert_eq!(
+            line_protocol(&metric()).as_deref(),
+            Some("kestrel,name=motor.speed,type=u8 value=3.0 0")
+        );
+    }
+
+    #[test]
+    fn drops_points_while_the_database_hangs() {
+        // Accepts connections but never answers, so the first batch blocks the sender
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let url = format!("http://{}/write", listener.local_addr().unwrap());
+        let sink = InfluxSink::spawn(url, 1).unwrap();
+
+        for _ in 0..QUEUE_CAPACITY + 100 {
+            sink.send(&metric());
+        }
+
+        assert!(sink.dropped_points() >= 98, "{}", sink.dropped_points());
+        drop(listener);
+    }
+}
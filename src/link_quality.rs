@@ -0,0 +1,101 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use eframe::{
+    egui::{RichText, Ui},
+    epaint::Color32,
+};
+use kestrel_serial::PacketCounts;
+
+/// How far back the error rate is computed over
+const WINDOW: Duration = Duration::from_secs(10);
+/// How often the packet counts are sampled
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Fractions of bad packets above which the link is shown as degraded and as bad
+const DEGRADED_RATIO: f64 = 0.01;
+const BAD_RATIO: f64 = 0.1;
+
+/// Health of the serial link, from the share of packets that failed to read recently
+#[derive(Debug, Default)]
+pub struct LinkQuality {
+    samples: VecDeque<(Instant, PacketCounts)>,
+}
+
+impl LinkQuality {
+    /// Sample the running packet counts of the worker
+    pub fn poll(&mut self, now: Instant, counts: PacketCounts) {
+        if let Some((_, newest)) = self.samples.back() {
+            // A new worker starts counting from zero again
+            if counts.since(newest).is_none() {
+                self.samples.clear();
+            }
+        }
+
+        let due = self.samples.back().map_or(true, |(sampled, _)| {
+            now.duration_since(*sampled) >= SAMPLE_INTERVAL
+        });
+        if due {
+            self.samples.push_back((now, counts));
+        } else if let Some(newest) = self.samples.back_mut() {
+            newest.1 = counts;
+        }
+
+        while self
+            .samples
+            .front()
+            .is_some_and(|(sampled, _)| now.duration_since(*sampled) > WINDOW)
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Packets read within the window
+    pub fn recent(&self) -> PacketCounts {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((_, oldest)), Some((_, newest))) => newest.since(oldest).unwrap_or_default(),
+            _ => PacketCounts::default(),
+        }
+    }
+
+    /// Share of the recent packets that failed to read, `None` if none were read at all
+    pub fn error_ratio(&self) -> Option<f64> {
+        let recent = self.recent();
+
+        (recent.total() > 0).then(|| recent.errors() as f64 / recent.total() as f64)
+    }
+
+    pub fn ui(&self, ui: &mut Ui) {
+        let (color, text) = match self.error_ratio() {
+            None => (Color32::GRAY, "Link: idle".to_owned()),
+            Some(ratio) => {
+                let color = if ratio >= BAD_RATIO {
+                    Color32::RED
+                } else if ratio >= DEGRADED_RATIO {
+                    Color32::YELLOW
+                } else {
+                    Color32::GREEN
+                };
+
+                (color, format!("Link: {:.1}% errors", ratio * 100.0))
+            }
+        };
+
+        let recent = self.recent();
+
+        ui.label(RichText::new(format!("⏺ {text}")).color(color))
+            .on_hover_text_at_pointer(format!(
+                "Over the last {} seconds:\n{} good packets\n{} malformed COBS\n{} bad packet length\n{} bad value length\n{} bad layout\n{} bad checksum\n{} other errors",
+                WINDOW.as_secs(),
+                recent.good,
+                recent.malformed_cobs,
+                recent.bad_length,
+                recent.bad_value_length,
+                recent.poor_layout,
+                recent.bad_checksum,
+                recent.other,
+            ));
+    }
+}
@@ -1,19 +1,90 @@
-use std::collections::{BTreeMap, BTreeSet};
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
 
 use app::Application;
 use argh::FromArgs;
-use eframe::{egui::CentralPanel, NativeOptions};
-use kestrel_metric::timestamp::Timestamp;
-use kestrel_serial::SerialWorkerController;
+use auto_clear::AutoClear;
+use auto_hide::StaleAutoHide;
+use autosave::Autosave;
+use eframe::NativeOptions;
+use kestrel_metric::timestamp::{Timestamp, TimestampUnit};
+use kestrel_serial::{
+    Framing, MetricSource, ReplayWorkerController, SerialWorkerConfig, SerialWorkerController,
+    COMMON_BAUD_RATES,
+};
 use ringbuffer::AllocRingBuffer;
-use tracing::info;
+use serde_json::Value;
+use tracing::{info, warn};
 use tracing_subscriber::EnvFilter;
 
-use crate::version::GIT_VERSION;
+use crate::{
+    aliases::Aliases,
+    command_ack::CommandAcks,
+    confirmation::Confirmations,
+    export::Interpolation,
+    favorites::Favorites,
+    firmware_log::FirmwareLog,
+    influx::InfluxSink,
+    link_quality::LinkQuality,
+    mqtt::MqttPublisher,
+    pause_clock::PauseClock,
+    profile::ConnectionProfile,
+    prometheus::PrometheusExporter,
+    rate_limit::RateLimiter,
+    recent_changes::RecentChanges,
+    runs::Runs,
+    session_summary::SessionTracker,
+    theme::ThemePreference,
+    type_change::{TypeChangePolicy, TypeChanges},
+    units::Units,
+    version::GIT_VERSION,
+    visualization::{
+        array_profile::DEFAULT_MAX_ARRAY_ELEMENTS, focused_metrics::PlotStyle,
+        format_cache::FormatCache, histogram::HistogramView, metric_stats::StatsCache,
+        plot_view::PlotView, robot::SweepConfig, spectrum::SpectrumView, time_cursor::TimeCursor,
+    },
+    websocket::MetricBroadcaster,
+};
 
+mod aggregate;
+mod aliases;
 mod app;
+mod auto_clear;
+mod auto_hide;
+mod autosave;
+mod command_ack;
+mod command_list;
+mod command_macro;
+mod confirmation;
+mod diagnostics;
+mod export;
+mod favorites;
+mod firmware_log;
+mod formula;
+mod headless;
+mod influx;
+mod link_quality;
+mod mqtt;
+mod pause_clock;
+mod port_list;
+mod ports;
+mod profile;
+mod prometheus;
+mod rate_limit;
+mod recent_changes;
+mod runs;
+mod session_summary;
+mod theme;
+mod type_change;
+mod units;
 mod version;
 mod visualization;
+mod websocket;
 
 /// Visualization tool for the DBL Venus Exploration project
 #[derive(FromArgs, Debug)]
@@ -26,9 +97,131 @@ struct Args {
     #[argh(option)]
     baud: Option<u32>,
 
-    /// list the available ports
+    /// try common baud rates until one yields valid frames, instead of using --baud
+    #[argh(switch)]
+    autobaud: bool,
+
+    /// unit of the timestamps sent by the robot, `ms` or `us`
+    #[argh(option, default = "TimestampUnit::Millis")]
+    timestamp_unit: TimestampUnit,
+
+    /// byte ending each COBS frame, in decimal or hex such as 0xaa, defaults to 0
+    #[argh(option, default = "0x00", from_str_fn(parse_byte_arg))]
+    frame_delimiter: u8,
+
+    /// byte separating the name, type and value of a packet, in decimal or hex such
+    /// as 0x1f, defaults to 0
+    #[argh(option, default = "0x00", from_str_fn(parse_byte_arg))]
+    field_separator: u8,
+
+    /// lowercase metric names, merging names that only differ in case
+    #[argh(switch)]
+    lowercase_names: bool,
+
+    /// packets start with a protocol version byte, 0 for a u16 length trailer or 1 for a u32 one
+    #[argh(switch)]
+    versioned_packets: bool,
+
+    /// packets end with a CRC16 checksum to verify
+    #[argh(switch)]
+    crc16: bool,
+
+    /// detect the frame delimiter from the first data received
+    #[argh(switch)]
+    detect_framing: bool,
+
+    /// discard COBS frames longer than this many bytes, defaults to 65536
+    #[argh(option)]
+    max_frame_length: Option<usize>,
+
+    /// milliseconds a serial read waits for data, defaults to 100. The connection counts
+    /// as stalled after 20 reads in a row time out, so this also scales how long that takes
+    #[argh(option)]
+    read_timeout_ms: Option<u64>,
+
+    /// store at most this many samples per second of each metric
+    #[argh(option)]
+    max_rate: Option<u32>,
+
+    /// continuously append every metric to this JSON lines file
+    #[argh(option)]
+    autosave: Option<PathBuf>,
+
+    /// append every received metric to this CSV file as timestamp,name,type,value rows,
+    /// written by the serial worker even while ingest is paused; the file is never
+    /// rotated or truncated
+    #[argh(option)]
+    csv: Option<PathBuf>,
+
+    /// stream every metric as JSON to WebSocket clients connecting to this address
+    #[argh(option)]
+    ws_addr: Option<SocketAddr>,
+
+    /// accept line-based attach, detach, reset and send commands over TCP on this address,
+    /// defaults to 127.0.0.1:6969
+    #[argh(option)]
+    control_addr: Option<SocketAddr>,
+
+    /// POST every numeric metric in InfluxDB line protocol to this URL, such as
+    /// http://localhost:8086/api/v2/write?bucket=robot
+    #[argh(option)]
+    influx_url: Option<String>,
+
+    /// points per InfluxDB request, batches are also sent every second, defaults to 500
+    #[argh(option, default = "500")]
+    influx_batch: usize,
+
+    /// publish every numeric metric to the MQTT broker at this URL, such as
+    /// mqtt://localhost:1883
+    #[argh(option)]
+    mqtt_url: Option<String>,
+
+    /// topic the MQTT topics of the metrics are nested below, defaults to kestrel
+    #[argh(option, default = "String::from(\"kestrel\")")]
+    mqtt_topic: String,
+
+    /// quality of service of the MQTT publishes, 0, 1 or 2, defaults to 0
+    #[argh(option, default = "0")]
+    mqtt_qos: u8,
+
+    /// serve the latest value of every numeric metric for Prometheus to scrape on
+    /// /metrics at this address
+    #[argh(option)]
+    prometheus_addr: Option<SocketAddr>,
+
+    /// JSON file with an array of commands with a name, opcode and optional description,
+    /// shown as buttons that send their opcode
+    #[argh(option)]
+    commands: Option<PathBuf>,
+
+    /// load the most recent samples of every metric in the autosave file on startup
+    #[argh(switch)]
+    resume: bool,
+
+    /// replay a recorded session instead of connecting to the robot
+    #[argh(option)]
+    replay: Option<PathBuf>,
+
+    /// how many times faster than recorded to replay, defaults to 1
+    #[argh(option, default = "1.0")]
+    replay_speed: f32,
+
+    /// samples kept in the history of every metric and of all packets,
+    /// rounded up to a power of two, defaults to 1024
+    #[argh(option, default = "METRIC_HISTORY_LENGTH")]
+    history: usize,
+
+    /// print every metric to stdout as a JSON object per line instead of opening a window
+    #[argh(switch)]
+    headless: bool,
+
+    /// list the available ports, one per line with the USB details of USB ports
     #[argh(switch)]
     list: bool,
+
+    /// with --list, print the ports as a JSON array instead
+    #[argh(switch)]
+    json: bool,
 }
 
 fn main() -> color_eyre::Result<()> {
@@ -37,6 +230,8 @@ fn main() -> color_eyre::Result<()> {
         .with_env_filter(EnvFilter::from_default_env())
         .compact()
         .with_ansi(cfg!(debug_assertions))
+        // Stdout carries the metrics in headless mode
+        .with_writer(std::io::stderr)
         .init();
 
     info!(version = GIT_VERSION);
@@ -52,22 +247,178 @@ fn main() -> color_eyre::Result<()> {
     };
 
     if args.list {
-        // TODO:
-        dbg!(serial_ports()?);
+        // Unknown ports are skipped when picking a port, but may still be the right one
+        let ports = serialport::available_ports()?;
+
+        if args.json {
+            println!(
+                "{}",
+                Value::Array(ports.iter().map(port_list::port_json).collect())
+            );
+        } else if ports.is_empty() {
+            eprintln!("no serial ports found");
+        } else {
+            for port in &ports {
+                println!("{}", port_list::format_port(port));
+            }
+        }
 
         return Ok(());
     }
 
+    if args.history == 0 {
+        return Err(color_eyre::eyre::eyre!(
+            "--history must keep at least one sample"
+        ));
+    }
+    if !(args.replay_speed.is_finite() && args.replay_speed > 0.0) {
+        return Err(color_eyre::eyre::eyre!(
+            "--replay-speed must be a positive number"
+        ));
+    }
+    if args.read_timeout_ms == Some(0) {
+        return Err(color_eyre::eyre::eyre!(
+            "--read-timeout-ms must wait at least one millisecond"
+        ));
+    }
+    if args.max_frame_length == Some(0) {
+        return Err(color_eyre::eyre::eyre!(
+            "--max-frame-length must allow at least one byte"
+        ));
+    }
+    let Some(history_length) = args.history.checked_next_power_of_two() else {
+        return Err(color_eyre::eyre::eyre!(
+            "--history of {} samples is too long to round up to a power of two",
+            args.history
+        ));
+    };
+    if history_length != args.history {
+        info!(
+            requested = args.history,
+            history_length, "rounded the history length up to a power of two"
+        );
+    }
+
     let baud = args.baud.unwrap_or(115200);
-    let port = if let Some(port) = args.port {
-        port
-    } else {
-        serial_ports()?
-            .next()
-            .expect("no serial port available")
-            .port_name
-            .clone()
+    let timestamp_unit = args.timestamp_unit;
+    let config = SerialWorkerConfig {
+        baud_rate: baud,
+        timestamp_unit,
+        framing: Framing {
+            frame_delimiter: args.frame_delimiter,
+            field_separator: args.field_separator,
+        },
+        lowercase_names: args.lowercase_names,
+        versioned_packets: args.versioned_packets,
+        auto_detect_framing: args.detect_framing,
+        crc16: args.crc16,
+        control_addr: args
+            .control_addr
+            .unwrap_or(SerialWorkerConfig::default().control_addr),
+        max_frame_length: args
+            .max_frame_length
+            .unwrap_or(SerialWorkerConfig::default().max_frame_length),
+        read_timeout: args.read_timeout_ms.map_or(
+            SerialWorkerConfig::default().read_timeout,
+            Duration::from_millis,
+        ),
+    };
+    // A replay needs no port, unless one is given for the profile to switch to later
+    let port = match args.port {
+        Some(port) => Some(port),
+        None if args.replay.is_some() => None,
+        None => Some(
+            serial_ports()?
+                .next()
+                .map(|port| port.port_name)
+                .ok_or_else(|| {
+                    color_eyre::eyre::eyre!("no serial port available, pick one with --port")
+                })?,
+        ),
+    };
+
+    let detected_baud = port
+        .as_deref()
+        .filter(|_| args.autobaud && args.replay.is_none())
+        .and_then(|port| SerialWorkerController::autodetect_baud(port, COMMON_BAUD_RATES, &config));
+    let config = SerialWorkerConfig {
+        baud_rate: detected_baud.unwrap_or(config.baud_rate),
+        ..config
+    };
+    if args.autobaud && detected_baud.is_none() {
+        warn!(
+            baud = config.baud_rate,
+            "no baud rate detected, using the default"
+        );
+    }
+
+    if args.headless {
+        let source: Box<dyn MetricSource> = match args.replay {
+            Some(path) => Box::new(ReplayWorkerController::spawn(
+                path,
+                args.replay_speed,
+                Box::new(|| {}),
+            )?),
+            None => Box::new(SerialWorkerController::spawn(
+                port.expect("a port is picked unless replaying"),
+                config,
+                Box::new(|| {}),
+            )),
+        };
+
+        if let Some(path) = args.csv {
+            source.start_csv_log(path);
+        }
+
+        return headless::run(source);
+    }
+
+    let resumed = match &args.autosave {
+        Some(path) if args.resume && path.exists() => autosave::load_tail(path, history_length)?,
+        _ => Vec::new(),
+    };
+    let named_commands = match &args.commands {
+        Some(path) => command_list::load(path)?,
+        None => Vec::new(),
     };
+    let broadcaster = args.ws_addr.map(MetricBroadcaster::bind).transpose()?;
+    let influx = args
+        .influx_url
+        .map(|url| InfluxSink::spawn(url, args.influx_batch))
+        .transpose()?;
+    let mqtt = args
+        .mqtt_url
+        .map(|url| MqttPublisher::spawn(url, args.mqtt_topic, args.mqtt_qos))
+        .transpose()?;
+    let prometheus = args
+        .prometheus_addr
+        .map(PrometheusExporter::bind)
+        .transpose()?;
+    let autosave = args
+        .autosave
+        .map(|path| Autosave::open(path, Instant::now()))
+        .transpose()?;
+
+    // Started before the window to report a bad recording, repainting once the window exists
+    let repaint_ctx = Arc::new(OnceLock::<eframe::egui::Context>::new());
+    let replay = args
+        .replay
+        .map(|path| {
+            ReplayWorkerController::spawn(
+                path,
+                args.replay_speed,
+                Box::new({
+                    let repaint_ctx = Arc::clone(&repaint_ctx);
+
+                    move || {
+                        if let Some(ctx) = repaint_ctx.get() {
+                            ctx.request_repaint();
+                        }
+                    }
+                }),
+            )
+        })
+        .transpose()?;
 
     // let serial_ports = serial_ports()?.collect::<Vec<_>>();
 
@@ -126,30 +477,122 @@ fn main() -> color_eyre::Result<()> {
             ..Default::default()
         },
         Box::new(move |ctx| {
-            Box::new(Application {
+            let _ = repaint_ctx.set(ctx.egui_ctx.clone());
+
+            let mut application = Application {
                 pause_metrics: false,
                 show_visualization: false,
                 show_info: false,
-                connect_the_dots: true,
+                show_raw_bytes: false,
+                show_profiles: false,
+                show_explorer: false,
+                histogram: HistogramView::default(),
+                spectrum: SpectrumView::default(),
+                firmware_log: FirmwareLog::default(),
+                plot_style: PlotStyle::default(),
+                plot_view: PlotView::default(),
 
-                raw_metrics: new_metric_ring_buffer(),
+                raw_metrics: new_metric_ring_buffer(history_length),
+                history_length,
+                metrics_received: 0,
+                history_format_cache: FormatCache::default(),
+                inspected_packet: None,
                 sorted_metrics: BTreeMap::new(),
+                truncated_metrics: BTreeSet::new(),
+                value_changes: BTreeMap::new(),
+                recent_changes: RecentChanges::default(),
+                stats_cache: StatsCache::default(),
 
-                current_time: Timestamp::default(),
+                current_time: Timestamp::from_raw(0, timestamp_unit),
+                wall_clock_anchor: None,
+                rollover_window: Duration::from_secs(60),
+                timestamp_unit,
+                time_cursor: TimeCursor::default(),
+                pause_clock: PauseClock::default(),
+                auto_clear: AutoClear::new(Instant::now()),
+                sweep: SweepConfig::default(),
+                runs: Runs::default(),
 
                 focused_metrics: BTreeSet::new(),
                 hidden_metrics: BTreeSet::new(),
+                metric_filter: String::new(),
+                collapsed_groups: BTreeSet::new(),
+                stale_auto_hide: StaleAutoHide::default(),
+                favorites: Favorites::default(),
+                units: Units::default(),
+                aliases: Aliases::default(),
+                theme: ThemePreference::default(),
+                formulas: Vec::new(),
+                export_interpolation: Interpolation::default(),
+                aggregations: BTreeMap::new(),
+                array_profiles: BTreeSet::new(),
+                max_array_elements: DEFAULT_MAX_ARRAY_ELEMENTS,
+                latest_raw: BTreeMap::new(),
+
+                type_changes: TypeChanges::default(),
+                type_change_policy: TypeChangePolicy::default(),
+                rate_limiter: RateLimiter::new(args.max_rate),
+                session: SessionTracker::default(),
+                session_summary: None,
 
-                serial: SerialWorkerController::spawn(
-                    port,
-                    baud,
-                    Box::new({
+                serial: {
+                    let repaint = Box::new({
                         let ctx = ctx.egui_ctx.clone();
 
                         move || ctx.request_repaint()
-                    }),
-                ),
-            })
+                    });
+
+                    match replay {
+                        Some(replay) => Box::new(replay) as Box<dyn MetricSource>,
+                        None => Box::new(SerialWorkerController::spawn(
+                            port.clone().expect("a port is picked unless replaying"),
+                            config,
+                            repaint,
+                        )),
+                    }
+                },
+                paused_packets: VecDeque::new(),
+                background_ports: Vec::new(),
+                csv_log: args.csv,
+                link_quality: LinkQuality::default(),
+                read_timeout: config.read_timeout,
+                control_addr: config.control_addr,
+                control_offset: 0,
+                max_frame_length: config.max_frame_length,
+                detected_baud,
+                autosave,
+                broadcaster,
+                influx,
+                mqtt,
+                prometheus,
+                profiles: port
+                    .map(|port| ConnectionProfile::default_profile(port, config))
+                    .into_iter()
+                    .collect(),
+                active_profile: ConnectionProfile::DEFAULT_NAME.to_owned(),
+
+                macros: Vec::new(),
+                macro_recorder: None,
+                macro_playback: None,
+                payload_input: String::new(),
+                raw_command_input: String::new(),
+                named_commands,
+                command_acks: CommandAcks::default(),
+                confirmations: Confirmations::default(),
+            };
+
+            if let Some(storage) = ctx.storage {
+                application.restore(storage);
+            }
+            if let Some(path) = &application.csv_log {
+                application.serial.start_csv_log(path.clone());
+            }
+
+            for metric in resumed {
+                application.ingest(metric);
+            }
+
+            Box::new(application)
         }),
     )
     .unwrap(); // FIXME: not Send or Sync :/ color eyre does no like it
@@ -157,6 +600,56 @@ fn main() -> color_eyre::Result<()> {
     Ok(())
 }
 
-pub fn new_metric_ring_buffer<T>() -> AllocRingBuffer<T> {
-    AllocRingBuffer::new(1024)
+/// Parse a byte argument written in decimal, or in hex with a `0x` prefix
+fn parse_byte_arg(value: &str) -> Result<u8, String> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|err| format!("`{value}` is not a byte: {err}"))
+}
+
+/// Default number of samples kept in every metric history
+///
+/// Every metric and the packet history allocate their full capacity as soon as
+/// they are created, so memory grows with the length times the number of metrics.
+/// A tiny history keeps memory low but only plots the last few samples, while a
+/// huge one keeps long plots at the cost of memory and slower plotting and statistics.
+pub const METRIC_HISTORY_LENGTH: usize = 1024;
+
+/// History holding up to `capacity` samples, which must be a non-zero power of two
+pub fn new_metric_ring_buffer<T>(capacity: usize) -> AllocRingBuffer<T> {
+    AllocRingBuffer::new(capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_args_in_decimal_and_hex() {
+        assert_eq!(parse_byte_arg("0"), Ok(0));
+        assert_eq!(parse_byte_arg("170"), Ok(0xaa));
+        assert_eq!(parse_byte_arg("0xaa"), Ok(0xaa));
+        assert_eq!(parse_byte_arg("0X1F"), Ok(0x1f));
+        assert!(parse_byte_arg("0x100").is_err());
+        assert!(parse_byte_arg("0x").is_err());
+        assert!(parse_byte_arg("aa").is_err());
+    }
+
+    #[test]
+    fn framing_args_accept_hex() {
+        let args = Args::from_args(
+            &["kestrel"],
+            &["--frame-delimiter", "0xaa", "--field-separator", "31"],
+        )
+        .unwrap();
+
+        assert_eq!(args.frame_delimiter, 0xaa);
+        assert_eq!(args.field_separator, 0x1f);
+    }
 }
@@ -0,0 +1,138 @@
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use kestrel_metric::{name::MetricName, Metric};
+use rumqttc::{Client, ConnectionError, Event, MqttOptions, Packet, QoS};
+use tracing::{debug, info, warn};
+
+use crate::visualization::focused_metrics::as_plot_value;
+
+/// Publishes queued while the broker is unreachable, newer ones are dropped
+const QUEUE_CAPACITY: usize = 1024;
+/// Wait before reconnecting after the connection to the broker dropped
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Publishes every numeric metric to an MQTT broker, on a topic below `base_topic`
+/// with a level per namespace, such as `kestrel/ultrasonic/distance`
+///
+/// The connection is driven from its own thread, which reconnects whenever the
+/// broker drops. Metrics received while the queue is full are dropped.
+pub struct MqttPublisher {
+    url: String,
+    base_topic: String,
+    qos: QoS,
+    client: Client,
+    connected: Arc<AtomicBool>,
+}
+
+impl MqttPublisher {
+    /// Connect to a broker at a url such as `mqtt://localhost:1883`
+    pub fn spawn(url: String, base_topic: String, qos: u8) -> color_eyre::Result<Self> {
+        let qos = rumqttc::qos(qos).map_err(|err| color_eyre::eyre::eyre!("{err}"))?;
+
+        // The client id is required by the url parser, so one is picked if not given
+        let options_url = if url.contains("client_id=") {
+            url.clone()
+        } else {
+            let separator = if url.contains('?') { '&' } else { '?' };
+
+            format!("{url}{separator}client_id=kestrel-{}", process::id())
+        };
+        let options = MqttOptions::parse_url(options_url)?;
+
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+        let connected = Arc::new(AtomicBool::new(false));
+
+        thread::Builder::new()
+            .name("mqtt_publisher".into())
+            .spawn({
+                let connected = Arc::clone(&connected);
+
+                move || {
+                    for event in connection.iter() {
+                        match event {
+                            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                                info!("connected to mqtt broker");
+                                connected.store(true, Ordering::Relaxed);
+                            }
+                            Ok(_) => {}
+                            // Every client handle has been dropped
+                            Err(ConnectionError::RequestsDone) => return,
+                            Err(err) => {
+                                if connected.swap(false, Ordering::Relaxed) {
+                                    warn!(%err, "lost connection to mqtt broker, reconnecting");
+                                } else {
+                                    debug!(%err, "failed to connect to mqtt broker, retrying");
+                                }
+
+                                // Iterating again reconnects
+                                thread::sleep(RECONNECT_DELAY);
+                            }
+                        }
+                    }
+                }
+            })?;
+
+        info!(%url, base_topic, ?qos, "publishing metrics over mqtt");
+
+        Ok(Self {
+            url,
+            base_topic,
+            qos,
+            client,
+            connected,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// Queue a metric for publishing, metrics without a numeric value are skipped
+    pub fn send(&self, metric: &Metric) {
+        let Some(value) = as_plot_value(&metric.value) else {
+            return;
+        };
+
+        let topic = topic(&self.base_topic, &metric.name);
+
+        if let Err(err) = self
+            .client
+            .try_publish(topic, self.qos, false, value.to_string())
+        {
+            debug!(%err, "dropped mqtt publish");
+        }
+    }
+}
+
+/// The topic of a metric, a level per namespace below `base_topic`
+///
+/// MQTT wildcards and empty levels can not be published to, so `+` and `#` are
+/// replaced and empty components are skipped.
+fn topic(base_topic: &str, name: &MetricName) -> String {
+    let mut topic = base_topic.trim_end_matches('/').to_owned();
+
+    for component in name.flatten().filter(|component| !component.is_empty()) {
+        if !topic.is_empty() {
+            topic.push('/');
+        }
+
+        topic.extend(component.chars().map(|char| match char {
+            '+' | '#' | '/' => '_',
+            char => char,
+        }));
+    }
+
+    topic
+}
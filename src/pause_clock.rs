@@ -0,0 +1,104 @@
+use std::time::Instant;
+
+use kestrel_metric::timestamp::Timestamp;
+
+/// Optionally keeps the displayed time running while metric ingest is paused,
+/// so the age of the frozen metrics stays visible
+#[derive(Debug, Default)]
+pub struct PauseClock {
+    pub advance_while_paused: bool,
+
+    paused_at: Option<Instant>,
+}
+
+impl PauseClock {
+    /// The time to display, given the timestamp of the newest ingested metric
+    ///
+    /// The robot clock itself is left alone, so resuming ingest does not look
+    /// like the robot went back in time.
+    pub fn now(&mut self, current_time: Timestamp, paused: bool, now: Instant) -> Timestamp {
+        if !paused {
+            self.paused_at = None;
+
+            return current_time;
+        }
+
+        let paused_at = *self.paused_at.get_or_insert(now);
+
+        if self.advance_while_paused {
+            current_time.saturating_add_duration(now.saturating_duration_since(paused_at))
+        } else {
+            current_time
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn running_clock_shows_the_newest_metric() {
+        for advance_while_paused in [false, true] {
+            let mut clock = PauseClock {
+                advance_while_paused,
+                ..Default::default()
+            };
+            let now = Instant::now();
+
+            assert_eq!(
+                clock.now(Timestamp::from_millis(1_000), false, now),
+                Timestamp::from_millis(1_000)
+            );
+        }
+    }
+
+    #[test]
+    fn frozen_while_paused_by_default() {
+        let mut clock = PauseClock::default();
+        let start = Instant::now();
+        let time = Timestamp::from_millis(1_000);
+
+        assert_eq!(clock.now(time, true, start), time);
+        assert_eq!(clock.now(time, true, start + Duration::from_secs(3)), time);
+    }
+
+    #[test]
+    fn advances_from_the_moment_of_pausing() {
+        let mut clock = PauseClock {
+            advance_while_paused: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let time = Timestamp::from_millis(1_000);
+
+        assert_eq!(clock.now(time, true, start), time);
+        assert_eq!(
+            clock.now(time, true, start + Duration::from_millis(1_500)),
+            Timestamp::from_millis(2_500)
+        );
+    }
+
+    #[test]
+    fn resuming_restarts_the_pause() {
+        let mut clock = PauseClock {
+            advance_while_paused: true,
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let time = Timestamp::from_millis(1_000);
+
+        clock.now(time, true, start);
+        assert_eq!(clock.now(time, false, start + Duration::from_secs(2)), time);
+
+        // The second pause counts from when it began, not from the first one
+        let paused_again = start + Duration::from_secs(5);
+        assert_eq!(clock.now(time, true, paused_again), time);
+        assert_eq!(
+            clock.now(time, true, paused_again + Duration::from_millis(250)),
+            Timestamp::from_millis(1_250)
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use serde_json::{json, Value};
+use serialport::{SerialPortInfo, SerialPortType};
+
+/// Short name of the kind of a port
+fn port_kind(port_type: &SerialPortType) -> &'static str {
+    match port_type {
+        SerialPortType::UsbPort(_) => "usb",
+        SerialPortType::PciPort => "pci",
+        SerialPortType::BluetoothPort => "bluetooth",
+        SerialPortType::Unknown => "unknown",
+    }
+}
+
+/// One line per port: its name and kind, followed by the USB details if it has them
+pub fn format_port(port: &SerialPortInfo) -> String {
+    let mut line = format!("{}\t{}", port.port_name, port_kind(&port.port_type));
+
+    if let SerialPortType::UsbPort(info) = &port.port_type {
+        line.push_str(&format!("\t{:04x}:{:04x}", info.vid, info.pid));
+
+        if let Some(serial_number) = &info.serial_number {
+            line.push_str(&format!("\tserial={serial_number}"));
+        }
+
+        let description = [&info.manufacturer, &info.product]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        if !description.is_empty() {
+            line.push_str(&format!("\t{}", description.join(" ")));
+        }
+    }
+
+    line
+}
+
+/// A port as a JSON object, with `null` for the USB details of other ports
+pub fn port_json(port: &SerialPortInfo) -> Value {
+    let usb = match &port.port_type {
+        SerialPortType::UsbPort(info) => Some(info),
+        _ => None,
+    };
+
+    json!({
+        "name": port.port_name,
+        "type": port_kind(&port.port_type),
+        "vid": usb.map(|info| info.vid),
+        "pid": usb.map(|info| info.pid),
+        "serial_number": usb.and_then(|info| info.serial_number.as_deref()),
+        "manufacturer": usb.and_then(|info| info.manufacturer.as_deref()),
+        "product": usb.and_then(|info| info.product.as_deref()),
+    })
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+    net::SocketAddr,
+    time::Instant,
+};
+
+use eframe::egui::{Context, RichText, SelectableLabel, Ui};
+use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue, Metric};
+use kestrel_serial::{MetricSource, Packet, SerialWorkerConfig, SerialWorkerController};
+use ringbuffer::AllocRingBuffer;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::{
+    app::Application,
+    command_ack::CommandAcks,
+    firmware_log::FirmwareLog,
+    link_quality::LinkQuality,
+    new_metric_ring_buffer,
+    profile::ConnectionProfile,
+    rate_limit::RateLimiter,
+    runs::Runs,
+    session_summary::{SessionSummary, SessionTracker},
+    type_change::TypeChanges,
+    visualization::{
+        format_cache::FormatCache, metric_stats::StatsCache, packet_inspector::PacketInspection,
+    },
+};
+
+/// A serial port open next to the one shown, with the metrics received from it
+/// kept apart so names sent by two robots never mix
+///
+/// Only the port shown feeds the autosave, WebSocket and InfluxDB sinks.
+pub struct PortSession {
+    pub serial: Box<dyn MetricSource>,
+    pub paused_packets: VecDeque<Packet>,
+    pub current_time: Timestamp,
+    pub wall_clock_anchor: Option<(Timestamp, OffsetDateTime)>,
+    pub raw_metrics: AllocRingBuffer<Metric>,
+    pub metrics_received: u64,
+    pub history_format_cache: FormatCache,
+    pub inspected_packet: Option<PacketInspection>,
+    pub sorted_metrics:
+        BTreeMap<MetricName, AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>>,
+    pub truncated_metrics: BTreeSet<MetricName>,
+    pub value_changes: BTreeMap<MetricName, Timestamp>,
+    pub stats_cache: StatsCache,
+    pub latest_raw: BTreeMap<MetricName, Box<[u8]>>,
+    pub type_changes: TypeChanges,
+    pub link_quality: LinkQuality,
+    pub rate_limiter: RateLimiter,
+    pub runs: Runs,
+    pub session: SessionTracker,
+    /// Summary of a session of this port that ended in the background, shown once selected
+    pub session_summary: Option<SessionSummary>,
+    pub command_acks: CommandAcks,
+    pub firmware_log: FirmwareLog,
+    /// Offset of the control address of this port from the application's `control_addr`
+    pub control_offset: u16,
+}
+
+impl PortSession {
+    pub fn new(
+        serial: Box<dyn MetricSource>,
+        current_time: Timestamp,
+        history_length: usize,
+        control_offset: u16,
+    ) -> Self {
+        Self {
+            serial,
+            paused_packets: VecDeque::new(),
+            current_time,
+            wall_clock_anchor: None,
+            raw_metrics: new_metric_ring_buffer(history_length),
+            metrics_received: 0,
+            history_format_cache: FormatCache::default(),
+            inspected_packet: None,
+            sorted_metrics: BTreeMap::new(),
+            truncated_metrics: BTreeSet::new(),
+            value_changes: BTreeMap::new(),
+            stats_cache: StatsCache::default(),
+            latest_raw: BTreeMap::new(),
+            type_changes: TypeChanges::default(),
+            link_quality: LinkQuality::default(),
+            rate_limiter: RateLimiter::default(),
+            runs: Runs::default(),
+            session: SessionTracker::default(),
+            session_summary: None,
+            command_acks: CommandAcks::default(),
+            firmware_log: FirmwareLog::default(),
+            control_offset,
+        }
+    }
+
+    pub fn port_name(&self) -> &str {
+        self.serial.port_name()
+    }
+
+    /// Exchange the connection and metrics with those shown by the application
+    ///
+    /// Settings such as rate limits and aggregations are shared by every port,
+    /// only what was received from the robot is exchanged.
+    pub fn swap(&mut self, application: &mut Application) {
+        mem::swap(&mut self.serial, &mut application.serial);
+        mem::swap(&mut self.paused_packets, &mut application.paused_packets);
+        mem::swap(&mut self.current_time, &mut application.current_time);
+        mem::swap(&mut self.raw_metrics, &mut application.raw_metrics);
+        mem::swap(
+            &mut self.metrics_received,
+            &mut application.metrics_received,
+        );
+        mem::swap(
+            &mut self.history_format_cache,
+            &mut application.history_format_cache,
+        );
+        mem::swap(
+            &mut self.inspected_packet,
+            &mut application.inspected_packet,
+        );
+        mem::swap(&mut self.sorted_metrics, &mut application.sorted_metrics);
+        mem::swap(
+            &mut self.truncated_metrics,
+            &mut application.truncated_metrics,
+        );
+        mem::swap(&mut self.value_changes, &mut application.value_changes);
+        mem::swap(&mut self.stats_cache, &mut application.stats_cache);
+        mem::swap(&mut self.latest_raw, &mut application.latest_raw);
+        mem::swap(&mut self.type_changes, &mut application.type_changes);
+        mem::swap(&mut self.link_quality, &mut application.link_quality);
+        mem::swap(
+            &mut self.wall_clock_anchor,
+            &mut application.wall_clock_anchor,
+        );
+        self.rate_limiter.swap_counts(&mut application.rate_limiter);
+        mem::swap(&mut self.runs, &mut application.runs);
+        self.session.swap_session(&mut application.session);
+        mem::swap(&mut self.session_summary, &mut application.session_summary);
+        mem::swap(&mut self.command_acks, &mut application.command_acks);
+        self.firmware_log.swap_lines(&mut application.firmware_log);
+        mem::swap(&mut self.control_offset, &mut application.control_offset);
+    }
+}
+
+impl Application {
+    /// Store the metrics received by the ports in the background, as if each was shown
+    pub fn ingest_background_ports(&mut self) {
+        let mut ports = mem::take(&mut self.background_ports);
+
+        for port in &mut ports {
+            port.swap(self);
+
+            if !self.serial.state().is_connected() {
+                self.wall_clock_anchor = None;
+            }
+            let packets = self.serial.new_packets().collect::<Vec<_>>();
+            self.ingest_packets(packets);
+            self.poll_session_summary();
+            self.link_quality
+                .poll(Instant::now(), self.serial.packet_counts());
+
+            port.swap(self);
+        }
+
+        self.background_ports = ports;
+    }
+
+    /// Show the background port at `index`, moving the shown port to the background
+    ///
+    /// A playing macro and an action waiting for confirmation are dropped rather than
+    /// carried over, as they were meant for the robot that was shown.
+    pub fn show_port(&mut self, index: usize) {
+        let mut ports = mem::take(&mut self.background_ports);
+
+        if let Some(port) = ports.get_mut(index) {
+            // Both were meant for the robot shown so far and must never reach another one
+            if let Some(playback) = self.macro_playback.take() {
+                info!(
+                    command_macro = playback.name(),
+                    "stopped macro playback, another port was selected"
+                );
+            }
+            self.confirmations.cancel();
+
+            port.swap(self);
+        }
+
+        self.background_ports = ports;
+    }
+
+    /// Open another port in the background with the settings of the active profile
+    ///
+    /// Each port gets the first TCP control address after the one of the first port
+    /// that no other open port uses.
+    fn open_port(&mut self, ctx: &Context, port: String) {
+        let config = self
+            .profiles
+            .iter()
+            .find(|profile| profile.name == self.active_profile)
+            .or(self.profiles.first())
+            // Replaying without a port has no default profile
+            .map_or_else(SerialWorkerConfig::default, ConnectionProfile::config);
+
+        let offset = (1..=u16::MAX)
+            .find(|&offset| {
+                offset != self.control_offset
+                    && self
+                        .background_ports
+                        .iter()
+                        .all(|port| port.control_offset != offset)
+            })
+            .unwrap_or(u16::MAX);
+        let control_addr = self.control_addr_at(offset);
+
+        info!(port, %control_addr, "opening another serial port");
+
+        let serial = SerialWorkerController::spawn(
+            port,
+            SerialWorkerConfig {
+                control_addr,
+                max_frame_length: self.max_frame_length,
+                ..config
+            },
+            Box::new({
+                let ctx = ctx.clone();
+
+                move || ctx.request_repaint()
+            }),
+        );
+        serial.set_retain_raw_bytes(self.show_raw_bytes);
+        serial.set_read_timeout(self.read_timeout);
+
+        self.background_ports.push(PortSession::new(
+            Box::new(serial),
+            Timestamp::from_raw(0, self.timestamp_unit),
+            self.history_length,
+            offset,
+        ));
+    }
+
+    /// The TCP control address `offset` ports after the one of the first port
+    pub fn control_addr_at(&self, offset: u16) -> SocketAddr {
+        SocketAddr::new(
+            self.control_addr.ip(),
+            self.control_addr.port().saturating_add(offset),
+        )
+    }
+
+    /// Tabs switching between the open ports, with a menu to open more
+    pub fn ports_ui(&mut self, ctx: &Context, ui: &mut Ui) {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Ports");
+
+            ui.add(SelectableLabel::new(
+                true,
+                RichText::new(self.serial.port_name()).strong(),
+            ));
+
+            let mut to_show = None;
+            let mut to_close = None;
+            for (index, port) in self.background_ports.iter().enumerate() {
+                if ui
+                    .selectable_label(false, port.port_name())
+                    .on_hover_text_at_pointer(if port.session_summary.is_some() {
+                        format!(
+                            "{} metrics received in the background, \
+                             disconnected with a session summary to show",
+                            port.metrics_received
+                        )
+                    } else {
+                        format!(
+                            "{} metrics received in the background",
+                            port.metrics_received
+                        )
+                    })
+                    .clicked()
+                {
+                    to_show = Some(index);
+                }
+                if ui
+                    .small_button("🗙")
+                    .on_hover_text_at_pointer("Close this port")
+                    .clicked()
+                {
+                    to_close = Some(index);
+                }
+            }
+
+            ui.menu_button("Open Port…", |ui| {
+                let ports = match serialport::available_ports() {
+                    Ok(ports) => ports,
+                    Err(err) => {
+                        warn!(%err, "failed to list serial ports");
+                        Vec::new()
+                    }
+                };
+
+                let mut to_open = None;
+                for port in ports.into_iter().filter(|port| {
+                    port.port_name != self.serial.port_name()
+                        && !self
+                            .background_ports
+                            .iter()
+                            .any(|open| open.port_name() == port.port_name)
+                }) {
+                    if ui.button(&port.port_name).clicked() {
+                        to_open = Some(port.port_name);
+                    }
+                }
+
+                if let Some(port) = to_open {
+                    self.open_port(ctx, port);
+                    ui.close_menu();
+                }
+            })
+            .response
+            .on_hover_text_at_pointer(
+                "Connect to another robot with the settings of the active profile",
+            );
+
+            if let Some(index) = to_show {
+                self.show_port(index);
+            }
+            if let Some(index) = to_close {
+                let port = self.background_ports.remove(index);
+
+                info!(port = port.port_name(), "closed serial port");
+            }
+        });
+    }
+}
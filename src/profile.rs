@@ -0,0 +1,213 @@
+use kestrel_metric::timestamp::TimestampUnit;
+use kestrel_serial::{Framing, SerialWorkerConfig};
+use serde::{Deserialize, Serialize};
+
+/// How to find the serial port of a profile
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PortMatcher {
+    /// A fixed port name, such as `/dev/ttyACM0` or `COM3`
+    Name(String),
+    /// The first USB port with this serial number, regardless of its name
+    SerialNumber(String),
+}
+
+/// A named bundle of every per-connection setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectionProfile {
+    pub name: String,
+    pub port: PortMatcher,
+    pub baud_rate: u32,
+    pub timestamp_unit: TimestampUnit,
+    pub frame_delimiter: u8,
+    pub field_separator: u8,
+    #[serde(default)]
+    pub lowercase_names: bool,
+    #[serde(default)]
+    pub versioned_packets: bool,
+    #[serde(default)]
+    pub auto_detect_framing: bool,
+    #[serde(default)]
+    pub crc16: bool,
+}
+
+impl ConnectionProfile {
+    pub const DEFAULT_NAME: &'static str = "Default";
+
+    /// The profile built from the command line arguments
+    pub fn default_profile(port: String, config: SerialWorkerConfig) -> Self {
+        Self {
+            name: Self::DEFAULT_NAME.to_owned(),
+            port: PortMatcher::Name(port),
+            baud_rate: config.baud_rate,
+            timestamp_unit: config.timestamp_unit,
+            frame_delimiter: config.framing.frame_delimiter,
+            field_separator: config.framing.field_separator,
+            lowercase_names: config.lowercase_names,
+            versioned_packets: config.versioned_packets,
+            auto_detect_framing: config.auto_detect_framing,
+            crc16: config.crc16,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == Self::DEFAULT_NAME
+    }
+
+    /// Check a new name for a profile, as profiles are told apart by name
+    ///
+    /// `others` are the names of every other profile.
+    pub fn validate_name<'a>(
+        name: &str,
+        mut others: impl Iterator<Item = &'a str>,
+    ) -> Result<(), &'static str> {
+        if name.trim().is_empty() {
+            Err("the name can not be empty")
+        } else if name == Self::DEFAULT_NAME {
+            Err("this name is reserved for the profile from the command line")
+        } else if others.any(|other| other == name) {
+            Err("another profile already has this name")
+        } else {
+            Ok(())
+        }
+    }
+
+    /// First name of the form `Profile <n>` not used by any of `profiles`
+    pub fn unused_name(profiles: &[ConnectionProfile]) -> String {
+        (1..)
+            .map(|number| format!("Profile {number}"))
+            .find(|name| profiles.iter().all(|profile| profile.name != *name))
+            .expect("there are fewer profiles than numbers")
+    }
+
+    /// The settings to spawn a serial worker with
+    pub fn config(&self) -> SerialWorkerConfig {
+        SerialWorkerConfig {
+            baud_rate: self.baud_rate,
+            timestamp_unit: self.timestamp_unit,
+            framing: Framing {
+                frame_delimiter: self.frame_delimiter,
+                field_separator: self.field_separator,
+            },
+            lowercase_names: self.lowercase_names,
+            versioned_packets: self.versioned_packets,
+            auto_detect_framing: self.auto_detect_framing,
+            crc16: self.crc16,
+            ..SerialWorkerConfig::default()
+        }
+    }
+
+    /// Find the name of the port this profile should connect to
+    pub fn resolve_port(&self) -> Option<String> {
+        match &self.port {
+            PortMatcher::Name(name) => Some(name.clone()),
+            PortMatcher::SerialNumber(serial_number) => serialport::available_ports()
+                .ok()?
+                .into_iter()
+                .find(|port| match &port.port_type {
+                    serialport::SerialPortType::UsbPort(info) => {
+                        info.serial_number.as_ref() == Some(serial_number)
+                    }
+                    _ => false,
+                })
+                .map(|port| port.port_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> ConnectionProfile {
+        ConnectionProfile {
+            name: "Bench".to_owned(),
+            port: PortMatcher::SerialNumber("A1B2".to_owned()),
+            baud_rate: 9600,
+            timestamp_unit: TimestampUnit::Micros,
+            frame_delimiter: 0xaa,
+            field_separator: 0x1f,
+            lowercase_names: true,
+            versioned_packets: true,
+            auto_detect_framing: false,
+            crc16: true,
+        }
+    }
+
+    #[test]
+    fn serialization_round_trip() {
+        let json = serde_json::to_string(&profile()).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<ConnectionProfile>(&json).unwrap(),
+            profile()
+        );
+    }
+
+    #[test]
+    fn profiles_saved_before_the_optional_settings_still_load() {
+        let json = r#"{
+            "name": "Old",
+            "port": { "Name": "COM3" },
+            "baud_rate": 115200,
+            "timestamp_unit": "Millis",
+            "frame_delimiter": 0,
+            "field_separator": 0
+        }"#;
+
+        let profile = serde_json::from_str::<ConnectionProfile>(json).unwrap();
+
+        assert_eq!(profile.port, PortMatcher::Name("COM3".to_owned()));
+        assert!(!profile.lowercase_names && !profile.versioned_packets);
+        assert!(!profile.auto_detect_framing && !profile.crc16);
+    }
+
+    #[test]
+    fn config_applies_every_setting() {
+        let config = profile().config();
+
+        assert_eq!(config.baud_rate, 9600);
+        assert_eq!(config.timestamp_unit, TimestampUnit::Micros);
+        assert_eq!(config.framing.frame_delimiter, 0xaa);
+        assert_eq!(config.framing.field_separator, 0x1f);
+        assert!(config.lowercase_names && config.versioned_packets && config.crc16);
+        assert!(!config.auto_detect_framing);
+    }
+
+    #[test]
+    fn default_profile_spawns_with_the_command_line_config() {
+        let config = SerialWorkerConfig {
+            baud_rate: 57600,
+            framing: Framing {
+                frame_delimiter: 0x7e,
+                field_separator: 0x3a,
+            },
+            crc16: true,
+            ..SerialWorkerConfig::default()
+        };
+        let profile = ConnectionProfile::default_profile("/dev/ttyACM0".to_owned(), config);
+
+        assert!(profile.is_default());
+        assert_eq!(profile.resolve_port().as_deref(), Some("/dev/ttyACM0"));
+        assert_eq!(profile.config(), config);
+    }
+
+    #[test]
+    fn rejects_reserved_duplicate_and_empty_names() {
+        let others = ["Default", "Bench"];
+
+        assert!(ConnectionProfile::validate_name("Rover", others.into_iter()).is_ok());
+        assert!(ConnectionProfile::validate_name("Bench", others.into_iter()).is_err());
+        assert!(ConnectionProfile::validate_name("Default", [].into_iter()).is_err());
+        assert!(ConnectionProfile::validate_name("  ", [].into_iter()).is_err());
+    }
+
+    #[test]
+    fn unused_name_skips_taken_numbers() {
+        let mut first = profile();
+        first.name = "Profile 1".to_owned();
+        let mut third = profile();
+        third.name = "Profile 3".to_owned();
+
+        assert_eq!(ConnectionProfile::unused_name(&[first, third]), "Profile 2");
+    }
+}
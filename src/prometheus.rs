@@ -0,0 +1,343 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::visualization::focused_metrics::as_plot_value;
+
+/// Least time between two renders of the page, scrapes in between get the previous one
+const RENDER_INTERVAL: Duration = Duration::from_secs(1);
+/// Longest a scraper may take to send its request or read the page
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Prefix of every metric family, which also makes every name start with a valid character
+const PREFIX: &str = "kestrel_";
+
+/// Serves the latest value of every numeric metric on `/metrics`, in the Prometheus
+/// text exposition format
+///
+/// The page is rendered from the UI thread at most once a second and served as is
+/// from a thread per scrape, so a scrape never waits on the UI and the other way around.
+/// Each metric is a gauge named after the last component of its name, with its
+/// namespaces in the `namespace` label.
+pub struct PrometheusExporter {
+    addr: SocketAddr,
+    page: Arc<Mutex<String>>,
+    rendered_at: Option<Instant>,
+}
+
+impl PrometheusExporter {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+
+        let page = Arc::new(Mutex::new(String::new()));
+
+        thread::Builder::new().name("prometheus".into()).spawn({
+            let page = Arc::clone(&page);
+
+            move || {
+                for stream in listener.incoming() {
+                    let stream = match stream {
+                        Ok(stream) => stream,
+                        Err(err) => {
+                            warn!(%err, "failed to accept prometheus scrape");
+                            continue;
+                        }
+                    };
+
+                    // Each scrape gets its own thread, so a slow scraper can not hold up the others
+                    let spawned = thread::Builder::new()
+                        .name("prometheus_client".into())
+                        .spawn({
+                            let page = Arc::clone(&page);
+
+                            move || {
+                                if let Err(err) = serve(stream, &page) {
+                                    warn!(%err, "failed to serve prometheus scrape");
+                                }
+                            }
+                        });
+
+                    if let Err(err) = spawned {
+                        warn!(%err, "failed to spawn a thread for the prometheus scrape");
+                    }
+                }
+            }
+        })?;
+
+        info!(%addr, "serving prometheus metrics");
+
+        Ok(Self {
+            addr,
+            page,
+            rendered_at: None,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Render the latest values into the served page, if it was not rendered too recently
+    pub fn poll_render<'metric>(
+        &mut self,
+        now: Instant,
+        latest_values: impl Iterator<
+            Item = (
+                &'metric MetricName,
+                &'metric (Timestamp, OffsetDateTime, MetricValue),
+            ),
+        >,
+    ) {
+        if self
+            .rendered_at
+            .is_some_and(|rendered_at| now.duration_since(rendered_at) < RENDER_INTERVAL)
+        {
+            return;
+        }
+        self.rendered_at = Some(now);
+
+        let page = render(latest_values.map(|(name, (_, _, value))| (name, value)));
+
+        *self.page.lock().unwrap_or_else(PoisonError::into_inner) = page;
+    }
+}
+
+/// Format the numeric metrics as gauges, grouped in families by their sanitized name
+///
+/// Names that only differ in the characters sanitizing replaces would end up as the
+/// same series, so every name after the first gets a numbered suffix instead.
+fn render<'metric>(
+    metrics: impl Iterator<Item = (&'metric MetricName, &'metric MetricValue)>,
+) -> String {
+    // Grouped by the name as sent first, so the suffixes do not depend on the order of the metrics
+    let mut by_name = BTreeMap::<&str, Vec<(String, f64)>>::new();
+
+    for (name, value) in metrics {
+        let Some(value) = as_plot_value(value) else {
+            continue;
+        };
+
+        let components = name.flatten().collect::<Vec<_>>();
+        let Some((last, namespaces)) = components.split_last() else {
+            continue;
+        };
+
+        by_name
+            .entry(last)
+            .or_default()
+            .push((namespaces.join(":"), value));
+    }
+
+    let mut families = BTreeMap::<String, Vec<(String, f64)>>::new();
+    for (name, mut samples) in by_name {
+        let sanitized = sanitize_name(name);
+
+        let mut family = sanitized.clone();
+        let mut suffix = 1;
+        while families.contains_key(&family) {
+            suffix += 1;
+            family = format!("{sanitized}_{suffix}");
+        }
+
+        samples.sort_by(|(a, _), (b, _)| a.cmp(b));
+        families.insert(family, samples);
+    }
+
+    let mut page = String::new();
+    for (family, samples) in families {
+        // Formatting into a string can not fail
+        let _ = writeln!(page, "# TYPE {family} gauge");
+
+        for (namespace, value) in samples {
+            let _ = writeln!(
+                page,
+                "{family}{{namespace=\"{}\"}} {}",
+                escape_label(&namespace),
+                format_value(value)
+            );
+        }
+    }
+
+    page
+}
+
+/// A valid metric name for the last component of a metric name, prefixed with [`PREFIX`]
+fn sanitize_name(component: &str) -> String {
+    let mut name = String::from(PREFIX);
+
+    name.extend(component.chars().map(|char| {
+        if char.is_ascii_alphanumeric() || char == '_' {
+            char
+        } else {
+            '_'
+        }
+    }));
+
+    name
+}
+
+/// Escape the characters with a meaning inside a quoted label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format a sample value, with the spelling of the exposition format for the special values
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_owned()
+    } else if value.is_infinite() {
+        if value.is_sign_positive() {
+            "+Inf"
+        } else {
+            "-Inf"
+        }
+        .to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Answer a single HTTP request, with the page for `/metrics` and 404 for anything else
+fn serve(stream: TcpStream, page: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut reader = BufReader::new(&stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Read past the headers, the request has no body worth reading
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim_end() != "" {
+        header.clear();
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        (
+            "200 OK",
+            page.lock().unwrap_or_else(PoisonError::into_inner).clone(),
+        )
+    } else {
+        ("404 Not Found", String::from("only /metrics is served\n"))
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    fn render_all(metrics: &[(&str, MetricValue)]) -> String {
+        let metrics = metrics
+            .iter()
+            .map(|(name, value)| (name.parse::<MetricName>().unwrap(), value.clone()))
+            .collect::<Vec<_>>();
+
+        render(metrics.iter().map(|(name, value)| (name, value)))
+    }
+
+    #[test]
+    fn renders_numeric_metrics_as_gauges() {
+        let page = render_all(&[
+            ("motor:left:speed", MetricValue::One(OneValue::F32(1.5))),
+            ("motor:right:speed", MetricValue::One(OneValue::U16(3))),
+            ("armed", MetricValue::One(OneValue::Bool(true))),
+            ("currents", MetricValue::Many(ManyValues::F32([1.0].into()))),
+        ]);
+
+        assert_eq!(
+            page,
+            "# TYPE kestrel_armed gauge\n\
+             kestrel_armed{namespace=\"\"} 1\n\
+             # TYPE kestrel_speed gauge\n\
+             kestrel_speed{namespace=\"motor:left\"} 1.5\n\
+             kestrel_speed{namespace=\"motor:right\"} 3\n"
+        );
+    }
+
+    #[test]
+    fn names_that_sanitize_alike_get_a_suffix() {
+        let page = render_all(&[
+            ("speed.rpm", MetricValue::One(OneValue::U8(1))),
+            ("speed-rpm", MetricValue::One(OneValue::U8(2))),
+        ]);
+
+        assert_eq!(
+            page,
+            "# TYPE kestrel_speed_rpm gauge\n\
+             kestrel_speed_rpm{namespace=\"\"} 2\n\
+             # TYPE kestrel_speed_rpm_2 gauge\n\
+             kestrel_speed_rpm_2{namespace=\"\"} 1\n"
+        );
+    }
+
+    #[test]
+    fn sanitizes_names() {
+        assert_eq!(sanitize_name("speed"), "kestrel_speed");
+        assert_eq!(sanitize_name("3d pos.x"), "kestrel_3d_pos_x");
+        assert_eq!(sanitize_name("µs"), "kestrel__s");
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(escape_label("plain"), "plain");
+        assert_eq!(escape_label("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+
+    #[test]
+    fn formats_special_values() {
+        assert_eq!(format_value(1.25), "1.25");
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(format_value(f64::INFINITY), "+Inf");
+        assert_eq!(format_value(f64::NEG_INFINITY), "-Inf");
+    }
+
+    #[test]
+    fn idle_scraper_does_not_block_another() {
+        let exporter = PrometheusExporter::bind(([127, 0, 0, 1], 0).into()).unwrap();
+        *exporter.page.lock().unwrap() = "page\n".to_owned();
+
+        // Connects but never sends its request
+        let _idle = TcpStream::connect(exporter.addr()).unwrap();
+
+        let mut scraper = TcpStream::connect(exporter.addr()).unwrap();
+        scraper
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        scraper
+            .write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+
+        let mut response = String::new();
+        scraper.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\npage\n"));
+    }
+}
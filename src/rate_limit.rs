@@ -0,0 +1,248 @@
+use std::collections::BTreeMap;
+
+use eframe::egui::{DragValue, Grid, Ui};
+use kestrel_metric::name::MetricName;
+use time::{Duration, OffsetDateTime};
+
+/// What to do with an incoming sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateDecision {
+    /// Store the sample as usual
+    Keep,
+    /// The metric is over its rate, overwrite the newest stored sample instead of adding one
+    ReplaceLatest,
+}
+
+/// Caps how many samples per second each metric may store, so a single
+/// flooding metric can not push everything else out of the history
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    /// Samples per second allowed for every metric without an override
+    pub global_limit: Option<u32>,
+    /// Samples per second allowed for specific metrics
+    pub overrides: BTreeMap<MetricName, u32>,
+
+    last_kept: BTreeMap<MetricName, OffsetDateTime>,
+    dropped: BTreeMap<MetricName, u64>,
+}
+
+impl RateLimiter {
+    pub fn new(global_limit: Option<u32>) -> Self {
+        Self {
+            global_limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn limit(&self, name: &MetricName) -> Option<u32> {
+        self.overrides.get(name).copied().or(self.global_limit)
+    }
+
+    /// Decide whether a sample that arrived at `arrival` fits in the rate of its metric
+    pub fn admit(&mut self, name: &MetricName, arrival: OffsetDateTime) -> RateDecision {
+        let Some(limit) = self.limit(name).filter(|&limit| limit > 0) else {
+            return RateDecision::Keep;
+        };
+
+        let interval = Duration::SECOND / limit;
+
+        match self.last_kept.get(name) {
+            Some(&last) if arrival >= last && arrival - last < interval => {
+                *self.dropped.entry(name.clone()).or_default() += 1;
+
+                RateDecision::ReplaceLatest
+            }
+            _ => {
+                self.last_kept.insert(name.clone(), arrival);
+
+                RateDecision::Keep
+            }
+        }
+    }
+
+    /// Number of samples of a metric dropped for going over the rate
+    pub fn dropped(&self, name: &MetricName) -> u64 {
+        self.dropped.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn total_dropped(&self) -> u64 {
+        self.dropped.values().sum()
+    }
+
+    /// Exchange the timing and drop counts with those of another robot, keeping the configured limits
+    pub fn swap_counts(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.last_kept, &mut other.last_kept);
+        std::mem::swap(&mut self.dropped, &mut other.dropped);
+    }
+
+    /// Forget the timing and drop counts, keeping the configured limits
+    pub fn clear(&mut self) {
+        self.last_kept.clear();
+        self.dropped.clear();
+    }
+
+    /// Per metric overrides and drop counts
+    pub fn ui<'n>(
+        &mut self,
+        ui: &mut Ui,
+        names: impl Iterator<Item = &'n MetricName>,
+        default_limit: u32,
+    ) {
+        ui.collapsing(
+            format!("Rate limited samples ({})", self.total_dropped()),
+            |ui| {
+                Grid::new("rate_limits")
+                    .striped(true)
+                    .num_columns(3)
+                    .show(ui, |ui| {
+                        ui.label("metric");
+                        ui.label("limit").on_hover_text_at_pointer(
+                            "Override the global limit for a single metric",
+                        );
+                        ui.label("dropped");
+                        ui.end_row();
+
+                        for name in names {
+                            ui.monospace(name.to_string());
+
+                            ui.horizontal(|ui| {
+                                let mut overridden = self.overrides.contains_key(name);
+                                if ui.checkbox(&mut overridden, "").changed() {
+                                    if overridden {
+                                        let limit = self.global_limit.unwrap_or(default_limit);
+                                        self.overrides.insert(name.clone(), limit);
+                                    } else {
+                                        self.overrides.remove(name);
+                                    }
+                                }
+
+                                if let Some(limit) = self.overrides.get_mut(name) {
+                                    ui.add(
+                                        DragValue::new(limit).clamp_range(1..=10_000).suffix(" Hz"),
+                                    );
+                                } else if let Some(limit) = self.global_limit {
+                                    ui.weak(format!("{limit} Hz"));
+                                } else {
+                                    ui.weak("none");
+                                }
+                            });
+
+                            ui.monospace(self.dropped(name).to_string());
+                            ui.end_row();
+                        }
+                    });
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> MetricName {
+        name.parse().unwrap()
+    }
+
+    fn at(millis: i64) -> OffsetDateTime {
+        OffsetDateTime::UNIX_EPOCH + Duration::milliseconds(millis)
+    }
+
+    #[test]
+    fn without_a_limit_everything_is_kept() {
+        let mut limiter = RateLimiter::new(None);
+
+        for millis in 0..100 {
+            assert_eq!(limiter.admit(&name("a"), at(millis)), RateDecision::Keep);
+        }
+        assert_eq!(limiter.total_dropped(), 0);
+    }
+
+    #[test]
+    fn zero_limit_is_no_limit() {
+        let mut limiter = RateLimiter::new(Some(0));
+
+        assert_eq!(limiter.admit(&name("a"), at(0)), RateDecision::Keep);
+        assert_eq!(limiter.admit(&name("a"), at(0)), RateDecision::Keep);
+    }
+
+    #[test]
+    fn samples_over_the_rate_replace_the_latest() {
+        // One sample every 100 ms
+        let mut limiter = RateLimiter::new(Some(10));
+
+        let decisions =
+            [0, 50, 99, 100, 150, 250].map(|millis| limiter.admit(&name("a"), at(millis)));
+
+        assert_eq!(
+            decisions,
+            [
+                RateDecision::Keep,
+                RateDecision::ReplaceLatest,
+                RateDecision::ReplaceLatest,
+                RateDecision::Keep,
+                RateDecision::ReplaceLatest,
+                RateDecision::Keep,
+            ]
+        );
+        assert_eq!(limiter.dropped(&name("a")), 3);
+        assert_eq!(limiter.total_dropped(), 3);
+    }
+
+    #[test]
+    fn metrics_are_limited_separately() {
+        let mut limiter = RateLimiter::new(Some(10));
+
+        assert_eq!(limiter.admit(&name("a"), at(0)), RateDecision::Keep);
+        assert_eq!(limiter.admit(&name("b"), at(10)), RateDecision::Keep);
+        assert_eq!(
+            limiter.admit(&name("a"), at(20)),
+            RateDecision::ReplaceLatest
+        );
+        assert_eq!(limiter.dropped(&name("a")), 1);
+        assert_eq!(limiter.dropped(&name("b")), 0);
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let mut limiter = RateLimiter::new(Some(1));
+        limiter.overrides.insert(name("fast"), 1000);
+
+        assert_eq!(limiter.limit(&name("fast")), Some(1000));
+        assert_eq!(limiter.limit(&name("slow")), Some(1));
+
+        assert_eq!(limiter.admit(&name("fast"), at(0)), RateDecision::Keep);
+        assert_eq!(limiter.admit(&name("fast"), at(1)), RateDecision::Keep);
+        assert_eq!(limiter.admit(&name("slow"), at(0)), RateDecision::Keep);
+        assert_eq!(
+            limiter.admit(&name("slow"), at(999)),
+            RateDecision::ReplaceLatest
+        );
+    }
+
+    #[test]
+    fn arrival_going_back_is_kept() {
+        let mut limiter = RateLimiter::new(Some(10));
+
+        assert_eq!(limiter.admit(&name("a"), at(1000)), RateDecision::Keep);
+        assert_eq!(limiter.admit(&name("a"), at(990)), RateDecision::Keep);
+        assert_eq!(
+            limiter.admit(&name("a"), at(1000)),
+            RateDecision::ReplaceLatest
+        );
+    }
+
+    #[test]
+    fn clear_keeps_the_limits() {
+        let mut limiter = RateLimiter::new(Some(10));
+        limiter.overrides.insert(name("a"), 5);
+        limiter.admit(&name("a"), at(0));
+        limiter.admit(&name("a"), at(1));
+
+        limiter.clear();
+
+        assert_eq!(limiter.total_dropped(), 0);
+        assert_eq!(limiter.limit(&name("a")), Some(5));
+        assert_eq!(limiter.admit(&name("a"), at(1)), RateDecision::Keep);
+    }
+}
@@ -0,0 +1,45 @@
+use kestrel_metric::timestamp::Timestamp;
+
+/// Highlights the metrics whose value changed recently in the latest metrics table,
+/// optionally hiding the metrics that did not
+#[derive(Debug)]
+pub struct RecentChanges {
+    pub enabled: bool,
+    /// Hide the metrics that did not change recently, instead of only highlighting the ones that did
+    pub only_changed: bool,
+    /// Time since the latest change within which a metric counts as recently changed
+    pub window_secs: f64,
+}
+
+impl Default for RecentChanges {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            only_changed: false,
+            window_secs: 2.0,
+        }
+    }
+}
+
+impl RecentChanges {
+    /// Whether a metric whose value last changed at `changed` should be highlighted at `now`
+    pub fn is_recent(&self, now: Timestamp, changed: Option<Timestamp>) -> bool {
+        let Some(changed) = changed.filter(|_| self.enabled) else {
+            return false;
+        };
+        // Changes after a past time picked with the time cursor have not happened yet
+        if changed > now {
+            return false;
+        }
+
+        let ticks_per_second = f64::from(now.unit().ticks_per_second());
+        let since_change = f64::from((now - changed).timestamp()) / ticks_per_second;
+
+        since_change <= self.window_secs
+    }
+
+    /// Whether a metric whose value last changed at `changed` is shown in the table at `now`
+    pub fn is_shown(&self, now: Timestamp, changed: Option<Timestamp>) -> bool {
+        !(self.enabled && self.only_changed) || self.is_recent(now, changed)
+    }
+}
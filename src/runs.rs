@@ -0,0 +1,193 @@
+use kestrel_metric::{timestamp::Timestamp, Metric};
+
+/// A named segment of the metric stream, marked by the user
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub name: String,
+    pub start: Timestamp,
+    /// End of the run, or `None` while the run is still in progress
+    pub end: Option<Timestamp>,
+}
+
+impl Run {
+    /// The end of the run, treating an open run as ending at the current time
+    pub fn end_or(&self, current_time: Timestamp) -> Timestamp {
+        self.end.unwrap_or(current_time)
+    }
+
+    pub fn contains(&self, timestamp: Timestamp) -> bool {
+        self.start <= timestamp && self.end.map_or(true, |end| timestamp <= end)
+    }
+
+    /// The metrics received during the run, to export it on its own
+    pub fn metrics<'r, 'm>(
+        &'r self,
+        metrics: impl Iterator<Item = &'m Metric> + 'r,
+    ) -> impl Iterator<Item = &'m Metric> + 'r {
+        metrics.filter(|metric| self.contains(metric.timestamp))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Runs {
+    runs: Vec<Run>,
+    started: usize,
+}
+
+impl Runs {
+    /// Start a new run, stopping the currently open one if there is one
+    pub fn start(&mut self, now: Timestamp) {
+        self.stop(now);
+
+        self.started += 1;
+        self.runs.push(Run {
+            name: format!("Run {}", self.started),
+            start: now,
+            end: None,
+        });
+    }
+
+    /// Stop the currently open run, if any
+    pub fn stop(&mut self, now: Timestamp) {
+        if let Some(run) = self.runs.last_mut().filter(|run| run.end.is_none()) {
+            run.end = Some(now);
+        }
+    }
+
+    pub fn open_run(&self) -> Option<&Run> {
+        self.runs.last().filter(|run| run.end.is_none())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Run {
+        self.runs.remove(index)
+    }
+
+    pub fn clear(&mut self) {
+        self.runs.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Run> {
+        self.runs.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Run> {
+        self.runs.iter_mut()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{MetricValue, OneValue};
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    fn at(millis: u32) -> Timestamp {
+        Timestamp::from_millis(millis)
+    }
+
+    fn spans(runs: &Runs) -> Vec<(String, Timestamp, Option<Timestamp>)> {
+        runs.iter()
+            .map(|run| (run.name.clone(), run.start, run.end))
+            .collect()
+    }
+
+    #[test]
+    fn start_and_stop_segment_the_stream() {
+        let mut runs = Runs::default();
+
+        runs.start(at(10));
+        runs.stop(at(20));
+        runs.start(at(30));
+        runs.stop(at(40));
+
+        assert_eq!(
+            spans(&runs),
+            [
+                ("Run 1".to_owned(), at(10), Some(at(20))),
+                ("Run 2".to_owned(), at(30), Some(at(40))),
+            ]
+        );
+        assert!(runs.open_run().is_none());
+    }
+
+    #[test]
+    fn starting_stops_the_open_run() {
+        let mut runs = Runs::default();
+
+        runs.start(at(10));
+        runs.start(at(20));
+
+        assert_eq!(
+            spans(&runs),
+            [
+                ("Run 1".to_owned(), at(10), Some(at(20))),
+                ("Run 2".to_owned(), at(20), None),
+            ]
+        );
+        assert_eq!(runs.open_run().map(|run| run.start), Some(at(20)));
+    }
+
+    #[test]
+    fn stopping_without_an_open_run_does_nothing() {
+        let mut runs = Runs::default();
+        runs.stop(at(5));
+        assert!(runs.is_empty());
+
+        runs.start(at(10));
+        runs.stop(at(20));
+        runs.stop(at(30));
+        assert_eq!(spans(&runs), [("Run 1".to_owned(), at(10), Some(at(20)))]);
+    }
+
+    #[test]
+    fn names_keep_counting_after_removal() {
+        let mut runs = Runs::default();
+        runs.start(at(10));
+        runs.remove(0);
+        runs.start(at(20));
+
+        assert_eq!(spans(&runs), [("Run 2".to_owned(), at(20), None)]);
+    }
+
+    #[test]
+    fn open_run_ends_at_the_current_time() {
+        let mut runs = Runs::default();
+        runs.start(at(10));
+        let run = runs.open_run().unwrap();
+
+        assert_eq!(run.end_or(at(50)), at(50));
+        assert!(run.contains(at(1000)));
+        assert!(!run.contains(at(9)));
+    }
+
+    #[test]
+    fn export_takes_the_metrics_within_the_run() {
+        let metrics = [5, 10, 15, 20, 25].map(|millis| Metric {
+            timestamp: at(millis),
+            arrival: OffsetDateTime::UNIX_EPOCH,
+            name: "speed".parse().unwrap(),
+            value: MetricValue::One(OneValue::U32(millis)),
+            raw: None,
+        });
+        let exported = |run: &Run| {
+            run.metrics(metrics.iter())
+                .map(|metric| metric.timestamp)
+                .collect::<Vec<_>>()
+        };
+
+        let mut runs = Runs::default();
+        runs.start(at(10));
+        runs.stop(at(20));
+        runs.start(at(22));
+
+        let runs = runs.iter().collect::<Vec<_>>();
+        // Both boundaries are part of the run
+        assert_eq!(exported(runs[0]), [at(10), at(15), at(20)]);
+        assert_eq!(exported(runs[1]), [at(25)]);
+    }
+}
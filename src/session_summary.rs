@@ -0,0 +1,323 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use eframe::egui::{Grid, Ui};
+use kestrel_metric::name::MetricName;
+use kestrel_serial::SerialWorkerState;
+use time::OffsetDateTime;
+
+use crate::export::format_arrival;
+
+/// Running totals of the errors counted elsewhere, snapshotted at the start and end of a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub length_mismatches: u64,
+    pub rate_limited: u64,
+}
+
+impl ErrorCounts {
+    /// Errors counted since `start`, treating counters that were reset in between as starting at zero
+    fn since(self, start: Self) -> Self {
+        let delta = |now: u64, start: u64| if now >= start { now - start } else { now };
+
+        Self {
+            length_mismatches: delta(self.length_mismatches, start.length_mismatches),
+            rate_limited: delta(self.rate_limited, start.rate_limited),
+        }
+    }
+}
+
+/// Wrap-up of a single connection, from connecting until the robot disconnected
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub started: OffsetDateTime,
+    pub duration: Duration,
+    pub samples: BTreeMap<MetricName, u64>,
+    pub errors: ErrorCounts,
+}
+
+impl SessionSummary {
+    pub fn total_samples(&self) -> u64 {
+        self.samples.values().sum()
+    }
+
+    pub fn ui(&self, ui: &mut Ui) {
+        let seconds = self.duration.as_secs();
+
+        Grid::new("session_summary").num_columns(2).show(ui, |ui| {
+            ui.label("Started");
+            ui.monospace(format_arrival(self.started));
+            ui.end_row();
+
+            ui.label("Duration");
+            ui.monospace(format!(
+                "{:02}:{:02}:{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            ));
+            ui.end_row();
+
+            ui.label("Packets");
+            ui.monospace(self.total_samples().to_string());
+            ui.end_row();
+
+            ui.label("Length mismatches");
+            ui.monospace(self.errors.length_mismatches.to_string());
+            ui.end_row();
+
+            ui.label("Rate limited");
+            ui.monospace(self.errors.rate_limited.to_string());
+            ui.end_row();
+        });
+
+        ui.collapsing(format!("Samples of {} metrics", self.samples.len()), |ui| {
+            Grid::new("session_summary_samples")
+                .striped(true)
+                .num_columns(2)
+                .show(ui, |ui| {
+                    for (name, count) in &self.samples {
+                        ui.monospace(name.to_string());
+                        ui.monospace(count.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+}
+
+#[derive(Debug)]
+struct Session {
+    started: OffsetDateTime,
+    started_instant: Instant,
+    samples: BTreeMap<MetricName, u64>,
+    errors_at_start: ErrorCounts,
+}
+
+/// Follows the serial connection to produce a [`SessionSummary`] whenever the robot disconnects
+#[derive(Debug)]
+pub struct SessionTracker {
+    /// Produce summaries at all, the user can turn them off
+    pub enabled: bool,
+
+    last_state: Option<SerialWorkerState>,
+    session: Option<Session>,
+}
+
+impl Default for SessionTracker {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+
+            last_state: None,
+            session: None,
+        }
+    }
+}
+
+impl SessionTracker {
+    /// Exchange the session being followed with that of another robot, keeping the setting
+    pub fn swap_session(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.last_state, &mut other.last_state);
+        std::mem::swap(&mut self.session, &mut other.session);
+    }
+
+    /// Count a sample received during the current session
+    pub fn record(&mut self, name: &MetricName) {
+        if let Some(session) = &mut self.session {
+            *session.samples.entry(name.clone()).or_default() += 1;
+        }
+    }
+
+    /// Follow the state of the serial worker, returning a summary once a connected
+    /// session ends with the robot disconnecting
+    ///
+    /// Detaching on purpose ends the session without a summary.
+    pub fn poll(
+        &mut self,
+        state: SerialWorkerState,
+        now: OffsetDateTime,
+        errors: impl FnOnce() -> ErrorCounts,
+    ) -> Option<SessionSummary> {
+        let last_state = self.last_state.replace(state);
+
+        if last_state == Some(state) {
+            return None;
+        }
+
+        match state {
+            // Coming back from a reset continues the same session
+            SerialWorkerState::Connected | SerialWorkerState::Stalled => {
+                self.session.get_or_insert_with(|| Session {
+                    started: now,
+                    started_instant: Instant::now(),
+                    samples: BTreeMap::new(),
+                    errors_at_start: errors(),
+                });
+
+                None
+            }
+            SerialWorkerState::Disconnected => {
+                let session = self.session.take()?;
+
+                self.enabled.then(|| SessionSummary {
+                    started: session.started,
+                    duration: session.started_instant.elapsed(),
+                    samples: session.samples,
+                    errors: errors().since(session.errors_at_start),
+                })
+            }
+            SerialWorkerState::Detached => {
+                self.session = None;
+
+                None
+            }
+            SerialWorkerState::Resetting => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str) -> MetricName {
+        name.parse().unwrap()
+    }
+
+    fn errors(length_mismatches: u64, rate_limited: u64) -> ErrorCounts {
+        ErrorCounts {
+            length_mismatches,
+            rate_limited,
+        }
+    }
+
+    /// Connect, receive `samples`, and disconnect, returning the summary
+    fn session(
+        tracker: &mut SessionTracker,
+        samples: &[&str],
+        start: ErrorCounts,
+        end: ErrorCounts,
+    ) -> Option<SessionSummary> {
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        assert!(tracker
+            .poll(SerialWorkerState::Connected, now, || start)
+            .is_none());
+        for sample in samples {
+            tracker.record(&name(sample));
+        }
+
+        tracker.poll(SerialWorkerState::Disconnected, now, || end)
+    }
+
+    #[test]
+    fn summary_counts_samples_per_metric() {
+        let mut tracker = SessionTracker::default();
+
+        let summary = session(
+            &mut tracker,
+            &["a", "b", "a", "a"],
+            ErrorCounts::default(),
+            ErrorCounts::default(),
+        )
+        .unwrap();
+
+        assert_eq!(summary.started, OffsetDateTime::UNIX_EPOCH);
+        assert_eq!(summary.samples, [(name("a"), 3), (name("b"), 1)].into());
+        assert_eq!(summary.total_samples(), 4);
+    }
+
+    #[test]
+    fn summary_counts_errors_since_the_start() {
+        let mut tracker = SessionTracker::default();
+
+        let summary = session(&mut tracker, &[], errors(5, 10), errors(7, 15)).unwrap();
+        assert_eq!(summary.errors, errors(2, 5));
+
+        // A counter that was reset during the session counts from zero
+        let summary = session(&mut tracker, &[], errors(5, 10), errors(3, 12)).unwrap();
+        assert_eq!(summary.errors, errors(3, 2));
+    }
+
+    #[test]
+    fn samples_outside_a_session_are_not_counted() {
+        let mut tracker = SessionTracker::default();
+        tracker.record(&name("early"));
+
+        let summary = session(
+            &mut tracker,
+            &["a"],
+            ErrorCounts::default(),
+            ErrorCounts::default(),
+        )
+        .unwrap();
+        tracker.record(&name("late"));
+
+        assert_eq!(summary.samples, [(name("a"), 1)].into());
+        assert!(session(
+            &mut tracker,
+            &[],
+            ErrorCounts::default(),
+            ErrorCounts::default()
+        )
+        .unwrap()
+        .samples
+        .is_empty());
+    }
+
+    #[test]
+    fn reset_and_stall_continue_the_session() {
+        let mut tracker = SessionTracker::default();
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        for state in [
+            SerialWorkerState::Connected,
+            SerialWorkerState::Stalled,
+            SerialWorkerState::Resetting,
+            SerialWorkerState::Connected,
+        ] {
+            assert!(tracker.poll(state, now, ErrorCounts::default).is_none());
+            tracker.record(&name("a"));
+        }
+
+        let summary = tracker
+            .poll(SerialWorkerState::Disconnected, now, ErrorCounts::default)
+            .unwrap();
+        assert_eq!(summary.samples, [(name("a"), 4)].into());
+    }
+
+    #[test]
+    fn detaching_ends_the_session_without_a_summary() {
+        let mut tracker = SessionTracker::default();
+        let now = OffsetDateTime::UNIX_EPOCH;
+
+        tracker.poll(SerialWorkerState::Connected, now, ErrorCounts::default);
+        tracker.record(&name("a"));
+
+        assert!(tracker
+            .poll(SerialWorkerState::Detached, now, ErrorCounts::default)
+            .is_none());
+        assert!(tracker
+            .poll(SerialWorkerState::Disconnected, now, ErrorCounts::default)
+            .is_none());
+    }
+
+    #[test]
+    fn disabled_tracker_gives_no_summary() {
+        let mut tracker = SessionTracker {
+            enabled: false,
+            ..Default::default()
+        };
+
+        assert!(session(
+            &mut tracker,
+            &["a"],
+            ErrorCounts::default(),
+            ErrorCounts::default()
+        )
+        .is_none());
+    }
+}
@@ -0,0 +1,43 @@
+use eframe::egui::{Context, Visuals};
+use serde::{Deserialize, Serialize};
+
+/// Whether the interface is drawn dark or light
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ThemePreference {
+    /// Dark or light, like the operating system
+    #[default]
+    System,
+    Dark,
+    Light,
+}
+
+impl ThemePreference {
+    pub const ALL: [Self; 3] = [Self::System, Self::Dark, Self::Light];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ThemePreference::System => "Follow system",
+            ThemePreference::Dark => "Dark",
+            ThemePreference::Light => "Light",
+        }
+    }
+
+    /// Switch the visuals of `ctx` to this theme, if they are not already
+    ///
+    /// Without a known system theme, following the system falls back to dark.
+    pub fn apply(self, ctx: &Context, system_theme: Option<eframe::Theme>) {
+        let dark_mode = match self {
+            ThemePreference::System => system_theme != Some(eframe::Theme::Light),
+            ThemePreference::Dark => true,
+            ThemePreference::Light => false,
+        };
+
+        if ctx.style().visuals.dark_mode != dark_mode {
+            ctx.set_visuals(if dark_mode {
+                Visuals::dark()
+            } else {
+                Visuals::light()
+            });
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeMap;
+
+use kestrel_metric::{
+    name::MetricName,
+    timestamp::Timestamp,
+    value::{MetricValue, OneValue},
+};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
+
+use crate::visualization::focused_metrics::as_plot_value;
+
+/// What to do with samples of a metric once it has changed type mid-session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TypeChangePolicy {
+    /// Keep one series, widening every numeric sample to `f64`
+    #[default]
+    Coerce,
+    /// Store samples of a new type under a separate `name#type` series
+    Split,
+}
+
+impl TypeChangePolicy {
+    pub const fn label(self) -> &'static str {
+        match self {
+            TypeChangePolicy::Coerce => "Coerce to f64",
+            TypeChangePolicy::Split => "Split into series",
+        }
+    }
+}
+
+/// Tracks every type seen per metric, in order of first appearance
+#[derive(Debug, Default)]
+pub struct TypeChanges {
+    seen: BTreeMap<MetricName, Vec<String>>,
+}
+
+impl TypeChanges {
+    /// Record the type of a new sample, returning all types seen for this metric
+    pub fn observe(&mut self, name: &MetricName, ty: &str) -> &[String] {
+        let types = self.seen.entry(name.clone()).or_default();
+
+        if !types.iter().any(|seen| seen == ty) {
+            types.push(ty.to_owned());
+        }
+
+        types
+    }
+
+    /// All types a metric has been seen with, if it has changed type
+    pub fn changed_types(&self, name: &MetricName) -> Option<&[String]> {
+        self.seen
+            .get(name)
+            .filter(|types| types.len() > 1)
+            .map(Vec::as_slice)
+    }
+
+    pub fn clear(&mut self) {
+        self.seen.clear();
+    }
+
+    /// Route a sample according to the policy, returning the series it
+    /// should be stored under and the value to store
+    ///
+    /// When coercing, the samples already in the `history` of the metric are widened
+    /// too as soon as it first changes type, so the series never mixes types.
+    pub fn apply(
+        &mut self,
+        policy: TypeChangePolicy,
+        name: &MetricName,
+        value: &MetricValue,
+        history: Option<&mut AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>>,
+    ) -> (MetricName, MetricValue) {
+        let known_types = self.seen.get(name).map_or(0, Vec::len);
+        let types = self.observe(name, value.ty());
+
+        if types.len() == 1 {
+            return (name.clone(), value.clone());
+        }
+
+        let is_first_change = known_types == 1;
+        let is_first_type = types[0] == value.ty();
+
+        match policy {
+            TypeChangePolicy::Coerce => {
+                if let (true, Some(history)) = (is_first_change, history) {
+                    for (_, _, sample) in history.iter_mut() {
+                        *sample = coerce(sample);
+                    }
+                }
+
+                (name.clone(), coerce(value))
+            }
+            TypeChangePolicy::Split if !is_first_type => (
+                format!("{name}#{}", value.ty())
+                    .parse()
+                    .expect("metric name parsing must never fail"),
+                value.clone(),
+            ),
+            TypeChangePolicy::Split => (name.clone(), value.clone()),
+        }
+    }
+}
+
+/// Widen a single numeric value to `f64`, leaving every other value as it is
+fn coerce(value: &MetricValue) -> MetricValue {
+    match value {
+        MetricValue::One(_) => as_plot_value(value)
+            .map(|value| MetricValue::One(OneValue::F64(value)))
+            .unwrap_or_else(|| value.clone()),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::new_metric_ring_buffer;
+
+    use super::*;
+
+    fn name() -> MetricName {
+        "speed".parse().unwrap()
+    }
+
+    fn history(
+        values: &[MetricValue],
+    ) -> AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)> {
+        let mut history = new_metric_ring_buffer(8);
+        for value in values {
+            history.push((Timestamp::MIN, OffsetDateTime::UNIX_EPOCH, value.clone()));
+        }
+        history
+    }
+
+    #[test]
+    fn detects_a_type_change() {
+        let mut changes = TypeChanges::default();
+
+        changes.observe(&name(), "u8");
+        changes.observe(&name(), "u8");
+        assert_eq!(changes.changed_types(&name()), None);
+
+        changes.observe(&name(), "f32");
+        assert_eq!(
+            changes.changed_types(&name()),
+            Some(["u8".to_owned(), "f32".to_owned()].as_slice())
+        );
+
+        changes.clear();
+        assert_eq!(changes.changed_types(&name()), None);
+    }
+
+    #[test]
+    fn unchanged_type_is_stored_as_is() {
+        let mut changes = TypeChanges::default();
+        let value = MetricValue::One(OneValue::U8(3));
+
+        assert_eq!(
+            changes.apply(TypeChangePolicy::Coerce, &name(), &value, None),
+            (name(), value)
+        );
+    }
+
+    #[test]
+    fn coerce_widens_the_new_sample_and_the_history() {
+        let mut changes = TypeChanges::default();
+        let first = MetricValue::One(OneValue::U8(3));
+        let mut history = history(&[MetricValue::One(OneValue::U8(3))]);
+
+        changes.apply(TypeChangePolicy::Coerce, &name(), &first, None);
+        let (stored_as, value) = changes.apply(
+            TypeChangePolicy::Coerce,
+            &name(),
+            &MetricValue::One(OneValue::I16(-2)),
+            Some(&mut history),
+        );
+
+        assert_eq!(stored_as, name());
+        assert_eq!(value, MetricValue::One(OneValue::F64(-2.0)));
+        assert_eq!(
+            history
+                .iter()
+                .map(|(_, _, value)| value)
+                .collect::<Vec<_>>(),
+            [&MetricValue::One(OneValue::F64(3.0))]
+        );
+    }
+
+    #[test]
+    fn coerce_keeps_values_that_are_not_numbers() {
+        let mut changes = TypeChanges::default();
+        let text = MetricValue::One(OneValue::Str("idle".into()));
+
+        changes.apply(
+            TypeChangePolicy::Coerce,
+            &name(),
+            &MetricValue::One(OneValue::U8(1)),
+            None,
+        );
+
+        assert_eq!(
+            changes.apply(TypeChangePolicy::Coerce, &name(), &text, None),
+            (name(), text)
+        );
+    }
+
+    #[test]
+    fn split_stores_new_types_separately() {
+        let mut changes = TypeChanges::default();
+        let first = MetricValue::One(OneValue::U8(1));
+        let second = MetricValue::One(OneValue::F32(0.5));
+
+        changes.apply(TypeChangePolicy::Split, &name(), &first, None);
+
+        assert_eq!(
+            changes
+                .apply(TypeChangePolicy::Split, &name(), &second, None)
+                .0
+                .to_string(),
+            "speed#f32"
+        );
+        assert_eq!(
+            changes.apply(TypeChangePolicy::Split, &name(), &first, None),
+            (name(), first)
+        );
+    }
+}
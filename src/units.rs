@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+use kestrel_metric::name::MetricName;
+use serde::{Deserialize, Serialize};
+
+/// User chosen units of metrics, such as `cm` for `ultrasonic:distance`,
+/// shown next to their values
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Units {
+    units: BTreeMap<MetricName, String>,
+}
+
+impl Units {
+    /// The unit of a metric, `None` if it has none or it was cleared
+    pub fn get(&self, metric_name: &MetricName) -> Option<&str> {
+        self.units
+            .get(metric_name)
+            .map(|unit| unit.trim())
+            .filter(|unit| !unit.is_empty())
+    }
+
+    /// The unit of a metric for editing in place, empty if it has none
+    pub fn edit(&mut self, metric_name: &MetricName) -> &mut String {
+        self.units.entry(metric_name.clone()).or_default()
+    }
+
+    /// Forget the metrics whose unit was cleared
+    pub fn remove_empty(&mut self) {
+        self.units.retain(|_, unit| !unit.trim().is_empty());
+    }
+}
+
+/// A value followed by its unit, if it has one
+pub fn with_unit(value: String, unit: Option<&str>) -> String {
+    match unit {
+        Some(unit) => format!("{value} {unit}"),
+        None => value,
+    }
+}
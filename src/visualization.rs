@@ -1,5 +1,18 @@
+pub mod array_profile;
+pub mod bool_waveforms;
+pub mod category_lanes;
+pub mod copy_menu;
 pub mod focused_metrics;
+pub mod format_cache;
+pub mod hex_inspector;
+pub mod histogram;
 pub mod latest_metrics;
+pub mod metric_explorer;
+pub mod metric_stats;
 pub mod metrics_history;
+pub mod packet_inspector;
+pub mod plot_view;
 pub mod robot;
 mod sizes;
+pub mod spectrum;
+pub mod time_cursor;
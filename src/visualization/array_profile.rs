@@ -0,0 +1,94 @@
+use std::{iter::StepBy, ops::Range};
+
+use eframe::egui::Ui;
+use egui_plot::{Line, Plot, PlotPoints, Points};
+use kestrel_metric::{name::MetricName, value::MetricValue};
+
+use crate::aliases::Aliases;
+
+use super::focused_metrics::as_plot_values;
+
+/// Elements drawn per array unless configured otherwise
+pub const DEFAULT_MAX_ARRAY_ELEMENTS: usize = 512;
+
+const PROFILE_HEIGHT: f32 = 120.0;
+
+/// Indices of an array of `len` elements to draw, evenly spread so at most
+/// `max_elements` of them are drawn
+pub fn decimated_indices(len: usize, max_elements: usize) -> StepBy<Range<usize>> {
+    let stride = len.div_ceil(max_elements.max(1)).max(1);
+
+    (0..len).step_by(stride)
+}
+
+/// Plot the newest sample of an array metric against the element index
+///
+/// Large arrays are decimated to `max_elements` points, only the drawing is
+/// affected and the stored samples stay complete.
+pub fn array_profile(
+    ui: &mut Ui,
+    name: &MetricName,
+    value: &MetricValue,
+    max_elements: usize,
+    aliases: &Aliases,
+) {
+    let Some(values) = as_plot_values(value) else {
+        return;
+    };
+    let values = values.collect::<Vec<_>>();
+
+    let points = decimated_indices(values.len(), max_elements)
+        .map(|index| [index as f64, values[index]])
+        .collect::<Vec<_>>();
+
+    let label = aliases.display(name);
+    ui.label(if points.len() < values.len() {
+        format!("{label} ({} of {} elements)", points.len(), values.len())
+    } else {
+        format!("{label} ({} elements)", values.len())
+    });
+
+    Plot::new(("array_profile", name))
+        .height(PROFILE_HEIGHT)
+        .show(ui, |ui| {
+            ui.line(Line::new(PlotPoints::new(points.clone())));
+            ui.points(Points::new(PlotPoints::new(points)).radius(1.5));
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(len: usize, max_elements: usize) -> Vec<usize> {
+        decimated_indices(len, max_elements).collect()
+    }
+
+    #[test]
+    fn small_arrays_are_drawn_whole() {
+        assert_eq!(indices(4, 8), [0, 1, 2, 3]);
+        assert_eq!(indices(8, 8), (0..8).collect::<Vec<_>>());
+        assert!(indices(0, 8).is_empty());
+    }
+
+    #[test]
+    fn large_arrays_are_spread_evenly() {
+        assert_eq!(indices(10, 5), [0, 2, 4, 6, 8]);
+        assert_eq!(indices(10, 4), [0, 3, 6, 9]);
+
+        for (len, max_elements) in [(1000, 7), (1023, 512), (4096, 100)] {
+            let indices = indices(len, max_elements);
+
+            assert!(indices.len() <= max_elements, "{len} into {max_elements}");
+            assert!(
+                indices.len() > max_elements / 2,
+                "{len} into {max_elements}"
+            );
+        }
+    }
+
+    #[test]
+    fn zero_limit_draws_the_first_element() {
+        assert_eq!(indices(3, 0), [0]);
+    }
+}
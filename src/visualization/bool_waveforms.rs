@@ -0,0 +1,158 @@
+use eframe::{
+    egui::{Ui, Vec2},
+    epaint::Color32,
+};
+use egui_plot::{uniform_grid_spacer, Line, Plot, PlotPoints, Polygon};
+use kestrel_metric::{
+    name::MetricName,
+    timestamp::{Timestamp, TimestampUnit},
+    value::MetricValue,
+};
+use time::OffsetDateTime;
+
+use crate::aliases::Aliases;
+
+use super::focused_metrics::{
+    color_from_metric_name, time_grid_steps, x_value_formatter, TIME_AXIS_GROUP,
+};
+
+/// Height of a single track in points
+const TRACK_HEIGHT: f32 = 28.0;
+/// Distance of the high and low levels from the center of a track, in tracks
+const LEVEL_OFFSET: f64 = 0.35;
+
+/// The `start..end` stretches of time during which a bool metric was high
+///
+/// A high level lasts until the next low sample, or until `end` (usually the
+/// current time) if no low sample followed it.
+pub fn high_intervals(samples: impl Iterator<Item = (u32, bool)>, end: u32) -> Vec<(u32, u32)> {
+    let mut intervals = Vec::new();
+    let mut rising = None;
+
+    for (timestamp, high) in samples {
+        match (rising, high) {
+            (None, true) => rising = Some(timestamp),
+            (Some(start), false) => {
+                intervals.push((start, timestamp));
+                rising = None;
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(start) = rising {
+        intervals.push((start, end.max(start)));
+    }
+
+    intervals
+}
+
+/// Logic analyzer style view of bool metrics, one square waveform track per metric
+/// with the high regions filled, sharing its time axis with the focused metrics plot
+pub fn bool_waveforms<'ui, 'iter>(
+    ui: &'ui mut Ui,
+    tracks: Vec<(
+        &'iter MetricName,
+        impl Iterator<Item = &'iter (Timestamp, OffsetDateTime, MetricValue)>,
+    )>,
+    current_time: Timestamp,
+    timestamp_unit: TimestampUnit,
+    aliases: &Aliases,
+) {
+    let names = tracks
+        .iter()
+        .map(|(metric_name, _)| aliases.display(metric_name))
+        .collect::<Vec<_>>();
+    let track_count = tracks.len() as f64;
+
+    Plot::new("bool_waveforms")
+        .height(TRACK_HEIGHT * tracks.len() as f32 + 24.0)
+        .link_axis(TIME_AXIS_GROUP, true, false)
+        .link_cursor(TIME_AXIS_GROUP, true, false)
+        .allow_zoom([true, false])
+        .allow_drag([true, false])
+        .allow_scroll(false)
+        .show_y(false)
+        .include_y(0.5)
+        .include_y(0.5 - track_count)
+        .set_margin_fraction(Vec2::new(0.05, 0.0))
+        .x_axis_formatter(move |grid_mark, _chars, _range| {
+            x_value_formatter(grid_mark.value, timestamp_unit)
+        })
+        .x_grid_spacer(uniform_grid_spacer(move |_| {
+            time_grid_steps(timestamp_unit)
+        }))
+        .y_grid_spacer(uniform_grid_spacer(|_| [100.0, 10.0, 1.0]))
+        .y_axis_formatter(move |grid_mark, _chars, _range| {
+            // Tracks are laid out downwards from zero
+            let track = -grid_mark.value;
+
+            if track.fract() == 0.0 && track >= 0.0 {
+                names.get(track as usize).cloned().unwrap_or_default()
+            } else {
+                String::new()
+            }
+        })
+        .show(ui, |ui| {
+            for (track, (metric_name, values)) in tracks.into_iter().enumerate() {
+                let center = -(track as f64);
+                let low_level = center - LEVEL_OFFSET;
+                let high_level = center + LEVEL_OFFSET;
+                let color = color_from_metric_name(metric_name);
+
+                let samples = values
+                    .filter_map(|(timestamp, _arrival, value)| {
+                        Some((timestamp.timestamp(), value.as_bool()?))
+                    })
+                    .collect::<Vec<_>>();
+                let Some(&(first, _)) = samples.first() else {
+                    continue;
+                };
+
+                let level = |high: bool| if high { high_level } else { low_level };
+
+                // Step between the levels at every edge, holding the last level until now
+                let mut trace = Vec::with_capacity(samples.len() * 2 + 1);
+                let mut previous = None;
+                for &(timestamp, high) in &samples {
+                    let timestamp = f64::from(timestamp);
+
+                    if let Some(previous) = previous.filter(|&previous| previous != high) {
+                        trace.push([timestamp, level(previous)]);
+                    }
+                    if previous != Some(high) {
+                        trace.push([timestamp, level(high)]);
+                    }
+
+                    previous = Some(high);
+                }
+                if let Some(previous) = previous {
+                    let end = f64::from(current_time.timestamp()).max(f64::from(first));
+
+                    trace.push([end, level(previous)]);
+                }
+
+                for (start, end) in high_intervals(samples.into_iter(), current_time.timestamp()) {
+                    let (start, end) = (f64::from(start), f64::from(end));
+
+                    ui.polygon(
+                        Polygon::new(PlotPoints::new(vec![
+                            [start, low_level],
+                            [end, low_level],
+                            [end, high_level],
+                            [start, high_level],
+                        ]))
+                        .name(aliases.display(metric_name))
+                        .fill_color(color.linear_multiply(0.3))
+                        .stroke((0.0, Color32::TRANSPARENT)),
+                    );
+                }
+
+                ui.line(
+                    Line::new(PlotPoints::new(trace))
+                        .name(aliases.display(metric_name))
+                        .color(color),
+                );
+            }
+        });
+}
@@ -0,0 +1,231 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+use eframe::{
+    egui::{RichText, Ui, Vec2},
+    epaint::Color32,
+};
+use egui_plot::{uniform_grid_spacer, Plot, PlotPoint, PlotPoints, Polygon, Text};
+use kestrel_metric::{
+    name::MetricName,
+    timestamp::{Timestamp, TimestampUnit},
+    value::MetricValue,
+};
+use time::OffsetDateTime;
+
+use crate::aliases::Aliases;
+
+use super::focused_metrics::{time_grid_steps, x_value_formatter, TIME_AXIS_GROUP};
+
+/// Height of a single lane in points
+const LANE_HEIGHT: f32 = 28.0;
+
+/// A stretch of time during which a metric kept the same value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub start: u32,
+    pub end: u32,
+    pub label: String,
+}
+
+/// The category a non-numeric value is shown as
+pub fn category_label(value: &MetricValue) -> String {
+    match value {
+        // Firmware without a dedicated string type sends text as an unknown type
+        MetricValue::Unknown(_, bytes) => String::from_utf8_lossy(bytes).into_owned(),
+        value => value.value(),
+    }
+}
+
+/// Merge a series of `(timestamp, label)` samples into segments, starting a new
+/// segment whenever the label changes
+///
+/// The last segment lasts until `end`, usually the current time.
+pub fn category_segments(samples: impl Iterator<Item = (u32, String)>, end: u32) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+
+    for (timestamp, label) in samples {
+        if let Some(last) = segments.last_mut() {
+            if last.label == label {
+                continue;
+            }
+
+            last.end = timestamp;
+        }
+
+        segments.push(Segment {
+            start: timestamp,
+            end: timestamp,
+            label,
+        });
+    }
+
+    if let Some(last) = segments.last_mut() {
+        last.end = end.max(last.start);
+    }
+
+    segments
+}
+
+fn color_from_label(label: &str) -> Color32 {
+    let mut hasher = DefaultHasher::new();
+    label.hash(&mut hasher);
+
+    let palette = colorous::TABLEAU10;
+    let color = palette[hasher.finish() as usize % palette.len()];
+
+    Color32::from_rgb(color.r, color.g, color.b)
+}
+
+/// Timeline of non-numeric metrics, one horizontal lane per metric with a
+/// colored segment per value, sharing its time axis with the focused metrics plot
+pub fn category_lanes<'ui, 'iter>(
+    ui: &'ui mut Ui,
+    lanes: Vec<(
+        &'iter MetricName,
+        impl Iterator<Item = &'iter (Timestamp, OffsetDateTime, MetricValue)>,
+    )>,
+    current_time: Timestamp,
+    timestamp_unit: TimestampUnit,
+    aliases: &Aliases,
+) {
+    let names = lanes
+        .iter()
+        .map(|(metric_name, _)| aliases.display(metric_name))
+        .collect::<Vec<_>>();
+    let lane_count = lanes.len() as f64;
+
+    Plot::new("category_lanes")
+        .height(LANE_HEIGHT * lanes.len() as f32 + 24.0)
+        .link_axis(TIME_AXIS_GROUP, true, false)
+        .link_cursor(TIME_AXIS_GROUP, true, false)
+        .allow_zoom([true, false])
+        .allow_drag([true, false])
+        .allow_scroll(false)
+        .show_y(false)
+        .include_y(0.5)
+        .include_y(0.5 - lane_count)
+        .set_margin_fraction(Vec2::new(0.05, 0.0))
+        .x_axis_formatter(move |grid_mark, _chars, _range| {
+            x_value_formatter(grid_mark.value, timestamp_unit)
+        })
+        .x_grid_spacer(uniform_grid_spacer(move |_| {
+            time_grid_steps(timestamp_unit)
+        }))
+        .y_grid_spacer(uniform_grid_spacer(|_| [100.0, 10.0, 1.0]))
+        .y_axis_formatter(move |grid_mark, _chars, _range| {
+            // Lanes are laid out downwards from zero
+            let lane = -grid_mark.value;
+
+            if lane.fract() == 0.0 && lane >= 0.0 {
+                names.get(lane as usize).cloned().unwrap_or_default()
+            } else {
+                String::new()
+            }
+        })
+        .show(ui, |ui| {
+            for (lane, (metric_name, values)) in lanes.into_iter().enumerate() {
+                let center = -(lane as f64);
+
+                let segments = category_segments(
+                    values.map(|(timestamp, _arrival, value)| {
+                        (timestamp.timestamp(), category_label(value))
+                    }),
+                    current_time.timestamp(),
+                );
+
+                for segment in segments {
+                    let start = f64::from(segment.start);
+                    let end = f64::from(segment.end);
+                    let color = color_from_label(&segment.label);
+
+                    ui.polygon(
+                        Polygon::new(PlotPoints::new(vec![
+                            [start, center - 0.4],
+                            [end, center - 0.4],
+                            [end, center + 0.4],
+                            [start, center + 0.4],
+                        ]))
+                        .name(aliases.display(metric_name))
+                        .fill_color(color.linear_multiply(0.5))
+                        .stroke((1.0, color)),
+                    );
+                    ui.text(Text::new(
+                        PlotPoint::new((start + end) / 2.0, center),
+                        RichText::new(segment.label).monospace(),
+                    ));
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::OneValue;
+
+    use super::*;
+
+    fn segment(start: u32, end: u32, label: &str) -> Segment {
+        Segment {
+            start,
+            end,
+            label: label.to_owned(),
+        }
+    }
+
+    fn samples<'a>(samples: &'a [(u32, &'a str)]) -> impl Iterator<Item = (u32, String)> + 'a {
+        samples
+            .iter()
+            .map(|&(timestamp, label)| (timestamp, label.to_owned()))
+    }
+
+    #[test]
+    fn repeated_labels_extend_the_segment() {
+        let series = [
+            (0, "idle"),
+            (10, "idle"),
+            (20, "drive"),
+            (30, "drive"),
+            (40, "idle"),
+        ];
+
+        assert_eq!(
+            category_segments(samples(&series), 50),
+            [
+                segment(0, 20, "idle"),
+                segment(20, 40, "drive"),
+                segment(40, 50, "idle"),
+            ]
+        );
+    }
+
+    #[test]
+    fn last_segment_never_ends_before_it_starts() {
+        assert_eq!(
+            category_segments(samples(&[(0, "idle"), (30, "drive")]), 10),
+            [segment(0, 30, "idle"), segment(30, 30, "drive")]
+        );
+    }
+
+    #[test]
+    fn no_samples_no_segments() {
+        assert!(category_segments(samples(&[]), 10).is_empty());
+    }
+
+    #[test]
+    fn text_sent_as_an_unknown_type_is_its_own_label() {
+        assert_eq!(
+            category_label(&MetricValue::Unknown(
+                "text".to_owned(),
+                Box::from(&b"drive"[..])
+            )),
+            "drive"
+        );
+        assert_eq!(
+            category_label(&MetricValue::One(OneValue::Bool(true))),
+            MetricValue::One(OneValue::Bool(true)).value()
+        );
+    }
+}
@@ -0,0 +1,20 @@
+use eframe::egui::Ui;
+
+/// Context menu entries copying the name, the value or the whole row of a metric
+/// table to the clipboard, the row as tab separated fields
+pub fn copy_menu(ui: &mut Ui, name: &str, value: &str, row: &[&str]) {
+    let copied = if ui.button("Copy name").clicked() {
+        Some(name.to_owned())
+    } else if ui.button("Copy value").clicked() {
+        Some(value.to_owned())
+    } else if ui.button("Copy row as TSV").clicked() {
+        Some(row.join("\t"))
+    } else {
+        None
+    };
+
+    if let Some(copied) = copied {
+        ui.ctx().copy_text(copied);
+        ui.close_menu();
+    }
+}
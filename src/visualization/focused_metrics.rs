@@ -1,25 +1,113 @@
 use std::{
-    collections::hash_map::DefaultHasher,
+    collections::{hash_map::DefaultHasher, HashMap},
     hash::{Hash, Hasher},
 };
 
-use eframe::{egui::Ui, epaint::Color32};
-use egui_plot::{uniform_grid_spacer, Corner, Legend, Line, Plot, PlotPoint, PlotPoints, Points};
-use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use eframe::{
+    egui::{Ui, Vec2},
+    epaint::Color32,
+};
+use egui_plot::{
+    uniform_grid_spacer, Corner, Legend, Line, LineStyle, Plot, PlotPoint, PlotPoints, Points,
+    Polygon, VLine,
+};
+use kestrel_metric::{
+    name::MetricName,
+    timestamp::{Timestamp, TimestampUnit},
+    value::MetricValue,
+};
+
+use time::OffsetDateTime;
+
+use crate::{runs::Runs, units::with_unit};
 
-fn label_formatter(name: &str, value: &PlotPoint) -> String {
-    format!("{name}\n{}\n@ {}", value.y, x_value_formatter(value.x))
+use super::{plot_view::PlotView, time_cursor::TimeCursor};
+
+/// Plots sharing the time axis of the focused metrics
+pub const TIME_AXIS_GROUP: &str = "focused_metrics_time";
+
+fn label_formatter(
+    name: &str,
+    value: &PlotPoint,
+    value_unit: Option<&str>,
+    unit: TimestampUnit,
+    log_scale: bool,
+) -> String {
+    let y = if log_scale {
+        from_log_scale(value.y)
+    } else {
+        value.y
+    };
+
+    format!(
+        "{name}\n{}\n@ {}",
+        with_unit(y.to_string(), value_unit),
+        x_value_formatter(value.x, unit)
+    )
+}
+
+/// Map a value onto a log10 axis, values that are not positive have no place on it
+/// and become `NaN` so they are skipped
+pub fn to_log_scale(y: f64) -> f64 {
+    if y > 0.0 {
+        y.log10()
+    } else {
+        f64::NAN
+    }
+}
+
+/// The original value of a point on a log10 axis
+pub fn from_log_scale(y: f64) -> f64 {
+    10.0_f64.powf(y)
 }
 
-fn x_value_formatter(value: f64) -> String {
+pub fn x_value_formatter(value: f64, unit: TimestampUnit) -> String {
     format!(
         "{}{}",
         if value.is_sign_negative() { "-" } else { "" },
-        Timestamp::from_millis(value.abs() as u32)
+        Timestamp::from_raw(value.abs() as u32, unit)
     )
 }
 
-fn color_from_metric_name(metric_name: &MetricName) -> Color32 {
+/// Grid spacing of the time axis in raw timestamp ticks: minutes, seconds and tenths
+pub fn time_grid_steps(unit: TimestampUnit) -> [f64; 3] {
+    let second = f64::from(unit.ticks_per_second());
+
+    [60.0 * second, second, second / 10.0]
+}
+
+/// Widen any scalar numeric or boolean metric value into an `f64`
+pub fn as_plot_value(value: &MetricValue) -> Option<f64> {
+    value
+        .as_float()
+        .or_else(|| value.as_unsigned_integer().map(|int| int as f64))
+        .or_else(|| value.as_signed_integer().map(|int| int as f64))
+        .or_else(|| value.as_bool().map(|bool| if bool { 1.0 } else { 0.0 }))
+}
+
+/// Widen the elements of any numeric or boolean array metric value into `f64`s
+pub fn as_plot_values(value: &MetricValue) -> Option<Box<dyn Iterator<Item = f64> + '_>> {
+    value
+        .as_float_iter()
+        .or_else(|| {
+            value
+                .as_unsigned_integer_iter()
+                .map(|iter| Box::new(iter.map(|int| int as f64)) as Box<dyn Iterator<Item = f64>>)
+        })
+        .or_else(|| {
+            value
+                .as_signed_integer_iter()
+                .map(|iter| Box::new(iter.map(|int| int as f64)) as Box<dyn Iterator<Item = f64>>)
+        })
+        .or_else(|| {
+            value.as_bool_iter().map(|iter| {
+                Box::new(iter.map(|bool| if bool { 1.0 } else { 0.0 }))
+                    as Box<dyn Iterator<Item = f64>>
+            })
+        })
+}
+
+pub fn color_from_metric_name(metric_name: &MetricName) -> Color32 {
     let mut hasher = DefaultHasher::new();
 
     metric_name.hash(&mut hasher);
@@ -32,60 +120,500 @@ fn color_from_metric_name(metric_name: &MetricName) -> Color32 {
     Color32::from_rgb(color.r, color.g, color.b)
 }
 
+/// Color of the series of a single element of an array metric
+pub fn color_from_array_element(metric_name: &MetricName, index: usize) -> Color32 {
+    let mut hasher = DefaultHasher::new();
+
+    (metric_name, index).hash(&mut hasher);
+
+    let index = hasher.finish();
+
+    let color = colorous::RAINBOW.eval_rational(index as usize, u64::MAX as usize);
+
+    Color32::from_rgb(color.r, color.g, color.b)
+}
+
+/// Most elements of a single array metric plotted as their own series
+pub const MAX_ELEMENT_SERIES: usize = 32;
+
+/// How focused array metrics are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArrayPlotMode {
+    /// Every element is a series over time, named `name[index]`
+    #[default]
+    PerElement,
+    /// The newest sample is drawn against the element index, below the plot
+    LatestProfile,
+}
+
+impl ArrayPlotMode {
+    pub const ALL: [Self; 2] = [Self::PerElement, Self::LatestProfile];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ArrayPlotMode::PerElement => "Series per element",
+            ArrayPlotMode::LatestProfile => "Latest over index",
+        }
+    }
+}
+
+/// Split the samples of an array metric into a series per element, for at most
+/// the first [`MAX_ELEMENT_SERIES`] elements
+///
+/// Samples that are not an array, or too short to have an element, leave a gap in its series.
+pub fn element_series<'iter>(
+    metric_values: impl Iterator<Item = &'iter (Timestamp, OffsetDateTime, MetricValue)>,
+) -> Vec<Vec<PlotPoint>> {
+    let mut series: Vec<Vec<PlotPoint>> = Vec::new();
+
+    for (timestamp, _arrival, value) in metric_values {
+        let Some(values) = as_plot_values(value) else {
+            continue;
+        };
+
+        for (index, value) in values.take(MAX_ELEMENT_SERIES).enumerate() {
+            if series.len() <= index {
+                series.resize_with(index + 1, Vec::new);
+            }
+
+            series[index].push(PlotPoint::new(timestamp.timestamp(), value));
+        }
+    }
+
+    series
+}
+
+/// How the focused metrics are drawn
+#[derive(Debug, Clone, Copy)]
+pub struct PlotStyle {
+    /// Draw lines between the points of a series
+    pub connect_the_dots: bool,
+    /// Margin above and below the data, in percent of its range
+    pub y_margin_percent: f64,
+    /// Draw bool metrics as square waveforms in their own tracks, instead of as 0 and 1
+    pub bool_waveforms: bool,
+    /// Series with more points than this are reduced to the minimum and maximum
+    /// of every pixel column, `0` to always draw every point
+    pub downsample_threshold: usize,
+    /// Overlay a simple moving average over the last `smoothing_window` samples
+    pub moving_average: bool,
+    /// Overlay an exponential moving average with the same center of mass as the moving average
+    pub exponential_average: bool,
+    pub smoothing_window: usize,
+    pub array_mode: ArrayPlotMode,
+    /// Plot the log10 of the values, skipping values that are not positive
+    pub log_scale: bool,
+}
+
+impl Default for PlotStyle {
+    fn default() -> Self {
+        Self {
+            connect_the_dots: true,
+            y_margin_percent: 5.0,
+            bool_waveforms: false,
+            downsample_threshold: 2048,
+            moving_average: false,
+            exponential_average: false,
+            smoothing_window: 10,
+            array_mode: ArrayPlotMode::default(),
+            log_scale: false,
+        }
+    }
+}
+
+/// Mean of the last `window` finite samples at every finite sample
+pub fn moving_average(points: &[PlotPoint], window: usize) -> Vec<PlotPoint> {
+    let points = points
+        .iter()
+        .filter(|point| point.y.is_finite())
+        .collect::<Vec<_>>();
+    let window = window.max(1);
+
+    let mut sum = 0.0;
+
+    points
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            sum += point.y;
+            if index >= window {
+                sum -= points[index - window].y;
+            }
+
+            PlotPoint::new(point.x, sum / (index + 1).min(window) as f64)
+        })
+        .collect()
+}
+
+/// Exponential moving average of the finite samples, weighted with
+/// `2 / (window + 1)` so it lags about as much as a moving average over `window`
+pub fn exponential_average(points: &[PlotPoint], window: usize) -> Vec<PlotPoint> {
+    let alpha = 2.0 / (window.max(1) as f64 + 1.0);
+
+    let mut average = None;
+
+    points
+        .iter()
+        .filter(|point| point.y.is_finite())
+        .map(|point| {
+            let smoothed = average.map_or(point.y, |average: f64| {
+                average + alpha * (point.y - average)
+            });
+            average = Some(smoothed);
+
+            PlotPoint::new(point.x, smoothed)
+        })
+        .collect()
+}
+
+/// Widen the `min..max` range of the data by `margin` (a fraction of the range)
+/// on both sides, so lines are not drawn against the edges of the plot
+///
+/// A constant series has no range, so it is padded relative to its magnitude instead.
+pub fn y_bounds_with_margin(min: f64, max: f64, margin: f64) -> (f64, f64) {
+    let range = max - min;
+
+    let padding = if range > 0.0 {
+        range * margin
+    } else {
+        min.abs().max(1.0) * margin
+    };
+
+    (min - padding, max + padding)
+}
+
+/// Collapse the points within `x_range` into `columns` columns, keeping only the
+/// lowest and highest point of each so spikes stay visible
+///
+/// The points are returned as they are if no more than two fit in a column anyway,
+/// so zooming in far enough shows the full resolution again. Points outside of
+/// `x_range` are dropped, `None` covers all points.
+pub fn downsample_min_max(
+    points: Vec<PlotPoint>,
+    x_range: Option<(f64, f64)>,
+    columns: usize,
+) -> Vec<PlotPoint> {
+    let (start, end) = x_range.unwrap_or_else(|| {
+        points
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), point| {
+                (min.min(point.x), max.max(point.x))
+            })
+    });
+    let visible = |point: &&PlotPoint| (start..=end).contains(&point.x);
+
+    if columns == 0 || points.iter().filter(visible).count() <= columns * 2 {
+        return points;
+    }
+
+    let column_width = (end - start) / columns as f64;
+    let column_of = |point: &PlotPoint| ((point.x - start) / column_width) as usize;
+
+    let mut downsampled = Vec::with_capacity(columns * 2);
+    let mut current: Option<(usize, PlotPoint, PlotPoint)> = None;
+
+    let mut flush = |(_, min, max): (usize, PlotPoint, PlotPoint)| {
+        // Keep the order in time, so lines between the columns stay correct
+        if min.x <= max.x {
+            downsampled.extend([min, max]);
+        } else {
+            downsampled.extend([max, min]);
+        }
+    };
+
+    // Timestamps can jump back after a reboot, so consecutive runs are grouped
+    // per column instead of assuming the points are sorted
+    for point in points.iter().filter(visible) {
+        let column = column_of(point);
+
+        match &mut current {
+            Some((current_column, min, max)) if *current_column == column => {
+                if point.y < min.y {
+                    *min = *point;
+                }
+                if point.y > max.y {
+                    *max = *point;
+                }
+            }
+            _ => {
+                if let Some(finished) = current.replace((column, *point, *point)) {
+                    flush(finished);
+                }
+            }
+        }
+    }
+    if let Some(finished) = current {
+        flush(finished);
+    }
+
+    downsampled
+}
+
 pub fn focused_metrics_plot<'ui, 'iter>(
     ui: &'ui mut Ui,
     focused_metrics: impl Iterator<
             Item = (
                 &'iter MetricName,
-                impl Iterator<Item = &'iter (Timestamp, MetricValue)>,
+                String,
+                Option<&'iter str>,
+                impl Iterator<Item = &'iter (Timestamp, OffsetDateTime, MetricValue)>,
             ),
         > + 'iter,
-    connect_the_dots: bool,
+    style: PlotStyle,
+    view: &mut PlotView,
+    time_cursor: &mut TimeCursor,
+    runs: &Runs,
+    current_time: Timestamp,
 ) {
-    Plot::new("focused_metrics")
-        .include_y(0.0)
-        .include_y(1.0)
-        .x_axis_formatter(|grid_mark, chars, _range| {
-            // FIXME: assert!(chars >= 8, "Need to implement shrinkage");
+    let timestamp_unit = current_time.unit();
+    let columns = ui.available_width() as usize;
 
-            x_value_formatter(grid_mark.value)
-        })
-        .x_grid_spacer(uniform_grid_spacer(|_| [60.0 * 1000.0, 1000.0, 100.0]))
-        .label_formatter(label_formatter)
-        .legend(Legend::default().position(Corner::LeftTop))
-        .show(ui, |ui| {
-            for (metric_name, metric_values) in focused_metrics {
+    let mut value_units = HashMap::new();
+
+    let series = focused_metrics
+        .flat_map(|(metric_name, label, value_unit, metric_values)| {
+            let metric_values = metric_values.collect::<Vec<_>>();
+
+            // Array metrics are exploded into a series per element
+            let is_array = metric_values
+                .last()
+                .is_some_and(|(_, _, value)| as_plot_values(value).is_some());
+
+            let series = if is_array {
+                element_series(metric_values.into_iter())
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, values)| {
+                        (
+                            format!("{label}[{index}]"),
+                            color_from_array_element(metric_name, index),
+                            values,
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            } else {
                 let values = metric_values
-                    .map(|(timestamp, value)| {
+                    .into_iter()
+                    .map(|(timestamp, _arrival, value)| {
                         PlotPoint::new(
                             timestamp.timestamp(),
-                            value
-                                .as_float()
-                                .or_else(|| value.as_unsigned_integer().map(|int| int as f64))
-                                .or_else(|| value.as_signed_integer().map(|int| int as f64))
-                                .or_else(|| {
-                                    value.as_bool().map(|bool| if bool { 1.0 } else { 0.0 })
-                                })
-                                .unwrap_or(f64::NAN),
+                            as_plot_value(value).unwrap_or(f64::NAN),
                         )
                     })
                     .collect::<Vec<_>>();
 
-                let color = color_from_metric_name(metric_name);
+                vec![(label, color_from_metric_name(metric_name), values)]
+            };
 
-                if connect_the_dots {
-                    ui.line(
-                        Line::new(PlotPoints::Owned(values.clone()))
-                            .name(metric_name.to_string())
-                            .color(color),
-                    );
+            if let Some(value_unit) = value_unit {
+                for (name, _, _) in &series {
+                    value_units.insert(name.clone(), value_unit.to_owned());
                 }
-                ui.points(
-                    Points::new(PlotPoints::Owned(values))
-                        .radius(2.0)
-                        .name(metric_name.to_string())
+            }
+
+            series
+        })
+        .map(|(name, color, values)| {
+            // Smooth the full series, before any samples are dropped by downsampling
+            let mut overlays = Vec::new();
+            if style.moving_average {
+                overlays.push((
+                    format!("{name} SMA({})", style.smoothing_window),
+                    LineStyle::Solid,
+                    moving_average(&values, style.smoothing_window),
+                ));
+            }
+            if style.exponential_average {
+                overlays.push((
+                    format!("{name} EMA({})", style.smoothing_window),
+                    LineStyle::dashed_loose(),
+                    exponential_average(&values, style.smoothing_window),
+                ));
+            }
+
+            let downsample = |values: Vec<PlotPoint>| {
+                if style.downsample_threshold != 0 && values.len() > style.downsample_threshold {
+                    downsample_min_max(values, view.visible_x_range(), columns)
+                } else {
+                    values
+                }
+            };
+
+            let overlays = overlays
+                .into_iter()
+                .map(|(name, line_style, values)| (name, line_style, downsample(values)))
+                .collect::<Vec<_>>();
+
+            (name, color, downsample(values), overlays)
+        })
+        .map(|(name, color, mut values, mut overlays)| {
+            if style.log_scale {
+                for point in values
+                    .iter_mut()
+                    .chain(overlays.iter_mut().flat_map(|(_, _, values)| values))
+                {
+                    point.y = to_log_scale(point.y);
+                }
+            }
+
+            (name, color, values, overlays)
+        })
+        .collect::<Vec<_>>();
+
+    let (min_y, max_y) = series
+        .iter()
+        .flat_map(|(_, _, values, _)| values.iter().map(|point| point.y))
+        .filter(|y| y.is_finite())
+        .fold((0.0, 1.0), |(min, max): (f64, f64), y| {
+            (min.min(y), max.max(y))
+        });
+    let (min_y, max_y) = y_bounds_with_margin(min_y, max_y, style.y_margin_percent / 100.0);
+
+    let plot = Plot::new("focused_metrics")
+        .include_y(min_y)
+        .include_y(max_y)
+        // The y margin is already part of the included bounds
+        .set_margin_fraction(Vec2::new(0.05, 0.0))
+        .x_axis_formatter(move |grid_mark, _chars, _range| {
+            // FIXME: assert!(chars >= 8, "Need to implement shrinkage");
+
+            x_value_formatter(grid_mark.value, timestamp_unit)
+        })
+        .x_grid_spacer(uniform_grid_spacer(move |_| {
+            time_grid_steps(timestamp_unit)
+        }))
+        .label_formatter(move |name, value| {
+            label_formatter(
+                name,
+                value,
+                value_units.get(name).map(String::as_str),
+                timestamp_unit,
+                style.log_scale,
+            )
+        })
+        .legend(Legend::default().position(Corner::LeftTop))
+        .link_axis(TIME_AXIS_GROUP, true, false)
+        .link_cursor(TIME_AXIS_GROUP, true, false);
+
+    let plot = if style.log_scale {
+        plot.y_axis_formatter(|grid_mark, _chars, _range| {
+            from_log_scale(grid_mark.value).to_string()
+        })
+    } else {
+        plot
+    };
+
+    plot.show(ui, |ui| {
+        view.update(ui);
+
+        if let Some(time) = time_cursor.time() {
+            ui.vline(VLine::new(time).color(Color32::GRAY));
+        }
+
+        if ui.response().hovered() {
+            if let Some(pointer) = ui.pointer_coordinate() {
+                time_cursor.hover(pointer.x);
+            }
+        }
+
+        // Shade runs over the range of the data, so the shading itself
+        // does not influence the automatic plot bounds
+        for run in runs.iter() {
+            let start = f64::from(run.start.timestamp());
+            let end = f64::from(run.end_or(current_time).timestamp());
+
+            ui.polygon(
+                Polygon::new(PlotPoints::new(vec![
+                    [start, min_y],
+                    [end, min_y],
+                    [end, max_y],
+                    [start, max_y],
+                ]))
+                .name(&run.name)
+                .fill_color(Color32::LIGHT_BLUE.linear_multiply(0.05)),
+            );
+        }
+
+        for (name, color, values, overlays) in series {
+            if style.connect_the_dots {
+                ui.line(
+                    Line::new(PlotPoints::Owned(values.clone()))
+                        .name(&name)
                         .color(color),
                 );
             }
-        });
+            ui.points(
+                Points::new(PlotPoints::Owned(values))
+                    .radius(2.0)
+                    .name(&name)
+                    .color(color),
+            );
+
+            // Drawn over the raw points, in a muted shade so those stay visible
+            for (name, line_style, values) in overlays {
+                ui.line(
+                    Line::new(PlotPoints::Owned(values))
+                        .name(name)
+                        .color(color.gamma_multiply(0.6))
+                        .width(2.0)
+                        .style(line_style),
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn axis_labels_in_milliseconds() {
+        let unit = TimestampUnit::Millis;
+
+        assert_eq!(x_value_formatter(61_234.0, unit), "01:01.234");
+        assert_eq!(x_value_formatter(-1_500.0, unit), "-00:01.500");
+        assert_eq!(time_grid_steps(unit), [60_000.0, 1_000.0, 100.0]);
+    }
+
+    #[test]
+    fn axis_labels_in_microseconds() {
+        let unit = TimestampUnit::Micros;
+
+        assert_eq!(x_value_formatter(61_234_567.0, unit), "01:01.234567");
+        assert_eq!(x_value_formatter(-1_500_000.0, unit), "-00:01.500000");
+        assert_eq!(
+            time_grid_steps(unit),
+            [60_000_000.0, 1_000_000.0, 100_000.0]
+        );
+    }
+
+    #[test]
+    fn hover_label_shows_the_time_in_the_unit() {
+        let point = PlotPoint::new(2_500.0, 4.0);
+
+        assert_eq!(
+            label_formatter("speed", &point, None, TimestampUnit::Millis, false),
+            "speed\n4\n@ 00:02.500"
+        );
+        assert_eq!(
+            label_formatter("speed", &point, Some("m/s"), TimestampUnit::Micros, false),
+            "speed\n4 m/s\n@ 00:00.002500"
+        );
+    }
+
+    #[test]
+    fn margin_is_a_fraction_of_the_range() {
+        assert_eq!(y_bounds_with_margin(0.0, 10.0, 0.1), (-1.0, 11.0));
+        assert_eq!(y_bounds_with_margin(-4.0, 4.0, 0.25), (-6.0, 6.0));
+        assert_eq!(y_bounds_with_margin(2.0, 3.0, 0.0), (2.0, 3.0));
+    }
+
+    #[test]
+    fn constant_series_is_padded_by_its_magnitude() {
+        assert_eq!(y_bounds_with_margin(200.0, 200.0, 0.05), (190.0, 210.0));
+        assert_eq!(y_bounds_with_margin(-200.0, -200.0, 0.05), (-210.0, -190.0));
+        // Small magnitudes still get a visible margin
+        assert_eq!(y_bounds_with_margin(0.0, 0.0, 0.5), (-0.5, 0.5));
+    }
 }
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use kestrel_metric::value::MetricValue;
+
+/// Longest value string kept for display in a table cell, the full value is
+/// still available when hovering
+const MAX_CACHED_LENGTH: usize = 256;
+
+/// Cache of formatted metric values, keyed by the sequence number of the sample
+///
+/// Formatting large array values every frame while scrolling is expensive, so
+/// each sample is only ever formatted once while it remains in the history.
+#[derive(Debug, Default)]
+pub struct FormatCache {
+    entries: HashMap<u64, String>,
+}
+
+impl FormatCache {
+    pub fn get_or_format(&mut self, sequence: u64, value: &MetricValue) -> &str {
+        self.entries.entry(sequence).or_insert_with(|| {
+            let mut formatted = value.value();
+
+            if formatted.len() > MAX_CACHED_LENGTH {
+                let mut end = MAX_CACHED_LENGTH;
+                while !formatted.is_char_boundary(end) {
+                    end -= 1;
+                }
+
+                formatted.truncate(end);
+                formatted.push('…');
+            }
+
+            formatted
+        })
+    }
+
+    /// Forget every sample older than the given sequence number
+    pub fn evict_before(&mut self, oldest: u64) {
+        self.entries.retain(|&sequence, _| sequence >= oldest);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    #[test]
+    fn scrolling_only_formats_rows_coming_into_view() {
+        const ROWS: u64 = 30;
+        const FRAMES: u64 = 100;
+        const SCROLL_PER_FRAME: u64 = 5;
+
+        let value = MetricValue::Many(ManyValues::U16((0..64).collect()));
+        let mut cache = FormatCache::default();
+        let mut lookups = 0;
+
+        for frame in 0..FRAMES {
+            let first = frame * SCROLL_PER_FRAME;
+
+            for sequence in first..first + ROWS {
+                cache.get_or_format(sequence, &value);
+                lookups += 1;
+            }
+        }
+
+        // Every lookup that missed the cache formatted and stored a value
+        let formatted = cache.entries.len() as u64;
+        assert_eq!(lookups, FRAMES * ROWS);
+        assert_eq!(formatted, ROWS + (FRAMES - 1) * SCROLL_PER_FRAME);
+    }
+
+    #[test]
+    fn cached_value_is_returned_as_is() {
+        let mut cache = FormatCache::default();
+
+        assert_eq!(
+            cache.get_or_format(1, &MetricValue::One(OneValue::U8(7))),
+            "7"
+        );
+        // The sequence number identifies the sample, so the value is not looked at again
+        assert_eq!(
+            cache.get_or_format(1, &MetricValue::One(OneValue::U8(8))),
+            "7"
+        );
+    }
+
+    #[test]
+    fn long_values_are_capped() {
+        let mut cache = FormatCache::default();
+        let value = MetricValue::Many(ManyValues::U32((0..10_000).collect()));
+
+        let formatted = cache.get_or_format(0, &value);
+
+        assert_eq!(formatted.chars().last(), Some('…'));
+        assert!(formatted.len() <= MAX_CACHED_LENGTH + '…'.len_utf8());
+        assert!(value.value().starts_with(formatted.trim_end_matches('…')));
+    }
+
+    #[test]
+    fn capping_respects_char_boundaries() {
+        let mut cache = FormatCache::default();
+        let value = MetricValue::One(OneValue::Str("é".repeat(MAX_CACHED_LENGTH).into()));
+
+        let formatted = cache.get_or_format(0, &value);
+
+        assert!(formatted.ends_with('…'));
+        assert!(formatted.len() <= MAX_CACHED_LENGTH + '…'.len_utf8());
+    }
+
+    #[test]
+    fn evicts_samples_that_left_the_history() {
+        let mut cache = FormatCache::default();
+        for sequence in 0..10 {
+            cache.get_or_format(sequence, &MetricValue::One(OneValue::Bool(true)));
+        }
+
+        cache.evict_before(7);
+
+        let mut kept = cache.entries.keys().copied().collect::<Vec<_>>();
+        kept.sort_unstable();
+        assert_eq!(kept, [7, 8, 9]);
+    }
+}
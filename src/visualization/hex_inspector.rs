@@ -0,0 +1,27 @@
+use eframe::{
+    egui::{RichText, Ui},
+    epaint::Color32,
+};
+use kestrel_metric::{hex::HexView, value::MetricValue};
+
+/// Hover contents for a metric value, with a hex view of the bytes of unknown types
+/// instead of their debug formatting, to diagnose type tags the firmware and the
+/// app disagree on
+pub fn value_hover(ui: &mut Ui, value: &MetricValue) {
+    match value.raw_bytes().filter(|_| value.is_unknown()) {
+        Some(bytes) => {
+            ui.label(
+                RichText::new(format!(
+                    "unknown type \"{}\", {} bytes",
+                    value.ty().escape_debug(),
+                    bytes.len()
+                ))
+                .color(Color32::LIGHT_YELLOW),
+            );
+            ui.monospace(HexView(bytes).to_string());
+        }
+        None => {
+            ui.monospace(value.value_pretty());
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use eframe::egui::{ComboBox, DragValue, Ui};
+use egui_plot::{Bar, BarChart, Plot};
+use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
+
+use crate::aliases::Aliases;
+
+use super::focused_metrics::{as_plot_value, color_from_metric_name};
+
+/// Bins used until the user picks another number
+pub const DEFAULT_HISTOGRAM_BINS: usize = 20;
+
+/// Equally wide bins starting at `start`, with the number of values in each
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bins {
+    pub start: f64,
+    pub width: f64,
+    pub counts: Vec<u64>,
+}
+
+/// Count the finite values into `bins` equally wide bins between their minimum and maximum
+///
+/// `None` if there are no finite values. Equal values all land in a single bin of width 1.
+pub fn bin_counts(values: impl Iterator<Item = f64>, bins: usize) -> Option<Bins> {
+    let values = values.filter(|value| value.is_finite()).collect::<Vec<_>>();
+    let bins = bins.max(1);
+
+    let (min, max) = values
+        .iter()
+        .fold(None, |range: Option<(f64, f64)>, &value| match range {
+            Some((min, max)) => Some((min.min(value), max.max(value))),
+            None => Some((value, value)),
+        })?;
+
+    let width = if max > min {
+        (max - min) / bins as f64
+    } else {
+        1.0
+    };
+
+    let mut counts = vec![0; bins];
+    for value in values {
+        // The maximum belongs to the last bin instead of one past it
+        let bin = (((value - min) / width) as usize).min(bins - 1);
+
+        counts[bin] += 1;
+    }
+
+    Some(Bins {
+        start: min,
+        width,
+        counts,
+    })
+}
+
+/// Which history the cached bins were counted from, so they are only counted
+/// again once a sample arrives or the settings change
+#[derive(Debug, Clone, PartialEq)]
+struct CacheKey {
+    metric: MetricName,
+    bins: usize,
+    samples: usize,
+    newest: Option<Timestamp>,
+}
+
+/// Distribution of the history of a single focused metric
+#[derive(Debug)]
+pub struct HistogramView {
+    pub open: bool,
+    pub metric: Option<MetricName>,
+    pub bins: usize,
+    cache: Option<(CacheKey, Option<Bins>)>,
+}
+
+impl Default for HistogramView {
+    fn default() -> Self {
+        Self {
+            open: false,
+            metric: None,
+            bins: DEFAULT_HISTOGRAM_BINS,
+            cache: None,
+        }
+    }
+}
+
+impl HistogramView {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        focused_metrics: &BTreeSet<MetricName>,
+        sorted_metrics: &BTreeMap<
+            MetricName,
+            AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>,
+        >,
+        aliases: &Aliases,
+    ) {
+        let numeric = focused_metrics
+            .iter()
+            .filter(|name| {
+                sorted_metrics
+                    .get(*name)
+                    .and_then(|history| history.back())
+                    .is_some_and(|(_, _, value)| as_plot_value(value).is_some())
+            })
+            .collect::<Vec<_>>();
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("histogram_metric")
+                .selected_text(
+                    self.metric
+                        .as_ref()
+                        .map_or("Pick a focused metric".to_owned(), |metric| {
+                            aliases.display(metric)
+                        }),
+                )
+                .show_ui(ui, |ui| {
+                    for name in &numeric {
+                        ui.selectable_value(
+                            &mut self.metric,
+                            Some((*name).clone()),
+                            aliases.display(name),
+                        );
+                    }
+                });
+
+            ui.add(
+                DragValue::new(&mut self.bins)
+                    .clamp_range(1..=500)
+                    .suffix(" bins"),
+            );
+        });
+
+        if numeric.is_empty() {
+            ui.label("Focus a numeric metric to see its distribution");
+        }
+
+        let Some((metric, history)) = self
+            .metric
+            .as_ref()
+            .and_then(|metric| Some((metric, sorted_metrics.get(metric)?)))
+        else {
+            return;
+        };
+
+        let key = CacheKey {
+            metric: metric.clone(),
+            bins: self.bins,
+            samples: history.len(),
+            newest: history.back().map(|(timestamp, _, _)| *timestamp),
+        };
+        if self.cache.as_ref().map(|(cached, _)| cached) != Some(&key) {
+            let counts = bin_counts(
+                history
+                    .iter()
+                    .filter_map(|(_, _, value)| as_plot_value(value)),
+                self.bins,
+            );
+
+            self.cache = Some((key, counts));
+        }
+
+        let Some((
+            _,
+            Some(Bins {
+                start,
+                width,
+                counts,
+            }),
+        )) = &self.cache
+        else {
+            ui.label("No finite samples yet");
+            return;
+        };
+
+        let bars = counts
+            .iter()
+            .enumerate()
+            .map(|(bin, &count)| {
+                let low = start + width * bin as f64;
+
+                Bar::new(low + width / 2.0, count as f64)
+                    .width(*width)
+                    .name(format!("{low:.3} – {:.3}", low + width))
+            })
+            .collect();
+
+        Plot::new("histogram")
+            .height(240.0)
+            .allow_drag(false)
+            .allow_scroll(false)
+            .show(ui, |ui| {
+                ui.bar_chart(
+                    BarChart::new(bars)
+                        .name(aliases.display(metric))
+                        .color(color_from_metric_name(metric)),
+                );
+            });
+    }
+}
@@ -1,36 +1,79 @@
 use std::collections::BTreeSet;
 
 use eframe::{
-    egui::{self, Layout, RichText, Ui},
+    egui::{self, Layout, RichText, ScrollArea, Ui, WidgetText},
     emath,
     epaint::Color32,
 };
-use egui_extras::{Column, TableBuilder};
-use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use egui_extras::{Column, TableBuilder, TableRow};
+use kestrel_metric::{hex::HexDump, name::MetricName, timestamp::Timestamp, value::MetricValue};
+use time::OffsetDateTime;
 
-use super::sizes::{METRIC_NAME_WIDTH, METRIC_TYPE_WIDTH, MONOSPACE_CHAR_WIDTH, TIMESTAMP_WIDTH};
+use crate::{
+    favorites::{toggle_focus, Favorites},
+    type_change::TypeChanges,
+    units::with_unit,
+};
 
-pub fn latest_metrics<'ui, 'metric>(
-    ui: &'ui mut Ui,
-    current_time: Timestamp,
-    focused_metrics: &mut BTreeSet<MetricName>,
-    hidden_metrics: &mut BTreeSet<MetricName>,
-    latest_metrics: impl Iterator<
-        Item = (
-            &'metric MetricName,
-            &'metric (Timestamp, MetricValue),
-            usize,
-        ),
-    >,
-) -> Vec<MetricName> {
-    let mut to_clear = Vec::new();
+use super::{
+    copy_menu::copy_menu,
+    focused_metrics::as_plot_values,
+    hex_inspector::value_hover,
+    metric_stats::{format_stat, MetricStats},
+    sizes::{METRIC_NAME_WIDTH, METRIC_TYPE_WIDTH, MONOSPACE_CHAR_WIDTH, TIMESTAMP_WIDTH},
+};
+
+/// Longest the table grows before it scrolls, in points
+const MAX_TABLE_HEIGHT: f32 = 800.0;
+
+/// Whether a metric matches the filter typed above the latest metrics table, ignoring case
+///
+/// Every component before the last `:` of the filter must equal the leading
+/// namespaces of the metric, and the rest must be part of the remaining name,
+/// so `motor:` matches everything in the `motor` namespace. An empty filter matches everything.
+pub fn matches_filter(metric_name: &MetricName, filter: &str) -> bool {
+    let filter = filter.trim().to_lowercase();
+    let (namespaces, needle) = filter.rsplit_once(':').unwrap_or(("", &filter));
+
+    let mut components = metric_name
+        .flatten()
+        .map(|component| component.to_lowercase());
+
+    if !namespaces.is_empty()
+        && !namespaces
+            .split(':')
+            .all(|namespace| components.next().as_deref() == Some(namespace))
+    {
+        return false;
+    }
+
+    components.collect::<Vec<_>>().join(":").contains(needle)
+}
+
+/// The group a metric is listed under, its top-level namespace or `None` for
+/// metrics without one
+pub fn namespace_group(metric_name: &MetricName) -> Option<String> {
+    match metric_name {
+        MetricName::Namespace { .. } => metric_name.flatten().next().map(|name| name.to_string()),
+        MetricName::Name(_) => None,
+    }
+}
+
+/// Name of the header of a namespace group
+pub fn group_label(group: Option<&str>) -> &str {
+    group.unwrap_or("global")
+}
 
+/// Columns shared by the header and the table of every group, so they line up
+fn table(ui: &mut Ui) -> TableBuilder<'_> {
     TableBuilder::new(ui)
-        .column(Column::exact(MONOSPACE_CHAR_WIDTH * 11.0))
+        .vscroll(false)
+        .column(Column::exact(MONOSPACE_CHAR_WIDTH * 14.0))
         .column(Column::exact(TIMESTAMP_WIDTH))
-        .column(Column::exact(MONOSPACE_CHAR_WIDTH * 5.0))
+        .column(Column::exact(MONOSPACE_CHAR_WIDTH * 7.0))
         .column(Column::exact(METRIC_NAME_WIDTH))
         .column(Column::exact(METRIC_TYPE_WIDTH))
+        .columns(Column::exact(MONOSPACE_CHAR_WIDTH * 10.0), 4)
         .column(Column::remainder())
         .striped(true)
         .cell_layout(
@@ -38,6 +81,28 @@ pub fn latest_metrics<'ui, 'metric>(
                 .with_main_wrap(false)
                 .with_cross_align(emath::Align::Center),
         )
+}
+
+/// Background of the rows of metrics whose value changed recently
+const CHANGED_ROW_COLOR: Color32 = Color32::from_rgba_premultiplied(9, 14, 9, 14);
+
+/// Add a cell to a row, with the background of a recently changed row if `changed`
+fn cell(row: &mut TableRow<'_, '_>, changed: bool, add_contents: impl FnOnce(&mut Ui)) {
+    row.col(|ui| {
+        if changed {
+            ui.painter()
+                .rect_filled(ui.max_rect(), 0.0, CHANGED_ROW_COLOR);
+        }
+
+        add_contents(ui);
+    });
+}
+
+/// Header of the latest metrics table, shown once above the groups
+///
+/// The name column names the `port` the metrics came from while several ports are open.
+pub fn latest_metrics_header(ui: &mut Ui, port: Option<&str>) {
+    table(ui)
         .header(20.0, |mut header| {
             header.col(|_ui| {});
             header.col(|ui| {
@@ -48,96 +113,211 @@ pub fn latest_metrics<'ui, 'metric>(
                 ui.heading("Cnt").on_hover_text_at_pointer("Metric Count");
             });
             header.col(|ui| {
-                ui.heading("Name");
+                ui.heading(name_heading(port));
             });
             header.col(|ui| {
                 ui.heading("Type");
             });
+            header.col(|ui| {
+                ui.heading("Min");
+            });
+            header.col(|ui| {
+                ui.heading("Max");
+            });
+            header.col(|ui| {
+                ui.heading("Mean");
+            });
+            header.col(|ui| {
+                ui.heading("σ")
+                    .on_hover_text_at_pointer("Standard deviation over the stored history");
+            });
             header.col(|ui| {
                 ui.heading("Value");
             });
         })
-        .body(|mut body| {
-            for (metric_name, (timestamp, metric_value), count) in latest_metrics {
-                if hidden_metrics.contains(metric_name) {
-                    continue;
-                }
+        .body(|_body| {});
+}
 
-                let is_focusable = metric_value.is_float()
-                    || metric_value.is_signed_integer()
-                    || metric_value.is_unsigned_integer()
-                    || metric_value.is_bool();
-
-                body.row(20.0, |mut row| {
-                    row.col(|ui| {
-                        ui.horizontal_centered(|ui| {
-                            if ui
-                                .button(RichText::new("🗙").monospace().color(Color32::DARK_RED))
-                                .on_hover_text_at_pointer("Hide this metric")
-                                .clicked()
-                            {
-                                hidden_metrics.insert(metric_name.clone());
-                            };
-
-                            if ui
-                                .button(RichText::new("↩").monospace())
-                                .on_hover_text_at_pointer("Reset this metric")
-                                .clicked()
-                            {
-                                to_clear.push(metric_name.clone());
-                            };
-
-                            if is_focusable {
-                                let is_focused = focused_metrics.contains(metric_name);
-
-                                if ui
-                                    .selectable_label(is_focused, RichText::new("🗠").monospace())
-                                    .on_hover_text_at_pointer("Focus this metric")
-                                    .clicked()
-                                {
-                                    if is_focused {
-                                        focused_metrics.remove(metric_name);
-                                    } else {
-                                        focused_metrics.insert(metric_name.clone());
-                                    }
-                                }
-                            }
-                        });
+/// Scroll area around the header and all groups of the latest metrics table
+pub fn latest_metrics_scroll_area() -> ScrollArea {
+    ScrollArea::vertical()
+        .max_height(MAX_TABLE_HEIGHT)
+        .auto_shrink([false, true])
+}
+
+pub fn latest_metrics<'ui, 'metric>(
+    ui: &'ui mut Ui,
+    current_time: Timestamp,
+    focused_metrics: &mut BTreeSet<MetricName>,
+    hidden_metrics: &mut BTreeSet<MetricName>,
+    type_changes: &TypeChanges,
+    favorites: &mut Favorites,
+    latest_metrics: impl Iterator<
+        Item = (
+            &'metric MetricName,
+            WidgetText,
+            &'metric (Timestamp, OffsetDateTime, MetricValue),
+            usize,
+            bool,
+            bool,
+            Option<&'metric [u8]>,
+            Option<MetricStats>,
+            Option<&'metric str>,
+        ),
+    >,
+) -> Vec<MetricName> {
+    let mut to_clear = Vec::new();
+
+    table(ui).body(|mut body| {
+        for (metric_name, label, (timestamp, _arrival, metric_value), count, truncated, changed, raw, stats, unit) in
+            latest_metrics
+        {
+            if hidden_metrics.contains(metric_name) {
+                continue;
+            }
+
+            let is_focusable = metric_value.is_float()
+                || metric_value.is_signed_integer()
+                || metric_value.is_unsigned_integer()
+                || metric_value.is_bool()
+                || as_plot_values(metric_value).is_some();
+
+            body.row(20.0, |mut row| {
+                cell(&mut row, changed, |ui| {
+                    ui.horizontal_centered(|ui| {
+                        if ui
+                            .button(RichText::new("🗙").monospace().color(Color32::DARK_RED))
+                            .on_hover_text_at_pointer("Hide this metric")
+                            .clicked()
+                        {
+                            hidden_metrics.insert(metric_name.clone());
+                        };
+
+                        if ui
+                            .button(RichText::new("↩").monospace())
+                            .on_hover_text_at_pointer("Reset this metric")
+                            .clicked()
+                        {
+                            to_clear.push(metric_name.clone());
+                        };
+
+                        let is_focused = focused_metrics.contains(metric_name);
+
+                        if ui
+                            .selectable_label(is_focused, RichText::new("🗠").monospace())
+                            .on_hover_text_at_pointer(if is_focusable {
+                                "Focus this metric"
+                            } else {
+                                "Show this metric as a category lane"
+                            })
+                            .clicked()
+                        {
+                            toggle_focus(focused_metrics, metric_name);
+                        }
+
+                        if ui
+                            .selectable_label(
+                                favorites.contains(metric_name),
+                                RichText::new("★").monospace(),
+                            )
+                            .on_hover_text_at_pointer("Add this metric to the favorites bar")
+                            .clicked()
+                        {
+                            favorites.toggle(metric_name);
+                        }
                     });
-                    row.col(|ui| {
-                        ui.monospace((current_time - *timestamp).to_string());
+                });
+                cell(&mut row, changed, |ui| {
+                    ui.monospace((current_time - *timestamp).to_string());
+                });
+                cell(&mut row, changed, |ui| {
+                    ui.monospace(count.to_string());
+
+                    if truncated {
+                        ui.label(RichText::new("⟳").color(Color32::LIGHT_YELLOW))
+                            .on_hover_text_at_pointer(
+                                "History is full, every new sample drops the oldest one. Raise --history to keep more",
+                            );
+                    }
+                });
+                cell(&mut row, changed, |ui| {
+                    ui.label(label).on_hover_ui_at_pointer(|ui| {
+                        ui.label(metric_name);
                     });
-                    row.col(|ui| {
-                        ui.monospace(count.to_string());
+                });
+                cell(&mut row, changed, |ui| {
+                    let text =
+                        RichText::new(metric_value.ty())
+                            .monospace()
+                            .color(if is_focusable {
+                                Color32::LIGHT_GREEN
+                            } else {
+                                Color32::LIGHT_YELLOW
+                            });
+
+                    ui.label(text).on_hover_text_at_pointer(if is_focusable {
+                        RichText::new("type can be focused").color(Color32::LIGHT_GREEN)
+                    } else {
+                        RichText::new("type is shown as a category lane")
+                            .color(Color32::LIGHT_YELLOW)
                     });
-                    row.col(|ui| {
-                        ui.label(metric_name).on_hover_ui_at_pointer(|ui| {
-                            ui.label(metric_name);
-                        });
+
+                    if let Some(types) = type_changes.changed_types(metric_name) {
+                        ui.label(RichText::new("⚠").color(Color32::YELLOW))
+                            .on_hover_text_at_pointer(format!(
+                                "metric changed type mid-session: {}",
+                                types.join(" → ")
+                            ));
+                    }
+                });
+                for stat in [
+                    stats.map(|stats| stats.min),
+                    stats.map(|stats| stats.max),
+                    stats.map(|stats| stats.mean),
+                    stats.map(|stats| stats.std_dev),
+                ] {
+                    cell(&mut row, changed, |ui| {
+                        if let Some(stat) = stat {
+                            ui.monospace(format_stat(stat));
+                        }
                     });
-                    row.col(|ui| {
-                        let text =
-                            RichText::new(metric_value.ty())
-                                .monospace()
-                                .color(if is_focusable {
-                                    Color32::LIGHT_GREEN
-                                } else {
-                                    Color32::LIGHT_RED
-                                });
-
-                        ui.label(text).on_hover_text_at_pointer(if is_focusable {
-                            RichText::new("type can be focused").color(Color32::LIGHT_GREEN)
-                        } else {
-                            RichText::new("type can not be focused").color(Color32::LIGHT_RED)
+                }
+                cell(&mut row, changed, |ui| {
+                    ui.monospace(with_unit(metric_value.value(), unit))
+                        .on_hover_ui_at_pointer(|ui| {
+                            value_hover(ui, metric_value);
+
+                            if let Some(raw) = raw {
+                                ui.separator();
+                                ui.monospace(format!("raw: {}", HexDump(raw)));
+                            }
                         });
-                    });
-                    row.col(|ui| {
-                        ui.monospace(metric_value.value())
-                            .on_hover_text_at_pointer(metric_value.value_pretty());
-                    });
                 });
-            }
-        });
+
+                row.response().context_menu(|ui| {
+                    let name = metric_name.to_string();
+                    let value = metric_value.value();
+
+                    copy_menu(
+                        ui,
+                        &name,
+                        &value,
+                        &[
+                            &timestamp.timestamp().to_string(),
+                            &name,
+                            metric_value.ty(),
+                            &value,
+                        ],
+                    );
+                });
+            });
+        }
+    });
 
     to_clear
 }
+
+/// Heading of a metric name column, naming the port while several ports are open
+pub fn name_heading(port: Option<&str>) -> String {
+    port.map_or_else(|| "Name".to_owned(), |port| format!("Name on {port}"))
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use eframe::{
+    egui::{CollapsingHeader, RichText, Ui},
+    epaint::Color32,
+};
+use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
+
+use crate::{
+    aliases::Aliases,
+    favorites::toggle_focus,
+    units::{with_unit, Units},
+};
+
+/// Metric names grouped by namespace, for navigating large hierarchical metric sets
+#[derive(Debug, Default)]
+pub struct MetricTree {
+    /// The metric ending at this node, if any
+    ///
+    /// A node can be both a metric and a namespace, such as `motor` next to `motor:speed`.
+    pub metric: Option<MetricName>,
+    pub children: BTreeMap<String, MetricTree>,
+}
+
+impl MetricTree {
+    pub fn build<'n>(names: impl Iterator<Item = &'n MetricName>) -> Self {
+        let mut root = MetricTree::default();
+
+        for name in names {
+            let node = name.flatten().fold(&mut root, |node, component| {
+                node.children.entry(component.to_string()).or_default()
+            });
+
+            node.metric = Some(name.clone());
+        }
+
+        root
+    }
+}
+
+/// Collapsible tree of all metrics, with their latest value and focus and hide toggles
+pub fn metric_explorer(
+    ui: &mut Ui,
+    tree: &MetricTree,
+    sorted_metrics: &BTreeMap<
+        MetricName,
+        AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>,
+    >,
+    focused_metrics: &mut BTreeSet<MetricName>,
+    hidden_metrics: &mut BTreeSet<MetricName>,
+    units: &mut Units,
+    aliases: &mut Aliases,
+) {
+    for (component, node) in &tree.children {
+        if node.children.is_empty() {
+            if let Some(metric_name) = &node.metric {
+                metric_row(
+                    ui,
+                    metric_name,
+                    sorted_metrics,
+                    focused_metrics,
+                    hidden_metrics,
+                    units,
+                    aliases,
+                );
+            }
+
+            continue;
+        }
+
+        CollapsingHeader::new(RichText::new(component).color(Color32::KHAKI))
+            .id_source((ui.id(), component))
+            .show(ui, |ui| {
+                if let Some(metric_name) = &node.metric {
+                    metric_row(
+                        ui,
+                        metric_name,
+                        sorted_metrics,
+                        focused_metrics,
+                        hidden_metrics,
+                        units,
+                        aliases,
+                    );
+                }
+
+                metric_explorer(
+                    ui,
+                    node,
+                    sorted_metrics,
+                    focused_metrics,
+                    hidden_metrics,
+                    units,
+                    aliases,
+                );
+            });
+    }
+}
+
+fn metric_row(
+    ui: &mut Ui,
+    metric_name: &MetricName,
+    sorted_metrics: &BTreeMap<
+        MetricName,
+        AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>,
+    >,
+    focused_metrics: &mut BTreeSet<MetricName>,
+    hidden_metrics: &mut BTreeSet<MetricName>,
+    units: &mut Units,
+    aliases: &mut Aliases,
+) {
+    let component = metric_name.flatten().last().unwrap_or_default();
+
+    ui.horizontal(|ui| {
+        if ui
+            .selectable_label(
+                focused_metrics.contains(metric_name),
+                RichText::new("🗠").monospace(),
+            )
+            .on_hover_text_at_pointer("Focus this metric")
+            .clicked()
+        {
+            toggle_focus(focused_metrics, metric_name);
+        }
+
+        let is_hidden = hidden_metrics.contains(metric_name);
+        if ui
+            .selectable_label(is_hidden, RichText::new("🗙").monospace())
+            .on_hover_text_at_pointer("Hide this metric from the latest metrics table")
+            .clicked()
+        {
+            if is_hidden {
+                hidden_metrics.remove(metric_name);
+            } else {
+                hidden_metrics.insert(metric_name.clone());
+            }
+        }
+
+        ui.label(RichText::new(aliases.get(metric_name).unwrap_or(component)).color(Color32::GOLD))
+            .on_hover_text_at_pointer("Right click to set the unit and display name")
+            .context_menu(|ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Unit");
+                    ui.text_edit_singleline(units.edit(metric_name));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Alias").on_hover_text_at_pointer(
+                        "Shown instead of the metric name, leave empty to show the name",
+                    );
+                    ui.text_edit_singleline(aliases.edit(metric_name));
+                });
+            });
+
+        if let Some((_, _, value)) = sorted_metrics
+            .get(metric_name)
+            .and_then(|history| history.back())
+        {
+            let unit = units.get(metric_name);
+
+            ui.monospace(with_unit(value.value(), unit))
+                .on_hover_ui_at_pointer(|ui| {
+                    ui.monospace(value.value_pretty());
+                });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(names: &[&str]) -> MetricTree {
+        let names = names
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect::<Vec<MetricName>>();
+
+        MetricTree::build(names.iter())
+    }
+
+    fn children(node: &MetricTree) -> Vec<&str> {
+        node.children.keys().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn nested_names_share_their_namespaces() {
+        let root = tree(&[
+            "motor:left:speed",
+            "motor:right:speed",
+            "motor:left:current",
+            "state",
+        ]);
+
+        assert_eq!(root.metric, None);
+        assert_eq!(children(&root), ["motor", "state"]);
+
+        let motor = &root.children["motor"];
+        assert_eq!(motor.metric, None);
+        assert_eq!(children(motor), ["left", "right"]);
+        assert_eq!(children(&motor.children["left"]), ["current", "speed"]);
+
+        let speed = &motor.children["left"].children["speed"];
+        assert_eq!(speed.metric, Some("motor:left:speed".parse().unwrap()));
+        assert!(speed.children.is_empty());
+
+        assert_eq!(
+            root.children["state"].metric,
+            Some("state".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn a_node_can_be_both_a_metric_and_a_namespace() {
+        let root = tree(&["motor:speed", "motor"]);
+
+        let motor = &root.children["motor"];
+        assert_eq!(motor.metric, Some("motor".parse().unwrap()));
+        assert_eq!(children(motor), ["speed"]);
+    }
+
+    #[test]
+    fn escaped_separators_stay_in_one_node() {
+        let root = tree(&[r"gps:time 12\:30"]);
+
+        assert_eq!(children(&root.children["gps"]), ["time 12:30"]);
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use kestrel_metric::name::MetricName;
+use time::OffsetDateTime;
+
+/// Summary statistics over the stored history of a numeric metric
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MetricStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+}
+
+impl MetricStats {
+    /// Statistics over every finite value, `None` if there are none
+    pub fn compute(values: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut count = 0.0;
+        let mut min = f64::INFINITY;
+        let mut max = f64::NEG_INFINITY;
+        let mut mean = 0.0;
+        // Sum of squared differences from the mean, updated as in Welford's algorithm
+        let mut m2 = 0.0;
+
+        for value in values.filter(|value| value.is_finite()) {
+            count += 1.0;
+            min = min.min(value);
+            max = max.max(value);
+
+            let delta = value - mean;
+            mean += delta / count;
+            m2 += delta * (value - mean);
+        }
+
+        (count > 0.0).then(|| Self {
+            min,
+            max,
+            mean,
+            std_dev: (m2 / count).sqrt(),
+        })
+    }
+}
+
+/// Format a statistic compactly, switching to scientific notation for very large or small values
+pub fn format_stat(value: f64) -> String {
+    let magnitude = value.abs();
+
+    if magnitude >= 1e6 || (magnitude < 1e-3 && magnitude != 0.0) {
+        format!("{value:.3e}")
+    } else {
+        format!("{value:.3}")
+    }
+}
+
+/// Cache of statistics per metric, only recomputed once the history of the metric changed
+///
+/// The history only ever changes by a new sample being pushed, so the arrival
+/// of the newest sample together with the length identifies its contents.
+#[derive(Debug, Default)]
+pub struct StatsCache {
+    entries: HashMap<MetricName, ((usize, OffsetDateTime), Option<MetricStats>)>,
+}
+
+impl StatsCache {
+    pub fn get_or_compute<I: Iterator<Item = f64>>(
+        &mut self,
+        name: &MetricName,
+        len: usize,
+        newest_arrival: OffsetDateTime,
+        values: impl FnOnce() -> I,
+    ) -> Option<MetricStats> {
+        let key = (len, newest_arrival);
+
+        match self.entries.get(name) {
+            Some((cached_key, stats)) if *cached_key == key => *stats,
+            _ => {
+                let stats = MetricStats::compute(values());
+                self.entries.insert(name.clone(), (key, stats));
+
+                stats
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
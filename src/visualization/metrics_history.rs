@@ -1,14 +1,61 @@
 use eframe::{
-    egui::{Align, Layout, RichText, Ui},
+    egui::{Align, Layout, RichText, Sense, Ui},
     epaint::Color32,
 };
 use egui_extras::{Column, TableBuilder};
-use kestrel_metric::Metric;
+use kestrel_metric::{hex::HexDump, timestamp::Timestamp, Metric};
 use ringbuffer::{AllocRingBuffer, RingBuffer};
+use time::OffsetDateTime;
 
+use crate::{
+    aliases::Aliases,
+    firmware_log::{level_color, log_level},
+};
+
+use super::copy_menu::copy_menu;
+use super::format_cache::FormatCache;
+use super::hex_inspector::value_hover;
+use super::latest_metrics::name_heading;
+use super::packet_inspector::PacketInspection;
 use super::sizes::{METRIC_NAME_WIDTH, METRIC_TYPE_WIDTH, TIMESTAMP_WIDTH};
 
-pub fn metrics_history(ui: &mut Ui, metrics: &AllocRingBuffer<Metric>) {
+/// The metric shown in a row of the history, newest first, with its sequence number
+fn history_row(metrics: &AllocRingBuffer<Metric>, received: u64, row: usize) -> (u64, &Metric) {
+    let metric = metrics
+        .get_signed(-(row as isize + 1))
+        .expect("rows never exceed the history");
+
+    (received - 1 - row as u64, metric)
+}
+
+/// Table of the raw metric history, newest first
+///
+/// `received` is the total number of metrics pushed into `metrics`, used to
+/// identify each sample in the format cache and the inspector.
+///
+/// `wall_clock_anchor` pairs a robot timestamp with its arrival time, to show
+/// the wall-clock time of each row when hovering its timestamp.
+///
+/// Firmware log lines are colored by their level.
+///
+/// The name column names the `port` the metrics came from while several ports are open.
+///
+/// Returns the packet whose row was clicked, if any.
+#[allow(clippy::too_many_arguments)]
+pub fn metrics_history(
+    ui: &mut Ui,
+    metrics: &AllocRingBuffer<Metric>,
+    received: u64,
+    format_cache: &mut FormatCache,
+    inspected: Option<u64>,
+    wall_clock_anchor: Option<(Timestamp, OffsetDateTime)>,
+    aliases: &Aliases,
+    port: Option<&str>,
+) -> Option<PacketInspection> {
+    let mut clicked = None;
+
+    format_cache.evict_before(received.saturating_sub(metrics.len() as u64));
+
     ui.push_id("metrics_history", |ui| {
         TableBuilder::new(ui)
             .column(Column::exact(TIMESTAMP_WIDTH))
@@ -16,6 +63,7 @@ pub fn metrics_history(ui: &mut Ui, metrics: &AllocRingBuffer<Metric>) {
             .column(Column::exact(METRIC_TYPE_WIDTH))
             .column(Column::remainder())
             .striped(true)
+            .sense(Sense::click())
             .cell_layout(Layout::left_to_right(Align::Center).with_main_wrap(false))
             .header(20.0, |mut header| {
                 header.col(|ui| {
@@ -23,7 +71,7 @@ pub fn metrics_history(ui: &mut Ui, metrics: &AllocRingBuffer<Metric>) {
                         .on_hover_text_at_pointer("Time since the robot has been powered up");
                 });
                 header.col(|ui| {
-                    ui.heading("Name");
+                    ui.heading(name_heading(port));
                 });
                 header.col(|ui| {
                     ui.heading("Type");
@@ -34,15 +82,28 @@ pub fn metrics_history(ui: &mut Ui, metrics: &AllocRingBuffer<Metric>) {
             })
             .body(|body| {
                 body.rows(15.0, metrics.len(), |mut row| {
-                    let metric = &metrics.get_signed(-(row.index() as isize + 1)).unwrap();
+                    let (sequence, metric) = history_row(metrics, received, row.index());
+
+                    row.set_selected(inspected == Some(sequence));
 
                     row.col(|ui| {
-                        ui.monospace(metric.timestamp.to_string());
+                        let response = ui.monospace(metric.timestamp.to_string());
+                        if let Some((anchor, anchor_time)) = wall_clock_anchor {
+                            let wall_clock = metric.timestamp.to_wall_clock(anchor, anchor_time);
+                            response.on_hover_text_at_pointer(format!(
+                                "{:02}:{:02}:{:02}.{:03} UTC",
+                                wall_clock.hour(),
+                                wall_clock.minute(),
+                                wall_clock.second(),
+                                wall_clock.millisecond()
+                            ));
+                        }
                     });
                     row.col(|ui| {
-                        ui.label(&metric.name).on_hover_ui_at_pointer(|ui| {
-                            ui.label(&metric.name);
-                        });
+                        ui.label(aliases.label(&metric.name))
+                            .on_hover_ui_at_pointer(|ui| {
+                                ui.label(&metric.name);
+                            });
                     });
                     row.col(|ui| {
                         let ty = RichText::new(metric.value.ty())
@@ -52,12 +113,77 @@ pub fn metrics_history(ui: &mut Ui, metrics: &AllocRingBuffer<Metric>) {
                         ui.label(ty.clone()).on_hover_text_at_pointer(ty);
                     });
                     row.col(|ui| {
-                        ui.monospace(RichText::new(metric.value.value()).monospace())
-                            .on_hover_text_at_pointer(
-                                RichText::new(metric.value.value_pretty()).monospace(),
-                            );
+                        let mut value =
+                            RichText::new(format_cache.get_or_format(sequence, &metric.value))
+                                .monospace();
+                        if let Some(level) = log_level(&metric.name) {
+                            value = value.color(level_color(level));
+                        }
+
+                        ui.label(value).on_hover_ui_at_pointer(|ui| {
+                            value_hover(ui, &metric.value);
+
+                            if let Some(raw) = &metric.raw {
+                                ui.separator();
+                                ui.monospace(format!("raw: {}", HexDump(raw)));
+                            }
+                        });
+                    });
+
+                    let response = row.response();
+                    if response.clicked() {
+                        clicked = Some(PacketInspection::new(sequence, metric));
+                    }
+                    response.context_menu(|ui| {
+                        let name = metric.name.to_string();
+                        let value = metric.value.value();
+
+                        copy_menu(
+                            ui,
+                            &name,
+                            &value,
+                            &[
+                                &metric.timestamp.timestamp().to_string(),
+                                &name,
+                                metric.value.ty(),
+                                &value,
+                            ],
+                        );
                     });
                 })
             });
     });
+
+    clicked
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{MetricValue, OneValue};
+
+    use super::*;
+
+    #[test]
+    fn rows_are_newest_first_with_their_sequence() {
+        let mut metrics = AllocRingBuffer::new(4);
+        for millis in 0..6 {
+            metrics.push(Metric {
+                timestamp: Timestamp::from_millis(millis),
+                arrival: OffsetDateTime::UNIX_EPOCH,
+                name: "speed".parse().unwrap(),
+                value: MetricValue::One(OneValue::U32(millis)),
+                raw: None,
+            });
+        }
+
+        // Six received, the oldest two already pushed out
+        let rows = (0..metrics.len())
+            .map(|row| {
+                let (sequence, metric) = history_row(&metrics, 6, row);
+                (sequence, metric.timestamp.timestamp())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(rows, [(5, 5), (4, 4), (3, 3), (2, 2)]);
+    }
 }
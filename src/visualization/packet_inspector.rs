@@ -0,0 +1,122 @@
+use eframe::egui::{Grid, RichText, Ui};
+use kestrel_metric::{hex::HexDump, name::MetricName, timestamp::Timestamp, Metric};
+use time::OffsetDateTime;
+
+use crate::export::format_arrival;
+
+/// Snapshot of a single received packet, kept around for inspection after it
+/// has scrolled out of the history
+#[derive(Debug, Clone)]
+pub struct PacketInspection {
+    /// Position of the packet in the stream of all received metrics
+    pub sequence: u64,
+    pub timestamp: Timestamp,
+    pub arrival: OffsetDateTime,
+    pub name: MetricName,
+    pub ty: String,
+    pub value: String,
+    pub raw: Option<Box<[u8]>>,
+}
+
+impl PacketInspection {
+    pub fn new(sequence: u64, metric: &Metric) -> Self {
+        Self {
+            sequence,
+            timestamp: metric.timestamp,
+            arrival: metric.arrival,
+            name: metric.name.clone(),
+            ty: metric.value.ty().to_owned(),
+            value: metric.value.value_pretty(),
+            raw: metric.raw.clone(),
+        }
+    }
+
+    pub fn ui(&self, ui: &mut Ui) {
+        Grid::new("packet_inspector")
+            .num_columns(2)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label("Robot time");
+                ui.monospace(self.timestamp.to_string());
+                ui.end_row();
+
+                ui.label("Arrival time");
+                ui.monospace(format_arrival(self.arrival));
+                ui.end_row();
+
+                ui.label("Name");
+                ui.label(&self.name);
+                ui.end_row();
+
+                ui.label("Type");
+                ui.monospace(&self.ty);
+                ui.end_row();
+
+                ui.label("Value");
+                ui.monospace(&self.value);
+                ui.end_row();
+
+                ui.label("Raw bytes");
+                match &self.raw {
+                    Some(raw) => ui.monospace(HexDump(raw).to_string()),
+                    None => ui
+                        .label(RichText::new("not retained").italics())
+                        .on_hover_text_at_pointer(
+                        "Enable \"Show raw value bytes\" in the information window to retain them",
+                    ),
+                };
+                ui.end_row();
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kestrel_metric::value::{ManyValues, MetricValue};
+
+    use super::*;
+
+    #[test]
+    fn inspection_holds_the_whole_packet() {
+        let arrival = OffsetDateTime::UNIX_EPOCH + time::Duration::seconds(5);
+        let metric = Metric {
+            timestamp: Timestamp::from_millis(1_234),
+            arrival,
+            name: "motor:currents".parse().unwrap(),
+            value: MetricValue::Many(ManyValues::I16([-1, 2].into())),
+            raw: Some([0xff, 0xff, 0x02, 0x00].into()),
+        };
+
+        let inspection = PacketInspection::new(42, &metric);
+
+        assert_eq!(inspection.sequence, 42);
+        assert_eq!(inspection.timestamp, Timestamp::from_millis(1_234));
+        assert_eq!(inspection.arrival, arrival);
+        assert_eq!(inspection.name.to_string(), "motor:currents");
+        assert_eq!(inspection.ty, "[i16]");
+        assert_eq!(inspection.value, metric.value.value_pretty());
+        assert_eq!(
+            inspection
+                .raw
+                .as_deref()
+                .map(|raw| HexDump(raw).to_string()),
+            Some("ff ff 02 00".to_owned())
+        );
+    }
+
+    #[test]
+    fn inspection_without_raw_bytes() {
+        let metric = Metric {
+            timestamp: Timestamp::from_micros(7),
+            arrival: OffsetDateTime::UNIX_EPOCH,
+            name: "state".parse().unwrap(),
+            value: MetricValue::One(kestrel_metric::value::OneValue::Bool(true)),
+            raw: None,
+        };
+
+        let inspection = PacketInspection::new(0, &metric);
+
+        assert_eq!(inspection.ty, "bool");
+        assert_eq!(inspection.raw, None);
+    }
+}
@@ -0,0 +1,176 @@
+use egui_plot::{PlotBounds, PlotUi};
+use kestrel_serial::SerialWorkerState;
+
+/// What to do with the plot bounds on the frame after a reconnect
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReconnectView {
+    /// Put back the bounds the user panned or zoomed to
+    Restore(PlotBounds),
+    /// Fit the plot to the new data again
+    Follow,
+}
+
+/// Keeps the pan and zoom of the focused metrics plot across a reconnect,
+/// during which both the data and the current time jump
+#[derive(Debug, Default)]
+pub struct PlotView {
+    /// Always fit the plot to the data after a reconnect, even if the user moved the view
+    pub follow: bool,
+
+    user_adjusted: Option<PlotBounds>,
+    last_state: Option<SerialWorkerState>,
+    pending: Option<ReconnectView>,
+}
+
+impl PlotView {
+    /// Fit the plot to the data again on the next frame
+    pub fn fit_to_data(&mut self) {
+        self.pending = Some(ReconnectView::Follow);
+    }
+
+    /// Follow the state of the serial worker, deciding what to do with the view
+    /// once it connects again after having been connected before
+    pub fn observe_state(&mut self, state: SerialWorkerState) {
+        let last_state = self.last_state.replace(state);
+
+        let reconnected =
+            state.is_connected() && last_state.is_some_and(|last| !last.is_connected());
+
+        if reconnected {
+            self.pending = self.reconnect_view();
+        }
+    }
+
+    /// The view to apply after a reconnect, `None` to leave the automatic bounds alone
+    pub fn reconnect_view(&self) -> Option<ReconnectView> {
+        if self.follow {
+            Some(ReconnectView::Follow)
+        } else {
+            self.user_adjusted.map(ReconnectView::Restore)
+        }
+    }
+
+    /// The time range the user panned or zoomed to, `None` while the plot fits itself to the data
+    pub fn visible_x_range(&self) -> Option<(f64, f64)> {
+        self.user_adjusted
+            .map(|bounds| (bounds.min()[0], bounds.max()[0]))
+    }
+
+    /// Remember the current view of the plot, applying any pending reconnect decision first
+    pub fn update(&mut self, plot_ui: &mut PlotUi) {
+        match self.pending.take() {
+            Some(ReconnectView::Restore(bounds)) => plot_ui.set_plot_bounds(bounds),
+            Some(ReconnectView::Follow) => plot_ui.set_auto_bounds(true.into()),
+            None => {}
+        }
+
+        // The plot turns off its automatic bounds once the user pans or zooms,
+        // and turns them back on after a double click
+        self.user_adjusted = (!plot_ui.auto_bounds().any()).then(|| plot_ui.plot_bounds());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds() -> PlotBounds {
+        PlotBounds::from_min_max([10.0, -1.0], [20.0, 1.0])
+    }
+
+    /// Drive the view through a sequence of worker states, returning the decision left pending
+    fn observe(view: &mut PlotView, states: &[SerialWorkerState]) -> Option<ReconnectView> {
+        for &state in states {
+            view.observe_state(state);
+        }
+
+        view.pending
+    }
+
+    const RECONNECT: [SerialWorkerState; 3] = [
+        SerialWorkerState::Connected,
+        SerialWorkerState::Disconnected,
+        SerialWorkerState::Connected,
+    ];
+
+    #[test]
+    fn first_connection_leaves_the_view_alone() {
+        let mut view = PlotView {
+            user_adjusted: Some(bounds()),
+            ..Default::default()
+        };
+
+        assert_eq!(observe(&mut view, &[SerialWorkerState::Connected]), None);
+    }
+
+    #[test]
+    fn reconnect_restores_the_adjusted_view() {
+        let mut view = PlotView {
+            user_adjusted: Some(bounds()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            observe(&mut view, &RECONNECT),
+            Some(ReconnectView::Restore(bounds()))
+        );
+        assert_eq!(view.visible_x_range(), Some((10.0, 20.0)));
+    }
+
+    #[test]
+    fn reconnect_without_adjusting_keeps_the_automatic_bounds() {
+        let mut view = PlotView::default();
+
+        assert_eq!(observe(&mut view, &RECONNECT), None);
+        assert_eq!(view.visible_x_range(), None);
+    }
+
+    #[test]
+    fn follow_fits_to_the_data_after_a_reconnect() {
+        let mut view = PlotView {
+            follow: true,
+            user_adjusted: Some(bounds()),
+            ..Default::default()
+        };
+
+        assert_eq!(observe(&mut view, &RECONNECT), Some(ReconnectView::Follow));
+    }
+
+    #[test]
+    fn stalling_is_not_a_reconnect_but_a_reset_is() {
+        let mut view = PlotView {
+            user_adjusted: Some(bounds()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            observe(
+                &mut view,
+                &[
+                    SerialWorkerState::Connected,
+                    SerialWorkerState::Stalled,
+                    SerialWorkerState::Connected,
+                ]
+            ),
+            None
+        );
+        assert_eq!(
+            observe(
+                &mut view,
+                &[SerialWorkerState::Resetting, SerialWorkerState::Connected]
+            ),
+            Some(ReconnectView::Restore(bounds()))
+        );
+    }
+
+    #[test]
+    fn fit_to_data_is_pending_until_the_next_frame() {
+        let mut view = PlotView {
+            user_adjusted: Some(bounds()),
+            ..Default::default()
+        };
+
+        view.fit_to_data();
+        assert_eq!(view.pending, Some(ReconnectView::Follow));
+    }
+}
@@ -1,18 +1,258 @@
 use eframe::{
-    egui::{Sense, Ui},
-    emath::{Align2, Rect, Vec2},
+    egui::{DragValue, Sense, Ui, Visuals},
+    emath::{Align2, Pos2, Rect, Vec2},
     epaint::{Color32, FontId, Shape, Stroke},
 };
 use kestrel_metric::{metric_name, name::MetricName, value::MetricValue};
 
+/// Layout of the ultrasonic sweep held in `ultrasonic:last_readings`
+///
+/// The number of beams is inferred from the length of the readings array,
+/// with the beams spread evenly across the span, centered on straight ahead.
+#[derive(Debug, Clone, Copy)]
+pub struct SweepConfig {
+    pub span_degrees: f32,
+    pub max_range_cm: f32,
+}
+
+impl Default for SweepConfig {
+    fn default() -> Self {
+        Self {
+            span_degrees: 180.0,
+            max_range_cm: 300.0,
+        }
+    }
+}
+
+impl SweepConfig {
+    pub fn ui(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Sweep span");
+            ui.add(
+                DragValue::new(&mut self.span_degrees)
+                    .clamp_range(0.0..=360.0)
+                    .suffix("°"),
+            );
+            ui.label("Range");
+            ui.add(
+                DragValue::new(&mut self.max_range_cm)
+                    .clamp_range(1.0..=10_000.0)
+                    .suffix("cm"),
+            );
+        });
+    }
+
+    /// Heading of a beam in degrees, where 0 is straight ahead and positive is clockwise
+    pub fn beam_heading(&self, beam: usize, beams: usize) -> f32 {
+        if beams <= 1 {
+            return 0.0;
+        }
+
+        self.span_degrees * (count_to_f32(beam) / count_to_f32(beams - 1)) - self.span_degrees / 2.0
+    }
+
+    /// Offset from the sensor of a reading, given the radius of the full range
+    ///
+    /// Readings beyond the range are drawn at the edge of the range
+    pub fn reading_offset(&self, heading: f32, distance: f32, radius: f32) -> Vec2 {
+        let fraction = (distance / self.max_range_cm).clamp(0.0, 1.0);
+        let fraction = if fraction.is_nan() { 0.0 } else { fraction };
+
+        radius * fraction * heading_direction(heading)
+    }
+}
+
+/// Convert a distance reading to a float without any lossy casts, saturating
+/// absurdly large readings
+fn clamp_distance(distance: u64) -> f32 {
+    f32::from(u16::try_from(distance).unwrap_or(u16::MAX))
+}
+
+/// Convert a beam or ring count to a float without any lossy casts, saturating at
+/// `u16::MAX`, far beyond the beams of any sensor
+fn count_to_f32(count: usize) -> f32 {
+    f32::from(u16::try_from(count).unwrap_or(u16::MAX))
+}
+
+/// Wrap a heading in degrees into the range `-180..=180`
+fn normalize_heading(heading: i64) -> f32 {
+    let heading = i16::try_from(heading.rem_euclid(360)).expect("0..360 always fits in an i16");
+
+    f32::from(if heading > 180 {
+        heading - 360
+    } else {
+        heading
+    })
+}
+
+/// Screen space unit vector for a heading in degrees, where 0 is up
+fn heading_direction(heading: f32) -> Vec2 {
+    let direction = Vec2::angled((heading + 90.0).to_radians());
+
+    Vec2::new(-direction.x, -direction.y)
+}
+
+/// Colors of the robot drawing, so it stays legible on the canvas of a dark and a light theme
+#[derive(Debug, Clone, Copy)]
+struct Palette {
+    /// Outlines and readings, in contrast with the canvas
+    foreground: Color32,
+    /// Text drawn on top of the filled robot
+    on_robot: Color32,
+    trajectory: Color32,
+    ultrasonic: Color32,
+}
+
+impl Palette {
+    fn new(visuals: &Visuals) -> Self {
+        if visuals.dark_mode {
+            Self {
+                foreground: Color32::WHITE,
+                on_robot: Color32::BLACK,
+                trajectory: Color32::LIGHT_GREEN,
+                ultrasonic: Color32::KHAKI,
+            }
+        } else {
+            Self {
+                foreground: Color32::BLACK,
+                on_robot: Color32::WHITE,
+                trajectory: Color32::DARK_GREEN,
+                ultrasonic: Color32::from_rgb(140, 110, 0),
+            }
+        }
+    }
+}
+
+fn polar_sweep(
+    origin: Pos2,
+    radius: f32,
+    config: &SweepConfig,
+    readings: &[u64],
+    palette: Palette,
+) -> Vec<Shape> {
+    const RINGS: usize = 4;
+    const RING_SEGMENTS: usize = 32;
+
+    let ring_stroke = Stroke::new(1.0, Color32::GRAY.linear_multiply(0.5));
+
+    let mut shapes = (1..=RINGS)
+        .map(|ring| {
+            let ring_radius = radius * count_to_f32(ring) / count_to_f32(RINGS);
+
+            Shape::line(
+                (0..=RING_SEGMENTS)
+                    .map(|segment| {
+                        origin
+                            + ring_radius
+                                * heading_direction(config.beam_heading(segment, RING_SEGMENTS + 1))
+                    })
+                    .collect(),
+                ring_stroke,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    for edge in [-config.span_degrees / 2.0, config.span_degrees / 2.0] {
+        shapes.push(Shape::line_segment(
+            [origin, origin + radius * heading_direction(edge)],
+            ring_stroke,
+        ));
+    }
+
+    let points = readings
+        .iter()
+        .enumerate()
+        .map(|(beam, &distance)| {
+            let heading = config.beam_heading(beam, readings.len());
+
+            origin + config.reading_offset(heading, clamp_distance(distance), radius)
+        })
+        .collect::<Vec<_>>();
+
+    shapes.extend(points.iter().map(|&point| {
+        Shape::line_segment(
+            [origin, point],
+            Stroke::new(1.0, palette.foreground.linear_multiply(0.1)),
+        )
+    }));
+    shapes.push(Shape::line(
+        points.clone(),
+        Stroke::new(1.0, palette.foreground.linear_multiply(0.6)),
+    ));
+    shapes.extend(
+        points
+            .into_iter()
+            .map(|point| Shape::circle_filled(point, 1.5, palette.foreground)),
+    );
+
+    shapes
+}
+
+/// Polyline through the positions of the robot, scaled to fit the canvas with
+/// the y axis pointing up, and a dot at the newest position
+///
+/// Positions that are not finite are skipped, so the line continues from the
+/// last good position instead of breaking.
+fn trajectory(
+    canvas: Rect,
+    positions: impl Iterator<Item = (f32, f32)>,
+    color: Color32,
+) -> Vec<Shape> {
+    const MARGIN: f32 = 20.0;
+
+    let positions = positions
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .map(|(x, y)| Pos2::new(x, y))
+        .collect::<Vec<_>>();
+
+    let Some(&newest) = positions.last() else {
+        return Vec::new();
+    };
+
+    let bounds = Rect::from_points(&positions);
+    let area = canvas.shrink(MARGIN);
+    // Keep the aspect ratio, and do not blow up a robot that stood still
+    let scale = (area.size() / bounds.size().max(Vec2::splat(1.0))).min_elem();
+
+    let to_screen = |position: Pos2| {
+        area.center() + scale * Vec2::new(1.0, -1.0) * (position - bounds.center())
+    };
+
+    let stroke = Stroke::new(2.0, color.linear_multiply(0.8));
+
+    vec![
+        Shape::line(positions.into_iter().map(to_screen).collect(), stroke),
+        Shape::circle_filled(to_screen(newest), 4.0, color),
+    ]
+}
+
+/// Smallest canvas side the robot can be drawn in, in points
+const MIN_CANVAS_SIZE: f32 = 80.0;
+
+/// Whether the robot can be drawn in the available size
+///
+/// The heading arrows shrink to nothing below `MIN_CANVAS_SIZE`, and a collapsed
+/// window can even report a degenerate (negative or NaN) area. Each side is
+/// compared on its own, as `min_elem` skips a NaN side.
+fn fits_canvas(available: Vec2) -> bool {
+    available.x >= MIN_CANVAS_SIZE && available.y >= MIN_CANVAS_SIZE
+}
+
 pub fn robot<'ui, 'metric>(
     ui: &'ui mut Ui,
+    sweep: &SweepConfig,
     get_latest_value: impl Fn(MetricName) -> Option<&'metric MetricValue>,
+    positions: impl Iterator<Item = &'metric MetricValue>,
 ) {
-    let (canvas, _response) = ui.allocate_exact_size(
-        ui.available_rect_before_wrap().size(),
-        Sense::focusable_noninteractive(),
-    );
+    let available = ui.available_rect_before_wrap().size();
+
+    if !fits_canvas(available) {
+        ui.label("Enlarge the window to show the robot");
+        return;
+    }
+
+    let (canvas, _response) = ui.allocate_exact_size(available, Sense::focusable_noninteractive());
+    let palette = Palette::new(ui.visuals());
 
     // TODO: better (native) canvas coordinates
     let square_dimension = canvas.width().min(canvas.height());
@@ -23,8 +263,8 @@ pub fn robot<'ui, 'metric>(
     );
 
     let robot = [
-        Shape::rect_filled(robot_rect, 0.0, Color32::WHITE.linear_multiply(0.5)),
-        Shape::rect_stroke(robot_rect, 0.0, Stroke::new(2.0, Color32::WHITE)),
+        Shape::rect_filled(robot_rect, 0.0, palette.foreground.linear_multiply(0.5)),
+        Shape::rect_stroke(robot_rect, 0.0, Stroke::new(2.0, palette.foreground)),
         ui.fonts(|fonts| {
             Shape::text(
                 fonts,
@@ -32,30 +272,39 @@ pub fn robot<'ui, 'metric>(
                 Align2::CENTER_CENTER,
                 "Robot",
                 FontId::monospace(26.0),
-                Color32::BLACK,
+                palette.on_robot,
             )
         }),
     ];
 
     ui.painter().extend(robot.to_vec());
 
+    // History of `navigation:position`, samples of any other type are skipped
+    ui.painter().extend(trajectory(
+        canvas,
+        positions.filter_map(MetricValue::as_vec2),
+        palette.trajectory,
+    ));
+
     let heading_length = square_dimension / 4.0 - 15.0;
     let get_heading = |distance: u64, heading: i64| {
-        (heading_length * (distance as f32 / 300.0))
-            * Vec2::angled((heading as f32 + 90.0).to_radians())
+        sweep.reading_offset(
+            normalize_heading(heading),
+            clamp_distance(distance),
+            heading_length,
+        )
     };
 
-    if let Some(front_points) = get_latest_value(metric_name!("ultrasonic", "last_readings"))
+    if let Some(readings) = get_latest_value(metric_name!("ultrasonic", "last_readings"))
         .and_then(|distance| distance.as_unsigned_integer_iter())
     {
-        ui.painter()
-            .extend(front_points.enumerate().map(|(heading, distance)| {
-                Shape::circle_filled(
-                    robot_rect.center_top() - get_heading(distance, heading as i64 - 90),
-                    1.0,
-                    Color32::WHITE,
-                )
-            }));
+        ui.painter().extend(polar_sweep(
+            robot_rect.center_top(),
+            heading_length,
+            sweep,
+            &readings.collect::<Vec<_>>(),
+            palette,
+        ));
     }
 
     if let Some((distance, heading)) = Option::zip(
@@ -79,20 +328,20 @@ pub fn robot<'ui, 'metric>(
         shapes.push(Shape::line_segment(
             [
                 robot_rect.center_top(),
-                robot_rect.center_top() - ultrasonic_heading,
+                robot_rect.center_top() + ultrasonic_heading,
             ],
-            Stroke::new(2.0, Color32::KHAKI),
+            Stroke::new(2.0, palette.ultrasonic),
         ));
 
         shapes.extend_from_slice(&ui.fonts(|fonts| {
             [
                 Shape::text(
                     fonts,
-                    robot_rect.center_top() - ultrasonic_heading,
+                    robot_rect.center_top() + ultrasonic_heading,
                     Align2::CENTER_BOTTOM,
                     format!("{}cm", distance),
                     FontId::monospace(15.0),
-                    Color32::KHAKI,
+                    palette.ultrasonic,
                 ),
                 Shape::text(
                     fonts,
@@ -108,7 +357,7 @@ pub fn robot<'ui, 'metric>(
                     Align2::CENTER_TOP,
                     "Ultrasonic Sensor",
                     FontId::monospace(15.0),
-                    Color32::BLACK,
+                    palette.on_robot,
                 ),
             ]
         }));
@@ -129,8 +378,11 @@ pub fn robot<'ui, 'metric>(
             };
 
             let arrow_base = robot_rect.center() + 13.0 * direction;
+            // Speed is a ratio of the maximum, so clamp absurd values to the robot. Within
+            // 0..=1 narrowing to an f32 only rounds, and a NaN speed is drawn at full length
+            let speed_ratio = speed.abs().min(1.0) as f32;
             let arrow_tip =
-                arrow_base + direction * ((robot_rect.height() / 4.0 * speed.abs() as f32) - 13.0);
+                arrow_base + direction * ((robot_rect.height() / 4.0 * speed_ratio) - 13.0);
 
             let shapes = [
                 Shape::line(vec![arrow_base, arrow_tip], Stroke::new(7.0, color)),
@@ -150,3 +402,246 @@ pub fn robot<'ui, 'metric>(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use eframe::egui::{CentralPanel, Context, RawInput};
+    use kestrel_metric::value::{ManyValues, OneValue};
+
+    use super::*;
+
+    fn assert_close(actual: Vec2, expected: Vec2) {
+        assert!(
+            (actual - expected).length() < 1e-3,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn extreme_headings_wrap() {
+        assert_eq!(normalize_heading(0), 0.0);
+        assert_eq!(normalize_heading(180), 180.0);
+        assert_eq!(normalize_heading(181), -179.0);
+        assert_eq!(normalize_heading(-90), -90.0);
+        assert_eq!(normalize_heading(720 + 45), 45.0);
+        assert_eq!(
+            normalize_heading(i64::MAX),
+            normalize_heading(i64::MAX.rem_euclid(360))
+        );
+        assert_eq!(
+            normalize_heading(i64::MIN),
+            normalize_heading(i64::MIN.rem_euclid(360))
+        );
+    }
+
+    #[test]
+    fn extreme_distances_saturate() {
+        assert_eq!(clamp_distance(0), 0.0);
+        assert_eq!(clamp_distance(150), 150.0);
+        assert_eq!(clamp_distance(u64::MAX), f32::from(u16::MAX));
+    }
+
+    #[test]
+    fn extreme_readings_stay_on_the_range() {
+        let sweep = SweepConfig::default();
+
+        for (distance, heading) in [(u64::MAX, i64::MAX), (u64::MAX, i64::MIN), (0, i64::MIN)] {
+            let offset =
+                sweep.reading_offset(normalize_heading(heading), clamp_distance(distance), 100.0);
+
+            assert!(offset.x.is_finite() && offset.y.is_finite());
+            assert!(offset.length() <= 100.0 + 1e-3);
+        }
+
+        assert_close(
+            sweep.reading_offset(0.0, clamp_distance(u64::MAX), 100.0),
+            Vec2::new(0.0, -100.0),
+        );
+        assert_close(sweep.reading_offset(90.0, f32::NAN, 100.0), Vec2::ZERO);
+        assert_close(
+            sweep.reading_offset(-90.0, f32::INFINITY, 100.0),
+            Vec2::new(-100.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn huge_beam_counts_stay_within_the_span() {
+        let sweep = SweepConfig::default();
+
+        for beam in [0, 1, usize::MAX / 2, usize::MAX - 1] {
+            let heading = sweep.beam_heading(beam, usize::MAX);
+
+            assert!(
+                (-90.0..=90.0).contains(&heading),
+                "beam {beam} at {heading}"
+            );
+        }
+    }
+
+    /// Draw the robot with some readings and a trajectory in a window of the
+    /// given size, returning every shape painted
+    fn paint(size: Vec2) -> Vec<Shape> {
+        let values = [
+            (
+                metric_name!("ultrasonic", "last_readings"),
+                MetricValue::Many(ManyValues::U16([20, 150, 400].into())),
+            ),
+            (
+                metric_name!("ultrasonic", "distance"),
+                MetricValue::One(OneValue::U16(120)),
+            ),
+            (
+                metric_name!("ultrasonic", "heading"),
+                MetricValue::One(OneValue::I16(-30)),
+            ),
+        ]
+        .into_iter()
+        .collect::<BTreeMap<_, _>>();
+        let positions =
+            [(0.0, 0.0), (3.0, 4.0)].map(|(x, y)| MetricValue::One(OneValue::Vec2 { x, y }));
+
+        let input = RawInput {
+            screen_rect: Some(Rect::from_min_size(Pos2::ZERO, size)),
+            ..Default::default()
+        };
+        let output = Context::default().run(input, |ctx| {
+            CentralPanel::default().show(ctx, |ui| {
+                robot(
+                    ui,
+                    &SweepConfig::default(),
+                    |name| values.get(&name),
+                    positions.iter(),
+                );
+            });
+        });
+
+        output
+            .shapes
+            .into_iter()
+            .map(|clipped| clipped.shape)
+            .collect()
+    }
+
+    fn assert_no_nan(shapes: &[Shape]) {
+        for shape in shapes {
+            assert!(!shape.visual_bounding_rect().any_nan(), "NaN in {shape:?}");
+        }
+    }
+
+    #[test]
+    fn degenerate_sizes_do_not_fit() {
+        assert!(fits_canvas(Vec2::splat(MIN_CANVAS_SIZE)));
+        assert!(!fits_canvas(Vec2::new(1000.0, MIN_CANVAS_SIZE - 1.0)));
+        assert!(!fits_canvas(Vec2::ZERO));
+        assert!(!fits_canvas(Vec2::splat(-10.0)));
+        assert!(!fits_canvas(Vec2::new(f32::NAN, 1000.0)));
+        assert!(!fits_canvas(Vec2::splat(f32::NAN)));
+    }
+
+    #[test]
+    fn degenerate_windows_draw_no_nan() {
+        for size in [Vec2::ZERO, Vec2::splat(1.0), Vec2::new(1000.0, 0.0)] {
+            assert_no_nan(&paint(size));
+        }
+    }
+
+    #[test]
+    fn roomy_window_draws_the_robot() {
+        let shapes = paint(Vec2::splat(400.0));
+
+        assert!(shapes.len() > 10, "only drew {shapes:?}");
+        assert_no_nan(&shapes);
+    }
+
+    #[test]
+    fn trajectory_in_an_empty_canvas_has_no_nan() {
+        let canvas = Rect::from_min_size(Pos2::ZERO, Vec2::ZERO);
+
+        assert_no_nan(&trajectory(
+            canvas,
+            [(0.0, 0.0), (1.0, 1.0)].into_iter(),
+            Color32::RED,
+        ));
+        assert_no_nan(&trajectory(
+            canvas,
+            [(f32::NAN, 0.0), (2.0, 2.0)].into_iter(),
+            Color32::RED,
+        ));
+    }
+
+    #[test]
+    fn beams_spread_evenly_across_the_span() {
+        let sweep = SweepConfig::default();
+
+        let headings = (0..5)
+            .map(|beam| sweep.beam_heading(beam, 5))
+            .collect::<Vec<_>>();
+        assert_eq!(headings, [-90.0, -45.0, 0.0, 45.0, 90.0]);
+
+        let narrow = SweepConfig {
+            span_degrees: 60.0,
+            ..SweepConfig::default()
+        };
+        assert_eq!(narrow.beam_heading(0, 2), -30.0);
+        assert_eq!(narrow.beam_heading(1, 2), 30.0);
+    }
+
+    #[test]
+    fn single_beam_points_straight_ahead() {
+        let sweep = SweepConfig::default();
+
+        assert_eq!(sweep.beam_heading(0, 1), 0.0);
+        assert_eq!(sweep.beam_heading(0, 0), 0.0);
+    }
+
+    #[test]
+    fn readings_map_to_points_by_heading_and_range() {
+        let sweep = SweepConfig::default();
+
+        // Straight ahead is up on the screen, clockwise is to the right
+        assert_close(
+            sweep.reading_offset(0.0, 300.0, 100.0),
+            Vec2::new(0.0, -100.0),
+        );
+        assert_close(
+            sweep.reading_offset(90.0, 150.0, 100.0),
+            Vec2::new(50.0, 0.0),
+        );
+        assert_close(
+            sweep.reading_offset(-90.0, 75.0, 100.0),
+            Vec2::new(-25.0, 0.0),
+        );
+        assert_close(
+            sweep.reading_offset(180.0, 300.0, 100.0),
+            Vec2::new(0.0, 100.0),
+        );
+        assert_close(sweep.reading_offset(45.0, 0.0, 100.0), Vec2::ZERO);
+    }
+
+    #[test]
+    fn polar_sweep_draws_a_point_per_reading() {
+        let palette = Palette::new(&Visuals::dark());
+        let origin = Pos2::new(200.0, 200.0);
+        let sweep = SweepConfig::default();
+
+        let dots = |readings: &[u64]| {
+            polar_sweep(origin, 100.0, &sweep, readings, palette)
+                .into_iter()
+                .filter_map(|shape| match shape {
+                    Shape::Circle(circle) => Some(circle.center),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let points = dots(&[300, 150, 300]);
+        assert_eq!(points.len(), 3);
+        assert_close(points[0] - origin, Vec2::new(-100.0, 0.0));
+        assert_close(points[1] - origin, Vec2::new(0.0, -50.0));
+        assert_close(points[2] - origin, Vec2::new(100.0, 0.0));
+
+        assert!(dots(&[]).is_empty());
+    }
+}
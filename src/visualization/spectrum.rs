@@ -0,0 +1,269 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use eframe::egui::{ComboBox, Ui};
+use egui_plot::{Line, Plot, PlotPoints};
+use kestrel_metric::{name::MetricName, timestamp::Timestamp, value::MetricValue};
+use ringbuffer::{AllocRingBuffer, RingBuffer};
+use rustfft::{num_complex::Complex, FftPlanner};
+use time::OffsetDateTime;
+
+use crate::aliases::Aliases;
+
+use super::focused_metrics::{as_plot_value, color_from_metric_name};
+
+/// Window sizes the user can pick from, powers of two for the fastest transform
+pub const SPECTRUM_WINDOWS: [usize; 6] = [64, 128, 256, 512, 1024, 2048];
+/// Window size used until the user picks another one
+pub const DEFAULT_SPECTRUM_WINDOW: usize = 256;
+
+/// Single-sided amplitude spectrum of a uniformly resampled window of samples
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum {
+    /// Uniform rate the samples were resampled to, in Hz
+    pub sample_rate: f64,
+    /// Amplitude of each frequency bin, from 0 Hz up to half the sample rate
+    pub magnitudes: Vec<f64>,
+}
+
+impl Spectrum {
+    /// Width of a frequency bin in Hz
+    pub fn resolution(&self) -> f64 {
+        self.sample_rate / ((self.magnitudes.len() - 1) * 2) as f64
+    }
+}
+
+/// Resample `(seconds, value)` samples, sorted by time, to `len` equally spaced
+/// samples across their time span by linear interpolation
+///
+/// `None` if the samples span no time, or there are fewer than two.
+pub fn resample(samples: &[(f64, f64)], len: usize) -> Option<(f64, Vec<f64>)> {
+    let (&(start, _), &(end, _)) = (samples.first()?, samples.last()?);
+    if samples.len() < 2 || len < 2 || end <= start {
+        return None;
+    }
+
+    let step = (end - start) / (len - 1) as f64;
+
+    let mut next = 1;
+    let resampled = (0..len)
+        .map(|index| {
+            let time = start + step * index as f64;
+
+            // Find the pair of samples around this time, they only move forwards
+            while next < samples.len() - 1 && samples[next].0 < time {
+                next += 1;
+            }
+            let (before_time, before) = samples[next - 1];
+            let (after_time, after) = samples[next];
+
+            if after_time > before_time {
+                before + (after - before) * ((time - before_time) / (after_time - before_time))
+            } else {
+                after
+            }
+        })
+        .collect();
+
+    Some((1.0 / step, resampled))
+}
+
+/// Amplitude spectrum of the last `window` samples, resampled to a uniform rate first
+///
+/// The mean is removed and a Hann window applied before the transform, so the
+/// 0 Hz bin and the leakage between bins stay small. `None` if there are fewer
+/// than `window` samples or they span no time.
+pub fn spectrum(samples: &[(f64, f64)], window: usize) -> Option<Spectrum> {
+    let samples = samples.get(samples.len().checked_sub(window)?..)?;
+    let (sample_rate, values) = resample(samples, window)?;
+
+    let mean = values.iter().sum::<f64>() / window as f64;
+    let hann = (0..window)
+        .map(|index| 0.5 - 0.5 * (std::f64::consts::TAU * index as f64 / (window - 1) as f64).cos())
+        .collect::<Vec<_>>();
+    let gain = hann.iter().sum::<f64>();
+
+    let mut buffer = values
+        .iter()
+        .zip(&hann)
+        .map(|(value, weight)| Complex::new((value - mean) * weight, 0.0))
+        .collect::<Vec<_>>();
+
+    FftPlanner::new()
+        .plan_fft_forward(window)
+        .process(&mut buffer);
+
+    // Double every bin but 0 Hz and the Nyquist frequency to fold in the negative frequencies
+    let magnitudes = buffer[..=window / 2]
+        .iter()
+        .enumerate()
+        .map(|(bin, value)| {
+            let scale = if bin == 0 || bin == window / 2 {
+                1.0
+            } else {
+                2.0
+            };
+
+            value.norm() * scale / gain
+        })
+        .collect();
+
+    Some(Spectrum {
+        sample_rate,
+        magnitudes,
+    })
+}
+
+/// Which history the cached spectrum was computed from, so it is only computed
+/// again once a sample arrives or the settings change
+#[derive(Debug, Clone, PartialEq)]
+struct CacheKey {
+    metric: MetricName,
+    window: usize,
+    samples: usize,
+    newest: Option<Timestamp>,
+}
+
+/// Frequency spectrum of the recent history of a single focused metric
+#[derive(Debug)]
+pub struct SpectrumView {
+    pub open: bool,
+    pub metric: Option<MetricName>,
+    /// Number of the newest samples transformed
+    pub window: usize,
+    cache: Option<(CacheKey, Option<Spectrum>)>,
+}
+
+impl Default for SpectrumView {
+    fn default() -> Self {
+        Self {
+            open: false,
+            metric: None,
+            window: DEFAULT_SPECTRUM_WINDOW,
+            cache: None,
+        }
+    }
+}
+
+impl SpectrumView {
+    pub fn ui(
+        &mut self,
+        ui: &mut Ui,
+        focused_metrics: &BTreeSet<MetricName>,
+        sorted_metrics: &BTreeMap<
+            MetricName,
+            AllocRingBuffer<(Timestamp, OffsetDateTime, MetricValue)>,
+        >,
+        aliases: &Aliases,
+    ) {
+        let numeric = focused_metrics
+            .iter()
+            .filter(|name| {
+                sorted_metrics
+                    .get(*name)
+                    .and_then(|history| history.back())
+                    .is_some_and(|(_, _, value)| as_plot_value(value).is_some())
+            })
+            .collect::<Vec<_>>();
+
+        ui.horizontal(|ui| {
+            ComboBox::from_id_source("spectrum_metric")
+                .selected_text(
+                    self.metric
+                        .as_ref()
+                        .map_or("Pick a focused metric".to_owned(), |metric| {
+                            aliases.display(metric)
+                        }),
+                )
+                .show_ui(ui, |ui| {
+                    for name in &numeric {
+                        ui.selectable_value(
+                            &mut self.metric,
+                            Some((*name).clone()),
+                            aliases.display(name),
+                        );
+                    }
+                });
+
+            ComboBox::from_id_source("spectrum_window")
+                .selected_text(format!("{} samples", self.window))
+                .show_ui(ui, |ui| {
+                    for window in SPECTRUM_WINDOWS {
+                        ui.selectable_value(&mut self.window, window, format!("{window} samples"));
+                    }
+                })
+                .response
+                .on_hover_text_at_pointer(
+                    "Number of the newest samples transformed, more gives a finer frequency resolution but reacts slower",
+                );
+        });
+
+        if numeric.is_empty() {
+            ui.label("Focus a numeric metric to see its spectrum");
+        }
+
+        let Some((metric, history)) = self
+            .metric
+            .as_ref()
+            .and_then(|metric| Some((metric, sorted_metrics.get(metric)?)))
+        else {
+            return;
+        };
+
+        let key = CacheKey {
+            metric: metric.clone(),
+            window: self.window,
+            samples: history.len(),
+            newest: history.back().map(|(timestamp, _, _)| *timestamp),
+        };
+        if self.cache.as_ref().map(|(cached, _)| cached) != Some(&key) {
+            let samples = history
+                .iter()
+                .filter_map(|(timestamp, _, value)| {
+                    let seconds = f64::from(timestamp.timestamp())
+                        / f64::from(timestamp.unit().ticks_per_second());
+
+                    as_plot_value(value)
+                        .filter(|value| value.is_finite())
+                        .map(|value| (seconds, value))
+                })
+                .collect::<Vec<_>>();
+
+            self.cache = Some((key, spectrum(&samples, self.window)));
+        }
+
+        let Some((_, Some(spectrum))) = &self.cache else {
+            ui.label(format!(
+                "Waiting for {} finite samples spanning some time, {} so far",
+                self.window,
+                history.len()
+            ));
+            return;
+        };
+
+        ui.label(format!(
+            "Resampled to {:.1} Hz, {:.3} Hz per bin",
+            spectrum.sample_rate,
+            spectrum.resolution()
+        ));
+
+        let resolution = spectrum.resolution();
+        let points = spectrum
+            .magnitudes
+            .iter()
+            .enumerate()
+            .map(|(bin, &magnitude)| [bin as f64 * resolution, magnitude])
+            .collect::<PlotPoints>();
+
+        Plot::new("spectrum")
+            .height(240.0)
+            .x_axis_label("Frequency (Hz)")
+            .y_axis_label("Amplitude")
+            .show(ui, |ui| {
+                ui.line(
+                    Line::new(points)
+                        .name(aliases.display(metric))
+                        .color(color_from_metric_name(metric)),
+                );
+            });
+    }
+}
@@ -0,0 +1,81 @@
+/// A time cursor shared between every plot with a time axis
+///
+/// Whichever plot has the pointer during a frame reports the hovered time,
+/// which is then drawn by all plots on the following frame.
+#[derive(Debug, Default)]
+pub struct TimeCursor {
+    shown: Option<f64>,
+    hovered: Option<f64>,
+}
+
+impl TimeCursor {
+    /// Promote the time hovered during the last frame to the displayed time
+    pub fn begin_frame(&mut self) {
+        self.shown = self.hovered.take();
+    }
+
+    /// The time to draw the cursor at, if any plot was hovered last frame
+    pub fn time(&self) -> Option<f64> {
+        self.shown
+    }
+
+    /// Report the time under the pointer of a hovered plot
+    pub fn hover(&mut self, time: f64) {
+        self.hovered = Some(time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hover_is_shown_on_the_next_frame() {
+        let mut cursor = TimeCursor::default();
+
+        cursor.begin_frame();
+        cursor.hover(1.5);
+        assert_eq!(cursor.time(), None);
+
+        cursor.begin_frame();
+        assert_eq!(cursor.time(), Some(1.5));
+    }
+
+    #[test]
+    fn cursor_disappears_once_no_plot_is_hovered() {
+        let mut cursor = TimeCursor::default();
+        cursor.hover(1.5);
+        cursor.begin_frame();
+
+        cursor.begin_frame();
+        assert_eq!(cursor.time(), None);
+    }
+
+    #[test]
+    fn every_plot_sees_the_same_time() {
+        let mut cursor = TimeCursor::default();
+        cursor.hover(2.0);
+        cursor.begin_frame();
+
+        // Plots drawn after the hovered one still draw the time of the last frame
+        let first = cursor.time();
+        cursor.hover(3.0);
+        let second = cursor.time();
+
+        assert_eq!(first, Some(2.0));
+        assert_eq!(second, Some(2.0));
+
+        cursor.begin_frame();
+        assert_eq!(cursor.time(), Some(3.0));
+    }
+
+    #[test]
+    fn last_hovered_plot_wins() {
+        let mut cursor = TimeCursor::default();
+        cursor.hover(1.0);
+        cursor.hover(4.0);
+        cursor.begin_frame();
+
+        assert_eq!(cursor.time(), Some(4.0));
+    }
+}
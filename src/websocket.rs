@@ -0,0 +1,260 @@
+use std::{
+    io, mem,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use kestrel_metric::{value::MetricValue, Metric};
+use serde_json::{json, Value};
+use tracing::{debug, info, warn};
+use tungstenite::{Message, WebSocket};
+
+/// Longest a client may block a broadcast before it is dropped
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How often the frames sent by clients are read, to answer pings and notice closes
+const READ_INTERVAL: Duration = Duration::from_millis(250);
+
+type Clients = Arc<Mutex<Vec<WebSocket<TcpStream>>>>;
+
+/// Streams every received metric as a JSON object to all connected WebSocket clients,
+/// for dashboards outside of the app
+///
+/// Clients are accepted and written to on their own threads, so a slow client
+/// never stalls the UI. The listener stays open for the rest of the process.
+pub struct MetricBroadcaster {
+    addr: SocketAddr,
+    /// Number of connected clients, kept apart from the clients so the UI never
+    /// waits on a broadcast to read it
+    client_count: Arc<AtomicUsize>,
+    message_tx: Sender<String>,
+}
+
+impl MetricBroadcaster {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+
+        let clients = Clients::default();
+        let client_count = Arc::new(AtomicUsize::new(0));
+        let (message_tx, message_rx) = channel::<String>();
+
+        thread::Builder::new()
+            .name("websocket_accept".into())
+            .spawn({
+                let clients = Arc::clone(&clients);
+                let client_count = Arc::clone(&client_count);
+
+                move || accept_clients(&listener, &clients, &client_count)
+            })?;
+
+        thread::Builder::new()
+            .name("websocket_broadcast".into())
+            .spawn({
+                let client_count = Arc::clone(&client_count);
+
+                move || broadcast_messages(&message_rx, &clients, &client_count)
+            })?;
+
+        info!(%addr, "streaming metrics over websocket");
+
+        Ok(Self {
+            addr,
+            client_count,
+            message_tx,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.client_count.load(Ordering::Relaxed)
+    }
+
+    /// Queue a metric for every connected client, skipped entirely while nobody is listening
+    pub fn send(&self, metric: &Metric) {
+        if self.client_count() == 0 {
+            return;
+        }
+
+        let message = json!({
+            "timestamp": metric.timestamp.timestamp(),
+            "name": metric.name.to_string(),
+            "type": metric.value.ty(),
+            "value": json_value(&metric.value),
+        });
+
+        if self.message_tx.send(message.to_string()).is_err() {
+            warn!("websocket broadcast thread has exited");
+        }
+    }
+}
+
+fn accept_clients(listener: &TcpListener, clients: &Clients, client_count: &Arc<AtomicUsize>) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                warn!(%err, "failed to accept websocket connection");
+                continue;
+            }
+        };
+
+        // The handshake waits on the client, so it must not hold up other connections
+        let spawned = thread::Builder::new()
+            .name("websocket_handshake".into())
+            .spawn({
+                let clients = Arc::clone(clients);
+                let client_count = Arc::clone(client_count);
+
+                move || {
+                    let peer = stream.peer_addr().ok();
+
+                    if let Err(err) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+                        warn!(%err, "failed to set websocket write timeout");
+                    }
+
+                    match tungstenite::accept(stream) {
+                        Ok(client) => {
+                            info!(?peer, "websocket client connected");
+
+                            clients
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .push(client);
+                            // Counted rather than read from the list, which is empty while broadcasting
+                            client_count.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(err) => warn!(%err, ?peer, "websocket handshake failed"),
+                    }
+                }
+            });
+
+        if let Err(err) = spawned {
+            warn!(%err, "failed to spawn websocket handshake thread");
+        }
+    }
+}
+
+/// Broadcast the queued messages until the broadcaster is dropped, reading what the
+/// clients sent every [`READ_INTERVAL`] even while there is nothing to send
+fn broadcast_messages(
+    message_rx: &Receiver<String>,
+    clients: &Clients,
+    client_count: &AtomicUsize,
+) {
+    let mut read_at = Instant::now();
+
+    loop {
+        let message = match message_rx.recv_timeout(READ_INTERVAL) {
+            Ok(message) => Some(message),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => return,
+        };
+
+        let read = read_at.elapsed() >= READ_INTERVAL;
+        if read {
+            read_at = Instant::now();
+        }
+
+        if message.is_some() || read {
+            broadcast(clients, client_count, message.as_deref(), read);
+        }
+    }
+}
+
+/// Send a message to every client, dropping the clients that disconnected or fell behind
+///
+/// The clients are taken out of the list while they are written to, so a slow
+/// client does not hold up the clients being accepted meanwhile.
+fn broadcast(clients: &Clients, client_count: &AtomicUsize, message: Option<&str>, read: bool) {
+    let mut writing = mem::take(&mut *clients.lock().unwrap_or_else(PoisonError::into_inner));
+
+    writing.retain_mut(|client| {
+        if read && !read_incoming(client) {
+            return false;
+        }
+
+        let Some(message) = message else {
+            return true;
+        };
+
+        match client.send(Message::Text(message.to_owned())) {
+            Ok(()) => true,
+            Err(err) => {
+                debug!(%err, "dropping websocket client");
+                false
+            }
+        }
+    });
+
+    let mut clients = clients.lock().unwrap_or_else(PoisonError::into_inner);
+    clients.append(&mut writing);
+    client_count.store(clients.len(), Ordering::Relaxed);
+}
+
+/// Read the frames a client sent without waiting for more, returning whether it is still connected
+///
+/// Pings are answered by the next write, and a close is answered right away.
+fn read_incoming(client: &mut WebSocket<TcpStream>) -> bool {
+    if let Err(err) = client.get_mut().set_nonblocking(true) {
+        debug!(%err, "dropping websocket client");
+        return false;
+    }
+
+    let connected = loop {
+        match client.read() {
+            Ok(Message::Close(_)) => {
+                let _ = client.flush();
+                debug!("websocket client closed the connection");
+                break false;
+            }
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err)) if err.kind() == io::ErrorKind::WouldBlock => {
+                break true;
+            }
+            Err(err) => {
+                debug!(%err, "dropping websocket client");
+                break false;
+            }
+        }
+    };
+
+    connected && client.get_mut().set_nonblocking(false).is_ok()
+}
+
+/// The value as a JSON number, bool, string or array, falling back to its formatted form
+fn json_value(value: &MetricValue) -> Value {
+    if let Some(value) = value.as_bool() {
+        json!(value)
+    } else if let Some(value) = value.as_unsigned_integer() {
+        json!(value)
+    } else if let Some(value) = value.as_signed_integer() {
+        json!(value)
+    } else if let Some(value) = value.as_float() {
+        json!(value)
+    } else if let Some(value) = value.as_str() {
+        json!(value)
+    } else if let Some((x, y)) = value.as_vec2() {
+        json!([x, y])
+    } else if let Some(values) = value.as_bool_iter() {
+        json!(values.collect::<Vec<_>>())
+    } else if let Some(values) = value.as_unsigned_integer_iter() {
+        json!(values.collect::<Vec<_>>())
+    } else if let Some(values) = value.as_signed_integer_iter() {
+        json!(values.collect::<Vec<_>>())
+    } else if let Some(values) = value.as_float_iter() {
+        json!(values.collect::<Vec<_>>())
+    } else if let Some(values) = value.as_vec2_iter() {
+        json!(values.map(|(x, y)| [x, y]).collect::<Vec<_>>())
+    } else {
+        json!(value.value())
+    }
+}